@@ -1,7 +1,7 @@
 use std::convert::TryInto;
 use std::path::Path;
 use std::path::PathBuf;
-use std::time::UNIX_EPOCH;
+use std::time::{Duration, UNIX_EPOCH};
 
 use anyhow::{anyhow, bail, Context as _};
 use base64::prelude::*;
@@ -24,9 +24,13 @@ use presage::libsignal_service::protocol::ServiceId;
 use presage::libsignal_service::sender::AttachmentSpec;
 use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
 use presage::model::contacts::Contact;
+use presage::model::groups::AccessRequired;
 use presage::model::groups::Group;
+use presage::model::groups::GroupMemberRole;
 use presage::model::identity::OnNewIdentity;
+use presage::model::messages::AttachmentMetadata;
 use presage::model::messages::Received;
+use presage::model::messages::{TypingAction, TypingIndicator};
 use presage::proto::receipt_message;
 use presage::proto::EditMessage;
 use presage::proto::ReceiptMessage;
@@ -34,7 +38,9 @@ use presage::proto::SyncMessage;
 use presage::store::ContentExt;
 use presage::{
     libsignal_service::content::{Content, ContentBody, DataMessage, GroupContextV2},
-    manager::{Registered, RegistrationOptions},
+    manager::{
+        ReceiveOptions, Registered, RegistrationOptions, RetryPolicy, VerificationTransport,
+    },
     store::{Store, Thread},
     Manager,
 };
@@ -116,6 +122,13 @@ enum Cmd {
     ListDevices,
     #[clap(about = "Get information on the registered user")]
     Whoami,
+    #[clap(about = "Show the number of pre-keys available for new contacts")]
+    PreKeys {
+        #[clap(long, help = "Upload new pre-keys if the server runs low on them")]
+        replenish: bool,
+        #[clap(long, help = "Rotate the signed and last resort pre-keys")]
+        rotate: bool,
+    },
     #[clap(about = "Retrieve the user profile")]
     RetrieveProfile {
         /// Id of the user to retrieve the profile. When omitted, retrieves the registered user
@@ -126,6 +139,25 @@ enum Cmd {
         #[clap(long, value_parser = parse_base64_profile_key)]
         profile_key: Option<ProfileKey>,
     },
+    #[clap(about = "Update the profile of this account")]
+    UpdateProfile {
+        #[clap(long, help = "Given name")]
+        given_name: Option<String>,
+        #[clap(long, help = "Family name")]
+        family_name: Option<String>,
+        #[clap(long, help = "About text")]
+        about: Option<String>,
+        #[clap(long, help = "About emoji")]
+        about_emoji: Option<String>,
+        #[clap(
+            long,
+            help = "Path to the new avatar",
+            conflicts_with = "remove_avatar"
+        )]
+        avatar: Option<PathBuf>,
+        #[clap(long, help = "Remove the avatar")]
+        remove_avatar: bool,
+    },
     #[clap(about = "Receive all pending messages and saves them to disk")]
     Sync {
         #[clap(long = "notifications", short = 'n')]
@@ -140,6 +172,11 @@ enum Cmd {
         #[clap(long, short = 'v')]
         verbose: bool,
     },
+    #[clap(about = "Fetch the latest state of a group from the server")]
+    FetchGroup {
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: GroupMasterKeyBytes,
+    },
     #[clap(about = "List contacts")]
     ListContacts,
     #[clap(
@@ -196,6 +233,27 @@ enum Cmd {
         #[clap(long = "attach", help = "Path to a file to attach, can be repeated")]
         attachment_filepath: Vec<PathBuf>,
     },
+    #[clap(
+        about = "Delete a message sent by this account for everyone",
+        group(
+            ArgGroup::new("delete-message")
+                .required(true)
+                .args(&["recipient_uuid", "group_master_key"])
+        )
+    )]
+    DeleteMessage {
+        #[clap(long, short = 'u', help = "recipient UUID")]
+        recipient_uuid: Option<Uuid>,
+        #[clap(
+            long,
+            short = 'k',
+            help = "Master Key of the V2 group (hex string)",
+            value_parser = parse_group_master_key,
+        )]
+        group_master_key: Option<GroupMasterKeyBytes>,
+        #[clap(long, short = 't', help = "Timestamp of the message to delete")]
+        timestamp: u64,
+    },
     SyncContacts,
     #[clap(about = "Print various statistics useful for debugging")]
     Stats,
@@ -221,6 +279,13 @@ enum Cmd {
         #[clap(long, short = 's', help = "Selected option indices (0-based, can be repeated)", num_args = 1..)]
         selected_options: Vec<u32>,
     },
+    #[clap(about = "Retract your vote on a poll in a group")]
+    RetractVote {
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: GroupMasterKeyBytes,
+        #[clap(long, help = "Timestamp of the poll message")]
+        poll_timestamp: u64,
+    },
     #[clap(about = "Terminate a poll in a group")]
     TerminatePoll {
         #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
@@ -228,6 +293,13 @@ enum Cmd {
         #[clap(long, help = "Poll message timestamp to terminate")]
         poll_timestamp: u64,
     },
+    #[clap(about = "Show the results of a poll in a group")]
+    PollResults {
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: GroupMasterKeyBytes,
+        #[clap(long, help = "Timestamp of the poll message")]
+        poll_timestamp: u64,
+    },
     #[clap(about = "Create a new group")]
     CreateGroup {
         #[clap(long, help = "Title of the group")]
@@ -241,6 +313,8 @@ enum Cmd {
         master_key: GroupMasterKeyBytes,
         #[clap(long, short = 'u', help = "UUID of the member to add (can be repeated)", action = clap::ArgAction::Append)]
         uuid: Vec<Uuid>,
+        #[clap(long, help = "Add the members as administrators")]
+        admin: bool,
     },
     #[clap(about = "Remove members from an existing group")]
     RemoveMember {
@@ -249,13 +323,34 @@ enum Cmd {
         #[clap(long, short = 'u', help = "UUID of the member to remove (can be repeated)", action = clap::ArgAction::Append)]
         uuid: Vec<Uuid>,
     },
-    #[clap(about = "Set disappearing messages timer for a group")]
-    SetDisappearingTimer {
+    #[clap(about = "Leave a group")]
+    LeaveGroup {
         #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
         master_key: GroupMasterKeyBytes,
+    },
+    #[clap(
+        about = "Set disappearing messages timer for a contact or a group",
+        group(
+            ArgGroup::new("disappearing-timer-thread")
+                .required(true)
+                .args(&["recipient_uuid", "master_key"])
+        )
+    )]
+    SetDisappearingTimer {
+        #[clap(long, short = 'u', help = "recipient UUID")]
+        recipient_uuid: Option<Uuid>,
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: Option<GroupMasterKeyBytes>,
         #[clap(long, short = 'd', help = "Timer duration in seconds (0 to disable)")]
         duration: u32,
     },
+    #[clap(about = "Set a group's title")]
+    SetGroupTitle {
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: GroupMasterKeyBytes,
+        #[clap(long, short = 't', help = "New title")]
+        title: String,
+    },
     #[clap(about = "Set a group's description")]
     SetGroupDescription {
         #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
@@ -263,14 +358,50 @@ enum Cmd {
         #[clap(long, short = 'd', help = "New description (empty string to clear)")]
         description: String,
     },
-    #[clap(about = "Set group access control (who can edit attributes/add members)")]
+    #[clap(
+        about = "Set group access control (who can edit attributes/add members/join with the invite link)"
+    )]
     SetGroupAccess {
         #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
         master_key: GroupMasterKeyBytes,
         #[clap(long, help = "Who can edit group attributes: member, administrator", value_parser = parse_access_required)]
-        attributes: Option<String>,
+        attributes: Option<AccessRequired>,
         #[clap(long, help = "Who can add members: member, administrator", value_parser = parse_access_required)]
-        members: Option<String>,
+        members: Option<AccessRequired>,
+        #[clap(long, help = "Who can join with the invite link: any, administrator, unsatisfiable", value_parser = parse_access_required)]
+        invite_link: Option<AccessRequired>,
+    },
+    #[clap(
+        about = "Manage the invite link of a group and print it",
+        group(ArgGroup::new("invite-link-action").args(&["enable", "disable"]))
+    )]
+    GroupInviteLink {
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: GroupMasterKeyBytes,
+        #[clap(long, help = "Allow joining the group with the invite link")]
+        enable: bool,
+        #[clap(long, help = "Disallow joining the group with the invite link")]
+        disable: bool,
+        #[clap(long, help = "Rotate the invite link, previous links stop working")]
+        reset: bool,
+    },
+    #[clap(about = "Join a group with its invite link")]
+    JoinGroup {
+        #[clap(
+            long,
+            short = 'l',
+            help = "Invite link of the group (https://signal.group/#...)"
+        )]
+        link: Url,
+    },
+    #[clap(about = "List the requests to join a group, and approve or deny them")]
+    GroupRequests {
+        #[clap(long, short = 'k', help = "Master Key of the V2 group (hex string)", value_parser = parse_group_master_key)]
+        master_key: GroupMasterKeyBytes,
+        #[clap(long, help = "UUID of a user to let in the group (can be repeated)", action = clap::ArgAction::Append)]
+        approve: Vec<Uuid>,
+        #[clap(long, help = "UUID of a user to refuse in the group (can be repeated)", action = clap::ArgAction::Append)]
+        deny: Vec<Uuid>,
     },
     #[clap(about = "Set group to announcements-only mode (only admins can send)")]
     SetAnnouncementsOnly {
@@ -293,27 +424,19 @@ fn parse_group_master_key(value: &str) -> anyhow::Result<GroupMasterKeyBytes> {
         .map_err(|_| anyhow::format_err!("master key should be 32 bytes long"))
 }
 
-fn parse_access_required(value: &str) -> anyhow::Result<String> {
+fn parse_access_required(value: &str) -> anyhow::Result<AccessRequired> {
     match value.to_lowercase().as_str() {
-        "member" | "administrator" | "admin" | "any" | "unsatisfiable" => Ok(value.to_lowercase()),
+        "any" => Ok(AccessRequired::Any),
+        "member" => Ok(AccessRequired::Member),
+        "administrator" | "admin" => Ok(AccessRequired::Administrator),
+        "unsatisfiable" => Ok(AccessRequired::Unsatisfiable),
         _ => Err(anyhow!(
-            "Invalid access level: {}. Use: member, administrator, any",
+            "Invalid access level: {}. Use: member, administrator, any, unsatisfiable",
             value
         )),
     }
 }
 
-fn access_required_from_str(value: &str) -> presage::libsignal_service::groups_v2::AccessRequired {
-    use presage::libsignal_service::groups_v2::AccessRequired;
-    match value.to_lowercase().as_str() {
-        "any" => AccessRequired::Any,
-        "member" => AccessRequired::Member,
-        "administrator" | "admin" => AccessRequired::Administrator,
-        "unsatisfiable" => AccessRequired::Unsatisfiable,
-        _ => AccessRequired::Member,
-    }
-}
-
 fn attachments_tmp_dir() -> anyhow::Result<TempDir> {
     let attachments_tmp_dir = Builder::new().prefix("presage-attachments").tempdir()?;
     info!(
@@ -377,7 +500,7 @@ async fn send<S: Store>(
     }
 
     let messages = manager
-        .receive_messages()
+        .receive_pending_messages()
         .await
         .context("failed to initialize messages stream")?;
     pin_mut!(messages);
@@ -386,9 +509,15 @@ async fn send<S: Store>(
 
     while let Some(content) = messages.next().await {
         match content {
-            Received::QueueEmpty => break,
-            Received::Contacts => continue,
-            Received::Content(content) => {
+            Received::QueueEmpty
+            | Received::Contacts
+            | Received::Typing(_)
+            | Received::Receipt(_)
+            | Received::Read(_)
+            | Received::PollTerminated { .. }
+            | Received::CallEvent(_)
+            | Received::Reconnected => continue,
+            Received::Content(content, _) => {
                 process_incoming_message(manager, attachments_tmp_dir.path(), false, &content).await
             }
         }
@@ -450,8 +579,16 @@ async fn process_incoming_message<S: Store>(
                 .clone()
                 .unwrap_or_else(|| Local::now().format("%Y-%m-%d-%H-%M-%s").to_string());
             let file_path = attachments_tmp_dir.join(format!("presage-{filename}.{extension}",));
+            let metadata = AttachmentMetadata::from(attachment_pointer);
             match fs::write(&file_path, &attachment_data).await {
-                Ok(_) => info!(%sender, file_path =% file_path.display(), "saved attachment"),
+                Ok(_) => info!(
+                    %sender,
+                    file_path =% file_path.display(),
+                    dimensions = ?metadata.dimensions(),
+                    voice_message = metadata.voice_message,
+                    caption = ?metadata.caption,
+                    "saved attachment"
+                ),
                 Err(error) => error!(
                     %sender,
                     file_path =% file_path.display(),
@@ -538,6 +675,13 @@ async fn print_message<S: Store>(
                 poll_terminate: Some(_),
                 ..
             } => Some("🔒 Poll terminated".to_string()),
+            DataMessage {
+                gift_badge: Some(_),
+                ..
+            } => Some("🎁 Gift badge".to_string()),
+            DataMessage {
+                payment: Some(_), ..
+            } => Some("💸 Payment".to_string()),
             DataMessage {
                 quote:
                     Some(Quote {
@@ -580,18 +724,6 @@ async fn print_message<S: Store>(
         }
     }
 
-    async fn format_contact<S: Store>(uuid: &Uuid, manager: &Manager<S, Registered>) -> String {
-        manager
-            .store()
-            .contact_by_id(uuid)
-            .await
-            .ok()
-            .flatten()
-            .filter(|c| !c.name.is_empty())
-            .map(|c| format!("{}: {}", c.name, uuid))
-            .unwrap_or_else(|| uuid.to_string())
-    }
-
     async fn format_group<S: Store>(key: [u8; 32], manager: &Manager<S, Registered>) -> String {
         manager
             .store()
@@ -705,13 +837,45 @@ async fn print_message<S: Store>(
     }
 }
 
+async fn format_contact<S: Store>(uuid: &Uuid, manager: &Manager<S, Registered>) -> String {
+    manager
+        .store()
+        .contact_by_id(uuid)
+        .await
+        .ok()
+        .flatten()
+        .filter(|c| !c.name.is_empty())
+        .map(|c| format!("{}: {}", c.name, uuid))
+        .unwrap_or_else(|| uuid.to_string())
+}
+
+async fn print_typing<S: Store>(manager: &Manager<S, Registered>, typing: &TypingIndicator) {
+    let sender = format_contact(&typing.sender.raw_uuid(), manager).await;
+    let title = manager
+        .thread_title(&typing.thread)
+        .await
+        .unwrap_or_default();
+    match typing.action {
+        TypingAction::Started => println!("{sender} is typing in {title}..."),
+        TypingAction::Stopped => println!("{sender} stopped typing in {title}"),
+    }
+}
+
 async fn receive<S: Store>(
     mut manager: Manager<S, Registered>,
     notifications: bool,
 ) -> anyhow::Result<()> {
     let attachments_tmp_dir = attachments_tmp_dir()?;
+    let options = ReceiveOptions {
+        reconnect: Some(RetryPolicy {
+            max_attempts: u32::MAX,
+            ..Default::default()
+        }),
+        skip_blocked: true,
+        ..Default::default()
+    };
     let messages = manager
-        .receive_messages()
+        .receive_messages_with_options(options)
         .await
         .context("failed to initialize messages stream")?;
     pin_mut!(messages);
@@ -720,7 +884,34 @@ async fn receive<S: Store>(
         match content {
             Received::QueueEmpty => println!("done with synchronization"),
             Received::Contacts => println!("got contacts synchronization"),
-            Received::Content(content) => {
+            Received::Reconnected => println!("reconnected"),
+            Received::Typing(typing) => print_typing(&manager, &typing).await,
+            Received::Read(threads) => {
+                println!(
+                    "messages read on another device in {} threads",
+                    threads.len()
+                )
+            }
+            Received::PollTerminated {
+                thread,
+                poll_timestamp,
+            } => println!("🔒 poll sent at {poll_timestamp} terminated in {thread}"),
+            Received::CallEvent(call_event) => println!(
+                "📞 {:?} {:?} call ({:?}) with {:?} at {}",
+                call_event.status,
+                call_event.kind,
+                call_event.direction,
+                call_event.peer,
+                call_event.timestamp
+            ),
+            Received::Receipt(receipt) => {
+                let sender = format_contact(&receipt.sender.raw_uuid(), &manager).await;
+                println!(
+                    "got {:?} receipt from {sender} for messages sent at {:?}",
+                    receipt.kind, receipt.timestamps
+                );
+            }
+            Received::Content(content, _) => {
                 process_incoming_message(
                     &mut manager,
                     attachments_tmp_dir.path(),
@@ -749,8 +940,15 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
                 RegistrationOptions {
                     signal_servers: servers,
                     phone_number,
-                    use_voice_call,
+                    transport: if use_voice_call {
+                        VerificationTransport::Voice
+                    } else {
+                        VerificationTransport::Sms
+                    },
                     captcha: Some(captcha.host_str().unwrap()),
+                    push_token: None,
+                    push_challenge: None,
+                    session_token: None,
                     force,
                 },
             )
@@ -896,11 +1094,43 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
             } else {
                 println!("Retrieving profile for: {uuid:?} with profile_key");
             }
-            let profile = match profile_key {
-                None => manager.retrieve_profile().await?,
-                Some(profile_key) => manager.retrieve_profile_by_uuid(uuid, profile_key).await?,
-            };
-            println!("{profile:#?}");
+            match profile_key {
+                None => println!("{:#?}", manager.retrieve_profile().await?),
+                Some(profile_key) => println!(
+                    "{:#?}",
+                    manager.retrieve_profile_details(uuid, profile_key).await?
+                ),
+            }
+        }
+        Cmd::UpdateProfile {
+            given_name,
+            family_name,
+            about,
+            about_emoji,
+            avatar,
+            remove_avatar,
+        } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            if let Some(given_name) = given_name {
+                manager
+                    .set_profile(
+                        &given_name,
+                        family_name.as_deref(),
+                        about.as_deref(),
+                        about_emoji.as_deref(),
+                    )
+                    .await?;
+                println!("Profile updated.");
+            }
+            if let Some(avatar) = avatar {
+                manager
+                    .set_profile_avatar(Some(std::fs::read(avatar)?))
+                    .await?;
+                println!("Avatar updated.");
+            } else if remove_avatar {
+                manager.set_profile_avatar(None).await?;
+                println!("Avatar removed.");
+            }
         }
         Cmd::ListGroups {
             name_filter,
@@ -956,6 +1186,11 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
                 };
             }
         }
+        Cmd::FetchGroup { master_key } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            let group = manager.fetch_group(&master_key).await?;
+            println!("{group:#?}");
+        }
         Cmd::ListContacts => {
             let manager = load_registered_and_receive(store).await?;
             for Contact {
@@ -997,6 +1232,16 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
             let manager = load_registered_and_receive(store).await?;
             println!("{:?}", &manager.whoami().await?);
         }
+        Cmd::PreKeys { replenish, rotate } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            if replenish {
+                manager.replenish_prekeys().await?;
+            }
+            if rotate {
+                manager.rotate_signed_prekey().await?;
+            }
+            println!("{:#?}", manager.prekey_count().await?);
+        }
         Cmd::GetContact { ref uuid } => {
             let manager = load_registered_and_receive(store).await?;
             match manager.store().contact_by_id(uuid).await? {
@@ -1024,23 +1269,12 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
         }
         Cmd::SyncContacts => {
             let mut manager = load_registered_and_receive(store).await?;
-            manager.request_contacts().await?;
-
-            let messages = manager
-                .receive_messages()
+            println!("synchronizing messages until we get contacts");
+            let count = manager
+                .sync_contacts(Duration::from_secs(60))
                 .await
-                .context("failed to initialize messages stream")?;
-            pin_mut!(messages);
-
-            println!("synchronizing messages until we get contacts (dots are messages synced from the past timeline)");
-
-            while let Some(content) = messages.next().await {
-                match content {
-                    Received::QueueEmpty => break,
-                    Received::Contacts => println!("got contacts! thank you, come again."),
-                    Received::Content(_) => print!("."),
-                }
-            }
+                .context("failed to synchronize contacts")?;
+            println!("got {count} contacts! thank you, come again.");
         }
         Cmd::ListMessages {
             group_master_key,
@@ -1060,6 +1294,10 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
                 .filter_map(Result::ok)
             {
                 print_message(&manager, false, &msg).await;
+                for reaction in manager.store().reactions(&thread, msg.timestamp()).await? {
+                    let author = format_contact(&reaction.author.raw_uuid(), &manager).await;
+                    println!("  {} by {author}", reaction.emoji);
+                }
             }
         }
         Cmd::Stats => {
@@ -1110,6 +1348,20 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
 
             println!("{stats:#?}")
         }
+        Cmd::DeleteMessage {
+            recipient_uuid,
+            group_master_key,
+            timestamp,
+        } => {
+            let thread = match (recipient_uuid, group_master_key) {
+                (Some(uuid), _) => Thread::Contact(uuid),
+                (None, Some(key)) => Thread::Group(key),
+                _ => unreachable!(),
+            };
+            let mut manager = load_registered_and_receive(store).await?;
+            manager.delete_message(&thread, timestamp).await?;
+            println!("Message deleted.");
+        }
         Cmd::SendPoll {
             master_key,
             question,
@@ -1151,6 +1403,14 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
                 .await?;
             println!("Vote cast successfully");
         }
+        Cmd::RetractVote {
+            master_key,
+            poll_timestamp,
+        } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            manager.retract_vote(&master_key, poll_timestamp).await?;
+            println!("Vote retracted successfully");
+        }
         Cmd::TerminatePoll {
             master_key,
             poll_timestamp,
@@ -1159,6 +1419,32 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
             manager.terminate_poll(&master_key, poll_timestamp).await?;
             println!("Poll terminated successfully");
         }
+        Cmd::PollResults {
+            master_key,
+            poll_timestamp,
+        } => {
+            let manager = load_registered_and_receive(store).await?;
+            let results = manager
+                .poll_results(&Thread::Group(master_key), poll_timestamp)
+                .await?;
+            println!(
+                "📊 {}{}",
+                results.question,
+                if results.terminated { " (final)" } else { "" }
+            );
+            for option in &results.options {
+                let mut voters = Vec::with_capacity(option.voters.len());
+                for voter in &option.voters {
+                    voters.push(format_contact(&voter.raw_uuid(), &manager).await);
+                }
+                println!(
+                    "  {}: {} vote(s) {}",
+                    option.option,
+                    option.votes(),
+                    voters.join(", ")
+                );
+            }
+        }
         Cmd::CreateGroup { title, uuid } => {
             let mut manager = load_registered_and_receive(store).await?;
 
@@ -1198,13 +1484,27 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
                 }
             }
         }
-        Cmd::AddMember { master_key, uuid } => {
+        Cmd::AddMember {
+            master_key,
+            uuid,
+            admin,
+        } => {
             let mut manager = load_registered_and_receive(store).await?;
 
+            let role = if admin {
+                GroupMemberRole::Administrator
+            } else {
+                GroupMemberRole::Default
+            };
             for member_uuid in &uuid {
                 let profile_key = find_profile_key(&manager, member_uuid).await?;
                 manager
-                    .add_group_member(&master_key, (*member_uuid).into(), Some(profile_key))
+                    .add_group_member_with_role(
+                        &master_key,
+                        (*member_uuid).into(),
+                        Some(profile_key),
+                        role,
+                    )
                     .await?;
                 println!("Member {} added successfully!", member_uuid);
             }
@@ -1218,55 +1518,135 @@ async fn run<S: Store>(subcommand: Cmd, store: S) -> anyhow::Result<()> {
                 println!("Member {} removed successfully!", member_uuid);
             }
         }
+        Cmd::LeaveGroup { master_key } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            manager.leave_group(&master_key).await?;
+            println!("Left the group");
+        }
         Cmd::SetDisappearingTimer {
+            recipient_uuid,
             master_key,
             duration,
         } => {
+            let thread = match (recipient_uuid, master_key) {
+                (Some(uuid), _) => Thread::Contact(uuid),
+                (None, Some(key)) => Thread::Group(key),
+                _ => unreachable!(),
+            };
             let mut manager = load_registered_and_receive(store).await?;
-            manager
-                .set_disappearing_messages_timer(&master_key, duration)
-                .await?;
+            manager.set_expiration_timer(&thread, duration).await?;
             if duration == 0 {
                 println!("Disappearing messages disabled.");
             } else {
                 println!("Disappearing messages timer set to {} seconds.", duration);
             }
         }
+        Cmd::SetGroupTitle { master_key, title } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            let revision = manager.update_group_title(&master_key, &title).await?;
+            println!("Group title updated (revision {revision}).");
+        }
         Cmd::SetGroupDescription {
             master_key,
             description,
         } => {
             let mut manager = load_registered_and_receive(store).await?;
-            manager
-                .set_group_description(&master_key, &description)
+            let revision = manager
+                .update_group_description(&master_key, &description)
                 .await?;
-            println!("Group description updated.");
+            println!("Group description updated (revision {revision}).");
         }
         Cmd::SetGroupAccess {
             master_key,
             attributes,
             members,
+            invite_link,
         } => {
-            if attributes.is_none() && members.is_none() {
-                bail!("At least one of --attributes or --members must be specified");
+            if attributes.is_none() && members.is_none() && invite_link.is_none() {
+                bail!("At least one of --attributes, --members or --invite-link must be specified");
             }
             let mut manager = load_registered_and_receive(store).await?;
-            manager
+            // Keep the current access control for the options which are not specified
+            let current = manager.fetch_group(&master_key).await?.access_control;
+            let revision = manager
                 .set_group_access_control(
                     &master_key,
-                    attributes.as_deref().map(access_required_from_str),
-                    members.as_deref().map(access_required_from_str),
+                    members
+                        .or(current.as_ref().map(|access| access.members.into()))
+                        .unwrap_or(AccessRequired::Member),
+                    attributes
+                        .or(current.as_ref().map(|access| access.attributes.into()))
+                        .unwrap_or(AccessRequired::Member),
+                    invite_link
+                        .or(current
+                            .as_ref()
+                            .map(|access| access.add_from_invite_link.into()))
+                        .unwrap_or(AccessRequired::Unsatisfiable),
                 )
                 .await?;
-            println!("Group access control updated.");
+            println!("Group access control updated (revision {revision}).");
+        }
+        Cmd::GroupInviteLink {
+            master_key,
+            enable,
+            disable,
+            reset,
+        } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            if enable || disable {
+                manager
+                    .set_group_invite_link_enabled(&master_key, enable)
+                    .await?;
+            }
+            if reset {
+                manager.reset_group_invite_link(&master_key).await?;
+            }
+            match manager.group_invite_link(&master_key).await? {
+                Some(url) => println!("{url}"),
+                None => println!("Group invite link is disabled."),
+            }
+        }
+        Cmd::JoinGroup { link } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            match manager.join_group_by_invite_link(&link).await {
+                Ok(master_key) => {
+                    println!("Joined group, master key: {}", hex::encode(master_key))
+                }
+                Err(presage::Error::GroupJoinPendingApproval(master_key)) => println!(
+                    "Requested to join group {}, waiting for an administrator to approve",
+                    hex::encode(master_key)
+                ),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Cmd::GroupRequests {
+            master_key,
+            approve,
+            deny,
+        } => {
+            let mut manager = load_registered_and_receive(store).await?;
+            manager.fetch_group(&master_key).await?;
+            for uuid in approve {
+                manager
+                    .approve_group_request(&master_key, uuid.into())
+                    .await?;
+                println!("Approved the request of {uuid}");
+            }
+            for uuid in deny {
+                manager.deny_group_request(&master_key, uuid.into()).await?;
+                println!("Denied the request of {uuid}");
+            }
+            for aci in manager.pending_group_requests(&master_key).await? {
+                println!("Pending request: {}", aci.service_id_string());
+            }
         }
         Cmd::SetAnnouncementsOnly { master_key, enable } => {
             let mut manager = load_registered_and_receive(store).await?;
-            manager
+            let revision = manager
                 .set_group_announcements_only(&master_key, enable)
                 .await?;
             println!(
-                "Group announcements-only mode {}.",
+                "Group announcements-only mode {} (revision {revision}).",
                 if enable { "enabled" } else { "disabled" }
             );
         }
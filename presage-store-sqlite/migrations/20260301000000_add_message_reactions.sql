@@ -0,0 +1,11 @@
+-- Reactions to messages, at most one per author and message
+-- The target message might not be stored (yet), so there is no foreign key to thread_messages
+CREATE TABLE IF NOT EXISTS message_reactions (
+  thread_id INTEGER NOT NULL,
+  target_ts INTEGER NOT NULL,
+  author_service_id TEXT NOT NULL,
+  emoji TEXT NOT NULL,
+  ts INTEGER NOT NULL,
+  PRIMARY KEY (thread_id, target_ts, author_service_id),
+  FOREIGN KEY (thread_id) REFERENCES threads (id) ON DELETE CASCADE
+);
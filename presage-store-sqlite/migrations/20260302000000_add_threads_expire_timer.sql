@@ -0,0 +1,3 @@
+-- Disappearing messages timer of a thread, for both contacts and groups
+ALTER TABLE threads ADD COLUMN expire_timer INTEGER;
+ALTER TABLE threads ADD COLUMN expire_timer_version INTEGER NOT NULL DEFAULT 1;
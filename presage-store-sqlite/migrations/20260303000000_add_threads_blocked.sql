@@ -0,0 +1,2 @@
+-- Blocked contacts and groups
+ALTER TABLE threads ADD COLUMN blocked BOOLEAN NOT NULL DEFAULT 0;
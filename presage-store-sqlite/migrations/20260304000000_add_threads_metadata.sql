@@ -0,0 +1,10 @@
+-- Metadata of the last message of each thread, maintained when saving messages
+ALTER TABLE threads ADD COLUMN last_message_ts INTEGER;
+ALTER TABLE threads ADD COLUMN last_message_snippet TEXT;
+ALTER TABLE threads ADD COLUMN unread_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE threads SET last_message_ts = (
+  SELECT max(ts) FROM thread_messages WHERE thread_id = threads.id
+);
+
+CREATE INDEX threads_last_message_ts ON threads (last_message_ts DESC);
@@ -0,0 +1,3 @@
+-- Read state of received messages
+ALTER TABLE thread_messages ADD COLUMN unread BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE threads ADD COLUMN last_read_ts INTEGER NOT NULL DEFAULT 0;
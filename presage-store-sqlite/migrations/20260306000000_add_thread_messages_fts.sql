@@ -0,0 +1,5 @@
+-- Full-text search over the bodies of messages, indexed by the rowid of thread_messages.
+--
+-- Bodies are encoded in thread_messages.content_body, so the index is maintained by the store when
+-- saving and deleting messages (messages stored before this migration are not indexed).
+CREATE VIRTUAL TABLE thread_messages_fts USING fts5(body);
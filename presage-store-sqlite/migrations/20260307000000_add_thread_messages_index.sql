@@ -0,0 +1,2 @@
+-- Messages are mostly looked up by thread, then by timestamp
+CREATE INDEX thread_messages_thread_id_ts ON thread_messages (thread_id, ts);
@@ -0,0 +1,2 @@
+-- Identities can be untrusted explicitly, or when they changed and new identities are rejected
+ALTER TABLE identities ADD COLUMN trusted BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+-- Whether only administrators can send messages in the group
+ALTER TABLE groups ADD COLUMN announcements_only BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,7 @@
+-- Server GUIDs of the processed envelopes, to drop the ones delivered again
+CREATE TABLE processed_envelopes (
+    server_guid BLOB PRIMARY KEY NOT NULL,
+    processed_at INTEGER NOT NULL
+);
+
+CREATE INDEX processed_envelopes_processed_at ON processed_envelopes(processed_at);
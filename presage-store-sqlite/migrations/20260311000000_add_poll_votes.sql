@@ -0,0 +1,13 @@
+-- Votes in polls, at most one per voter and poll
+-- The poll might not be stored (yet), so there is no foreign key to thread_messages
+CREATE TABLE IF NOT EXISTS poll_votes (
+  thread_id INTEGER NOT NULL,
+  poll_ts INTEGER NOT NULL,
+  voter_service_id TEXT NOT NULL,
+  -- JSON array of the indexes of the selected options
+  option_indexes TEXT NOT NULL,
+  vote_count INTEGER NOT NULL,
+  ts INTEGER NOT NULL,
+  PRIMARY KEY (thread_id, poll_ts, voter_service_id),
+  FOREIGN KEY (thread_id) REFERENCES threads (id) ON DELETE CASCADE
+);
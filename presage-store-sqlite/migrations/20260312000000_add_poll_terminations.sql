@@ -0,0 +1,8 @@
+-- When polls were terminated, after which votes don't count anymore
+CREATE TABLE IF NOT EXISTS poll_terminations (
+  thread_id INTEGER NOT NULL,
+  poll_ts INTEGER NOT NULL,
+  ts INTEGER NOT NULL,
+  PRIMARY KEY (thread_id, poll_ts),
+  FOREIGN KEY (thread_id) REFERENCES threads (id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Outgoing messages which could not be sent yet, stored like thread messages
+CREATE TABLE IF NOT EXISTS pending_sends (
+  ts INTEGER NOT NULL,
+  thread_id INTEGER NOT NULL,
+  sender_service_id TEXT NOT NULL,
+  sender_device_id INTEGER NOT NULL,
+  destination_service_id TEXT NOT NULL,
+  content_body BLOB NOT NULL,
+  PRIMARY KEY (ts, thread_id),
+  FOREIGN KEY (thread_id) REFERENCES threads (id) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Furthest status reached by the messages we sent, as told by receipts (1: delivered, 2: read)
+CREATE TABLE IF NOT EXISTS message_statuses (
+  thread_id INTEGER NOT NULL,
+  ts INTEGER NOT NULL,
+  status INTEGER NOT NULL,
+  PRIMARY KEY (thread_id, ts),
+  FOREIGN KEY (thread_id) REFERENCES threads (id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Resolving the ACI of a contact from their phone number
+CREATE INDEX contacts_phone_number ON contacts (phone_number);
@@ -0,0 +1,3 @@
+-- Archived and muted contacts and groups, muted until a timestamp in milliseconds
+ALTER TABLE threads ADD COLUMN archived BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE threads ADD COLUMN muted_until INTEGER;
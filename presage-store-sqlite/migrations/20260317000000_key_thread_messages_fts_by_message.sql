@@ -0,0 +1,13 @@
+-- Key the full-text search index by thread and timestamp of the messages, instead of by the rowid of
+-- thread_messages which VACUUM (or exporting the database to re-encrypt it) may change.
+DROP TABLE thread_messages_fts;
+CREATE VIRTUAL TABLE thread_messages_fts USING fts5(body, thread_id UNINDEXED, ts UNINDEXED);
+
+-- Messages whose body still has to be indexed. Bodies are encoded in thread_messages.content_body,
+-- so the store indexes them when it is opened.
+CREATE TABLE thread_messages_fts_backfill (
+  thread_id INTEGER NOT NULL,
+  ts INTEGER NOT NULL,
+  PRIMARY KEY (thread_id, ts)
+);
+INSERT INTO thread_messages_fts_backfill SELECT thread_id, ts FROM thread_messages;
@@ -4,23 +4,85 @@ use presage::{
     AvatarBytes,
     libsignal_service::{
         Profile,
-        content::Metadata,
-        prelude::{Content, ProfileKey, Uuid},
+        content::{ContentBody, Metadata},
+        prelude::{Content, ProfileKey, Uuid, phonenumber::PhoneNumber},
         protocol::ServiceId,
         zkgroup::GroupMasterKeyBytes,
     },
-    model::{contacts::Contact, groups::Group},
+    model::{
+        contacts::Contact,
+        groups::Group,
+        messages::{MessageStatus, PollVote, Reaction},
+    },
     proto::{Verified, verified},
-    store::{ContentsStore, StickerPack, Thread},
+    store::{ContentExt, ContentsStore, MessageRange, StickerPack, Thread, ThreadMetadata},
 };
 use sqlx::{query, query_as, query_scalar, types::Json};
+use tracing::trace;
 
 use crate::{
     SqliteStore, SqliteStoreError,
-    data::{SqlContact, SqlGroup, SqlMessage, SqlProfile, SqlStickerPack},
+    data::{SqlContact, SqlGroup, SqlGroupRow, SqlMessage, SqlProfile, SqlStickerPack},
     error::SqlxErrorExt,
 };
 
+impl SqliteStore {
+    /// Indexes the bodies of the messages saved before the full-text search index was keyed by
+    /// message, see the `key_thread_messages_fts_by_message` migration.
+    pub(crate) async fn backfill_search_index(&self) -> Result<(), SqliteStoreError> {
+        let mut tx = self.db.begin().await?;
+        let messages: Vec<(i64, i64)> =
+            sqlx::query_as("SELECT thread_id, ts FROM thread_messages_fts_backfill")
+                .fetch_all(&mut *tx)
+                .await?;
+        if !messages.is_empty() {
+            trace!(
+                messages = messages.len(),
+                "indexing the bodies of stored messages"
+            );
+        }
+        for (thread_id, timestamp) in messages {
+            let row: Option<SqlMessageRow> = sqlx::query_as(
+                "SELECT
+                    ts,
+                    sender_service_id,
+                    sender_device_id,
+                    destination_service_id,
+                    needs_receipt,
+                    unidentified_sender,
+                    content_body,
+                    was_plaintext
+                FROM thread_messages
+                WHERE thread_id = ? AND ts = ?",
+            )
+            .bind(thread_id)
+            .bind(timestamp)
+            .fetch_optional(&mut *tx)
+            .await?;
+            // messages which cannot be decoded are left out of the index
+            let Some(body) = row
+                .map(message_from_row)
+                .and_then(Result::ok)
+                .as_ref()
+                .and_then(searchable_body)
+            else {
+                continue;
+            };
+            sqlx::query("INSERT INTO thread_messages_fts(body, thread_id, ts) VALUES (?, ?, ?)")
+                .bind(body)
+                .bind(thread_id)
+                .bind(timestamp)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM thread_messages_fts_backfill")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
 impl ContentsStore for SqliteStore {
     type ContentsStoreError = SqliteStoreError;
 
@@ -56,6 +118,21 @@ impl ContentsStore for SqliteStore {
 
     async fn clear_contents(&mut self) -> Result<(), Self::ContentsStoreError> {
         let mut transaction = self.db.begin().await.into_protocol_error()?;
+        sqlx::query("DELETE FROM message_reactions")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM poll_votes")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM poll_terminations")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM message_statuses")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM thread_messages_fts")
+            .execute(&mut *transaction)
+            .await?;
         query!("DELETE FROM thread_messages")
             .execute(&mut *transaction)
             .await?;
@@ -77,24 +154,89 @@ impl ContentsStore for SqliteStore {
         query!("DELETE FROM sticker_packs")
             .execute(&mut *transaction)
             .await?;
+        sqlx::query("DELETE FROM processed_envelopes")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM pending_sends")
+            .execute(&mut *transaction)
+            .await?;
         transaction.commit().await.into_protocol_error()?;
         Ok(())
     }
 
     async fn clear_messages(&mut self) -> Result<(), Self::ContentsStoreError> {
         let mut transaction = self.db.begin().await.into_protocol_error()?;
-        query!("DELETE FROM thread_messages")
+        sqlx::query("DELETE FROM message_reactions")
             .execute(&mut *transaction)
             .await?;
-        query!("DELETE FROM threads")
+        sqlx::query("DELETE FROM poll_votes")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM poll_terminations")
             .execute(&mut *transaction)
             .await?;
+        sqlx::query("DELETE FROM message_statuses")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM thread_messages_fts")
+            .execute(&mut *transaction)
+            .await?;
+        query!("DELETE FROM thread_messages")
+            .execute(&mut *transaction)
+            .await?;
+        // threads also hold settings like the expire timer, which are kept
+        sqlx::query(
+            "UPDATE threads
+            SET last_message_ts = NULL, last_message_snippet = NULL, unread_count = 0",
+        )
+        .execute(&mut *transaction)
+        .await?;
         transaction.commit().await.into_protocol_error()?;
         Ok(())
     }
 
     async fn clear_thread(&mut self, thread: &Thread) -> Result<(), Self::ContentsStoreError> {
         let (group_master_key, recipient_id) = thread.unzip();
+        sqlx::query(
+            "DELETE FROM message_reactions WHERE thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
+        sqlx::query(
+            "DELETE FROM poll_votes WHERE thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
+        sqlx::query(
+            "DELETE FROM poll_terminations WHERE thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
+        sqlx::query(
+            "DELETE FROM message_statuses WHERE thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
+        sqlx::query(
+            "DELETE FROM thread_messages_fts WHERE thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
         query!(
             "DELETE FROM thread_messages WHERE thread_id = (
                 SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
@@ -103,14 +245,29 @@ impl ContentsStore for SqliteStore {
         )
         .execute(&self.db)
         .await?;
+        sqlx::query(
+            "UPDATE threads
+            SET last_message_ts = NULL, last_message_snippet = NULL, unread_count = 0
+            WHERE group_master_key = ? OR recipient_id = ?",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
         Ok(())
     }
 
     async fn save_message(
         &self,
         thread: &Thread,
-        Content { metadata, body }: Content,
+        content: Content,
     ) -> Result<(), Self::ContentsStoreError> {
+        let snippet = snippet(&content);
+        let searchable_body = searchable_body(&content);
+        let incoming = matches!(content.body, ContentBody::DataMessage(_))
+            && self.own_aci() != Some(content.metadata.sender);
+        let Content { metadata, body } = content;
+
         let mut tx = self.db.begin().await?;
 
         let thread_id = match thread {
@@ -155,6 +312,32 @@ impl ContentsStore for SqliteStore {
             .try_into()
             .map_err(|_| SqliteStoreError::InvalidFormat)?;
 
+        // a message saved again (e.g. when edited) keeps its read state
+        let existing_unread: Option<bool> =
+            sqlx::query_scalar("SELECT unread FROM thread_messages WHERE ts = ? AND thread_id = ?")
+                .bind(timestamp)
+                .bind(thread_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let unread = match existing_unread {
+            Some(unread) => unread,
+            None if incoming => {
+                sqlx::query_scalar("SELECT last_read_ts < ? FROM threads WHERE id = ?")
+                    .bind(timestamp)
+                    .bind(thread_id)
+                    .fetch_one(&mut *tx)
+                    .await?
+            }
+            None => false,
+        };
+
+        // the body of a message saved again (e.g. when edited) replaces the previous one
+        sqlx::query("DELETE FROM thread_messages_fts WHERE ts = ? AND thread_id = ?")
+            .bind(timestamp)
+            .bind(thread_id)
+            .execute(&mut *tx)
+            .await?;
+
         query!(
             "INSERT OR REPLACE INTO thread_messages (
                 ts,
@@ -181,6 +364,39 @@ impl ContentsStore for SqliteStore {
         .execute(&mut *tx)
         .await?;
 
+        if let Some(body) = searchable_body {
+            sqlx::query("INSERT INTO thread_messages_fts(body, thread_id, ts) VALUES (?, ?, ?)")
+                .bind(body)
+                .bind(thread_id)
+                .bind(timestamp)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            "UPDATE threads SET last_message_ts = ?1, last_message_snippet = ?2
+            WHERE id = ?3 AND coalesce(last_message_ts <= ?1, true)",
+        )
+        .bind(timestamp)
+        .bind(snippet)
+        .bind(thread_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if unread {
+            sqlx::query("UPDATE thread_messages SET unread = 1 WHERE ts = ? AND thread_id = ?")
+                .bind(timestamp)
+                .bind(thread_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if unread && existing_unread.is_none() {
+            sqlx::query("UPDATE threads SET unread_count = unread_count + 1 WHERE id = ?")
+                .bind(thread_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
         tx.commit().await?;
         Ok(())
     }
@@ -194,17 +410,92 @@ impl ContentsStore for SqliteStore {
             .try_into()
             .map_err(|_| SqliteStoreError::InvalidFormat)?;
         let (group_master_key, recipient_id) = thread.unzip();
-        let res = query!(
-            "DELETE FROM thread_messages
-            WHERE ts = ? AND thread_id = (
-                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
-            timestamp,
-            group_master_key,
-            recipient_id,
+
+        let mut tx = self.db.begin().await?;
+
+        let Some(thread_id): Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?",
         )
-        .execute(&self.db)
-        .await?;
-        Ok(res.rows_affected() > 0)
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+        // reactions and statuses can be saved before their message, so they are deleted anyway
+        let unread: Option<bool> =
+            sqlx::query_scalar("SELECT unread FROM thread_messages WHERE ts = ? AND thread_id = ?")
+                .bind(timestamp)
+                .bind(thread_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+        for statement in [
+            "DELETE FROM message_reactions WHERE target_ts = ? AND thread_id = ?",
+            "DELETE FROM poll_votes WHERE poll_ts = ? AND thread_id = ?",
+            "DELETE FROM poll_terminations WHERE poll_ts = ? AND thread_id = ?",
+            "DELETE FROM message_statuses WHERE ts = ? AND thread_id = ?",
+            "DELETE FROM thread_messages_fts WHERE ts = ? AND thread_id = ?",
+            "DELETE FROM thread_messages WHERE ts = ? AND thread_id = ?",
+        ] {
+            sqlx::query(statement)
+                .bind(timestamp)
+                .bind(thread_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if unread == Some(true) {
+            sqlx::query("UPDATE threads SET unread_count = unread_count - 1 WHERE id = ?")
+                .bind(thread_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // the previous message becomes the last one of the thread
+        let last_message_ts: Option<i64> =
+            sqlx::query_scalar("SELECT last_message_ts FROM threads WHERE id = ?")
+                .bind(thread_id)
+                .fetch_one(&mut *tx)
+                .await?;
+        if last_message_ts == Some(timestamp) {
+            let last_message: Option<SqlMessageRow> = sqlx::query_as(
+                "SELECT
+                    ts,
+                    sender_service_id,
+                    sender_device_id,
+                    destination_service_id,
+                    needs_receipt,
+                    unidentified_sender,
+                    content_body,
+                    was_plaintext
+                FROM thread_messages
+                WHERE thread_id = ?
+                ORDER BY ts DESC
+                LIMIT 1",
+            )
+            .bind(thread_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let last_message_ts = last_message.as_ref().map(|row| row.0);
+            let last_message_snippet = last_message
+                .map(message_from_row)
+                .and_then(Result::ok)
+                .as_ref()
+                .and_then(snippet);
+            sqlx::query(
+                "UPDATE threads SET last_message_ts = ?, last_message_snippet = ? WHERE id = ?",
+            )
+            .bind(last_message_ts)
+            .bind(last_message_snippet)
+            .bind(thread_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(unread.is_some())
     }
 
     async fn message(
@@ -249,34 +540,865 @@ impl ContentsStore for SqliteStore {
         let (start_incl, start_excl) = range.start_bound().into_sql_bound();
         let (end_incl, end_excl) = range.end_bound().into_sql_bound();
 
-        let rows = query_as!(
-            SqlMessage,
-            r#"SELECT
-                ts AS "ts: _",
-                sender_service_id,
-                sender_device_id AS "sender_device_id: _",
-                destination_service_id,
-                needs_receipt,
-                unidentified_sender,
-                content_body,
-                was_plaintext
-            FROM thread_messages
-            WHERE thread_id = (
-                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)
-                AND coalesce(ts > ?, ts >= ?, true)
-                AND coalesce(ts < ?, ts <= ?, true)
-            ORDER BY ts DESC"#,
-            group_master_key,
-            recipient_id,
-            start_incl,
-            start_excl,
-            end_incl,
-            end_excl
+        let rows = query_as!(
+            SqlMessage,
+            r#"SELECT
+                ts AS "ts: _",
+                sender_service_id,
+                sender_device_id AS "sender_device_id: _",
+                destination_service_id,
+                needs_receipt,
+                unidentified_sender,
+                content_body,
+                was_plaintext
+            FROM thread_messages
+            WHERE thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)
+                AND coalesce(ts > ?, ts >= ?, true)
+                AND coalesce(ts < ?, ts <= ?, true)
+            ORDER BY ts DESC"#,
+            group_master_key,
+            recipient_id,
+            start_incl,
+            start_excl,
+            end_incl,
+            end_excl
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(Box::new(rows.into_iter().map(TryInto::try_into)))
+    }
+
+    async fn expire_timer(
+        &self,
+        thread: &Thread,
+    ) -> Result<Option<(u32, u32)>, Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        let timer: Option<(Option<i64>, i64)> = sqlx::query_as(
+            "SELECT expire_timer, expire_timer_version FROM threads
+            WHERE group_master_key = ? OR recipient_id = ?",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        match (timer, thread) {
+            (Some((Some(timer), version)), _) => Ok(Some((timer as u32, version as u32))),
+            // fall back to the timer of synchronized contacts
+            (_, Thread::Contact(uuid)) => Ok(self
+                .contact_by_id(uuid)
+                .await?
+                .map(|c| (c.expire_timer, c.expire_timer_version))),
+            (_, Thread::Group(_)) => Ok(None),
+        }
+    }
+
+    async fn update_expire_timer(
+        &mut self,
+        thread: &Thread,
+        timer: u32,
+        version: u32,
+    ) -> Result<(), Self::ContentsStoreError> {
+        trace!(%thread, timer, version, "updating expire timer");
+        if let Thread::Contact(uuid) = thread {
+            // group timers are versioned by the group revision instead
+            if let Some((_, current_version)) = self.expire_timer(thread).await? {
+                if version <= current_version {
+                    return Ok(());
+                }
+            }
+
+            if let Some(mut contact) = self.contact_by_id(uuid).await? {
+                contact.expire_timer = timer;
+                contact.expire_timer_version = version;
+                self.save_contact(&contact).await?;
+            }
+        }
+
+        let (group_master_key, recipient_id) = thread.unzip();
+        sqlx::query(
+            "INSERT INTO threads(recipient_id, group_master_key, expire_timer, expire_timer_version)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT DO UPDATE SET expire_timer = ?3, expire_timer_version = ?4",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .bind(timer)
+        .bind(version)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn save_reaction(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        Reaction {
+            emoji,
+            author,
+            timestamp,
+        }: Reaction,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let target_ts: i64 = target_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let timestamp: i64 = timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+
+        let mut tx = self.db.begin().await?;
+
+        // the reaction might arrive before any message in the thread
+        let thread_id: i64 = sqlx::query_scalar(
+            "INSERT INTO threads(recipient_id, group_master_key) VALUES (?1, ?2)
+            ON CONFLICT DO UPDATE SET recipient_id = ?1, group_master_key = ?2 RETURNING id",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO message_reactions (
+                thread_id,
+                target_ts,
+                author_service_id,
+                emoji,
+                ts
+            )
+            VALUES(?, ?, ?, ?, ?)",
+        )
+        .bind(thread_id)
+        .bind(target_ts)
+        .bind(author.service_id_string())
+        .bind(emoji)
+        .bind(timestamp)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_reaction(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        author: &ServiceId,
+    ) -> Result<bool, Self::ContentsStoreError> {
+        let target_ts: i64 = target_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        let res = sqlx::query(
+            "DELETE FROM message_reactions
+            WHERE target_ts = ? AND author_service_id = ? AND thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(target_ts)
+        .bind(author.service_id_string())
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn search_messages(
+        &self,
+        query: &str,
+        thread: Option<&Thread>,
+        limit: usize,
+    ) -> Result<Vec<Content>, Self::ContentsStoreError> {
+        // search for all the words, ignoring the FTS5 query syntax
+        let query = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (group_master_key, recipient_id) = thread.map(|t| t.unzip()).unwrap_or_default();
+        let rows: Vec<SqlMessageRow> = sqlx::query_as(
+            "SELECT
+                m.ts,
+                m.sender_service_id,
+                m.sender_device_id,
+                m.destination_service_id,
+                m.needs_receipt,
+                m.unidentified_sender,
+                m.content_body,
+                m.was_plaintext
+            FROM thread_messages_fts
+            JOIN thread_messages m
+                ON m.thread_id = thread_messages_fts.thread_id AND m.ts = thread_messages_fts.ts
+            WHERE thread_messages_fts MATCH ?1
+                AND (?2 OR m.thread_id = (
+                    SELECT id FROM threads WHERE group_master_key = ?3 OR recipient_id = ?4))
+            ORDER BY m.ts DESC
+            LIMIT ?5",
+        )
+        .bind(query)
+        .bind(thread.is_none())
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .bind(limit as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter().map(message_from_row).collect()
+    }
+
+    async fn messages_page(
+        &self,
+        thread: &Thread,
+        range: MessageRange,
+        limit: usize,
+    ) -> Result<Vec<Content>, Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        let (query, timestamp) = match range {
+            MessageRange::Latest => (MESSAGES_BEFORE, None),
+            MessageRange::Before(timestamp) => (MESSAGES_BEFORE, Some(timestamp)),
+            MessageRange::After(timestamp) => (MESSAGES_AFTER, Some(timestamp)),
+        };
+        let timestamp: Option<i64> = timestamp
+            .map(TryInto::try_into)
+            .transpose()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+
+        let rows: Vec<SqlMessageRow> = sqlx::query_as(query)
+            .bind(group_master_key)
+            .bind(recipient_id)
+            .bind(timestamp)
+            .bind(limit as i64)
+            .fetch_all(&self.db)
+            .await?;
+
+        rows.into_iter().map(message_from_row).collect()
+    }
+
+    async fn threads(&self) -> Result<Vec<ThreadMetadata>, Self::ContentsStoreError> {
+        let rows: Vec<SqlThreadRow> = sqlx::query_as(
+            "SELECT group_master_key, recipient_id, last_message_ts, last_message_snippet,
+                    unread_count, archived, muted_until
+                FROM threads
+                WHERE last_message_ts IS NOT NULL
+                ORDER BY last_message_ts DESC",
+        )
+        .fetch_all(&self.db)
+        .await?;
+        rows.into_iter()
+            .map(
+                |(
+                    group_master_key,
+                    recipient_id,
+                    last_message_ts,
+                    snippet,
+                    unread_count,
+                    archived,
+                    muted_until,
+                )| {
+                    let thread = match (group_master_key, recipient_id) {
+                        (Some(group_master_key), _) => Thread::Group(
+                            group_master_key
+                                .try_into()
+                                .map_err(|_| SqliteStoreError::InvalidFormat)?,
+                        ),
+                        (None, Some(recipient_id)) => Thread::Contact(recipient_id),
+                        (None, None) => return Err(SqliteStoreError::InvalidFormat),
+                    };
+                    Ok(ThreadMetadata {
+                        thread,
+                        last_message_timestamp: Some(last_message_ts as u64),
+                        unread_count: unread_count as u64,
+                        snippet,
+                        archived,
+                        muted_until: muted_until.map(|ts| ts as u64),
+                    })
+                },
+            )
+            .collect()
+    }
+
+    async fn unread_count(&self, thread: &Thread) -> Result<u64, Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        let unread_count: Option<i64> = sqlx::query_scalar(
+            "SELECT unread_count FROM threads WHERE group_master_key = ? OR recipient_id = ?",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(unread_count.unwrap_or_default() as u64)
+    }
+
+    async fn mark_read(
+        &mut self,
+        thread: &Thread,
+        up_to_timestamp: u64,
+    ) -> Result<Vec<(ServiceId, u64)>, Self::ContentsStoreError> {
+        let up_to_ts: i64 = up_to_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+
+        let mut tx = self.db.begin().await?;
+
+        let thread_id: i64 = sqlx::query_scalar(
+            "INSERT INTO threads(recipient_id, group_master_key) VALUES (?1, ?2)
+            ON CONFLICT DO UPDATE SET recipient_id = ?1, group_master_key = ?2 RETURNING id",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let read: Vec<(String, i64)> = sqlx::query_as(
+            "UPDATE thread_messages SET unread = 0
+            WHERE thread_id = ? AND ts <= ? AND unread
+            RETURNING sender_service_id, ts",
+        )
+        .bind(thread_id)
+        .bind(up_to_ts)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE threads
+            SET unread_count = unread_count - ?, last_read_ts = max(last_read_ts, ?)
+            WHERE id = ?",
+        )
+        .bind(read.len() as i64)
+        .bind(up_to_ts)
+        .bind(thread_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        read.into_iter()
+            .map(|(sender_service_id, ts)| {
+                let sender = ServiceId::parse_from_service_id_string(&sender_service_id)
+                    .ok_or(SqliteStoreError::InvalidFormat)?;
+                Ok((sender, ts as u64))
+            })
+            .collect()
+    }
+
+    async fn set_blocked(
+        &mut self,
+        thread: &Thread,
+        blocked: bool,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        sqlx::query(
+            "INSERT INTO threads(recipient_id, group_master_key, blocked) VALUES (?1, ?2, ?3)
+            ON CONFLICT DO UPDATE SET blocked = ?3",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .bind(blocked)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn is_blocked(&self, thread: &Thread) -> Result<bool, Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        let blocked: Option<bool> = sqlx::query_scalar(
+            "SELECT blocked FROM threads WHERE group_master_key = ? OR recipient_id = ?",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(blocked.unwrap_or_default())
+    }
+
+    async fn blocked(&self) -> Result<Vec<Thread>, Self::ContentsStoreError> {
+        let rows: Vec<(Option<Vec<u8>>, Option<Uuid>)> =
+            sqlx::query_as("SELECT group_master_key, recipient_id FROM threads WHERE blocked")
+                .fetch_all(&self.db)
+                .await?;
+        rows.into_iter()
+            .map(|row| match row {
+                (Some(group_master_key), _) => group_master_key
+                    .try_into()
+                    .map(Thread::Group)
+                    .map_err(|_| SqliteStoreError::InvalidFormat),
+                (None, Some(recipient_id)) => Ok(Thread::Contact(recipient_id)),
+                (None, None) => Err(SqliteStoreError::InvalidFormat),
+            })
+            .collect()
+    }
+
+    async fn set_archived(
+        &mut self,
+        thread: &Thread,
+        archived: bool,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        sqlx::query(
+            "INSERT INTO threads(recipient_id, group_master_key, archived) VALUES (?1, ?2, ?3)
+            ON CONFLICT DO UPDATE SET archived = ?3",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .bind(archived)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn is_archived(&self, thread: &Thread) -> Result<bool, Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        let archived: Option<bool> = sqlx::query_scalar(
+            "SELECT archived FROM threads WHERE group_master_key = ? OR recipient_id = ?",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(archived.unwrap_or_default())
+    }
+
+    async fn set_muted_until(
+        &mut self,
+        thread: &Thread,
+        muted_until: Option<u64>,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let muted_until: Option<i64> = muted_until
+            .map(|ts| ts.try_into().map_err(|_| SqliteStoreError::InvalidFormat))
+            .transpose()?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        sqlx::query(
+            "INSERT INTO threads(recipient_id, group_master_key, muted_until) VALUES (?1, ?2, ?3)
+            ON CONFLICT DO UPDATE SET muted_until = ?3",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .bind(muted_until)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn muted_until(&self, thread: &Thread) -> Result<Option<u64>, Self::ContentsStoreError> {
+        let (group_master_key, recipient_id) = thread.unzip();
+        let muted_until: Option<Option<i64>> = sqlx::query_scalar(
+            "SELECT muted_until FROM threads WHERE group_master_key = ? OR recipient_id = ?",
+        )
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(muted_until.flatten().map(|ts| ts as u64))
+    }
+
+    async fn reactions(
+        &self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+    ) -> Result<Vec<Reaction>, Self::ContentsStoreError> {
+        let target_ts: i64 = target_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        let rows: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT emoji, author_service_id, ts FROM message_reactions
+            WHERE target_ts = ? AND thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)
+            ORDER BY ts ASC",
+        )
+        .bind(target_ts)
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter()
+            .map(|(emoji, author_service_id, ts)| {
+                Ok(Reaction {
+                    emoji,
+                    author: ServiceId::parse_from_service_id_string(&author_service_id)
+                        .ok_or(SqliteStoreError::InvalidFormat)?,
+                    timestamp: ts.try_into().map_err(|_| SqliteStoreError::InvalidFormat)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn save_poll_vote(
+        &mut self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+        PollVote {
+            voter,
+            option_indexes,
+            vote_count,
+            timestamp,
+        }: PollVote,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let poll_ts: i64 = poll_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let timestamp: i64 = timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+
+        let mut tx = self.db.begin().await?;
+
+        // the vote might arrive before any message in the thread
+        let thread_id: i64 = sqlx::query_scalar(
+            "INSERT INTO threads(recipient_id, group_master_key) VALUES (?1, ?2)
+            ON CONFLICT DO UPDATE SET recipient_id = ?1, group_master_key = ?2 RETURNING id",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO poll_votes (
+                thread_id,
+                poll_ts,
+                voter_service_id,
+                option_indexes,
+                vote_count,
+                ts
+            )
+            VALUES(?, ?, ?, ?, ?, ?)",
+        )
+        .bind(thread_id)
+        .bind(poll_ts)
+        .bind(voter.service_id_string())
+        .bind(Json(option_indexes))
+        .bind(vote_count)
+        .bind(timestamp)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn poll_votes(
+        &self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+    ) -> Result<Vec<PollVote>, Self::ContentsStoreError> {
+        let poll_ts: i64 = poll_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        let rows: Vec<(String, Json<Vec<u32>>, u32, i64)> = sqlx::query_as(
+            "SELECT voter_service_id, option_indexes, vote_count, ts FROM poll_votes
+            WHERE poll_ts = ? AND thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)
+            ORDER BY ts ASC",
+        )
+        .bind(poll_ts)
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter()
+            .map(|(voter_service_id, Json(option_indexes), vote_count, ts)| {
+                Ok(PollVote {
+                    voter: ServiceId::parse_from_service_id_string(&voter_service_id)
+                        .ok_or(SqliteStoreError::InvalidFormat)?,
+                    option_indexes,
+                    vote_count,
+                    timestamp: ts.try_into().map_err(|_| SqliteStoreError::InvalidFormat)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn save_poll_termination(
+        &mut self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+        timestamp: u64,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let poll_ts: i64 = poll_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let timestamp: i64 = timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+
+        let mut tx = self.db.begin().await?;
+
+        // the termination might arrive before any message in the thread
+        let thread_id: i64 = sqlx::query_scalar(
+            "INSERT INTO threads(recipient_id, group_master_key) VALUES (?1, ?2)
+            ON CONFLICT DO UPDATE SET recipient_id = ?1, group_master_key = ?2 RETURNING id",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO poll_terminations (thread_id, poll_ts, ts) VALUES(?, ?, ?)",
+        )
+        .bind(thread_id)
+        .bind(poll_ts)
+        .bind(timestamp)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn poll_termination(
+        &self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+    ) -> Result<Option<u64>, Self::ContentsStoreError> {
+        let poll_ts: i64 = poll_sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        let ts: Option<i64> = sqlx::query_scalar(
+            "SELECT ts FROM poll_terminations
+            WHERE poll_ts = ? AND thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(poll_ts)
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+        ts.map(|ts| ts.try_into().map_err(|_| SqliteStoreError::InvalidFormat))
+            .transpose()
+    }
+
+    async fn save_message_status(
+        &mut self,
+        thread: &Thread,
+        sent_timestamp: u64,
+        status: MessageStatus,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let ts: i64 = sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let status = message_status_to_sql(status);
+        let (group_master_key, recipient_id) = thread.unzip();
+
+        let mut tx = self.db.begin().await?;
+
+        let thread_id: i64 = sqlx::query_scalar(
+            "INSERT INTO threads(recipient_id, group_master_key) VALUES (?1, ?2)
+            ON CONFLICT DO UPDATE SET recipient_id = ?1, group_master_key = ?2 RETURNING id",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // statuses only move forward, e.g. a late delivery receipt doesn't undo a read one
+        sqlx::query(
+            "INSERT INTO message_statuses (thread_id, ts, status) VALUES(?, ?, ?)
+            ON CONFLICT DO UPDATE SET status = MAX(status, excluded.status)",
+        )
+        .bind(thread_id)
+        .bind(ts)
+        .bind(status)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn message_status(
+        &self,
+        thread: &Thread,
+        sent_timestamp: u64,
+    ) -> Result<Option<MessageStatus>, Self::ContentsStoreError> {
+        let ts: i64 = sent_timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        let status: Option<i64> = sqlx::query_scalar(
+            "SELECT status FROM message_statuses
+            WHERE ts = ? AND thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(ts)
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .fetch_optional(&self.db)
+        .await?;
+        status.map(message_status_from_sql).transpose()
+    }
+
+    async fn save_pending_send(
+        &mut self,
+        thread: &Thread,
+        content: Content,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let Content { metadata, body } = content;
+        let timestamp: i64 = metadata
+            .timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let sender_device: u8 = metadata.sender_device.into();
+        let content_body = prost::Message::encode_to_vec(&body.into_proto());
+        let (group_master_key, recipient_id) = thread.unzip();
+
+        let mut tx = self.db.begin().await?;
+
+        // the message might be the first one in the thread
+        let thread_id: i64 = sqlx::query_scalar(
+            "INSERT INTO threads(recipient_id, group_master_key) VALUES (?1, ?2)
+            ON CONFLICT DO UPDATE SET recipient_id = ?1, group_master_key = ?2 RETURNING id",
+        )
+        .bind(recipient_id)
+        .bind(group_master_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO pending_sends (
+                ts,
+                thread_id,
+                sender_service_id,
+                sender_device_id,
+                destination_service_id,
+                content_body
+            )
+            VALUES(?, ?, ?, ?, ?, ?)",
+        )
+        .bind(timestamp)
+        .bind(thread_id)
+        .bind(metadata.sender.service_id_string())
+        .bind(sender_device)
+        .bind(metadata.destination.service_id_string())
+        .bind(content_body)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_pending_send(
+        &mut self,
+        thread: &Thread,
+        timestamp: u64,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let timestamp: i64 = timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let (group_master_key, recipient_id) = thread.unzip();
+        sqlx::query(
+            "DELETE FROM pending_sends
+            WHERE ts = ? AND thread_id = (
+                SELECT id FROM threads WHERE group_master_key = ? OR recipient_id = ?)",
+        )
+        .bind(timestamp)
+        .bind(group_master_key)
+        .bind(recipient_id)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_sends(&self) -> Result<Vec<(Thread, Content)>, Self::ContentsStoreError> {
+        let rows: Vec<(
+            Option<Vec<u8>>,
+            Option<Uuid>,
+            i64,
+            String,
+            i64,
+            String,
+            Vec<u8>,
+        )> = sqlx::query_as(
+            "SELECT t.group_master_key, t.recipient_id, p.ts, p.sender_service_id,
+                        p.sender_device_id, p.destination_service_id, p.content_body
+                    FROM pending_sends p
+                    INNER JOIN threads t ON t.id = p.thread_id
+                    ORDER BY p.ts",
         )
         .fetch_all(&self.db)
         .await?;
+        rows.into_iter()
+            .map(
+                |(
+                    group_master_key,
+                    recipient_id,
+                    ts,
+                    sender_service_id,
+                    sender_device_id,
+                    destination_service_id,
+                    content_body,
+                )| {
+                    let thread = match (group_master_key, recipient_id) {
+                        (Some(group_master_key), _) => Thread::Group(
+                            group_master_key
+                                .try_into()
+                                .map_err(|_| SqliteStoreError::InvalidFormat)?,
+                        ),
+                        (None, Some(recipient_id)) => Thread::Contact(recipient_id),
+                        (None, None) => return Err(SqliteStoreError::InvalidFormat),
+                    };
+                    let content = message_from_row((
+                        ts,
+                        sender_service_id,
+                        sender_device_id,
+                        destination_service_id,
+                        false,
+                        false,
+                        content_body,
+                        false,
+                    ))?;
+                    Ok((thread, content))
+                },
+            )
+            .collect()
+    }
 
-        Ok(Box::new(rows.into_iter().map(TryInto::try_into)))
+    async fn mark_envelope_processed(
+        &self,
+        server_guid: Uuid,
+        timestamp: u64,
+        expire_before: u64,
+    ) -> Result<bool, Self::ContentsStoreError> {
+        let timestamp: i64 = timestamp
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+        let expire_before: i64 = expire_before
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?;
+
+        let mut transaction = self.db.begin().await?;
+        sqlx::query("DELETE FROM processed_envelopes WHERE processed_at < ?")
+            .bind(expire_before)
+            .execute(&mut *transaction)
+            .await?;
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO processed_envelopes (server_guid, processed_at) VALUES (?, ?)",
+        )
+        .bind(server_guid)
+        .bind(timestamp)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(res.rows_affected() > 0)
     }
 
     async fn clear_contacts(&mut self) -> Result<(), Self::ContentsStoreError> {
@@ -395,6 +1517,21 @@ impl ContentsStore for SqliteStore {
         .transpose()
     }
 
+    async fn contact_by_phone_number(
+        &self,
+        phone_number: &PhoneNumber,
+    ) -> Result<Option<Contact>, Self::ContentsStoreError> {
+        let uuid: Option<Uuid> =
+            sqlx::query_scalar("SELECT uuid FROM contacts WHERE phone_number = ? LIMIT 1")
+                .bind(phone_number.to_string())
+                .fetch_optional(&self.db)
+                .await?;
+        match uuid {
+            Some(uuid) => self.contact_by_id(&uuid).await,
+            None => Ok(None),
+        }
+    }
+
     async fn clear_groups(&mut self) -> Result<(), Self::ContentsStoreError> {
         let mut transaction = self.db.begin().await.into_protocol_error()?;
         query!("DELETE FROM groups")
@@ -413,72 +1550,59 @@ impl ContentsStore for SqliteStore {
         group: impl Into<Group>,
     ) -> Result<(), Self::ContentsStoreError> {
         let g = SqlGroup::from_group(&master_key, group.into());
-        let master_key = g.master_key.as_ref();
-        query!(
-            "INSERT OR REPLACE INTO groups VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            master_key,
-            g.title,
-            g.revision,
-            g.invite_link_password,
-            g.access_control,
-            g.avatar,
-            g.description,
-            g.members,
-            g.pending_members,
-            g.requesting_members,
-        )
-        .execute(&self.db)
-        .await?;
-        Ok(())
-    }
-
-    async fn groups(&self) -> Result<Self::GroupsIter, Self::ContentsStoreError> {
-        let sql_groups = query_as!(
-            SqlGroup,
-            r#"SELECT
+        sqlx::query(
+            "INSERT OR REPLACE INTO groups (
                 master_key,
                 title,
-                revision AS "revision: _",
+                revision,
                 invite_link_password,
-                access_control AS "access_control: _",
+                access_control,
                 avatar,
                 description,
-                members AS "members: _",
-                pending_members AS "pending_members: _",
-                requesting_members AS "requesting_members: _"
-            FROM groups"#,
+                members,
+                pending_members,
+                requesting_members,
+                announcements_only
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
-        .fetch_all(&self.db)
+        .bind(g.master_key.as_ref())
+        .bind(g.title)
+        .bind(g.revision)
+        .bind(g.invite_link_password)
+        .bind(g.access_control)
+        .bind(g.avatar)
+        .bind(g.description)
+        .bind(g.members)
+        .bind(g.pending_members)
+        .bind(g.requesting_members)
+        .bind(g.announcements_only)
+        .execute(&self.db)
         .await?;
-        Ok(Box::new(sql_groups.into_iter().map(SqlGroup::into_group)))
+        Ok(())
+    }
+
+    async fn groups(&self) -> Result<Self::GroupsIter, Self::ContentsStoreError> {
+        let rows: Vec<SqlGroupRow> = sqlx::query_as(SELECT_GROUPS).fetch_all(&self.db).await?;
+        Ok(Box::new(
+            rows.into_iter()
+                .map(|row| SqlGroup::from_row(row).into_group()),
+        ))
     }
 
     async fn group(
         &self,
         master_key: GroupMasterKeyBytes,
     ) -> Result<Option<Group>, Self::ContentsStoreError> {
-        let master_key_bytes = master_key.as_slice();
-        query_as!(
-            SqlGroup,
-            r#"SELECT
-                master_key,
-                title,
-                revision AS "revision: _",
-                invite_link_password,
-                access_control AS "access_control: _",
-                avatar,
-                description,
-                members AS "members: _",
-                pending_members AS "pending_members: _",
-                requesting_members AS "requesting_members: _"
-            FROM groups
-            WHERE master_key = ?
-            LIMIT 1"#,
-            master_key_bytes,
-        )
-        .fetch_optional(&self.db)
-        .await?
-        .map(|g| g.into_group().map(|(_master_key, group)| group))
+        let row: Option<SqlGroupRow> =
+            sqlx::query_as(&format!("{SELECT_GROUPS} WHERE master_key = ? LIMIT 1"))
+                .bind(master_key.as_slice())
+                .fetch_optional(&self.db)
+                .await?;
+        row.map(|row| {
+            SqlGroup::from_row(row)
+                .into_group()
+                .map(|(_master_key, group)| group)
+        })
         .transpose()
     }
 
@@ -624,6 +1748,24 @@ impl ContentsStore for SqliteStore {
             .map_err(From::from)
     }
 
+    async fn clear_profile(&mut self, uuid: &Uuid) -> Result<(), Self::ContentsStoreError> {
+        let mut transaction = self.db.begin().await?;
+        sqlx::query("DELETE FROM profiles WHERE uuid = ?")
+            .bind(uuid)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM profile_avatars WHERE uuid = ?")
+            .bind(uuid)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM profile_credentials WHERE uuid = ?")
+            .bind(uuid.to_string())
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
     async fn save_profile_credential(
         &mut self,
         uuid: Uuid,
@@ -726,6 +1868,121 @@ impl ContentsStore for SqliteStore {
     }
 }
 
+const MESSAGES_BEFORE: &str = "SELECT
+        ts,
+        sender_service_id,
+        sender_device_id,
+        destination_service_id,
+        needs_receipt,
+        unidentified_sender,
+        content_body,
+        was_plaintext
+    FROM thread_messages
+    WHERE thread_id = (SELECT id FROM threads WHERE group_master_key = ?1 OR recipient_id = ?2)
+        AND coalesce(ts < ?3, true)
+    ORDER BY ts DESC
+    LIMIT ?4";
+
+const MESSAGES_AFTER: &str = "SELECT
+        ts,
+        sender_service_id,
+        sender_device_id,
+        destination_service_id,
+        needs_receipt,
+        unidentified_sender,
+        content_body,
+        was_plaintext
+    FROM thread_messages
+    WHERE thread_id = (SELECT id FROM threads WHERE group_master_key = ?1 OR recipient_id = ?2)
+        AND ts > ?3
+    ORDER BY ts ASC
+    LIMIT ?4";
+
+const SELECT_GROUPS: &str = "SELECT
+        master_key,
+        title,
+        revision,
+        invite_link_password,
+        access_control,
+        avatar,
+        description,
+        members,
+        pending_members,
+        requesting_members,
+        announcements_only
+    FROM groups";
+
+/// Columns of [SqlMessage] queried without the `query_as!` macro
+type SqlMessageRow = (i64, String, i64, String, bool, bool, Vec<u8>, bool);
+
+type SqlThreadRow = (
+    Option<Vec<u8>>,
+    Option<Uuid>,
+    i64,
+    Option<String>,
+    i64,
+    bool,
+    Option<i64>,
+);
+
+fn message_from_row(
+    (
+        ts,
+        sender_service_id,
+        sender_device_id,
+        destination_service_id,
+        needs_receipt,
+        unidentified_sender,
+        content_body,
+        was_plaintext,
+    ): SqlMessageRow,
+) -> Result<Content, SqliteStoreError> {
+    SqlMessage {
+        ts: ts as u64,
+        sender_service_id,
+        sender_device_id: sender_device_id
+            .try_into()
+            .map_err(|_| SqliteStoreError::InvalidFormat)?,
+        destination_service_id,
+        needs_receipt,
+        unidentified_sender,
+        content_body,
+        was_plaintext,
+    }
+    .try_into()
+}
+
+/// Maximum number of characters of [ThreadMetadata::snippet]
+const SNIPPET_LEN: usize = 100;
+
+fn message_status_to_sql(status: MessageStatus) -> i64 {
+    match status {
+        MessageStatus::Pending => -1,
+        MessageStatus::Sent => 0,
+        MessageStatus::Delivered => 1,
+        MessageStatus::Read => 2,
+    }
+}
+
+fn message_status_from_sql(status: i64) -> Result<MessageStatus, SqliteStoreError> {
+    match status {
+        -1 => Ok(MessageStatus::Pending),
+        0 => Ok(MessageStatus::Sent),
+        1 => Ok(MessageStatus::Delivered),
+        2 => Ok(MessageStatus::Read),
+        _ => Err(SqliteStoreError::InvalidFormat),
+    }
+}
+
+fn searchable_body(content: &Content) -> Option<String> {
+    content.data_message()?.body.clone()
+}
+
+fn snippet(content: &Content) -> Option<String> {
+    let body = content.data_message()?.body.as_deref()?;
+    Some(body.chars().take(SNIPPET_LEN).collect())
+}
+
 trait ThreadExt {
     fn group_master_key(&self) -> Option<&[u8]>;
     fn recipient_id(&self) -> Option<Uuid>;
@@ -764,3 +2021,554 @@ impl BoundExt for Bound<&u64> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use presage::{
+        libsignal_service::{prelude::phonenumber, protocol::Aci, push_service::DEFAULT_DEVICE_ID},
+        model::identity::OnNewIdentity,
+        proto::DataMessage,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reactions_before_and_after_target_message() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let thread = Thread::Contact(Uuid::new_v4());
+        let author: ServiceId = Aci::from(Uuid::new_v4()).into();
+
+        // the reaction is received before the message it reacts to
+        store
+            .save_reaction(
+                &thread,
+                1000,
+                Reaction {
+                    emoji: "👍".to_owned(),
+                    author,
+                    timestamp: 1001,
+                },
+            )
+            .await?;
+
+        let message = Content {
+            metadata: Metadata {
+                sender: author,
+                destination: author,
+                sender_device: *DEFAULT_DEVICE_ID,
+                timestamp: 1000,
+                needs_receipt: false,
+                unidentified_sender: false,
+                was_plaintext: false,
+                server_guid: None,
+            },
+            body: DataMessage {
+                body: Some("hello".to_owned()),
+                ..Default::default()
+            }
+            .into(),
+        };
+        store.save_message(&thread, message).await?;
+
+        let reactions = store.reactions(&thread, 1000).await?;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].author, author);
+
+        // reacting again replaces the previous reaction
+        store
+            .save_reaction(
+                &thread,
+                1000,
+                Reaction {
+                    emoji: "❤️".to_owned(),
+                    author,
+                    timestamp: 1002,
+                },
+            )
+            .await?;
+        let reactions = store.reactions(&thread, 1000).await?;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "❤️");
+
+        assert!(store.remove_reaction(&thread, 1000, &author).await?);
+        assert!(store.reactions(&thread, 1000).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn poll_votes_replace_previous_votes() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let thread = Thread::Group([7; 32]);
+        let (alice, bob): (ServiceId, ServiceId) = (
+            Aci::from(Uuid::new_v4()).into(),
+            Aci::from(Uuid::new_v4()).into(),
+        );
+        let vote = |voter, option_indexes: Vec<u32>, vote_count, timestamp| PollVote {
+            voter,
+            option_indexes,
+            vote_count,
+            timestamp,
+        };
+
+        store
+            .save_poll_vote(&thread, 1000, vote(alice, vec![0, 2], 1, 1001))
+            .await?;
+        store
+            .save_poll_vote(&thread, 1000, vote(bob, vec![1], 1, 1002))
+            .await?;
+        store
+            .save_poll_vote(&thread, 1000, vote(alice, vec![1], 2, 1003))
+            .await?;
+
+        let votes = store.poll_votes(&thread, 1000).await?;
+        assert_eq!(
+            votes,
+            [vote(bob, vec![1], 1, 1002), vote(alice, vec![1], 2, 1003)]
+        );
+        assert!(store.poll_votes(&thread, 2000).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expire_timer_is_stored_per_thread() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let contact_thread = Thread::Contact(Uuid::new_v4());
+        let group_thread = Thread::Group([42; 32]);
+
+        assert_eq!(store.expire_timer(&contact_thread).await?, None);
+        assert_eq!(store.expire_timer(&group_thread).await?, None);
+
+        store.update_expire_timer(&contact_thread, 3600, 2).await?;
+        store.update_expire_timer(&group_thread, 60, 5).await?;
+        assert_eq!(store.expire_timer(&contact_thread).await?, Some((3600, 2)));
+        assert_eq!(store.expire_timer(&group_thread).await?, Some((60, 5)));
+
+        // outdated versions are ignored for contacts
+        store.update_expire_timer(&contact_thread, 0, 1).await?;
+        assert_eq!(store.expire_timer(&contact_thread).await?, Some((3600, 2)));
+
+        // disabling the timer
+        store.update_expire_timer(&contact_thread, 0, 3).await?;
+        assert_eq!(store.expire_timer(&contact_thread).await?, Some((0, 3)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn block_and_unblock_threads() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let contact = Thread::Contact(Uuid::new_v4());
+        let group = Thread::Group([42; 32]);
+
+        assert!(!store.is_blocked(&contact).await?);
+        store.set_blocked(&contact, true).await?;
+        store.set_blocked(&group, true).await?;
+        assert!(store.is_blocked(&contact).await?);
+        assert!(store.is_blocked(&group).await?);
+        assert_eq!(store.blocked().await?, vec![contact.clone(), group.clone()]);
+
+        store.set_blocked(&contact, false).await?;
+        assert!(!store.is_blocked(&contact).await?);
+        assert_eq!(store.blocked().await?, vec![group]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn archived_and_muted_threads() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let thread = Thread::Contact(sender.raw_uuid());
+
+        assert!(!store.is_archived(&thread).await?);
+        assert_eq!(store.muted_until(&thread).await?, None);
+
+        store.set_archived(&thread, true).await?;
+        store.set_muted_until(&thread, Some(5000)).await?;
+        store
+            .save_message(&thread, text_message(sender, 1000, "hello"))
+            .await?;
+        assert!(store.is_archived(&thread).await?);
+        assert_eq!(store.muted_until(&thread).await?, Some(5000));
+
+        let threads = store.threads().await?;
+        assert!(threads[0].archived);
+        assert!(threads[0].is_muted_at(4000));
+        assert!(!threads[0].is_muted_at(5000));
+
+        store.set_archived(&thread, false).await?;
+        store.set_muted_until(&thread, None).await?;
+        assert!(!store.is_archived(&thread).await?);
+        assert_eq!(store.muted_until(&thread).await?, None);
+
+        Ok(())
+    }
+
+    fn text_message(sender: ServiceId, timestamp: u64, body: &str) -> Content {
+        Content {
+            metadata: Metadata {
+                sender,
+                destination: sender,
+                sender_device: *DEFAULT_DEVICE_ID,
+                timestamp,
+                needs_receipt: false,
+                unidentified_sender: false,
+                was_plaintext: false,
+                server_guid: None,
+            },
+            body: DataMessage {
+                body: Some(body.to_owned()),
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn threads_metadata() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let contact = Thread::Contact(sender.raw_uuid());
+        let group = Thread::Group([42; 32]);
+
+        store
+            .save_message(&contact, text_message(sender, 1000, "hello"))
+            .await?;
+        store
+            .save_message(&group, text_message(sender, 2000, "hello group"))
+            .await?;
+        store
+            .save_message(&contact, text_message(sender, 3000, &"a".repeat(500)))
+            .await?;
+        // saving a message again does not count it twice
+        store
+            .save_message(&contact, text_message(sender, 3000, "edited"))
+            .await?;
+
+        let threads = store.threads().await?;
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].thread, contact);
+        assert_eq!(threads[0].last_message_timestamp, Some(3000));
+        assert_eq!(threads[0].unread_count, 2);
+        assert_eq!(threads[0].snippet.as_deref(), Some("edited"));
+        assert_eq!(threads[1].thread, group);
+        assert_eq!(threads[1].unread_count, 1);
+
+        // older messages do not replace the last one
+        store
+            .save_message(&group, text_message(sender, 1500, "older"))
+            .await?;
+        let threads = store.threads().await?;
+        assert_eq!(threads[1].snippet.as_deref(), Some("hello group"));
+        assert_eq!(threads[1].unread_count, 2);
+
+        // deleting the last message shows the previous one
+        store.delete_message(&contact, 3000).await?;
+        let threads = store.threads().await?;
+        assert_eq!(threads[0].thread, group);
+        assert_eq!(threads[1].last_message_timestamp, Some(1000));
+        assert_eq!(threads[1].snippet.as_deref(), Some("hello"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mark_thread_read() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let thread = Thread::Contact(sender.raw_uuid());
+
+        for ts in [1000, 2000, 3000] {
+            store
+                .save_message(&thread, text_message(sender, ts, "hello"))
+                .await?;
+        }
+        assert_eq!(store.unread_count(&thread).await?, 3);
+
+        let read = store.mark_read(&thread, 2000).await?;
+        assert_eq!(read.len(), 2);
+        assert!(read.contains(&(sender, 1000)));
+        assert_eq!(store.unread_count(&thread).await?, 1);
+
+        // marking as read again has no effect
+        assert!(store.mark_read(&thread, 2000).await?.is_empty());
+        assert_eq!(store.unread_count(&thread).await?, 1);
+
+        // read messages stay read when saved again, and late messages are already read
+        store
+            .save_message(&thread, text_message(sender, 1000, "edited"))
+            .await?;
+        store
+            .save_message(&thread, text_message(sender, 1500, "late"))
+            .await?;
+        assert_eq!(store.unread_count(&thread).await?, 1);
+
+        assert_eq!(store.mark_read(&thread, 3000).await?, vec![(sender, 3000)]);
+        assert_eq!(store.unread_count(&thread).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_messages() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let contact = Thread::Contact(sender.raw_uuid());
+        let group = Thread::Group([42; 32]);
+
+        store
+            .save_message(&contact, text_message(sender, 1000, "Hello world"))
+            .await?;
+        store
+            .save_message(&group, text_message(sender, 2000, "hello \"group\""))
+            .await?;
+        store
+            .save_message(&contact, text_message(sender, 3000, "bye"))
+            .await?;
+
+        let timestamps = |messages: Vec<Content>| -> Vec<u64> {
+            messages.iter().map(|m| m.metadata.timestamp).collect()
+        };
+        assert_eq!(
+            timestamps(store.search_messages("hello", None, 10).await?),
+            [2000, 1000]
+        );
+        assert_eq!(
+            timestamps(store.search_messages("hello", Some(&contact), 10).await?),
+            [1000]
+        );
+        assert_eq!(
+            timestamps(store.search_messages("hello", None, 1).await?),
+            [2000]
+        );
+        // the query syntax of FTS5 is not interpreted
+        assert!(
+            store
+                .search_messages("\"group\" OR", None, 10)
+                .await?
+                .is_empty()
+        );
+
+        // edited and deleted messages are kept in sync
+        store
+            .save_message(&contact, text_message(sender, 1000, "Goodbye world"))
+            .await?;
+        assert_eq!(
+            timestamps(store.search_messages("hello", None, 10).await?),
+            [2000]
+        );
+        store.delete_message(&group, 2000).await?;
+        assert!(store.search_messages("hello", None, 10).await?.is_empty());
+        assert_eq!(
+            timestamps(store.search_messages("world", None, 10).await?),
+            [1000]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn backfill_search_index() -> Result<(), Box<dyn std::error::Error>> {
+        let store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let thread = Thread::Contact(sender.raw_uuid());
+        store
+            .save_message(&thread, text_message(sender, 1000, "hello"))
+            .await?;
+
+        // as after the migration keying the index by message
+        sqlx::query("DELETE FROM thread_messages_fts")
+            .execute(&store.db)
+            .await?;
+        sqlx::query(
+            "INSERT INTO thread_messages_fts_backfill SELECT thread_id, ts FROM thread_messages",
+        )
+        .execute(&store.db)
+        .await?;
+        assert!(store.search_messages("hello", None, 10).await?.is_empty());
+
+        store.backfill_search_index().await?;
+        let found = store.search_messages("hello", None, 10).await?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata.timestamp, 1000);
+        let pending: i64 = sqlx::query_scalar("SELECT count(*) FROM thread_messages_fts_backfill")
+            .fetch_one(&store.db)
+            .await?;
+        assert_eq!(pending, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn messages_pages() -> Result<(), Box<dyn std::error::Error>> {
+        let store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let thread = Thread::Contact(sender.raw_uuid());
+        for ts in 1..=10 {
+            store
+                .save_message(&thread, text_message(sender, ts, "hello"))
+                .await?;
+        }
+        store
+            .save_message(&Thread::Group([42; 32]), text_message(sender, 11, "hello"))
+            .await?;
+
+        let page = |range, limit| {
+            let store = &store;
+            let thread = &thread;
+            async move {
+                let messages = store.messages_page(thread, range, limit).await?;
+                Ok::<_, SqliteStoreError>(
+                    messages
+                        .iter()
+                        .map(|m| m.metadata.timestamp)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        assert_eq!(page(MessageRange::Latest, 3).await?, [10, 9, 8]);
+        assert_eq!(page(MessageRange::Before(8), 3).await?, [7, 6, 5]);
+        assert_eq!(page(MessageRange::Before(3), 3).await?, [2, 1]);
+        assert_eq!(page(MessageRange::After(8), 3).await?, [9, 10]);
+        assert_eq!(page(MessageRange::After(0), 2).await?, [1, 2]);
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn message_statuses() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let thread = Thread::Group([42; 32]);
+
+        assert_eq!(store.message_status(&thread, 1000).await?, None);
+        store
+            .save_message_status(&thread, 1000, MessageStatus::Delivered)
+            .await?;
+        store
+            .save_message_status(&thread, 1000, MessageStatus::Read)
+            .await?;
+        store
+            .save_message_status(&thread, 1000, MessageStatus::Delivered)
+            .await?;
+        assert_eq!(
+            store.message_status(&thread, 1000).await?,
+            Some(MessageStatus::Read)
+        );
+
+        store.delete_message(&thread, 1000).await?;
+        assert_eq!(store.message_status(&thread, 1000).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pending_sends() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let contact = Thread::Contact(sender.raw_uuid());
+        let group = Thread::Group([42; 32]);
+
+        store
+            .save_pending_send(&contact, text_message(sender, 2000, "second"))
+            .await?;
+        store
+            .save_pending_send(&group, text_message(sender, 1000, "first"))
+            .await?;
+        store
+            .save_pending_send(&contact, text_message(sender, 2000, "second, again"))
+            .await?;
+
+        let pending_sends = store.pending_sends().await?;
+        let pending: Vec<_> = pending_sends
+            .iter()
+            .map(|(thread, content)| {
+                (
+                    thread,
+                    content.metadata.timestamp,
+                    content.data_message().and_then(|m| m.body.as_deref()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pending,
+            [
+                (&group, 1000, Some("first")),
+                (&contact, 2000, Some("second, again"))
+            ]
+        );
+
+        store.remove_pending_send(&group, 1000).await?;
+        store.remove_pending_send(&contact, 1000).await?;
+        assert_eq!(store.pending_sends().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn contacts_by_phone_number() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let phone_number = phonenumber::parse(None, "+41791234567")?;
+        let contact = Contact {
+            uuid: Uuid::new_v4(),
+            phone_number: Some(phone_number.clone()),
+            name: "Alice".to_owned(),
+            verified: Default::default(),
+            profile_key: vec![0; 32],
+            expire_timer: 0,
+            expire_timer_version: 2,
+            inbox_position: 0,
+            avatar: None,
+        };
+        store.save_contact(&contact).await?;
+
+        let found = store.contact_by_phone_number(&phone_number).await?;
+        assert_eq!(found.map(|contact| contact.uuid), Some(contact.uuid));
+        let other_phone_number = phonenumber::parse(None, "+41797654321")?;
+        assert!(
+            store
+                .contact_by_phone_number(&other_phone_number)
+                .await?
+                .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleting_unread_message() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let sender: ServiceId = Aci::from(Uuid::new_v4()).into();
+        let thread = Thread::Contact(sender.raw_uuid());
+
+        store
+            .save_message(&thread, text_message(sender, 1000, "hello"))
+            .await?;
+        assert_eq!(store.unread_count(&thread).await?, 1);
+
+        assert!(store.delete_message(&thread, 1000).await?);
+        assert_eq!(store.unread_count(&thread).await?, 0);
+        assert!(!store.delete_message(&thread, 1000).await?);
+        assert_eq!(store.unread_count(&thread).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn processed_envelopes() -> Result<(), Box<dyn std::error::Error>> {
+        let store = SqliteStore::open(":memory:", OnNewIdentity::Trust).await?;
+        let (guid, other_guid) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // the same envelope delivered twice is only processed once
+        assert!(store.mark_envelope_processed(guid, 10, 0).await?);
+        assert!(!store.mark_envelope_processed(guid, 20, 0).await?);
+        assert!(store.mark_envelope_processed(other_guid, 30, 0).await?);
+
+        // until it is forgotten after the retention window
+        assert!(store.mark_envelope_processed(guid, 40, 15).await?);
+        assert!(!store.mark_envelope_processed(other_guid, 50, 15).await?);
+
+        Ok(())
+    }
+}
@@ -126,8 +126,24 @@ pub(crate) struct SqlGroup<'a> {
     pub(crate) members: Json<Vec<Member>>,
     pub(crate) pending_members: Json<Vec<PendingMember>>,
     pub(crate) requesting_members: Json<Vec<RequestingMember>>,
+    pub(crate) announcements_only: bool,
 }
 
+/// Columns of [SqlGroup] queried without the `query_as!` macro
+pub(crate) type SqlGroupRow = (
+    Vec<u8>,
+    String,
+    u32,
+    Option<Vec<u8>>,
+    Option<Json<AccessControl>>,
+    String,
+    Option<String>,
+    Json<Vec<Member>>,
+    Json<Vec<PendingMember>>,
+    Json<Vec<RequestingMember>>,
+    bool,
+);
+
 impl SqlGroup<'_> {
     #[tracing::instrument]
     pub fn from_group(master_key: &GroupMasterKeyBytes, group: Group) -> SqlGroup<'_> {
@@ -142,6 +158,37 @@ impl SqlGroup<'_> {
             members: Json(group.members),
             pending_members: Json(group.pending_members),
             requesting_members: Json(group.requesting_members),
+            announcements_only: group.announcements_only,
+        }
+    }
+
+    pub(crate) fn from_row(
+        (
+            master_key,
+            title,
+            revision,
+            invite_link_password,
+            access_control,
+            avatar,
+            description,
+            members,
+            pending_members,
+            requesting_members,
+            announcements_only,
+        ): SqlGroupRow,
+    ) -> SqlGroup<'static> {
+        SqlGroup {
+            master_key: Cow::Owned(master_key),
+            title,
+            revision,
+            invite_link_password,
+            access_control,
+            avatar,
+            description,
+            members,
+            pending_members,
+            requesting_members,
+            announcements_only,
         }
     }
 
@@ -158,6 +205,7 @@ impl SqlGroup<'_> {
             members: Json(members),
             pending_members: Json(pending_members),
             requesting_members: Json(requesting_members),
+            announcements_only,
         } = self;
         let master_key = master_key
             .as_ref()
@@ -175,6 +223,7 @@ impl SqlGroup<'_> {
             requesting_members,
             invite_link_password: invite_link_password.unwrap_or_default(),
             description,
+            announcements_only,
         };
         Ok((master_key, group))
     }
@@ -16,6 +16,13 @@ pub enum SqliteStoreError {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error(
+        "database schema version {version} is newer than the supported version {supported_version}, presage needs to be upgraded"
+    )]
+    UnsupportedSchemaVersion {
+        version: i64,
+        supported_version: i64,
+    },
     #[error(transparent)]
     Json(#[from] serde_json::Error),
     #[error(transparent)]
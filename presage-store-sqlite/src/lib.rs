@@ -1,5 +1,12 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
 use presage::{
-    libsignal_service::{prelude::MasterKey, protocol::SenderCertificate},
+    libsignal_service::{
+        prelude::MasterKey,
+        protocol::{SenderCertificate, ServiceId},
+    },
+    manager::RegistrationData,
+    model::settings::AccountSettings,
     store::{StateStore, Store},
 };
 use protocol::{IdentityType, SqliteProtocolStore};
@@ -20,6 +27,8 @@ use crate::error::SqlxErrorExt;
 pub struct SqliteStore {
     pub(crate) db: SqlitePool,
     pub(crate) trust_new_identities: OnNewIdentity,
+    /// ACI of the registered account, kept to tell incoming messages apart when saving them
+    own_aci: Arc<RwLock<Option<ServiceId>>>,
 }
 
 impl SqliteStore {
@@ -105,11 +114,112 @@ impl SqliteStore {
                 .await?;
         }
 
-        sqlx::migrate!().run(&db).await?;
-        Ok(Self {
+        // Refuse to open a database migrated by a newer version, which this version might corrupt
+        let migrator = sqlx::migrate!();
+        let supported_version = migrator.iter().map(|m| m.version).max().unwrap_or_default();
+        let version = connection
+            .list_applied_migrations()
+            .await?
+            .iter()
+            .map(|m| m.version)
+            .max();
+        if let Some(version) = version.filter(|&version| version > supported_version) {
+            return Err(SqliteStoreError::UnsupportedSchemaVersion {
+                version,
+                supported_version,
+            });
+        }
+        drop(connection);
+
+        migrator.run(&db).await?;
+        let store = Self {
             db,
             trust_new_identities,
-        })
+            own_aci: Default::default(),
+        };
+        store.backfill_search_index().await?;
+        let registration_data = store.load_registration_data().await.ok().flatten();
+        store.set_own_aci(registration_data.as_ref());
+        Ok(store)
+    }
+
+    pub(crate) fn own_aci(&self) -> Option<ServiceId> {
+        *self.own_aci.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set_own_aci(&self, registration_data: Option<&RegistrationData>) {
+        *self.own_aci.write().unwrap_or_else(PoisonError::into_inner) =
+            registration_data.map(|data| data.service_ids.aci().into());
+    }
+
+    /// Version of the schema of the database, i.e. of the last applied migration.
+    pub async fn schema_version(&self) -> Result<i64, SqliteStoreError> {
+        let version: Option<i64> =
+            sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations WHERE success")
+                .fetch_one(&self.db)
+                .await?;
+        Ok(version.unwrap_or_default())
+    }
+
+    /// Change the passphrase used to encrypt the database at `url`, re-encrypting it.
+    ///
+    /// `None` as `old` means that the database is currently not encrypted, and `None` as `new`
+    /// decrypts it entirely.
+    ///
+    /// The database is exported to a new file which then replaces the current one, so it stays
+    /// readable with the old passphrase if this fails midway. It must not be open meanwhile: drop
+    /// the store (and its clones, e.g. used by a `Manager`) before, and open it again after with
+    /// the new passphrase.
+    pub async fn change_passphrase(
+        url: &str,
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> Result<(), SqliteStoreError> {
+        use sqlx::{ConnectOptions, Connection};
+
+        let options: SqliteConnectOptions = url.parse()?;
+        let path = options.get_filename().to_owned();
+        if !path.is_file() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "only the passphrase of a database file can be changed",
+            )
+            .into());
+        }
+
+        // Escape the passphrase, an empty key meaning that the database is not encrypted.
+        let escape = |passphrase: Option<&str>| passphrase.unwrap_or_default().replace("'", "''");
+        let (old, new) = (escape(old), escape(new));
+        let options = options.pragma("key", format!("'{old}'"));
+
+        // Check the old passphrase before exporting the database.
+        let mut connection = options.connect().await?;
+        query_scalar::<_, i64>("SELECT count(*) FROM sqlite_master")
+            .fetch_one(&mut connection)
+            .await?;
+
+        let mut rekeyed_path = path.clone().into_os_string();
+        rekeyed_path.push(".rekey");
+        let rekeyed_path = std::path::PathBuf::from(rekeyed_path);
+        // Leftover of a previous attempt which failed
+        if rekeyed_path.exists() {
+            std::fs::remove_file(&rekeyed_path)?;
+        }
+
+        // See `open_migrate_to_encrypted` about the injection.
+        sqlx::raw_sql(&format!(
+            "ATTACH DATABASE '{}' AS rekeyed KEY '{new}';
+            SELECT sqlcipher_export('rekeyed');
+            DETACH DATABASE rekeyed;",
+            rekeyed_path.display().to_string().replace("'", "''")
+        ))
+        .execute(&mut connection)
+        .await?;
+        connection.close().await?;
+
+        // Renaming is atomic: the database is either the old or the re-encrypted one.
+        std::fs::rename(&rekeyed_path, &path)?;
+        Ok(())
     }
 
     /// There sadly does not seem to be a good migration strategy contained within the database we are trying to migrate.
@@ -171,6 +281,7 @@ impl Store for SqliteStore {
 
     async fn clear(&mut self) -> Result<(), SqliteStoreError> {
         query!("DELETE FROM kv").execute(&self.db).await?;
+        self.set_own_aci(None);
         Ok(())
     }
 
@@ -194,7 +305,7 @@ impl StateStore for SqliteStore {
 
     async fn load_registration_data(
         &self,
-    ) -> Result<Option<presage::manager::RegistrationData>, Self::StateStoreError> {
+    ) -> Result<Option<RegistrationData>, Self::StateStoreError> {
         query_scalar!("SELECT value FROM kv WHERE key = 'registration'")
             .fetch_optional(&self.db)
             .await?
@@ -205,7 +316,7 @@ impl StateStore for SqliteStore {
 
     async fn save_registration_data(
         &mut self,
-        state: &presage::manager::RegistrationData,
+        state: &RegistrationData,
     ) -> Result<(), Self::StateStoreError> {
         let value = serde_json::to_string(state)?;
         query!(
@@ -214,6 +325,7 @@ impl StateStore for SqliteStore {
         )
         .execute(&self.db)
         .await?;
+        self.set_own_aci(Some(state));
         Ok(())
     }
 
@@ -226,6 +338,9 @@ impl StateStore for SqliteStore {
         query!("DELETE FROM kv WHERE key = 'registration'")
             .execute(&mut *transaction)
             .await?;
+        sqlx::query("DELETE FROM kv WHERE key = 'last_pre_key_rotation'")
+            .execute(&mut *transaction)
+            .await?;
         query!("DELETE FROM sessions")
             .execute(&mut *transaction)
             .await?;
@@ -245,6 +360,7 @@ impl StateStore for SqliteStore {
             .execute(&mut *transaction)
             .await?;
         transaction.commit().await.into_protocol_error()?;
+        self.set_own_aci(None);
         Ok(())
     }
 
@@ -303,6 +419,52 @@ impl StateStore for SqliteStore {
         Ok(())
     }
 
+    async fn account_settings(&self) -> Result<Option<AccountSettings>, Self::StateStoreError> {
+        let value: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT value FROM kv WHERE key = 'account_settings'")
+                .fetch_optional(&self.db)
+                .await?;
+        value
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(From::from)
+    }
+
+    async fn save_account_settings(
+        &self,
+        settings: &AccountSettings,
+    ) -> Result<(), Self::StateStoreError> {
+        let value = serde_json::to_vec(settings)?;
+        sqlx::query("INSERT OR REPLACE INTO kv (key, value) VALUES ('account_settings', ?)")
+            .bind(value)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn last_pre_key_rotation(&self) -> Result<Option<u64>, Self::StateStoreError> {
+        let value: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT value FROM kv WHERE key = 'last_pre_key_rotation'")
+                .fetch_optional(&self.db)
+                .await?;
+        value
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(From::from)
+    }
+
+    async fn save_last_pre_key_rotation(
+        &self,
+        timestamp: u64,
+    ) -> Result<(), Self::StateStoreError> {
+        let value = serde_json::to_vec(&timestamp)?;
+        sqlx::query("INSERT OR REPLACE INTO kv (key, value) VALUES ('last_pre_key_rotation', ?)")
+            .bind(value)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
     async fn fetch_master_key(&self) -> Result<Option<MasterKey>, Self::StateStoreError> {
         query_scalar!("SELECT value FROM kv WHERE key = 'master_key' LIMIT 1")
             .fetch_optional(&self.db)
@@ -326,3 +488,87 @@ impl StateStore for SqliteStore {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::migrate::Migrate;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn refuse_newer_schema() -> Result<(), Box<dyn std::error::Error>> {
+        let path =
+            std::env::temp_dir().join(format!("presage-schema-{}.db", rand::random::<u64>()));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+
+        let store = SqliteStore::open_with_options(options.clone(), OnNewIdentity::Trust).await?;
+        let version = store.schema_version().await?;
+        assert_eq!(
+            version,
+            sqlx::migrate!().iter().map(|m| m.version).max().unwrap()
+        );
+
+        // simulate a migration applied by a newer version
+        let mut connection = store.db.acquire().await?;
+        connection
+            .apply(&sqlx::migrate::Migration::new(
+                version + 1,
+                "from the future".into(),
+                sqlx::migrate::MigrationType::Simple,
+                "SELECT 1".into(),
+                false,
+            ))
+            .await?;
+        drop(connection);
+        store.db.close().await;
+
+        let result = SqliteStore::open_with_options(options, OnNewIdentity::Trust).await;
+        std::fs::remove_file(&path)?;
+        assert!(matches!(
+            result,
+            Err(SqliteStoreError::UnsupportedSchemaVersion { supported_version, .. })
+                if supported_version == version
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn change_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!("presage-rekey-{}.db", rand::random::<u64>()));
+        let url = path.to_str().unwrap();
+
+        let store =
+            SqliteStore::open_with_passphrase(url, Some("old"), OnNewIdentity::Trust).await?;
+        let version = store.schema_version().await?;
+        store.db.close().await;
+
+        // a wrong passphrase leaves the database as it is
+        assert!(
+            SqliteStore::change_passphrase(url, Some("wrong"), Some("new"))
+                .await
+                .is_err()
+        );
+
+        SqliteStore::change_passphrase(url, Some("old"), Some("new")).await?;
+        assert!(
+            SqliteStore::open_with_passphrase(url, Some("old"), OnNewIdentity::Trust)
+                .await
+                .is_err()
+        );
+        let store =
+            SqliteStore::open_with_passphrase(url, Some("new"), OnNewIdentity::Trust).await?;
+        assert_eq!(store.schema_version().await?, version);
+        store.db.close().await;
+
+        SqliteStore::change_passphrase(url, Some("new"), None).await?;
+        let store = SqliteStore::open_with_passphrase(url, None, OnNewIdentity::Trust).await?;
+        assert_eq!(store.schema_version().await?, version);
+        store.db.close().await;
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
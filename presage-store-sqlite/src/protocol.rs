@@ -14,7 +14,7 @@ use presage::{
         push_service::DEFAULT_DEVICE_ID,
     },
     model::identity::OnNewIdentity,
-    store::StateStore,
+    store::{IdentityTrustStore, StateStore},
 };
 use sqlx::{query, query_scalar};
 use tracing::warn;
@@ -632,20 +632,23 @@ impl IdentityKeyStore for SqliteProtocolStore {
         identity: &IdentityKey,
         _direction: Direction,
     ) -> Result<bool, SignalProtocolError> {
-        if let Some(trusted_key) = self.get_identity(address).await? {
+        match self.identity_trust(address).await? {
+            Some((known_key, trusted)) if identity == &known_key => Ok(trusted),
             // when we encounter some identity we know, we need to decide whether we trust it or not
-            if identity == &trusted_key {
-                Ok(true)
-            } else {
-                match self.store.trust_new_identities {
-                    OnNewIdentity::Trust => Ok(true),
-                    OnNewIdentity::Reject => Ok(false),
+            Some(_) => match self.store.trust_new_identities {
+                OnNewIdentity::Trust => Ok(true),
+                OnNewIdentity::Reject => {
+                    // keep the new identity, so it can be trusted explicitly
+                    warn!(%address, "rejecting changed identity");
+                    self.save_identity_trust(address, identity, false).await?;
+                    Ok(false)
                 }
+            },
+            None => {
+                // when we encounter a new identity, we trust it by default
+                warn!(%address, "trusting new identity");
+                Ok(true)
             }
-        } else {
-            // when we encounter a new identity, we trust it by default
-            warn!(%address, "trusting new identity");
-            Ok(true)
         }
     }
 
@@ -669,6 +672,57 @@ impl IdentityKeyStore for SqliteProtocolStore {
     }
 }
 
+impl SqliteProtocolStore {
+    async fn save_identity_trust(
+        &self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        trusted: bool,
+    ) -> Result<(), SignalProtocolError> {
+        sqlx::query(
+            "INSERT INTO identities (address, identity, record, trusted)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT DO UPDATE SET record = ?3, trusted = ?4",
+        )
+        .bind(address.name())
+        .bind(self.identity)
+        .bind(identity.serialize())
+        .bind(trusted)
+        .execute(&self.store.db)
+        .await
+        .into_protocol_error()?;
+        Ok(())
+    }
+}
+
+impl IdentityTrustStore for SqliteProtocolStore {
+    async fn set_identity_trust(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        trusted: bool,
+    ) -> Result<(), SignalProtocolError> {
+        self.save_identity_trust(address, identity, trusted).await
+    }
+
+    async fn identity_trust(
+        &self,
+        address: &ProtocolAddress,
+    ) -> Result<Option<(IdentityKey, bool)>, SignalProtocolError> {
+        let row: Option<(Vec<u8>, bool)> = sqlx::query_as(
+            "SELECT record, trusted FROM identities
+            WHERE address = ? AND identity = ?",
+        )
+        .bind(address.name())
+        .bind(self.identity)
+        .fetch_optional(&self.store.db)
+        .await
+        .into_protocol_error()?;
+        row.map(|(bytes, trusted)| Ok((IdentityKey::decode(&bytes)?, trusted)))
+            .transpose()
+    }
+}
+
 #[async_trait(?Send)]
 impl SenderKeyStore for SqliteProtocolStore {
     /// Assign `record` to the entry for `(sender, distribution_id)`.
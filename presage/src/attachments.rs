@@ -0,0 +1,191 @@
+//! Incremental decryption of attachments, to avoid buffering large files in memory.
+
+use aes::Aes256;
+use cbc::cipher::{generic_array::GenericArray, BlockDecryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use libsignal_service::attachment_cipher::AttachmentCipherError;
+use sha2::{Digest, Sha256};
+
+const IV_LEN: usize = 16;
+const BLOCK_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+
+/// Decrypts an attachment (`IV || AES-256-CBC ciphertext || HMAC-SHA256`) chunk by chunk.
+///
+/// The MAC and the digest can only be checked once the whole attachment went through
+/// [AttachmentDecryptor::update], so the plaintext returned before [AttachmentDecryptor::finalize]
+/// must be discarded if the latter fails.
+pub(crate) struct AttachmentDecryptor {
+    aes_key: [u8; 32],
+    mac: Hmac<Sha256>,
+    digest: Sha256,
+    decryptor: Option<cbc::Decryptor<Aes256>>,
+    /// Ciphertext which cannot be decrypted yet: the MAC and the last (padded) block are
+    /// always kept until the end.
+    pending: Vec<u8>,
+    /// Number of plaintext bytes still expected, the rest being padding.
+    remaining: Option<usize>,
+}
+
+impl AttachmentDecryptor {
+    pub(crate) fn new(key: [u8; 64], plaintext_len: Option<usize>) -> Self {
+        let (aes_key, mac_key) = key.split_at(32);
+        Self {
+            aes_key: aes_key.try_into().expect("32 bytes"),
+            mac: Hmac::<Sha256>::new_from_slice(mac_key).expect("HMAC accepts any key length"),
+            digest: Sha256::new(),
+            decryptor: None,
+            pending: Vec::new(),
+            remaining: plaintext_len,
+        }
+    }
+
+    /// Feeds the next chunk of ciphertext and returns the plaintext that could be decrypted.
+    pub(crate) fn update(&mut self, ciphertext: &[u8]) -> Vec<u8> {
+        self.digest.update(ciphertext);
+        self.pending.extend_from_slice(ciphertext);
+
+        if self.decryptor.is_none() {
+            if self.pending.len() < IV_LEN {
+                return Vec::new();
+            }
+            let iv: Vec<u8> = self.pending.drain(..IV_LEN).collect();
+            self.mac.update(&iv);
+            self.decryptor = Some(cbc::Decryptor::new(
+                GenericArray::from_slice(&self.aes_key),
+                GenericArray::from_slice(&iv),
+            ));
+        }
+
+        let kept = MAC_LEN + BLOCK_LEN;
+        if self.pending.len() <= kept {
+            return Vec::new();
+        }
+        let decryptable = (self.pending.len() - kept) / BLOCK_LEN * BLOCK_LEN;
+        let mut blocks: Vec<u8> = self.pending.drain(..decryptable).collect();
+        self.decrypt_blocks(&mut blocks);
+        self.take_plaintext(blocks)
+    }
+
+    /// Checks the MAC and returns the last plaintext bytes along with the SHA-256 digest of the
+    /// whole ciphertext.
+    pub(crate) fn finalize(mut self) -> Result<(Vec<u8>, [u8; 32]), AttachmentCipherError> {
+        if self.decryptor.is_none()
+            || self.pending.len() < MAC_LEN + BLOCK_LEN
+            || (self.pending.len() - MAC_LEN) % BLOCK_LEN != 0
+        {
+            return Err(AttachmentCipherError::MacError);
+        }
+
+        let mac_start = self.pending.len() - MAC_LEN;
+        let their_mac = self.pending.split_off(mac_start);
+        let mut blocks = std::mem::take(&mut self.pending);
+        self.mac.update(&blocks);
+        self.mac
+            .clone()
+            .verify_slice(&their_mac)
+            .map_err(|_| AttachmentCipherError::MacError)?;
+
+        self.decrypt_blocks(&mut blocks);
+
+        // remove the PKCS#7 padding
+        let padding = *blocks.last().expect("at least one block") as usize;
+        if padding == 0
+            || padding > BLOCK_LEN
+            || !blocks[blocks.len() - padding..]
+                .iter()
+                .all(|&b| b as usize == padding)
+        {
+            return Err(AttachmentCipherError::PaddingError);
+        }
+        blocks.truncate(blocks.len() - padding);
+
+        let digest = self.digest.clone().finalize().into();
+        Ok((self.take_plaintext(blocks), digest))
+    }
+
+    fn decrypt_blocks(&mut self, blocks: &mut [u8]) {
+        let decryptor = self.decryptor.as_mut().expect("initialized with the IV");
+        for block in blocks.chunks_exact_mut(BLOCK_LEN) {
+            decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+    }
+
+    /// Strips the plaintext from the zero padding appended after the announced length
+    fn take_plaintext(&mut self, mut plaintext: Vec<u8>) -> Vec<u8> {
+        if let Some(remaining) = self.remaining.as_mut() {
+            plaintext.truncate(*remaining);
+            *remaining -= plaintext.len();
+        }
+        plaintext
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libsignal_service::attachment_cipher::{encrypt_in_place, AttachmentCipherError};
+    use sha2::{Digest, Sha256};
+
+    use super::AttachmentDecryptor;
+
+    fn encrypt(plaintext: &[u8], padded_len: usize) -> ([u8; 64], Vec<u8>) {
+        let key: [u8; 64] = std::array::from_fn(|i| i as u8);
+        let mut data = plaintext.to_vec();
+        data.resize(padded_len, 0);
+        encrypt_in_place([7; 16], key, &mut data);
+        (key, data)
+    }
+
+    fn decrypt(
+        key: [u8; 64],
+        ciphertext: &[u8],
+        plaintext_len: Option<usize>,
+        chunk_size: usize,
+    ) -> Result<(Vec<u8>, [u8; 32]), AttachmentCipherError> {
+        let mut decryptor = AttachmentDecryptor::new(key, plaintext_len);
+        let mut plaintext = Vec::new();
+        for chunk in ciphertext.chunks(chunk_size) {
+            plaintext.extend(decryptor.update(chunk));
+        }
+        let (tail, digest) = decryptor.finalize()?;
+        plaintext.extend(tail);
+        Ok((plaintext, digest))
+    }
+
+    #[test]
+    fn decrypt_in_chunks() {
+        let plaintext: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        let (key, ciphertext) = encrypt(&plaintext, 12_000);
+
+        for chunk_size in [1, 15, 16, 17, 48, 1000, 100_000] {
+            let (decrypted, digest) =
+                decrypt(key, &ciphertext, Some(plaintext.len()), chunk_size).unwrap();
+            assert_eq!(decrypted, plaintext, "chunk size {chunk_size}");
+            assert_eq!(digest[..], Sha256::digest(&ciphertext)[..]);
+        }
+    }
+
+    #[test]
+    fn decrypt_without_length_keeps_padding() {
+        let (key, ciphertext) = encrypt(b"hello", 32);
+        let (decrypted, _) = decrypt(key, &ciphertext, None, 7).unwrap();
+        assert_eq!(&decrypted[..5], b"hello");
+        assert_eq!(decrypted.len(), 32);
+    }
+
+    #[test]
+    fn reject_tampered_attachment() {
+        let (key, mut ciphertext) = encrypt(b"hello", 5);
+        ciphertext[20] ^= 1;
+        assert!(matches!(
+            decrypt(key, &ciphertext, Some(5), 64),
+            Err(AttachmentCipherError::MacError)
+        ));
+    }
+
+    #[test]
+    fn reject_truncated_attachment() {
+        let (key, ciphertext) = encrypt(b"hello", 5);
+        assert!(decrypt(key, &ciphertext[..ciphertext.len() - 1], Some(5), 64).is_err());
+    }
+}
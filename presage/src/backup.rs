@@ -0,0 +1,870 @@
+//! Import of the backups exported by the Signal Android app (`signal-*.backup` files).
+//!
+//! Such a backup is a sequence of frames, mostly the SQL statements restoring the database of the
+//! app, each encrypted with AES-256-CTR and authenticated with a truncated HMAC-SHA256. Attachments,
+//! stickers and avatars follow the frame describing them, encrypted the same way.
+//!
+//! Besides the raw frames, the rows of the `message` table are converted to [Content] when
+//! possible: only text messages are supported so far, and only the `message` table of the recent
+//! versions of the app (the older `sms` and `mms` tables are yielded as plain statements).
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read},
+    path::Path,
+};
+
+use aes::Aes256;
+use ctr::cipher::{generic_array::GenericArray, KeyIvInit, StreamCipher};
+use futures::{stream, Stream};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use libsignal_service::{
+    content::{Content, Metadata},
+    prelude::Uuid,
+    proto::{DataMessage, GroupContextV2},
+    protocol::{Aci, ServiceId},
+    push_service::DEFAULT_DEVICE_ID,
+    zkgroup::GroupMasterKeyBytes,
+};
+use prost::Message;
+use sha2::{Digest, Sha256, Sha512};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::store::Thread;
+
+pub mod proto;
+
+const DIGEST_ROUNDS: usize = 250_000;
+const IV_LEN: usize = 16;
+const MAC_LEN: usize = 10;
+/// Latest version of the backup format, the length of the frames being encrypted since version 1
+const MAX_VERSION: u32 = 1;
+
+/// Key of the [proto::KeyValue] holding the ACI of the account
+const ACCOUNT_ACI_KEY: &str = "account.aci";
+
+// Types of the rows of the `message` table, see `MessageTypes.java` in the Signal Android app
+const BASE_TYPE_MASK: i64 = 0x1F;
+const BASE_INBOX_TYPE: i64 = 20;
+const BASE_OUTBOX_TYPE: i64 = 21;
+const BASE_PENDING_INSECURE_SMS_FALLBACK: i64 = 26;
+/// Key exchanges, group updates, expiration timer updates and other special messages
+const SPECIAL_TYPE_MASK: i64 = 0x8000 | 0x10000 | 0x20000 | 0x40000 | 0x80000 | 0xF_0000_0000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("failed to read the backup: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to decode a frame of the backup: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("missing or invalid backup header")]
+    InvalidHeader,
+    #[error("unsupported backup version {0}")]
+    UnsupportedVersion(u32),
+    #[error("invalid MAC, the passphrase is probably wrong")]
+    BadMac,
+}
+
+/// A frame of a backup, along with the data following it
+#[derive(Debug)]
+pub enum BackupFrame {
+    /// Version of the database of the app
+    DatabaseVersion(u32),
+    /// SQL statement restoring the database of the app, e.g. creating a table or inserting a row
+    Statement(proto::SqlStatement),
+    /// Message converted from a row of the `message` table
+    ///
+    /// Messages are only yielded at the end of the backup, since the recipients and the threads
+    /// they refer to can be restored after them.
+    Message {
+        thread: Thread,
+        content: Content,
+    },
+    Preference(proto::SharedPreference),
+    KeyValue(proto::KeyValue),
+    Attachment {
+        row_id: u64,
+        attachment_id: u64,
+        data: Vec<u8>,
+    },
+    Sticker {
+        row_id: u64,
+        data: Vec<u8>,
+    },
+    Avatar {
+        name: Option<String>,
+        recipient_id: Option<String>,
+        data: Vec<u8>,
+    },
+}
+
+/// Reads the backup at `path`, decrypting it with the 30 digits `passphrase` shown by the app when
+/// enabling backups (spaces are ignored).
+///
+/// The file is read and decrypted on a blocking thread, so this must be polled from a Tokio
+/// runtime. The stream ends after the first error.
+pub fn import_signal_backup(
+    path: impl AsRef<Path>,
+    passphrase: &str,
+) -> impl Stream<Item = Result<BackupFrame, BackupError>> {
+    let path = path.as_ref().to_owned();
+    let passphrase = passphrase.to_owned();
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::task::spawn_blocking(move || {
+        let reader = std::fs::File::open(&path)
+            .map_err(BackupError::from)
+            .and_then(|file| BackupReader::new(io::BufReader::new(file), &passphrase));
+        match reader {
+            Ok(reader) => {
+                for frame in reader {
+                    if tx.blocking_send(frame).is_err() {
+                        debug!("backup stream dropped, stop reading");
+                        break;
+                    }
+                }
+            }
+            Err(error) => {
+                let _ = tx.blocking_send(Err(error));
+            }
+        }
+    });
+
+    stream::unfold(rx, |mut rx| async move {
+        let frame = rx.recv().await?;
+        Some((frame, rx))
+    })
+}
+
+/// Synchronous reader of a backup, iterating over its frames
+///
+/// The iterator ends after the first error.
+pub struct BackupReader<R> {
+    reader: R,
+    version: u32,
+    iv: [u8; IV_LEN],
+    counter: u32,
+    cipher_key: [u8; 32],
+    mac_key: [u8; 32],
+    database: Database,
+    /// Messages converted at the end of the backup
+    pending: VecDeque<BackupFrame>,
+    finished: bool,
+}
+
+impl<R: Read> BackupReader<R> {
+    /// Reads the (plaintext) header of the backup and derives the keys from the passphrase.
+    pub fn new(mut reader: R, passphrase: &str) -> Result<Self, BackupError> {
+        let mut length = [0; 4];
+        reader.read_exact(&mut length)?;
+        let frame = read_exact_vec(&mut reader, u32::from_be_bytes(length))?;
+        let header = proto::BackupFrame::decode(&*frame)?
+            .header
+            .ok_or(BackupError::InvalidHeader)?;
+
+        let version = header.version();
+        if version > MAX_VERSION {
+            return Err(BackupError::UnsupportedVersion(version));
+        }
+        let iv: [u8; IV_LEN] = header
+            .iv()
+            .try_into()
+            .map_err(|_| BackupError::InvalidHeader)?;
+        let (cipher_key, mac_key) = derive_keys(passphrase, header.salt.as_deref());
+
+        Ok(Self {
+            reader,
+            version,
+            counter: u32::from_be_bytes(iv[..4].try_into().expect("4 bytes")),
+            iv,
+            cipher_key,
+            mac_key,
+            database: Database::default(),
+            pending: VecDeque::new(),
+            finished: false,
+        })
+    }
+
+    /// Starts decrypting the next frame or blob, each one having its own IV
+    fn next_cipher(&mut self) -> ctr::Ctr128BE<Aes256> {
+        self.iv[..4].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter = self.counter.wrapping_add(1);
+        ctr::Ctr128BE::<Aes256>::new(
+            GenericArray::from_slice(&self.cipher_key),
+            GenericArray::from_slice(&self.iv),
+        )
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.mac_key).expect("HMAC accepts any key length")
+    }
+
+    fn read_frame(&mut self) -> Result<proto::BackupFrame, BackupError> {
+        let mut cipher = self.next_cipher();
+        let mut mac = self.mac();
+
+        let mut length = [0; 4];
+        self.reader.read_exact(&mut length)?;
+        if self.version >= 1 {
+            mac.update(&length);
+            cipher.apply_keystream(&mut length);
+        }
+        let length = u32::from_be_bytes(length);
+        if (length as usize) < MAC_LEN {
+            return Err(BackupError::BadMac);
+        }
+
+        let mut frame = read_exact_vec(&mut self.reader, length)?;
+        let (ciphertext, their_mac) = frame.split_at_mut(length as usize - MAC_LEN);
+        mac.update(ciphertext);
+        mac.verify_truncated_left(their_mac)
+            .map_err(|_| BackupError::BadMac)?;
+        cipher.apply_keystream(ciphertext);
+
+        Ok(proto::BackupFrame::decode(&*ciphertext)?)
+    }
+
+    /// Reads the attachment, sticker or avatar following a frame
+    fn read_blob(&mut self, length: u32) -> Result<Vec<u8>, BackupError> {
+        let mut cipher = self.next_cipher();
+        let mut mac = self.mac();
+        mac.update(&self.iv);
+
+        let mut data = read_exact_vec(&mut self.reader, length)?;
+        mac.update(&data);
+        let mut their_mac = [0; MAC_LEN];
+        self.reader.read_exact(&mut their_mac)?;
+        mac.verify_truncated_left(&their_mac)
+            .map_err(|_| BackupError::BadMac)?;
+        cipher.apply_keystream(&mut data);
+
+        Ok(data)
+    }
+
+    /// Reads the next frame, returning `None` if it does not have to be yielded (yet)
+    fn read_next(&mut self) -> Result<Option<BackupFrame>, BackupError> {
+        let frame = self.read_frame()?;
+
+        if frame.end() {
+            self.finished = true;
+            self.pending = self.database.take_messages().collect();
+            return Ok(None);
+        }
+
+        if let Some(statement) = frame.statement {
+            Ok(self
+                .database
+                .statement(statement)
+                .map(BackupFrame::Statement))
+        } else if let Some(attachment) = frame.attachment {
+            let data = self.read_blob(attachment.length())?;
+            Ok(Some(BackupFrame::Attachment {
+                row_id: attachment.row_id(),
+                attachment_id: attachment.attachment_id(),
+                data,
+            }))
+        } else if let Some(sticker) = frame.sticker {
+            let data = self.read_blob(sticker.length())?;
+            Ok(Some(BackupFrame::Sticker {
+                row_id: sticker.row_id(),
+                data,
+            }))
+        } else if let Some(avatar) = frame.avatar {
+            let data = self.read_blob(avatar.length())?;
+            Ok(Some(BackupFrame::Avatar {
+                name: avatar.name,
+                recipient_id: avatar.recipient_id,
+                data,
+            }))
+        } else if let Some(key_value) = frame.key_value {
+            self.database.key_value(&key_value);
+            Ok(Some(BackupFrame::KeyValue(key_value)))
+        } else if let Some(preference) = frame.preference {
+            Ok(Some(BackupFrame::Preference(preference)))
+        } else if let Some(version) = frame.version {
+            Ok(Some(BackupFrame::DatabaseVersion(version.version())))
+        } else {
+            debug!("skipping unknown backup frame");
+            Ok(None)
+        }
+    }
+}
+
+impl<R: Read> Iterator for BackupReader<R> {
+    type Item = Result<BackupFrame, BackupError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Some(Ok(frame));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.read_next() {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => continue,
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// Derives the cipher and MAC keys from the passphrase and the salt of the header
+fn derive_keys(passphrase: &str, salt: Option<&[u8]>) -> ([u8; 32], [u8; 32]) {
+    let input = passphrase.replace(' ', "");
+    let input = input.as_bytes();
+
+    let mut digest = Sha512::new();
+    if let Some(salt) = salt {
+        digest.update(salt);
+    }
+    let mut hash = input.to_vec();
+    for _ in 0..DIGEST_ROUNDS {
+        digest.update(&hash);
+        digest.update(input);
+        hash = digest.finalize_reset().to_vec();
+    }
+
+    let mut secrets = [0; 64];
+    Hkdf::<Sha256>::new(None, &hash[..32])
+        .expand(b"Backup Export", &mut secrets)
+        .expect("64 bytes is a valid HKDF output length");
+    let (cipher_key, mac_key) = secrets.split_at(32);
+    (
+        cipher_key.try_into().expect("32 bytes"),
+        mac_key.try_into().expect("32 bytes"),
+    )
+}
+
+/// Reads exactly `length` bytes, without trusting `length` for the allocation since it cannot be
+/// authenticated before being read.
+fn read_exact_vec(reader: &mut impl Read, length: u32) -> Result<Vec<u8>, BackupError> {
+    let mut data = Vec::new();
+    reader.take(length.into()).read_to_end(&mut data)?;
+    if data.len() != length as usize {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(data)
+}
+
+#[derive(Debug, Default)]
+struct Recipient {
+    aci: Option<Aci>,
+    group_id: Option<String>,
+}
+
+/// What is needed from the restored database to convert its messages
+#[derive(Default)]
+struct Database {
+    /// Columns of the tables, from their `CREATE TABLE` statement
+    columns: HashMap<String, Vec<String>>,
+    own_aci: Option<Aci>,
+    recipients: HashMap<i64, Recipient>,
+    group_master_keys: HashMap<String, GroupMasterKeyBytes>,
+    /// Recipient of each thread
+    threads: HashMap<i64, i64>,
+    messages: Vec<proto::SqlStatement>,
+}
+
+impl Database {
+    /// Records the statement, returning it unless it is a message to convert at the end
+    fn statement(&mut self, statement: proto::SqlStatement) -> Option<proto::SqlStatement> {
+        if let Some((table, columns)) = parse_create_table(statement.statement()) {
+            self.columns.insert(table, columns);
+            return Some(statement);
+        }
+
+        match parse_insert_table(statement.statement()).as_deref() {
+            Some("recipient") => {
+                let row = self.row("recipient", &statement);
+                if let Some(id) = row.integer("_id") {
+                    let aci = row
+                        .string("aci")
+                        .or_else(|| row.string("uuid"))
+                        .and_then(Aci::parse_from_service_id_string);
+                    let group_id = row.string("group_id").map(ToOwned::to_owned);
+                    self.recipients.insert(id, Recipient { aci, group_id });
+                }
+            }
+            Some("groups") => {
+                let row = self.row("groups", &statement);
+                if let (Some(group_id), Some(master_key)) = (
+                    row.string("group_id"),
+                    row.blob("master_key")
+                        .and_then(|key| GroupMasterKeyBytes::try_from(key).ok()),
+                ) {
+                    self.group_master_keys
+                        .insert(group_id.to_owned(), master_key);
+                }
+            }
+            Some("thread") => {
+                let row = self.row("thread", &statement);
+                if let (Some(id), Some(recipient_id)) =
+                    (row.integer("_id"), row.integer("recipient_id"))
+                {
+                    self.threads.insert(id, recipient_id);
+                }
+            }
+            Some("message") => {
+                self.messages.push(statement);
+                return None;
+            }
+            _ => {}
+        }
+
+        Some(statement)
+    }
+
+    fn key_value(&mut self, key_value: &proto::KeyValue) {
+        if key_value.key() == ACCOUNT_ACI_KEY {
+            self.own_aci = key_value
+                .string_value
+                .as_deref()
+                .and_then(Aci::parse_from_service_id_string);
+        }
+    }
+
+    /// Converts the buffered messages, yielding the ones which cannot be converted as statements
+    fn take_messages(&mut self) -> impl Iterator<Item = BackupFrame> + '_ {
+        std::mem::take(&mut self.messages)
+            .into_iter()
+            .map(|statement| match self.message(&statement) {
+                Some((thread, content)) => BackupFrame::Message { thread, content },
+                None => BackupFrame::Statement(statement),
+            })
+    }
+
+    fn message(&self, statement: &proto::SqlStatement) -> Option<(Thread, Content)> {
+        let row = self.row("message", statement);
+
+        let message_type = row.integer("type")?;
+        if message_type & SPECIAL_TYPE_MASK != 0 {
+            return None;
+        }
+        let outgoing = match message_type & BASE_TYPE_MASK {
+            BASE_INBOX_TYPE => false,
+            BASE_OUTBOX_TYPE..=BASE_PENDING_INSECURE_SMS_FALLBACK => true,
+            _ => return None,
+        };
+        let body = row.string("body")?.to_owned();
+        let timestamp = row.integer("date_sent")? as u64;
+
+        let thread_recipient = self
+            .threads
+            .get(&row.integer("thread_id")?)
+            .and_then(|id| self.recipients.get(id))?;
+        let (thread, contact, group_v2) = match &thread_recipient.group_id {
+            Some(group_id) => {
+                let master_key = *self.group_master_keys.get(group_id)?;
+                let context = GroupContextV2 {
+                    master_key: Some(master_key.to_vec()),
+                    ..Default::default()
+                };
+                (Thread::Group(master_key), None, Some(context))
+            }
+            None => {
+                let aci = thread_recipient.aci?;
+                (Thread::Contact(Uuid::from(aci)), Some(aci), None)
+            }
+        };
+
+        let (sender, destination) = if outgoing {
+            let own_aci = self.own_aci?;
+            (own_aci, contact.unwrap_or(own_aci))
+        } else {
+            let sender = self
+                .recipients
+                .get(&row.integer("from_recipient_id")?)?
+                .aci?;
+            (sender, self.own_aci.unwrap_or(sender))
+        };
+
+        let content = Content {
+            metadata: Metadata {
+                sender: ServiceId::from(sender),
+                destination: ServiceId::from(destination),
+                sender_device: *DEFAULT_DEVICE_ID,
+                server_guid: None,
+                timestamp,
+                needs_receipt: false,
+                unidentified_sender: false,
+                was_plaintext: false,
+            },
+            body: DataMessage {
+                body: Some(body),
+                timestamp: Some(timestamp),
+                group_v2,
+                ..Default::default()
+            }
+            .into(),
+        };
+        Some((thread, content))
+    }
+
+    fn row<'a>(&'a self, table: &str, statement: &'a proto::SqlStatement) -> Row<'a> {
+        Row {
+            columns: self.columns.get(table).map(Vec::as_slice).unwrap_or(&[]),
+            parameters: &statement.parameters,
+        }
+    }
+}
+
+/// Values of an `INSERT INTO <table> VALUES (?, ...)` statement, by column name
+struct Row<'a> {
+    columns: &'a [String],
+    parameters: &'a [proto::sql_statement::SqlParameter],
+}
+
+impl Row<'_> {
+    fn get(&self, column: &str) -> Option<&proto::sql_statement::SqlParameter> {
+        let index = self.columns.iter().position(|c| c == column)?;
+        self.parameters
+            .get(index)
+            .filter(|parameter| !parameter.null_parameter())
+    }
+
+    fn integer(&self, column: &str) -> Option<i64> {
+        Some(self.get(column)?.integer_parameter? as i64)
+    }
+
+    fn string(&self, column: &str) -> Option<&str> {
+        self.get(column)?.string_parameter.as_deref()
+    }
+
+    fn blob(&self, column: &str) -> Option<&[u8]> {
+        self.get(column)?.blob_parameter.as_deref()
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+fn unquote(name: &str) -> String {
+    name.trim()
+        .trim_matches(|c| matches!(c, '"' | '`' | '\'' | '[' | ']'))
+        .to_owned()
+}
+
+/// Table name of an `INSERT INTO` statement
+fn parse_insert_table(sql: &str) -> Option<String> {
+    let rest = strip_prefix_ignore_case(sql.trim_start(), "INSERT INTO")?;
+    let name = rest
+        .trim_start()
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()?;
+    Some(unquote(name))
+}
+
+/// Table name and column names of a `CREATE TABLE` statement
+fn parse_create_table(sql: &str) -> Option<(String, Vec<String>)> {
+    let rest = strip_prefix_ignore_case(sql.trim_start(), "CREATE TABLE")?.trim_start();
+    let rest = strip_prefix_ignore_case(rest, "IF NOT EXISTS").unwrap_or(rest);
+    let (name, rest) = rest.split_once('(')?;
+    let (definitions, _) = rest.rsplit_once(')')?;
+
+    let columns = split_definitions(definitions)
+        .filter_map(|definition| {
+            let name = definition.split_whitespace().next()?;
+            let is_constraint = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+                .iter()
+                .any(|keyword| name.eq_ignore_ascii_case(keyword));
+            (!is_constraint).then(|| unquote(name))
+        })
+        .collect();
+    Some((unquote(name), columns))
+}
+
+/// Splits the column definitions and table constraints on the commas outside of parentheses and
+/// string literals.
+fn split_definitions(definitions: &str) -> impl Iterator<Item = &str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut in_literal = false;
+    let mut start = 0;
+    for (i, c) in definitions.char_indices() {
+        match c {
+            '\'' => in_literal = !in_literal,
+            '(' if !in_literal => depth += 1,
+            ')' if !in_literal => depth = depth.saturating_sub(1),
+            ',' if !in_literal && depth == 0 => {
+                parts.push(&definitions[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&definitions[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use aes::Aes256;
+    use ctr::cipher::{generic_array::GenericArray, KeyIvInit, StreamCipher};
+    use hmac::{Hmac, Mac};
+    use libsignal_service::{content::ContentBody, prelude::Uuid, protocol::Aci};
+    use prost::Message;
+    use sha2::Sha256;
+
+    use super::{
+        derive_keys, parse_create_table,
+        proto::{self, sql_statement::SqlParameter},
+        BackupError, BackupFrame, BackupReader, MAC_LEN,
+    };
+    use crate::store::Thread;
+
+    const PASSPHRASE: &str = "12345 67890 12345 67890 12345 67890";
+
+    /// Writes a backup the way the Signal Android app does
+    struct BackupWriter {
+        data: Vec<u8>,
+        iv: [u8; 16],
+        counter: u32,
+        cipher_key: [u8; 32],
+        mac_key: [u8; 32],
+    }
+
+    impl BackupWriter {
+        fn new() -> Self {
+            let iv = [3; 16];
+            let salt = vec![5; 32];
+            let header = proto::BackupFrame {
+                header: Some(proto::Header {
+                    iv: Some(iv.to_vec()),
+                    salt: Some(salt.clone()),
+                    version: Some(1),
+                }),
+                ..Default::default()
+            }
+            .encode_to_vec();
+
+            let mut data = (header.len() as u32).to_be_bytes().to_vec();
+            data.extend(header);
+            let (cipher_key, mac_key) = derive_keys(PASSPHRASE, Some(&salt));
+            Self {
+                data,
+                iv,
+                counter: u32::from_be_bytes([3; 4]),
+                cipher_key,
+                mac_key,
+            }
+        }
+
+        fn next_cipher(&mut self) -> ctr::Ctr128BE<Aes256> {
+            self.iv[..4].copy_from_slice(&self.counter.to_be_bytes());
+            self.counter += 1;
+            ctr::Ctr128BE::<Aes256>::new(
+                GenericArray::from_slice(&self.cipher_key),
+                GenericArray::from_slice(&self.iv),
+            )
+        }
+
+        fn frame(&mut self, frame: proto::BackupFrame) {
+            let mut cipher = self.next_cipher();
+            let mut mac = Hmac::<Sha256>::new_from_slice(&self.mac_key).unwrap();
+
+            let mut plaintext = frame.encode_to_vec();
+            let mut length = ((plaintext.len() + MAC_LEN) as u32).to_be_bytes();
+            cipher.apply_keystream(&mut length);
+            cipher.apply_keystream(&mut plaintext);
+            mac.update(&length);
+            mac.update(&plaintext);
+
+            self.data.extend(length);
+            self.data.extend(plaintext);
+            self.data.extend(&mac.finalize().into_bytes()[..MAC_LEN]);
+        }
+
+        fn blob(&mut self, mut data: Vec<u8>) {
+            let mut cipher = self.next_cipher();
+            let mut mac = Hmac::<Sha256>::new_from_slice(&self.mac_key).unwrap();
+            mac.update(&self.iv);
+            cipher.apply_keystream(&mut data);
+            mac.update(&data);
+
+            self.data.extend(data);
+            self.data.extend(&mac.finalize().into_bytes()[..MAC_LEN]);
+        }
+
+        fn statement(&mut self, sql: &str, parameters: Vec<SqlParameter>) {
+            self.frame(proto::BackupFrame {
+                statement: Some(proto::SqlStatement {
+                    statement: Some(sql.to_owned()),
+                    parameters,
+                }),
+                ..Default::default()
+            });
+        }
+    }
+
+    fn integer(value: u64) -> SqlParameter {
+        SqlParameter {
+            integer_parameter: Some(value),
+            ..Default::default()
+        }
+    }
+
+    fn string(value: &str) -> SqlParameter {
+        SqlParameter {
+            string_parameter: Some(value.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn null() -> SqlParameter {
+        SqlParameter {
+            null_parameter: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_columns() {
+        let (table, columns) = parse_create_table(
+            "CREATE TABLE \"message\" (_id INTEGER PRIMARY KEY AUTOINCREMENT, date_sent INTEGER \
+             NOT NULL, body TEXT DEFAULT 'a, b', type INTEGER, UNIQUE(_id, type))",
+        )
+        .unwrap();
+        assert_eq!(table, "message");
+        assert_eq!(columns, ["_id", "date_sent", "body", "type"]);
+    }
+
+    #[test]
+    fn read_backup() {
+        let own_aci = Aci::from(Uuid::from_u128(1));
+        let contact_aci = Aci::from(Uuid::from_u128(2));
+
+        let mut writer = BackupWriter::new();
+        writer.frame(proto::BackupFrame {
+            version: Some(proto::DatabaseVersion { version: Some(200) }),
+            ..Default::default()
+        });
+        writer.statement(
+            "CREATE TABLE message (_id INTEGER PRIMARY KEY, date_sent INTEGER, thread_id INTEGER, \
+             from_recipient_id INTEGER, type INTEGER, body TEXT)",
+            vec![],
+        );
+        writer.statement(
+            "INSERT INTO message VALUES (?,?,?,?,?,?)",
+            vec![
+                integer(1),
+                integer(1000),
+                integer(1),
+                integer(2),
+                integer(20),
+                string("hello"),
+            ],
+        );
+        writer.statement(
+            "INSERT INTO message VALUES (?,?,?,?,?,?)",
+            vec![
+                integer(2),
+                integer(2000),
+                integer(1),
+                integer(1),
+                integer(0x10000 | 23),
+                null(),
+            ],
+        );
+        writer.statement(
+            "CREATE TABLE recipient (_id INTEGER PRIMARY KEY, aci TEXT, group_id TEXT)",
+            vec![],
+        );
+        writer.statement(
+            "INSERT INTO recipient VALUES (?,?,?)",
+            vec![integer(1), string(&own_aci.service_id_string()), null()],
+        );
+        writer.statement(
+            "INSERT INTO recipient VALUES (?,?,?)",
+            vec![integer(2), string(&contact_aci.service_id_string()), null()],
+        );
+        writer.statement(
+            "CREATE TABLE thread (_id INTEGER PRIMARY KEY, recipient_id INTEGER)",
+            vec![],
+        );
+        writer.statement(
+            "INSERT INTO thread VALUES (?,?)",
+            vec![integer(1), integer(2)],
+        );
+        writer.frame(proto::BackupFrame {
+            key_value: Some(proto::KeyValue {
+                key: Some("account.aci".to_owned()),
+                string_value: Some(own_aci.service_id_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        writer.frame(proto::BackupFrame {
+            attachment: Some(proto::Attachment {
+                row_id: Some(1),
+                attachment_id: Some(2),
+                length: Some(5),
+            }),
+            ..Default::default()
+        });
+        writer.blob(b"image".to_vec());
+        writer.frame(proto::BackupFrame {
+            end: Some(true),
+            ..Default::default()
+        });
+
+        let frames: Vec<BackupFrame> = BackupReader::new(Cursor::new(&writer.data), PASSPHRASE)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert!(matches!(frames[0], BackupFrame::DatabaseVersion(200)));
+        assert!(frames.iter().any(|frame| matches!(
+            frame,
+            BackupFrame::Attachment { row_id: 1, attachment_id: 2, data } if data == b"image"
+        )));
+
+        let messages: Vec<_> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                BackupFrame::Message { thread, content } => Some((thread, content)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(messages.len(), 1);
+        let (thread, content) = messages[0];
+        assert_eq!(*thread, Thread::Contact(contact_aci.into()));
+        assert_eq!(content.metadata.sender, contact_aci.into());
+        assert_eq!(content.metadata.destination, own_aci.into());
+        assert_eq!(content.metadata.timestamp, 1000);
+        let ContentBody::DataMessage(message) = &content.body else {
+            panic!("not a data message");
+        };
+        assert_eq!(message.body(), "hello");
+
+        // the group update cannot be converted and is kept as a statement
+        assert!(matches!(frames.last(), Some(BackupFrame::Statement(_))));
+    }
+
+    #[test]
+    fn reject_wrong_passphrase() {
+        let mut writer = BackupWriter::new();
+        writer.frame(proto::BackupFrame {
+            end: Some(true),
+            ..Default::default()
+        });
+
+        let mut reader = BackupReader::new(Cursor::new(&writer.data), "000").unwrap();
+        assert!(matches!(reader.next(), Some(Err(BackupError::BadMac))));
+        assert!(reader.next().is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::time::Duration;
 
 use libsignal_service::prelude::MessageSenderError;
 use libsignal_service::websocket::registration::RegistrationSessionMetadataResponse;
@@ -10,8 +11,8 @@ use crate::store::StoreError;
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
 pub enum Error<S: std::error::Error> {
-    #[error("captcha from https://signalcaptchas.org/registration/generate.html required")]
-    CaptchaRequired,
+    #[error("registration failed: {0}")]
+    RegistrationError(#[from] RegistrationError),
     #[error("input/output error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("JSON error: {0}")]
@@ -52,18 +53,37 @@ pub enum Error<S: std::error::Error> {
     MessagePipeInterruptedError,
     #[error("failed to parse contact information: {0}")]
     ParseContactError(#[from] ParseContactError),
+    #[error("the profile of this account has no name, set one first")]
+    MissingProfileName,
+    #[error("failed to upload attachment: {0}")]
+    AttachmentUploadError(#[from] libsignal_service::sender::AttachmentUploadError),
+    #[error("view-once messages must have exactly one image or video attachment")]
+    InvalidViewOnceAttachments,
+    #[error("the URL of a link preview must be in the body of the message")]
+    InvalidLinkPreview,
     #[error("failed to decrypt attachment: {0}")]
     AttachmentCipherError(#[from] libsignal_service::attachment_cipher::AttachmentCipherError),
     #[error("unknown group")]
     UnknownGroup,
+    #[error("unknown poll")]
+    UnknownPoll,
     #[error("unknown recipient")]
     UnknownRecipient,
+    #[error("recipient {} is not registered with Signal", .0.service_id_string())]
+    UnregisteredRecipient(libsignal_service::protocol::ServiceId),
+    #[error(
+        "the identity key of {} is not trusted, verify and trust it before sending messages",
+        .service_id.service_id_string()
+    )]
+    UntrustedIdentity {
+        service_id: libsignal_service::protocol::ServiceId,
+        /// The identity key to trust (once verified) to send messages again
+        key: libsignal_service::protocol::IdentityKey,
+    },
     #[error("timeout: {0}")]
     Timeout(#[from] tokio::time::error::Elapsed),
     #[error("store error: {0}")]
     Store(S),
-    #[error("push challenge required (not implemented)")]
-    PushChallengeRequired,
     #[error("Not allowed to request verification code, reason unknown: {0:?}")]
     RequestingCodeForbidden(RegistrationSessionMetadataResponse),
     #[error("attachment sha256 checksum did not match")]
@@ -74,6 +94,8 @@ pub enum Error<S: std::error::Error> {
     ProfileCipherError(#[from] libsignal_service::profile_cipher::ProfileCipherError),
     #[error("An operation was requested that requires the registration to be primary, but it was only secondary")]
     NotPrimaryDevice,
+    #[error("the primary device cannot be unlinked, delete the account instead")]
+    NotSecondaryDevice,
     #[error("Failed to get initial messages after uploading pre-keys")]
     UpdatePreKeyFailure,
     #[error("invalid device ID (out of bounds)")]
@@ -84,11 +106,60 @@ pub enum Error<S: std::error::Error> {
     ZkGroupVerificationFailure,
     #[error("credential deserialization error")]
     CredentialDeserializationError,
+    #[error("message is too old to be edited")]
+    EditWindowExpired,
+    #[error("no message sent by this account found with this timestamp")]
+    UnknownOutgoingMessage,
+    #[error("invalid group invite link")]
+    InvalidGroupInviteLink,
+    #[error("the invite link of this group is disabled")]
+    GroupInviteLinkDisabled,
+    #[error(
+        "joining this group requires approval from an administrator, a request to join was sent"
+    )]
+    GroupJoinPendingApproval(libsignal_service::zkgroup::GroupMasterKeyBytes),
+    #[error("the last administrator cannot leave a group without other members to promote")]
+    NoGroupAdministratorSuccessor,
+    #[error("only administrators of the group can do this")]
+    NotGroupAdministrator,
+    #[error("only administrators can send messages in this group")]
+    GroupAnnouncementsOnly,
+    #[error("uploading group avatars is not supported yet")]
+    GroupAvatarUploadUnsupported,
+    #[error("invalid group change: {0}")]
+    InvalidGroupChange(#[from] crate::model::groups::GroupChangeError),
+    #[error("{0}")]
+    GroupValidation(#[from] crate::model::groups::GroupValidationError),
+    #[error("account backup version {0} is not supported, presage needs to be upgraded")]
+    UnsupportedAccountBackupVersion(u32),
+}
+
+/// Failures of the registration which the user can do something about
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RegistrationError {
+    /// A captcha must be solved before requesting a verification code
+    ///
+    /// The `token` identifies the registration session the solved captcha has to be submitted to.
+    #[error("captcha from https://signalcaptchas.org/registration/generate.html required")]
+    CaptchaRequired { token: String },
+    /// The push challenge sent to the push token of the device must be submitted before requesting
+    /// a verification code
+    ///
+    /// The `token` identifies the registration session the challenge has to be submitted to.
+    #[error("push challenge sent to the push token of the device required")]
+    PushChallengeRequired { token: String },
+    /// Too many attempts, the request can be retried after the given delay
+    #[error("rate limited by the server, retry after {}s", .retry_after.as_secs())]
+    RateLimited { retry_after: Duration },
 }
 
 impl<S: std::error::Error> From<MessageSenderError> for Error<S> {
     fn from(v: MessageSenderError) -> Self {
-        Self::MessageSenderError(Box::new(v))
+        match v {
+            MessageSenderError::NotFound { service_id } => Self::UnregisteredRecipient(service_id),
+            v => Self::MessageSenderError(Box::new(v)),
+        }
     }
 }
 
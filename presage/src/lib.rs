@@ -1,3 +1,5 @@
+mod attachments;
+pub mod backup;
 mod errors;
 pub mod manager;
 pub mod model;
@@ -8,7 +10,7 @@ pub use libsignal_service;
 /// Protobufs used in Signal protocol and service communication
 pub use libsignal_service::proto;
 
-pub use errors::Error;
+pub use errors::{Error, RegistrationError};
 pub use manager::Manager;
 
 const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "-rs-", env!("CARGO_PKG_VERSION"));
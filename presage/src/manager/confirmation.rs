@@ -18,6 +18,7 @@ use crate::manager::registered::RegistrationData;
 use crate::store::Store;
 use crate::{Error, Manager};
 
+use super::registration::rate_limited;
 use super::Registered;
 
 /// Manager state after a successful registration of new main device
@@ -77,7 +78,8 @@ impl<S: Store> Manager<S, Confirmation> {
 
         let session = identified_websocket
             .submit_verification_code(session_id, confirmation_code.as_ref())
-            .await?;
+            .await
+            .map_err(|error| rate_limited(error, None))?;
 
         trace!("verification code submitted");
 
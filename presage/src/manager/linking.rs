@@ -28,6 +28,9 @@ impl<S: Store> Manager<S, Linking> {
     /// Links this client as a secondary device from the device used to register the account (usually a phone).
     /// The URL to present to the user will be sent in the channel given as the argument.
     ///
+    /// The `device_name` is encrypted with the identity key of the account and shown in the list of
+    /// linked devices of the other devices.
+    ///
     /// ```no_run
     /// use futures::{channel::oneshot, future, StreamExt};
     /// use presage::libsignal_service::configuration::SignalServers;
@@ -60,10 +63,42 @@ impl<S: Store> Manager<S, Linking> {
     /// }
     /// ```
     pub async fn link_secondary_device(
-        mut store: S,
+        store: S,
         signal_servers: SignalServers,
         device_name: String,
         provisioning_link_channel: oneshot::Sender<Url>,
+    ) -> Result<Manager<S, Registered>, Error<S::Error>> {
+        let mut provisioning_link_channel = Some(provisioning_link_channel);
+        Self::link(store, signal_servers, device_name, false, move |url| {
+            provisioning_link_channel
+                .take()
+                .is_some_and(|channel| channel.send(url).is_ok())
+        })
+        .await
+    }
+
+    /// Like [Manager::link_secondary_device], except that a new URL is generated and sent in the
+    /// channel whenever the previous one expires before being scanned.
+    ///
+    /// Linking is given up once the receiving end of the channel is dropped.
+    pub async fn link_secondary_device_with_refresh(
+        store: S,
+        signal_servers: SignalServers,
+        device_name: String,
+        provisioning_links: mpsc::UnboundedSender<Url>,
+    ) -> Result<Manager<S, Registered>, Error<S::Error>> {
+        Self::link(store, signal_servers, device_name, true, move |url| {
+            provisioning_links.unbounded_send(url).is_ok()
+        })
+        .await
+    }
+
+    async fn link(
+        mut store: S,
+        signal_servers: SignalServers,
+        device_name: String,
+        refresh_expired_links: bool,
+        mut send_provisioning_link: impl FnMut(Url) -> bool,
     ) -> Result<Manager<S, Registered>, Error<S::Error>> {
         // clear the database: the moment we start the process, old API credentials are invalidated
         // and you won't be able to use this client anyways
@@ -77,42 +112,53 @@ impl<S: Store> Manager<S, Linking> {
         let mut signaling_key = [0u8; 52];
         rng.fill_bytes(&mut signaling_key);
 
-        let service_configuration: ServiceConfiguration = signal_servers.into();
-        let push_service = PushService::new(service_configuration, None, crate::USER_AGENT);
-
-        let (tx, mut rx) = mpsc::channel(1);
-
-        let (wait_for_qrcode_scan, registration_data) = future::join(
-            link_device(
-                &mut store.aci_protocol_store(),
-                &mut store.pni_protocol_store(),
-                &mut rng,
-                push_service,
-                &password,
-                &device_name,
-                tx,
-            ),
-            async move {
-                if let Some(SecondaryDeviceProvisioning::Url(url)) = rx.next().await {
-                    info!("generating qrcode from provisioning link: {}", &url);
-                    if provisioning_link_channel.send(url).is_err() {
+        let registration_data = loop {
+            let service_configuration: ServiceConfiguration = signal_servers.into();
+            let push_service = PushService::new(service_configuration, None, crate::USER_AGENT);
+
+            let (tx, mut rx) = mpsc::channel(1);
+
+            let (wait_for_qrcode_scan, registration_data) = future::join(
+                link_device(
+                    &mut store.aci_protocol_store(),
+                    &mut store.pni_protocol_store(),
+                    &mut rng,
+                    push_service,
+                    &password,
+                    &device_name,
+                    tx,
+                ),
+                async {
+                    if let Some(SecondaryDeviceProvisioning::Url(url)) = rx.next().await {
+                        info!("generating qrcode from provisioning link: {}", &url);
+                        if !send_provisioning_link(url) {
+                            return Err(Error::LinkingError);
+                        }
+                    } else {
                         return Err(Error::LinkingError);
                     }
-                } else {
-                    return Err(Error::LinkingError);
-                }
-                if let Some(SecondaryDeviceProvisioning::NewDeviceRegistration(data)) =
-                    rx.next().await
-                {
-                    Ok(data)
-                } else {
-                    Err(Error::NoProvisioningMessageReceived)
-                }
-            },
-        )
-        .await;
-
-        wait_for_qrcode_scan?;
+                    if let Some(SecondaryDeviceProvisioning::NewDeviceRegistration(data)) =
+                        rx.next().await
+                    {
+                        Ok(data)
+                    } else {
+                        Err(Error::NoProvisioningMessageReceived)
+                    }
+                },
+            )
+            .await;
+
+            // the provisioning socket was closed before the link was scanned
+            if refresh_expired_links
+                && matches!(registration_data, Err(Error::NoProvisioningMessageReceived))
+            {
+                info!("provisioning link expired, generating a new one");
+                continue;
+            }
+
+            wait_for_qrcode_scan?;
+            break registration_data;
+        };
 
         match registration_data {
             Ok(NewDeviceRegistration {
@@ -9,8 +9,12 @@ use std::{fmt, sync::Arc};
 
 pub use self::confirmation::Confirmation;
 pub use self::linking::Linking;
-pub use self::registered::{Registered, RegistrationData, RegistrationType};
-pub use self::registration::{Registration, RegistrationOptions};
+pub use self::registered::{
+    AccountBackup, ConnectionState, DataMessageBuilder, EnvelopeAction, EnvelopeFilter,
+    ReceiveOptions, Registered, RegistrationData, RegistrationType, RetryPolicy, SealedSenderMode,
+};
+pub use self::registration::{PushToken, Registration, RegistrationOptions};
+pub use libsignal_service::websocket::registration::VerificationTransport;
 
 /// Signal manager
 ///
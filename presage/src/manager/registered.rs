@@ -1,33 +1,49 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, OnceLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use futures::future::select;
-use futures::{future, pin_mut, AsyncReadExt, Stream, StreamExt};
+use futures::{future, pin_mut, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stream, StreamExt};
 use libsignal_service::prelude::MasterKey;
 use libsignal_service::websocket::account::{
     AccountAttributes, DeviceCapabilities, DeviceInfo, WhoAmIResponse,
 };
 use libsignal_service::{
+    account_manager::encrypt_device_name,
     attachment_cipher::decrypt_in_place,
     cipher,
     configuration::{ServiceConfiguration, SignalServers, SignalingKey},
     content::{Content, ContentBody, DataMessageFlags, Metadata},
-    groups_v2::{decrypt_group, GroupsManager, InMemoryCredentialsCache},
+    groups_v2::{decrypt_group, GroupOperations, GroupsManager, InMemoryCredentialsCache, Timer},
     messagepipe::{Incoming, MessagePipe, ServiceCredentials},
-    prelude::{phonenumber::PhoneNumber, DeviceId, MessageSenderError, ProtobufMessage, Uuid},
+    pre_keys::{PreKeyState, PreKeysStore},
+    prelude::{
+        phonenumber::{self, PhoneNumber},
+        DeviceId, MessageSenderError, ProtobufMessage, Uuid,
+    },
     profile_cipher::ProfileCipher,
+    profile_name::ProfileName,
     proto::{
-        data_message::{Delete, PollCreate, PollTerminate, PollVote},
+        data_message::{self, quote, Delete, PollCreate, PollTerminate, PollVote, Quote, Reaction},
+        envelope, group_change, receipt_message,
         sync_message::{self, sticker_pack_operation, StickerPackOperation},
-        AttachmentPointer, DataMessage, EditMessage, GroupContextV2, NullMessage, SyncMessage,
-        Verified,
+        typing_message, AttachmentPointer, BodyRange, DataMessage, EditMessage, Envelope,
+        GroupContextV2, NullMessage, Preview, ReceiptMessage, SyncMessage, TypingMessage, Verified,
+    },
+    protocol::{
+        kem, sealed_sender_decrypt_to_usmc, Aci, CiphertextMessageType, Fingerprint, IdentityKey,
+        IdentityKeyPair, IdentityKeyStore, KeyPair, KyberPreKeyId, KyberPreKeyRecord, Pni,
+        PreKeySignalMessage, ProtocolAddress, SenderCertificate, ServiceId, ServiceIdKind,
+        SignalProtocolError, SignedPreKeyId, SignedPreKeyRecord, Timestamp,
     },
-    protocol::{Aci, IdentityKeyStore, SenderCertificate, ServiceId, ServiceIdKind},
     provisioning::ProvisioningError,
-    push_service::{PushService, ServiceError, ServiceIds, DEFAULT_DEVICE_ID},
+    push_service::{AvatarWrite, PushService, ServiceError, ServiceIds, DEFAULT_DEVICE_ID},
     receiver::MessageReceiver,
-    sender::{AttachmentSpec, AttachmentUploadError},
+    sender::{AttachmentSpec, AttachmentUploadError, SentMessage},
     sticker_cipher::derive_key,
     unidentified_access::UnidentifiedAccess,
     utils::serde_signaling_key,
@@ -37,26 +53,185 @@ use libsignal_service::{
     zkgroup::{
         groups::{GroupMasterKey, GroupSecretParams},
         profiles::ProfileKey,
+        GroupMasterKeyBytes,
     },
     AccountManager, Profile, ServiceIdExt,
 };
 use rand::rng;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, watch, Mutex, OwnedMutexGuard};
 use tracing::{debug, error, info, trace, warn};
 use url::Url;
 
-use crate::model::contacts::Contact;
-use crate::serde::serde_profile_key;
-use crate::store::{ContentsStore, Sticker, StickerPack, StickerPackManifest, Store, Thread};
+use crate::attachments::AttachmentDecryptor;
+use crate::model::contacts::{Contact, ProfileCapabilities, ProfileDetails};
+use crate::model::groups::{
+    AccessRequired, GroupChangeSpec, GroupField, GroupId, GroupInviteLink, GroupMemberRole,
+};
+use crate::model::identity::{IdentityChange, IdentityState};
+use crate::model::pre_keys::PreKeyCounts;
+use crate::model::settings::AccountSettings;
+use crate::serde::{serde_identity_key_pair, serde_optional_master_key, serde_profile_key};
+use crate::store::{
+    ContentExt, ContentsStore, IdentityTrustStore, MessageRange, StateStore, Sticker, StickerPack,
+    StickerPackManifest, Store, Thread, ThreadMetadata,
+};
 use crate::{model::groups::Group, AvatarBytes, Error, Manager};
 
 pub use crate::model::messages::Received;
+use crate::model::messages::{
+    CallEvent, EnvelopeMetadata, IncomingEnvelope, LinkPreview, Mention, MessageStatus,
+    PollResults, Receipt, TextStyleRange, TypingAction, TypingIndicator,
+};
 
 type ServiceCipher<S> = cipher::ServiceCipher<S>;
 type MessageSender<S> = libsignal_service::prelude::MessageSender<S>;
 
+/// How long envelopes are remembered to drop duplicates by default, see
+/// [Manager::set_dedup_window]
+const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How many times a message is sent again after the recipient's devices changed (see
+/// [Manager::send_message])
+const MAX_DEVICE_MISMATCH_RETRIES: u32 = 3;
+
+/// How long after sending a message it can still be edited
+const EDIT_MESSAGE_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Version of the safety numbers computed from ACIs, and number of hash iterations, as in the
+/// Signal apps
+const SAFETY_NUMBER_VERSION: u32 = 2;
+const SAFETY_NUMBER_ITERATIONS: u32 = 5200;
+
+/// How to retry failed attachment downloads (see [Manager::download_attachment_with_retry]) or
+/// reconnections (see [ReceiveOptions::reconnect]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// How long to wait before the first retry, doubled after each attempt
+    pub initial_backoff: Duration,
+    /// Upper bound of the time to wait between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What to do with a received envelope before decrypting it, see [EnvelopeFilter].
+///
+/// Envelopes can't be left on the server for later: the message pipe of `libsignal-service`
+/// acknowledges each of them as soon as it is received, before the filter is called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvelopeAction {
+    /// Decrypt, store and yield the envelope as usual
+    Keep,
+    /// Skip the envelope without decrypting it
+    ///
+    /// As it was already acknowledged to the server, it is not delivered again.
+    Drop,
+}
+
+/// Callback inspecting each received envelope before it is decrypted, see
+/// [ReceiveOptions::envelope_filter].
+///
+/// As it is called from the receiving loop, the callback should return quickly.
+#[derive(Clone)]
+pub struct EnvelopeFilter(Arc<dyn Fn(&IncomingEnvelope) -> EnvelopeAction + Send + Sync>);
+
+impl EnvelopeFilter {
+    pub fn new(
+        filter: impl Fn(&IncomingEnvelope) -> EnvelopeAction + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(filter))
+    }
+}
+
+impl fmt::Debug for EnvelopeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvelopeFilter").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for EnvelopeFilter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for EnvelopeFilter {}
+
+/// Options of the receiving loop, see [Manager::receive_messages_with_options].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceiveOptions {
+    /// How long the connection can stay idle before checking that it is still alive
+    ///
+    /// The connection is considered lost when the check does not succeed within another interval.
+    pub keepalive_interval: Duration,
+    /// How to reconnect when the connection is lost, or [None] to end the stream instead
+    ///
+    /// Each successful reconnection yields a [Received::Reconnected].
+    pub reconnect: Option<RetryPolicy>,
+    /// Whether to skip (neither store nor yield) messages from blocked contacts or groups
+    pub skip_blocked: bool,
+    /// Called with the metadata of each envelope before decrypting it, to skip some envelopes
+    /// without paying the cost of decryption, e.g. to drop messages from a denylist of senders.
+    ///
+    /// Senders using sealed sender are only known after decryption.
+    pub envelope_filter: Option<EnvelopeFilter>,
+}
+
+impl Default for ReceiveOptions {
+    fn default() -> Self {
+        Self {
+            keepalive_interval: Duration::from_secs(30),
+            reconnect: None,
+            skip_blocked: false,
+            envelope_filter: None,
+        }
+    }
+}
+
+/// State of the connection used to receive messages, see [Manager::connection_events].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The connection is being established
+    Connecting,
+    /// Messages are being received
+    Connected,
+    /// Messages are not being received (anymore)
+    Disconnected { reason: String },
+    /// The connection was lost and is being re-established, see [ReceiveOptions::reconnect]
+    Reconnecting { attempt: u32 },
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::Disconnected {
+            reason: "not receiving messages".to_owned(),
+        }
+    }
+}
+
+/// Whether to send messages with sealed sender (unidentified delivery), see
+/// [Manager::set_sealed_sender].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SealedSenderMode {
+    /// Hide the sender from the server when the profile key of the recipient is known
+    #[default]
+    Enabled,
+    /// Always send messages identified
+    Disabled,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RegistrationType {
     Primary,
@@ -70,6 +245,14 @@ pub struct Registered {
     pub(crate) identified_websocket: Arc<Mutex<Option<SignalWebSocket<websocket::Identified>>>>,
     pub(crate) unidentified_websocket: Arc<Mutex<Option<SignalWebSocket<websocket::Unidentified>>>>,
     pub(crate) unidentified_sender_certificate: Arc<Mutex<Option<SenderCertificate>>>,
+    pub(crate) connection_state: watch::Sender<ConnectionState>,
+    pub(crate) sealed_sender: AtomicBool,
+    /// How long (in milliseconds) the server GUIDs of processed envelopes are remembered
+    pub(crate) dedup_window: AtomicU64,
+    pub(crate) persist_failed_sends: AtomicBool,
+    pub(crate) identity_changes: broadcast::Sender<IdentityChange>,
+    /// Per-thread locks serializing outgoing messages, shared by all clones of the manager
+    pub(crate) send_locks: std::sync::Mutex<HashMap<Thread, Arc<Mutex<()>>>>,
 
     pub(crate) data: RegistrationData,
 }
@@ -88,10 +271,36 @@ impl Registered {
             identified_websocket: Default::default(),
             unidentified_websocket: Default::default(),
             unidentified_sender_certificate: Default::default(),
+            connection_state: watch::channel(Default::default()).0,
+            sealed_sender: AtomicBool::new(true),
+            dedup_window: AtomicU64::new(DEFAULT_DEDUP_WINDOW.as_millis() as u64),
+            persist_failed_sends: AtomicBool::new(false),
+            identity_changes: broadcast::channel(16).0,
+            send_locks: Default::default(),
             data,
         }
     }
 
+    /// Waits for the previous sends to `thread` to complete and returns a guard which holds back
+    /// the following ones until it is dropped.
+    ///
+    /// Waiters are served in the order they started waiting, so that messages to the same thread
+    /// go out in call order, while sends to different threads do not wait on each other.
+    async fn lock_thread_for_sending(&self, thread: &Thread) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut send_locks = self.send_locks.lock().expect("poisoned send locks");
+            // forget the locks nobody is holding or waiting for anymore
+            send_locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            send_locks.entry(thread.clone()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+
+    fn set_connection_state(&self, state: ConnectionState) {
+        debug!(?state, "connection state changed");
+        self.connection_state.send_replace(state);
+    }
+
     fn service_configuration(&self) -> ServiceConfiguration {
         self.data.signal_servers.into()
     }
@@ -158,6 +367,37 @@ impl RegistrationData {
     }
 }
 
+/// Current version of the [AccountBackup] format
+const ACCOUNT_BACKUP_VERSION: u32 = 1;
+
+/// Backup of an account, to restore it in another store without registering or linking again
+///
+/// Created with [Manager::export_account] and restored with [Manager::import_account]. Only the
+/// account itself is backed up: messages, contacts and groups are not.
+///
+/// **The backup contains all the secrets of the account** (credentials and identity keys):
+/// anybody getting hold of it can impersonate the account, so it must be stored securely, e.g.
+/// encrypted.
+#[derive(Serialize, Deserialize)]
+pub struct AccountBackup {
+    /// Version of the format of the backup
+    pub version: u32,
+    registration_data: RegistrationData,
+    #[serde(with = "serde_identity_key_pair")]
+    aci_identity_key_pair: IdentityKeyPair,
+    #[serde(with = "serde_identity_key_pair")]
+    pni_identity_key_pair: IdentityKeyPair,
+    #[serde(default, with = "serde_optional_master_key")]
+    master_key: Option<MasterKey>,
+}
+
+impl AccountBackup {
+    /// Registration data of the backed up account
+    pub fn registration_data(&self) -> &RegistrationData {
+        &self.registration_data
+    }
+}
+
 impl<S: Store> Manager<S, Registered> {
     /// Loads a previously registered account from the implemented [Store].
     ///
@@ -184,6 +424,56 @@ impl<S: Store> Manager<S, Registered> {
         })
     }
 
+    /// Restores an account exported with [Manager::export_account] in an empty store.
+    ///
+    /// Pre-keys are not part of the backup: new ones are uploaded when starting to receive
+    /// messages. Sessions are not either, and are established again with contacts as needed.
+    pub async fn import_account(
+        mut store: S,
+        backup: AccountBackup,
+    ) -> Result<Self, Error<S::Error>> {
+        if backup.version > ACCOUNT_BACKUP_VERSION {
+            return Err(Error::UnsupportedAccountBackupVersion(backup.version));
+        }
+        if store.is_registered().await {
+            return Err(Error::AlreadyRegisteredError);
+        }
+
+        store
+            .set_aci_identity_key_pair(backup.aci_identity_key_pair)
+            .await?;
+        store
+            .set_pni_identity_key_pair(backup.pni_identity_key_pair)
+            .await?;
+        store.store_master_key(backup.master_key.as_ref()).await?;
+        store
+            .save_registration_data(&backup.registration_data)
+            .await?;
+
+        Self::load_registered(store).await
+    }
+
+    /// Exports the account, to restore it later with [Manager::import_account].
+    ///
+    /// See [AccountBackup] about the secrets it contains.
+    pub async fn export_account(&self) -> Result<AccountBackup, Error<S::Error>> {
+        Ok(AccountBackup {
+            version: ACCOUNT_BACKUP_VERSION,
+            registration_data: self.state.data.clone(),
+            aci_identity_key_pair: self
+                .store
+                .aci_protocol_store()
+                .get_identity_key_pair()
+                .await?,
+            pni_identity_key_pair: self
+                .store
+                .pni_protocol_store()
+                .get_identity_key_pair()
+                .await?,
+            master_key: self.store.fetch_master_key().await?,
+        })
+    }
+
     /// Returns a handle to the [Store] implementation.
     pub fn store(&self) -> &S {
         &self.store
@@ -292,6 +582,272 @@ impl<S: Store> Manager<S, Registered> {
         Ok(())
     }
 
+    /// Requests the privacy settings of the account from the primary device.
+    ///
+    /// They are saved when received while receiving messages, see [Self::account_settings].
+    pub async fn request_configuration_sync(&mut self) -> Result<(), Error<S::Error>> {
+        trace!("requesting configuration sync");
+        let sync_message = SyncMessage {
+            request: Some(sync_message::Request {
+                r#type: Some(sync_message::request::Type::Configuration.into()),
+            }),
+            ..SyncMessage::with_padding(&mut rand::rng())
+        };
+        self.send_message(
+            self.state.data.service_ids.aci(),
+            sync_message,
+            timestamp_now(),
+        )
+        .await
+    }
+
+    /// Returns the privacy settings of the account, as synchronized from the primary device.
+    ///
+    /// The defaults of official clients are returned until they are received, see
+    /// [Self::request_configuration_sync].
+    pub async fn account_settings(&self) -> Result<AccountSettings, Error<S::Error>> {
+        Ok(self.store.account_settings().await?.unwrap_or_default())
+    }
+
+    /// Requests a contacts synchronization from the primary device, and waits until the contacts
+    /// have been received and saved in the store.
+    ///
+    /// Messages received in the meantime are processed and stored as with
+    /// [Self::receive_messages]. Fails with [Error::Timeout] if the contacts did not arrive in time.
+    ///
+    /// Returns the number of contacts in the store.
+    pub async fn sync_contacts(&mut self, timeout: Duration) -> Result<usize, Error<S::Error>> {
+        self.request_contacts().await?;
+
+        let messages = self.receive_messages().await?;
+        tokio::time::timeout(timeout, async move {
+            pin_mut!(messages);
+            while let Some(received) = messages.next().await {
+                if let Received::Contacts = received {
+                    return Ok(());
+                }
+            }
+            Err(Error::MessagePipeInterruptedError)
+        })
+        .await??;
+
+        Ok(self.store.contacts().await?.count())
+    }
+
+    /// Returns all the contacts in the store, e.g. after [Self::sync_contacts].
+    ///
+    /// Contacts come from the primary device and are identified by their ACI, profile names
+    /// can be retrieved with [Self::retrieve_profile_by_uuid].
+    pub async fn contacts(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Contact, Error<S::Error>>>, Error<S::Error>> {
+        Ok(self.store.contacts().await?.map(|contact| Ok(contact?)))
+    }
+
+    /// Returns the stored contact with this service ID (contacts being stored by ACI), if any
+    pub async fn contact_by_id(&self, id: &ServiceId) -> Result<Option<Contact>, Error<S::Error>> {
+        Ok(self.store.contact_by_id(&id.raw_uuid()).await?)
+    }
+
+    /// Returns the ACI of the stored contact with this phone number (in E.164 format), if any
+    ///
+    /// This does not query the server: the mapping is known from contact synchronization and
+    /// from the transcripts of messages sent by our other devices.
+    pub async fn aci_for_e164(&self, e164: &str) -> Result<Option<Aci>, Error<S::Error>> {
+        let phone_number = phonenumber::parse(None, e164)?;
+        Ok(self
+            .store
+            .contact_by_phone_number(&phone_number)
+            .await?
+            .map(|contact| contact.uuid.into()))
+    }
+
+    /// Returns the phone number (in E.164 format) of the stored contact with this ACI, if known
+    pub async fn e164_for_aci(&self, aci: Aci) -> Result<Option<String>, Error<S::Error>> {
+        Ok(self
+            .store
+            .contact_by_id(&aci.into())
+            .await?
+            .and_then(|contact| contact.phone_number)
+            .map(|phone_number| phone_number.to_string()))
+    }
+
+    /// Returns at most `limit` stored messages of a [Thread] relative to a timestamp, e.g. to
+    /// load them page by page, see [ContentsStore::messages_page].
+    pub async fn messages(
+        &self,
+        thread: &Thread,
+        range: MessageRange,
+        limit: usize,
+    ) -> Result<Vec<Content>, Error<S::Error>> {
+        Ok(self.store.messages_page(thread, range, limit).await?)
+    }
+
+    /// Searches for stored messages containing all the words of `query`, most recent first.
+    ///
+    /// The search is restricted to a [Thread] if provided.
+    pub async fn search_messages(
+        &self,
+        query: &str,
+        thread: Option<&Thread>,
+        limit: usize,
+    ) -> Result<Vec<Content>, Error<S::Error>> {
+        Ok(self.store.search_messages(query, thread, limit).await?)
+    }
+
+    /// Returns the metadata of all threads with messages, most recently active first.
+    pub async fn threads(&self) -> Result<impl Iterator<Item = ThreadMetadata>, Error<S::Error>> {
+        Ok(self.store.threads().await?.into_iter())
+    }
+
+    /// Number of received messages in a [Thread] which have not been read yet
+    pub async fn unread_count(&self, thread: &Thread) -> Result<u64, Error<S::Error>> {
+        Ok(self.store.unread_count(thread).await?)
+    }
+
+    /// Number of received messages in all threads which have not been read yet
+    ///
+    /// Unless `include_muted` is set, the messages of muted threads are not counted.
+    pub async fn total_unread_count(&self, include_muted: bool) -> Result<u64, Error<S::Error>> {
+        let now = timestamp_now();
+        Ok(self
+            .store
+            .threads()
+            .await?
+            .iter()
+            .filter(|thread| include_muted || !thread.is_muted_at(now))
+            .map(|thread| thread.unread_count)
+            .sum())
+    }
+
+    /// Archives or unarchives a [Thread].
+    ///
+    /// Official clients synchronize this through the storage service, which is not supported, so
+    /// the state is only kept in the store of this device.
+    pub async fn set_thread_archived(
+        &mut self,
+        thread: &Thread,
+        archived: bool,
+    ) -> Result<(), Error<S::Error>> {
+        Ok(self.store.set_archived(thread, archived).await?)
+    }
+
+    /// Whether a [Thread] is archived, see [Self::set_thread_archived].
+    pub async fn is_thread_archived(&self, thread: &Thread) -> Result<bool, Error<S::Error>> {
+        Ok(self.store.is_archived(thread).await?)
+    }
+
+    /// Mutes a [Thread] until the given time, or unmutes it with `None`.
+    ///
+    /// Like [Self::set_thread_archived], this is not synchronized with the other devices.
+    pub async fn set_thread_muted(
+        &mut self,
+        thread: &Thread,
+        until: Option<SystemTime>,
+    ) -> Result<(), Error<S::Error>> {
+        let muted_until = until.map(|until| {
+            until
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_millis() as u64)
+        });
+        Ok(self.store.set_muted_until(thread, muted_until).await?)
+    }
+
+    /// Returns until when a [Thread] is muted, or `None` if it is not muted (anymore).
+    pub async fn thread_muted_until(
+        &self,
+        thread: &Thread,
+    ) -> Result<Option<SystemTime>, Error<S::Error>> {
+        Ok(self
+            .store
+            .muted_until(thread)
+            .await?
+            .filter(|&until| until > timestamp_now())
+            .map(|until| UNIX_EPOCH + Duration::from_millis(until)))
+    }
+
+    /// Marks the received messages of a [Thread] sent up to `up_to_timestamp` (included) as read.
+    ///
+    /// If `sync` is set, the other devices of this account are notified that these messages were
+    /// read. Returns the number of messages which were not read yet.
+    pub async fn mark_thread_read(
+        &mut self,
+        thread: &Thread,
+        up_to_timestamp: u64,
+        sync: bool,
+    ) -> Result<usize, Error<S::Error>> {
+        let read = self.store.mark_read(thread, up_to_timestamp).await?;
+
+        if sync && !read.is_empty() {
+            let sync_message = SyncMessage {
+                read: read
+                    .iter()
+                    .map(|(sender, timestamp)| sync_message::Read {
+                        sender_aci: Some(sender.raw_uuid().to_string()),
+                        timestamp: Some(*timestamp),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..SyncMessage::with_padding(&mut rand::rng())
+            };
+            self.send_message(
+                self.state.data.service_ids.aci(),
+                sync_message,
+                timestamp_now(),
+            )
+            .await?;
+        }
+
+        Ok(read.len())
+    }
+
+    /// Blocks a contact, and synchronizes the list of blocked contacts and groups with the other
+    /// devices.
+    ///
+    /// Messages from blocked contacts can be skipped with [ReceiveOptions::skip_blocked].
+    pub async fn block_contact(&mut self, id: ServiceId) -> Result<(), Error<S::Error>> {
+        self.set_blocked(Thread::Contact(id.raw_uuid()), true).await
+    }
+
+    /// Unblocks a contact, see [Self::block_contact].
+    pub async fn unblock_contact(&mut self, id: ServiceId) -> Result<(), Error<S::Error>> {
+        self.set_blocked(Thread::Contact(id.raw_uuid()), false)
+            .await
+    }
+
+    /// Blocks a group, see [Self::block_contact].
+    pub async fn block_group(
+        &mut self,
+        master_key_bytes: GroupMasterKeyBytes,
+    ) -> Result<(), Error<S::Error>> {
+        self.set_blocked(Thread::Group(master_key_bytes), true)
+            .await
+    }
+
+    /// Unblocks a group, see [Self::block_contact].
+    pub async fn unblock_group(
+        &mut self,
+        master_key_bytes: GroupMasterKeyBytes,
+    ) -> Result<(), Error<S::Error>> {
+        self.set_blocked(Thread::Group(master_key_bytes), false)
+            .await
+    }
+
+    async fn set_blocked(&mut self, thread: Thread, blocked: bool) -> Result<(), Error<S::Error>> {
+        self.store.set_blocked(&thread, blocked).await?;
+
+        let sync_message = SyncMessage {
+            blocked: Some(blocked_sync_message(&self.store).await?),
+            ..SyncMessage::with_padding(&mut rand::rng())
+        };
+        self.send_message(
+            self.state.data.service_ids.aci(),
+            sync_message,
+            timestamp_now(),
+        )
+        .await
+    }
+
     async fn sender_certificate(&self) -> Result<SenderCertificate, Error<S::Error>> {
         let needs_renewal = |sender_certificate: Option<&SenderCertificate>| -> bool {
             if sender_certificate.is_none() {
@@ -357,59 +913,454 @@ impl<S: Store> Manager<S, Registered> {
         Ok(())
     }
 
-    /// Fetches basic information on the registered device.
-    pub async fn whoami(&self) -> Result<WhoAmIResponse, Error<S::Error>> {
-        Ok(self.identified_websocket(false).await?.whoami().await?)
+    /// Returns the number of pre-keys of the account identity (ACI) that new contacts can use to
+    /// start a session.
+    ///
+    /// One-time pre-keys are consumed by each new session and counted by the server, the signed
+    /// pre-keys are counted in the local store.
+    pub async fn prekey_count(&self) -> Result<PreKeyCounts, Error<S::Error>> {
+        let status = self
+            .identified_websocket(false)
+            .await?
+            .get_pre_key_status(ServiceIdKind::Aci)
+            .await?;
+        let signed = self
+            .store
+            .aci_protocol_store()
+            .signed_pre_keys_count()
+            .await?;
+        Ok(PreKeyCounts {
+            one_time: status.count,
+            kyber: status.pq_count,
+            signed: signed as u32,
+        })
     }
 
-    pub fn device_id(&self) -> DeviceId {
-        self.state.device_id()
+    /// Generates and uploads a new batch of pre-keys, for both the ACI and the PNI, if the server
+    /// runs low on them.
+    ///
+    /// This is also done each time messages start being received, see [Self::receive_messages].
+    pub async fn replenish_prekeys(&mut self) -> Result<(), Error<S::Error>> {
+        let mut account_manager = AccountManager::new(
+            self.identified_push_service(),
+            self.identified_websocket(false).await?,
+            None,
+        );
+        register_pre_keys(&self.store, &mut account_manager).await
     }
 
-    /// Fetches the profile (name, about, status emoji) of the registered user.
-    pub async fn retrieve_profile(&mut self) -> Result<Profile, Error<S::Error>> {
-        self.retrieve_profile_by_uuid(self.state.data.service_ids.aci, self.state.data.profile_key)
-            .await
+    /// Rotates the signed pre-key and the last resort Kyber pre-key, of both the ACI and the PNI.
+    ///
+    /// While receiving messages, this is done automatically every 48 hours: it is checked when the
+    /// stream starts and whenever the connection is idle. Previous keys are kept in the store, so
+    /// that sessions started with them can still be established.
+    pub async fn rotate_signed_prekey(&mut self) -> Result<(), Error<S::Error>> {
+        let mut websocket = self.identified_websocket(false).await?;
+        rotate_pre_keys(&self.store, &mut websocket).await
     }
 
-    /// Fetches the profile of the provided user by UUID and profile key.
-    pub async fn retrieve_profile_by_uuid(
-        &mut self,
-        aci: impl Into<Aci>,
-        profile_key: ProfileKey,
-    ) -> Result<Profile, Error<S::Error>> {
-        let aci = aci.into();
-
-        // Check if profile is cached.
-        // TODO: Create a migration in the store removing all profiles.
-        // TODO: Is there some way to know if this is outdated?
-        if let Some(profile) = self
-            .store
-            .profile(aci.into(), profile_key)
-            .await
-            .ok()
-            .flatten()
-        {
-            return Ok(profile);
-        }
+    /// Enables or disables sealed sender (unidentified delivery) for the messages sent from now on.
+    ///
+    /// When enabled (the default), messages to contacts whose profile key is known are sent
+    /// without revealing the sender to the server, and identified if the recipient refuses it.
+    pub fn set_sealed_sender(&self, mode: SealedSenderMode) {
+        self.state
+            .sealed_sender
+            .store(mode == SealedSenderMode::Enabled, Ordering::Relaxed);
+    }
 
-        let mut account_manager = AccountManager::new(
-            self.identified_push_service(),
-            self.identified_websocket(false).await?,
-            Some(profile_key),
-        );
+    /// Sets how long envelopes are remembered to drop the ones the server delivers again, e.g.
+    /// after reconnecting. Defaults to 7 days, [Duration::ZERO] disables deduplication.
+    ///
+    /// Envelopes are identified by the GUID given by the server, which is stored with the
+    /// contents (see [ContentsStore::mark_envelope_processed]).
+    pub fn set_dedup_window(&self, window: Duration) {
+        self.state
+            .dedup_window
+            .store(window.as_millis() as u64, Ordering::Relaxed);
+    }
 
-        let profile = account_manager.retrieve_profile(aci).await?;
+    /// Enables or disables keeping the messages which could not be sent because the server could
+    /// not be reached (e.g. while offline). Disabled by default.
+    ///
+    /// Kept messages are listed by [Self::pending_sends], and sent with [Self::retry_pending_sends]
+    /// once the connection is back. Only data messages (and edits) are kept: receipts, typing
+    /// indicators and sync messages are not worth sending late.
+    pub fn set_persist_failed_sends(&self, enabled: bool) {
+        self.state
+            .persist_failed_sends
+            .store(enabled, Ordering::Relaxed);
+    }
 
-        let _ = self
+    /// How far the message we sent at `sent_timestamp` in a [Thread] went: whether it is still
+    /// pending, was sent, or was delivered to or read by a recipient, according to the receipts
+    /// received so far. In groups, the status is the furthest one reached by any member.
+    ///
+    /// Returns [None] if the message is not in the store.
+    pub async fn message_status(
+        &self,
+        thread: &Thread,
+        sent_timestamp: u64,
+    ) -> Result<Option<MessageStatus>, Error<S::Error>> {
+        let pending = self
             .store
-            .save_profile(aci.into(), profile_key, profile.clone())
-            .await;
-        Ok(profile)
+            .pending_sends()
+            .await?
+            .iter()
+            .any(|(pending_thread, content)| {
+                pending_thread == thread && content.timestamp() == sent_timestamp
+            });
+        if pending {
+            return Ok(Some(MessageStatus::Pending));
+        }
+        if let Some(status) = self.store.message_status(thread, sent_timestamp).await? {
+            return Ok(Some(status));
+        }
+        let own_aci: ServiceId = self.state.data.service_ids.aci().into();
+        Ok(self
+            .store
+            .message(thread, sent_timestamp)
+            .await?
+            .filter(|message| message.metadata.sender == own_aci)
+            .map(|_| MessageStatus::Sent))
     }
 
-    pub async fn retrieve_group_avatar(
-        &mut self,
+    /// The messages which could not be sent yet with their thread, oldest first, e.g. to show
+    /// them as "sending" (see [Self::set_persist_failed_sends]).
+    pub async fn pending_sends(&self) -> Result<Vec<(Thread, Content)>, Error<S::Error>> {
+        Ok(self.store.pending_sends().await?)
+    }
+
+    /// Sends the pending messages again, oldest first (see [Self::set_persist_failed_sends]).
+    ///
+    /// Messages are forgotten once sent, or when sending them fails for good (e.g. the recipient
+    /// is not registered anymore). Sending stops at the first message which still can't reach the
+    /// server, and the error is returned: the remaining messages are kept for the next try.
+    ///
+    /// Group messages are only kept when they reached none of the members, and are sent again to
+    /// all of them. If a send failed after the message reached some members (e.g. while saving it),
+    /// these members receive it twice, with the same timestamp.
+    ///
+    /// Returns the number of messages which were sent.
+    pub async fn retry_pending_sends(&mut self) -> Result<usize, Error<S::Error>> {
+        let mut sent = 0;
+        for (thread, Content { metadata, body }) in self.store.pending_sends().await? {
+            let timestamp = metadata.timestamp;
+            let result = match &thread {
+                Thread::Contact(_) => {
+                    self.send_message(metadata.destination, body, timestamp)
+                        .await
+                }
+                Thread::Group(master_key_bytes) => {
+                    self.send_message_to_group(master_key_bytes, body, timestamp)
+                        .await
+                }
+            };
+            match result {
+                Ok(()) => sent += 1,
+                Err(error) if is_transient_send_error(&error) => return Err(error),
+                Err(error) => {
+                    warn!(%thread, timestamp, %error, "dropping pending message which can't be sent");
+                }
+            }
+            self.store.remove_pending_send(&thread, timestamp).await?;
+        }
+        Ok(sent)
+    }
+
+    /// A copy of an outgoing message, to keep it if sending it fails, when enabled
+    fn pending_send_copy(&self, content_body: &ContentBody) -> Option<ContentBody> {
+        let persist = self.state.persist_failed_sends.load(Ordering::Relaxed)
+            && matches!(
+                content_body,
+                ContentBody::DataMessage(_) | ContentBody::EditMessage(_)
+            );
+        persist.then(|| content_body.clone())
+    }
+
+    /// Keeps a message which could not be sent because the server could not be reached, to send
+    /// it again with [Self::retry_pending_sends]
+    async fn keep_pending_send(
+        &mut self,
+        thread: &Thread,
+        destination: ServiceId,
+        content_body: ContentBody,
+        timestamp: u64,
+        error: &Error<S::Error>,
+    ) {
+        if !is_transient_send_error(error) {
+            return;
+        }
+        let content = Content {
+            metadata: Metadata {
+                sender: self.state.data.service_ids.aci().into(),
+                destination,
+                sender_device: self.state.device_id(),
+                server_guid: None,
+                timestamp,
+                needs_receipt: false,
+                unidentified_sender: false,
+                was_plaintext: false,
+            },
+            body: content_body,
+        };
+        match self.store.save_pending_send(thread, content).await {
+            Ok(()) => debug!(%thread, timestamp, "message kept to be sent later"),
+            Err(error) => error!(%error, %thread, "failed to save pending message"),
+        }
+    }
+
+    /// Returns the unidentified access to send sealed sender messages to the recipient, if enabled
+    /// and possible.
+    async fn unidentified_access(
+        &self,
+        recipient: &ServiceId,
+    ) -> Result<Option<UnidentifiedAccess>, Error<S::Error>> {
+        // sealed sender is only possible with ACIs: a PNI has no profile key to derive the
+        // access key from
+        if !self.state.sealed_sender.load(Ordering::Relaxed)
+            || !matches!(recipient, ServiceId::Aci(_))
+        {
+            return Ok(None);
+        }
+        let Some(profile_key) = self.store.profile_key(recipient).await? else {
+            return Ok(None);
+        };
+        Ok(Some(UnidentifiedAccess {
+            key: profile_key.derive_access_key().to_vec(),
+            certificate: self.sender_certificate().await?,
+        }))
+    }
+
+    /// The Note to Self thread, i.e. the conversation with our own account
+    ///
+    /// Messages sent to it with [Self::send_message] (or to our own ACI) are synced to our other
+    /// devices, and show up in their Note to Self.
+    pub fn note_to_self_thread(&self) -> Thread {
+        Thread::Contact(Uuid::from(self.aci()))
+    }
+
+    /// Fetches basic information on the registered device.
+    pub async fn whoami(&self) -> Result<WhoAmIResponse, Error<S::Error>> {
+        Ok(self.identified_websocket(false).await?.whoami().await?)
+    }
+
+    pub fn device_id(&self) -> DeviceId {
+        self.state.device_id()
+    }
+
+    /// Returns the ACI of the account, its main identity.
+    pub fn aci(&self) -> Aci {
+        self.state.data.service_ids.aci()
+    }
+
+    /// Returns the PNI of the account, the identity associated with its phone number, unless it
+    /// was registered before PNIs were introduced and never relinked.
+    pub fn pni(&self) -> Option<Pni> {
+        (!self.state.data.service_ids.pni.is_nil()).then(|| self.state.data.service_ids.pni())
+    }
+
+    /// Fetches the profile (name, about, status emoji) of the registered user.
+    pub async fn retrieve_profile(&mut self) -> Result<Profile, Error<S::Error>> {
+        self.retrieve_profile_by_uuid(self.state.data.service_ids.aci, self.state.data.profile_key)
+            .await
+    }
+
+    /// Fetches the profile of the provided user by UUID and profile key.
+    pub async fn retrieve_profile_by_uuid(
+        &mut self,
+        aci: impl Into<Aci>,
+        profile_key: ProfileKey,
+    ) -> Result<Profile, Error<S::Error>> {
+        self.fetch_profile(aci.into(), profile_key).await
+    }
+
+    /// Fetches the profiles of several users at once, e.g. all the members of a group.
+    ///
+    /// Up to 16 profiles are fetched concurrently. The results are returned in the same order as
+    /// the `targets`, and failing to fetch one profile does not prevent fetching the others.
+    /// Profiles can only be fetched by ACI: [ServiceId::Pni] targets result in
+    /// [Error::UnknownRecipient].
+    pub async fn retrieve_profiles(
+        &self,
+        targets: Vec<(ServiceId, ProfileKey)>,
+    ) -> Vec<Result<Profile, Error<S::Error>>> {
+        const MAX_CONCURRENT_PROFILE_FETCHES: usize = 16;
+
+        futures::stream::iter(targets)
+            .map(|(service_id, profile_key)| async move {
+                let aci = service_id.aci().ok_or(Error::UnknownRecipient)?;
+                self.fetch_profile(aci, profile_key).await
+            })
+            .buffered(MAX_CONCURRENT_PROFILE_FETCHES)
+            .collect()
+            .await
+    }
+
+    async fn fetch_profile(
+        &self,
+        aci: Aci,
+        profile_key: ProfileKey,
+    ) -> Result<Profile, Error<S::Error>> {
+        // Check if profile is cached.
+        // TODO: Create a migration in the store removing all profiles.
+        // TODO: Is there some way to know if this is outdated?
+        if let Some(profile) = self
+            .store
+            .profile(aci.into(), profile_key)
+            .await
+            .ok()
+            .flatten()
+        {
+            return Ok(profile);
+        }
+
+        let mut account_manager = AccountManager::new(
+            self.identified_push_service(),
+            self.identified_websocket(false).await?,
+            Some(profile_key),
+        );
+
+        let profile = account_manager.retrieve_profile(aci).await?;
+
+        let _ = self
+            .store
+            .clone()
+            .save_profile(aci.into(), profile_key, profile.clone())
+            .await;
+        Ok(profile)
+    }
+
+    /// Fetches the profile of the provided user along with the capabilities of their account.
+    ///
+    /// Unlike [Self::retrieve_profile_by_uuid], this always queries the server since capabilities
+    /// change when the user links or upgrades a device. The decrypted profile is cached in the
+    /// store.
+    pub async fn retrieve_profile_details(
+        &self,
+        aci: impl Into<Aci>,
+        profile_key: ProfileKey,
+    ) -> Result<ProfileDetails, Error<S::Error>> {
+        let aci = aci.into();
+        let encrypted_profile = self
+            .identified_websocket(false)
+            .await?
+            .retrieve_profile_by_id(aci, Some(profile_key))
+            .await?;
+        let capabilities = ProfileCapabilities::from(&encrypted_profile.capabilities);
+        let profile = ProfileCipher::new(profile_key).decrypt(encrypted_profile)?;
+
+        let _ = self
+            .store
+            .clone()
+            .save_profile(aci.into(), profile_key, profile.clone())
+            .await;
+        Ok(ProfileDetails {
+            profile,
+            capabilities,
+        })
+    }
+
+    /// Updates the profile of this account, keeping its avatar.
+    ///
+    /// The profile is encrypted with the profile key of the account before being uploaded.
+    ///
+    /// # Arguments
+    /// * `given_name` - The given name (required by Signal)
+    /// * `family_name` - The optional family name
+    /// * `about` - The optional "about" text
+    /// * `about_emoji` - The optional emoji shown next to the "about" text
+    pub async fn set_profile(
+        &mut self,
+        given_name: &str,
+        family_name: Option<&str>,
+        about: Option<&str>,
+        about_emoji: Option<&str>,
+    ) -> Result<(), Error<S::Error>> {
+        let aci = self.state.data.service_ids.aci();
+        let mut account_manager = AccountManager::new(
+            self.identified_push_service(),
+            self.identified_websocket(false).await?,
+            Some(self.state.data.profile_key()),
+        );
+
+        account_manager
+            .upload_versioned_profile_without_avatar(
+                aci,
+                ProfileName {
+                    given_name,
+                    family_name,
+                },
+                about.map(ToOwned::to_owned),
+                about_emoji.map(ToOwned::to_owned),
+                true,
+                &mut rng(),
+            )
+            .await?;
+
+        // the cached profile is outdated
+        self.store.clear_profile(&aci.into()).await?;
+        Ok(())
+    }
+
+    /// Sets or removes the avatar of this account, keeping the rest of the profile.
+    ///
+    /// # Arguments
+    /// * `avatar` - The new avatar (usually a JPEG image), or `None` to remove the current one
+    pub async fn set_profile_avatar(
+        &mut self,
+        avatar: Option<Vec<u8>>,
+    ) -> Result<(), Error<S::Error>> {
+        let aci = self.state.data.service_ids.aci();
+        let profile = self.retrieve_profile().await?;
+        let name = profile.name.ok_or(Error::MissingProfileName)?;
+
+        let mut account_manager = AccountManager::new(
+            self.identified_push_service(),
+            self.identified_websocket(false).await?,
+            Some(self.state.data.profile_key()),
+        );
+
+        let mut avatar = avatar.map(std::io::Cursor::new);
+        let avatar = match avatar.as_mut() {
+            Some(avatar) => AvatarWrite::NewAvatar(avatar),
+            // removes the avatar from the CDN, and thus from the other devices
+            None => AvatarWrite::NoAvatar,
+        };
+        account_manager
+            .upload_versioned_profile(
+                aci,
+                ProfileName {
+                    given_name: name.given_name.as_str(),
+                    family_name: name.family_name.as_deref(),
+                },
+                profile.about,
+                profile.about_emoji,
+                avatar,
+                &mut rng(),
+            )
+            .await?;
+
+        self.store.clear_profile(&aci.into()).await?;
+        Ok(())
+    }
+
+    /// Updates the profile key of a contact, e.g. after it was shared out of band.
+    ///
+    /// The profile data cached with the previous profile key is dropped, so that the next
+    /// [Self::retrieve_profile_by_uuid] fetches it again with the new key. This also happens
+    /// automatically when receiving a message with a new profile key.
+    pub async fn update_contact_profile_key(
+        &mut self,
+        aci: Aci,
+        profile_key: ProfileKey,
+    ) -> Result<(), Error<S::Error>> {
+        update_profile_key(&mut self.store, aci, profile_key).await?;
+        Ok(())
+    }
+
+    pub async fn retrieve_group_avatar(
+        &mut self,
         context: GroupContextV2,
     ) -> Result<Option<AvatarBytes>, Error<S::Error>> {
         let master_key_bytes = context
@@ -430,30 +1381,70 @@ impl<S: Store> Manager<S, Registered> {
         }
 
         let mut gm = self.groups_manager().await?;
-        let Some(group) = upsert_group(
+        if upsert_group(
             &self.store,
             &mut gm,
             context.master_key(),
             &context.revision(),
         )
         .await?
-        else {
+        .is_none()
+        {
             return Ok(None);
-        };
+        }
+
+        self.group_avatar(&master_key_bytes).await
+    }
+
+    /// Returns the avatar of a GV2 group, downloaded from the CDN and decrypted with the master key
+    /// of the group.
+    ///
+    /// This is based on the group stored locally, see [Self::fetch_group] to refresh it. Avatars are
+    /// cached in the store once downloaded.
+    ///
+    /// # Returns
+    /// * `Ok(Some(avatar))` - The avatar image (usually a JPEG image)
+    /// * `Ok(None)` if the group has no avatar
+    /// * `Err(Error::UnknownGroup)` if the group is not stored locally
+    pub async fn group_avatar(
+        &self,
+        master_key_bytes: &[u8; 32],
+    ) -> Result<Option<AvatarBytes>, Error<S::Error>> {
+        let group = self
+            .store
+            .group(*master_key_bytes)
+            .await?
+            .ok_or(Error::UnknownGroup)?;
 
         // Empty path means no avatar was set.
         if group.avatar.is_empty() {
             return Ok(None);
         }
 
-        let avatar = gm
+        // TODO: Is there some way to know if this is outdated?
+        if let Some(avatar) = self
+            .store
+            .group_avatar(*master_key_bytes)
+            .await
+            .ok()
+            .flatten()
+        {
+            return Ok(Some(avatar));
+        }
+
+        let avatar = self
+            .groups_manager()
+            .await?
             .retrieve_avatar(
                 &group.avatar,
-                GroupSecretParams::derive_from_master_key(GroupMasterKey::new(master_key_bytes)),
+                GroupSecretParams::derive_from_master_key(GroupMasterKey::new(*master_key_bytes)),
             )
             .await?;
         if let Some(avatar) = &avatar {
-            let _ = self.store.save_group_avatar(master_key_bytes, avatar).await;
+            let _ = self
+                .store
+                .save_group_avatar(*master_key_bytes, avatar)
+                .await;
         }
         Ok(avatar)
     }
@@ -524,11 +1515,54 @@ impl<S: Store> Manager<S, Registered> {
 
     async fn receive_messages_encrypted(
         &mut self,
-    ) -> Result<impl Stream<Item = Result<Incoming, ServiceError>>, Error<S::Error>> {
+    ) -> Result<
+        (
+            SignalWebSocket<websocket::Identified>,
+            impl Stream<Item = Result<Incoming, ServiceError>>,
+        ),
+        Error<S::Error>,
+    > {
         let credentials = self.credentials();
         let ws = self.identified_websocket(true).await?;
-        let pipe = MessagePipe::from_socket(ws, credentials);
-        Ok(pipe.stream())
+        let pipe = MessagePipe::from_socket(ws.clone(), credentials);
+        Ok((ws, pipe.stream()))
+    }
+
+    /// Returns a stream of the states of the connection used to receive messages, starting with the
+    /// current one.
+    ///
+    /// This is independent of the stream of received messages, e.g. to show connectivity in a
+    /// user interface. Intermediate states might be skipped when they change faster than they are
+    /// consumed.
+    pub fn connection_events(&self) -> impl Stream<Item = ConnectionState> {
+        let receiver = self.state.connection_state.subscribe();
+        futures::stream::unfold((receiver, true), |(mut receiver, first)| async move {
+            if !first {
+                receiver.changed().await.ok()?;
+            }
+            let state = receiver.borrow_and_update().clone();
+            Some((state, (receiver, false)))
+        })
+    }
+
+    /// Returns a stream of the changes of identity keys of contacts noticed while receiving
+    /// messages, e.g. to tell the user that the safety number of a conversation changed.
+    ///
+    /// Changes are reported whatever the [OnNewIdentity](crate::model::identity::OnNewIdentity)
+    /// policy of the store, before the message carrying the new identity is yielded (or rejected).
+    pub fn identity_changes(&self) -> impl Stream<Item = IdentityChange> {
+        let receiver = self.state.identity_changes.subscribe();
+        futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(change) => return Some((change, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "identity changes were not consumed fast enough");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
     }
 
     /// Starts receiving and storing messages.
@@ -540,9 +1574,42 @@ impl<S: Store> Manager<S, Registered> {
     /// Returns a [futures::Stream] of messages to consume. Messages will also be stored by the implementation of the [Store].
     pub async fn receive_messages(
         &mut self,
+    ) -> Result<impl Stream<Item = Received>, Error<S::Error>> {
+        self.receive_messages_with_options(ReceiveOptions::default())
+            .await
+    }
+
+    /// Receives and stores the messages queued on the server since the last time, then ends the stream.
+    ///
+    /// Unlike [Self::receive_messages] which keeps waiting for new messages, the stream ends once
+    /// the server signals that its queue is empty ([Received::QueueEmpty] is not yielded).
+    pub async fn receive_pending_messages(
+        &mut self,
+    ) -> Result<impl Stream<Item = Received>, Error<S::Error>> {
+        Ok(self
+            .receive_messages()
+            .await?
+            .take_while(|received| future::ready(!matches!(received, Received::QueueEmpty))))
+    }
+
+    /// Same as [Self::receive_messages], with custom [ReceiveOptions].
+    ///
+    /// When no envelope was received during [ReceiveOptions::keepalive_interval], the connection is
+    /// checked with a request to the server, and the stream ends if it goes unanswered for another
+    /// interval. A dead connection (e.g. dropped by a NAT) is thus detected within two intervals.
+    ///
+    /// If [ReceiveOptions::reconnect] is set, the stream reconnects instead of ending when the
+    /// connection is lost, unless reconnecting fails with a fatal error (e.g. the account is not
+    /// registered anymore) or too many times in a row.
+    pub async fn receive_messages_with_options(
+        &mut self,
+        options: ReceiveOptions,
     ) -> Result<impl Stream<Item = Received>, Error<S::Error>> {
         struct StreamState<Receiver, Store, AciStore, PniStore> {
             first_run: bool,
+            manager: Manager<Store, Registered>,
+            options: ReceiveOptions,
+            receiving_websocket: SignalWebSocket<websocket::Identified>,
             store: Store,
             registration_data: RegistrationData,
             identified_push_service: PushService,
@@ -562,14 +1629,31 @@ impl<S: Store> Manager<S, Registered> {
 
         let registration_data = self.registration_data().clone();
 
+        self.state.set_connection_state(ConnectionState::Connecting);
+        let (receiving_websocket, encrypted_messages) =
+            match self.receive_messages_encrypted().await {
+                Ok(receiving) => receiving,
+                Err(error) => {
+                    self.state
+                        .set_connection_state(ConnectionState::Disconnected {
+                            reason: error.to_string(),
+                        });
+                    return Err(error);
+                }
+            };
+        self.state.set_connection_state(ConnectionState::Connected);
+
         let init = StreamState {
             first_run: true,
+            manager: self.clone(),
+            options,
+            receiving_websocket,
             store: self.store.clone(),
             registration_data,
             identified_push_service: self.identified_push_service(),
             identified_websocket: self.identified_websocket(false).await?,
             unidentified_websocket: self.unidentified_websocket().await?,
-            encrypted_messages: Box::pin(self.receive_messages_encrypted().await?),
+            encrypted_messages: Box::pin(encrypted_messages),
             message_receiver: MessageReceiver::new(identified_push_service),
             service_cipher_aci: self.new_service_cipher_aci(),
             service_cipher_pni: self.new_service_cipher_pni(),
@@ -592,6 +1676,7 @@ impl<S: Store> Manager<S, Registered> {
             state.first_run = false;
             let store_inner = state.store.clone();
             let registration_data_inner = state.registration_data.clone();
+            let mut websocket_inner = state.identified_websocket.clone();
 
             let refresh_registration = async move {
                 if !first_run {
@@ -599,9 +1684,12 @@ impl<S: Store> Manager<S, Registered> {
                     return Ok(());
                 }
 
-                if let Err(error) =
-                    set_account_attributes::<S>(&mut account_manager, &registration_data_inner)
-                        .await
+                if let Err(error) = set_account_attributes(
+                    &store_inner,
+                    &mut account_manager,
+                    &registration_data_inner,
+                )
+                .await
                 {
                     error!(%error, "failed to set account attributes, this is problematic and should never happen!");
                     return Err(());
@@ -612,23 +1700,134 @@ impl<S: Store> Manager<S, Registered> {
                     return Err(());
                 }
 
+                if let Err(error) = rotate_pre_keys_if_due(&store_inner, &mut websocket_inner).await
+                {
+                    warn!(%error, "failed to rotate pre-keys");
+                }
+
                 Ok(())
             };
 
             let incoming_messages_loop = async move {
                 loop {
-                    match state.encrypted_messages.next().await {
+                    let incoming = match tokio::time::timeout(
+                        state.options.keepalive_interval,
+                        state.encrypted_messages.next(),
+                    )
+                    .await
+                    {
+                        Ok(incoming) => incoming,
+                        Err(_) => {
+                            trace!("no incoming message, checking that the connection is alive");
+                            match tokio::time::timeout(
+                                state.options.keepalive_interval,
+                                state.receiving_websocket.whoami(),
+                            )
+                            .await
+                            {
+                                Ok(Ok(_)) => {
+                                    if let Err(error) = rotate_pre_keys_if_due(
+                                        &state.store,
+                                        &mut state.identified_websocket,
+                                    )
+                                    .await
+                                    {
+                                        warn!(%error, "failed to rotate pre-keys");
+                                    }
+                                    continue;
+                                }
+                                Ok(Err(error)) => {
+                                    warn!(%error, "keepalive failed, closing the receiving stream");
+                                    state.manager.state.set_connection_state(
+                                        ConnectionState::Disconnected {
+                                            reason: format!("keepalive failed: {error}"),
+                                        },
+                                    );
+                                }
+                                Err(_) => {
+                                    warn!("keepalive timed out, closing the receiving stream");
+                                    state.manager.state.set_connection_state(
+                                        ConnectionState::Disconnected {
+                                            reason: "keepalive timed out".to_owned(),
+                                        },
+                                    );
+                                }
+                            }
+                            None
+                        }
+                    };
+                    match incoming {
                         Some(Ok(Incoming::Envelope(envelope))) => {
-                            let envelope = {
-                                // the permit is released at the end of the block (impl Drop)
-                                match ServiceId::parse_from_service_id_string(
+                            // discarded when opening the envelope
+                            let server_received_timestamp = envelope.server_timestamp();
+                            let server_guid = envelope
+                                .server_guid
+                                .as_deref()
+                                .and_then(|guid| Uuid::parse_str(guid).ok());
+                            if let Some(filter) = &state.options.envelope_filter {
+                                let incoming_envelope = IncomingEnvelope {
+                                    sender: envelope
+                                        .source_service_id
+                                        .as_deref()
+                                        .and_then(ServiceId::parse_from_service_id_string),
+                                    sender_device: envelope.source_device,
+                                    destination: envelope
+                                        .destination_service_id
+                                        .as_deref()
+                                        .and_then(ServiceId::parse_from_service_id_string),
+                                    sent_timestamp: envelope.timestamp(),
+                                    server_received_timestamp,
+                                    server_guid,
+                                };
+                                if (filter.0)(&incoming_envelope) == EnvelopeAction::Drop {
+                                    trace!(?server_guid, "envelope dropped by the filter");
+                                    continue;
+                                }
+                            }
+                            if let Some(server_guid) = server_guid {
+                                let dedup_window =
+                                    state.manager.state.dedup_window.load(Ordering::Relaxed);
+                                if dedup_window > 0 {
+                                    let now = timestamp_now();
+                                    match state
+                                        .store
+                                        .mark_envelope_processed(
+                                            server_guid,
+                                            now,
+                                            now.saturating_sub(dedup_window),
+                                        )
+                                        .await
+                                    {
+                                        Ok(true) => (),
+                                        Ok(false) => {
+                                            debug!(%server_guid, "envelope delivered again, skipping it");
+                                            continue;
+                                        }
+                                        Err(error) => {
+                                            warn!(%error, "failed to record processed envelope")
+                                        }
+                                    }
+                                }
+                            }
+                            let envelope = {
+                                // the permit is released at the end of the block (impl Drop)
+                                match ServiceId::parse_from_service_id_string(
                                     envelope.destination_service_id(),
                                 ) {
                                     None | Some(ServiceId::Aci(_)) => {
-                                        state
+                                        let protocol_store = state.store.aci_protocol_store();
+                                        let identity_change =
+                                            identity_change(&envelope, &protocol_store).await;
+                                        let result = state
                                             .service_cipher_aci
                                             .open_envelope(envelope, &mut rng())
-                                            .await
+                                            .await;
+                                        notify_identity_change(
+                                            &state.manager.state,
+                                            identity_change,
+                                            &result,
+                                        );
+                                        result
                                     }
                                     Some(ServiceId::Pni(pni)) => {
                                         if pni == state.service_ids.pni()
@@ -637,10 +1836,19 @@ impl<S: Store> Manager<S, Registered> {
                                             warn!("Got a sealed sender message to our PNI? Invalid message, ignoring.");
                                             continue;
                                         }
-                                        state
+                                        let protocol_store = state.store.pni_protocol_store();
+                                        let identity_change =
+                                            identity_change(&envelope, &protocol_store).await;
+                                        let result = state
                                             .service_cipher_pni
                                             .open_envelope(envelope, &mut rng())
-                                            .await
+                                            .await;
+                                        notify_identity_change(
+                                            &state.manager.state,
+                                            identity_change,
+                                            &result,
+                                        );
+                                        result
                                     }
                                 }
                             };
@@ -705,16 +1913,23 @@ impl<S: Store> Manager<S, Registered> {
                                                 }
                                             }
                                             RequestType::Blocked => {
-                                                warn!("storing blocked user is not implemented yet! we will not report blocked users to the device requesting the sync.");
-                                                let result = state.message_sender.send_sync_message(SyncMessage {
-                                                    blocked: Some(libsignal_service::content::sync_message::Blocked {
-                                                        numbers: vec![],
-                                                        acis: vec![],
-                                                        acis_binary: vec![],
-                                                        group_ids: vec![],
-                                                    }),
-                                                    ..SyncMessage::with_padding(&mut rand::rng())
-                                                }).await;
+                                                let result = match blocked_sync_message(
+                                                    &state.store,
+                                                )
+                                                .await
+                                                {
+                                                    Ok(blocked) => state
+                                                        .message_sender
+                                                        .send_sync_message(SyncMessage {
+                                                            blocked: Some(blocked),
+                                                            ..SyncMessage::with_padding(
+                                                                &mut rand::rng(),
+                                                            )
+                                                        })
+                                                        .await
+                                                        .map_err(Error::from),
+                                                    Err(error) => Err(Error::Store(error)),
+                                                };
 
                                                 if let Err(error) = result {
                                                     warn!(%error, "Error sending blocked contacts to other devices");
@@ -726,6 +1941,61 @@ impl<S: Store> Manager<S, Registered> {
                                         }
                                     }
 
+                                    // blocked contacts and groups synchronization sent from another device
+                                    if let ContentBody::SynchronizeMessage(SyncMessage {
+                                        blocked: Some(blocked),
+                                        ..
+                                    }) = &content.body
+                                    {
+                                        if let Err(error) =
+                                            save_blocked(&mut state.store, blocked).await
+                                        {
+                                            warn!(%error, "failed to save blocked contacts and groups");
+                                        }
+                                    }
+
+                                    // privacy settings sent from the primary device
+                                    if let ContentBody::SynchronizeMessage(SyncMessage {
+                                        configuration: Some(configuration),
+                                        ..
+                                    }) = &content.body
+                                    {
+                                        if let Err(error) =
+                                            save_configuration(&state.store, configuration).await
+                                        {
+                                            warn!(%error, "failed to save account settings");
+                                        }
+                                    }
+
+                                    // messages read on another device
+                                    if let ContentBody::SynchronizeMessage(SyncMessage {
+                                        read,
+                                        ..
+                                    }) = &content.body
+                                    {
+                                        if !read.is_empty() {
+                                            match save_read(&mut state.store, read).await {
+                                                Ok(threads) => {
+                                                    return Some((Received::Read(threads), state))
+                                                }
+                                                Err(error) => {
+                                                    warn!(%error, "failed to mark messages read on another device as read");
+                                                }
+                                            }
+                                        }
+                                    }
+
+                                    if state.options.skip_blocked
+                                        && is_blocked(&state.store, &state.service_ids, &content)
+                                            .await
+                                    {
+                                        trace!(
+                                            sender = content.metadata.sender.service_id_string(),
+                                            "skipping message from blocked contact or group"
+                                        );
+                                        continue;
+                                    }
+
                                     // contacts synchronization sent from the primary device (happens after linking, or on demand)
                                     if let ContentBody::SynchronizeMessage(SyncMessage {
                                         contacts: Some(contacts),
@@ -815,6 +2085,46 @@ impl<S: Store> Manager<S, Registered> {
                                         }
                                     }
 
+                                    // receipts are not stored (only the statuses of the
+                                    // messages) and surfaced on their own
+                                    if let ContentBody::ReceiptMessage(receipt_message) =
+                                        &content.body
+                                    {
+                                        let receipt = Receipt {
+                                            sender: content.metadata.sender,
+                                            kind: receipt_message.r#type().into(),
+                                            timestamps: receipt_message.timestamp.clone(),
+                                        };
+                                        if let Err(error) = save_receipt(
+                                            &mut state.store,
+                                            ServiceId::Aci(state.service_ids.aci()),
+                                            &receipt,
+                                        )
+                                        .await
+                                        {
+                                            error!(%error, "failed to save message statuses");
+                                        }
+                                        return Some((Received::Receipt(receipt), state));
+                                    }
+
+                                    // typing indicators are not stored and surfaced on their own
+                                    if let ContentBody::TypingMessage(typing_message) =
+                                        &content.body
+                                    {
+                                        match typing_indicator(
+                                            &state.store,
+                                            &content.metadata,
+                                            typing_message,
+                                        )
+                                        .await
+                                        {
+                                            Some(typing) => {
+                                                return Some((Received::Typing(typing), state))
+                                            }
+                                            None => continue,
+                                        }
+                                    }
+
                                     // group update
                                     if let ContentBody::DataMessage(DataMessage {
                                         group_v2:
@@ -858,6 +2168,12 @@ impl<S: Store> Manager<S, Registered> {
                                         }
                                     }
 
+                                    if let Err(error) =
+                                        save_phone_number(&mut state.store, &content).await
+                                    {
+                                        error!(%error, "failed to save the phone number of a contact");
+                                    }
+
                                     if let Err(error) = save_message(
                                         &mut state.store,
                                         &mut state.identified_websocket,
@@ -869,7 +2185,43 @@ impl<S: Store> Manager<S, Registered> {
                                         error!(%error, "error saving message to store");
                                     }
 
-                                    return Some((Received::Content(Box::new(content)), state));
+                                    // terminated polls are surfaced on their own
+                                    if let (
+                                        Some(DataMessage {
+                                            poll_terminate:
+                                                Some(PollTerminate {
+                                                    target_sent_timestamp: Some(poll_timestamp),
+                                                }),
+                                            ..
+                                        }),
+                                        Ok(thread),
+                                    ) = (content.data_message(), Thread::try_from(&content))
+                                    {
+                                        let poll_timestamp = *poll_timestamp;
+                                        return Some((
+                                            Received::PollTerminated {
+                                                thread,
+                                                poll_timestamp,
+                                            },
+                                            state,
+                                        ));
+                                    }
+
+                                    // and so are call events
+                                    if let Some(call_event) = CallEvent::from_content(&content) {
+                                        return Some((Received::CallEvent(call_event), state));
+                                    }
+
+                                    let envelope_metadata = EnvelopeMetadata {
+                                        sender: content.metadata.sender,
+                                        sent_timestamp: content.metadata.timestamp,
+                                        server_received_timestamp,
+                                        server_guid,
+                                    };
+                                    return Some((
+                                        Received::Content(Box::new(content), envelope_metadata),
+                                        state,
+                                    ));
                                 }
                                 Ok(None) => {
                                     debug!("empty envelope, message will be skipped!")
@@ -886,7 +2238,78 @@ impl<S: Store> Manager<S, Registered> {
                         Some(Err(error)) => {
                             error!(%error, "unexpected error in message receiving loop")
                         }
-                        None => return None,
+                        None => {
+                            let connection_state =
+                                state.manager.state.connection_state.borrow().clone();
+                            if connection_state == ConnectionState::Connected {
+                                state.manager.state.set_connection_state(
+                                    ConnectionState::Disconnected {
+                                        reason: "connection closed".to_owned(),
+                                    },
+                                );
+                            }
+                            let policy = state.options.reconnect?;
+                            let mut backoff = policy.initial_backoff;
+                            let mut attempt = 1;
+                            loop {
+                                state.manager.state.set_connection_state(
+                                    ConnectionState::Reconnecting { attempt },
+                                );
+                                match state.manager.receive_messages_encrypted().await {
+                                    Ok((websocket, encrypted_messages)) => {
+                                        info!(attempt, "reconnected");
+                                        state
+                                            .manager
+                                            .state
+                                            .set_connection_state(ConnectionState::Connected);
+                                        state.identified_websocket = websocket.clone();
+                                        state.receiving_websocket = websocket;
+                                        state.encrypted_messages = Box::pin(encrypted_messages);
+                                        if let Ok(websocket) =
+                                            state.manager.unidentified_websocket().await
+                                        {
+                                            state.unidentified_websocket = websocket;
+                                        }
+                                        // the sender and groups manager hold the closed websockets
+                                        match state.manager.new_message_sender().await {
+                                            Ok(message_sender) => {
+                                                state.message_sender = message_sender
+                                            }
+                                            Err(error) => {
+                                                warn!(%error, "failed to rebuild the message sender")
+                                            }
+                                        }
+                                        match state.manager.groups_manager().await {
+                                            Ok(groups_manager) => {
+                                                state.groups_manager = groups_manager
+                                            }
+                                            Err(error) => {
+                                                warn!(%error, "failed to rebuild the groups manager")
+                                            }
+                                        }
+                                        return Some((Received::Reconnected, state));
+                                    }
+                                    Err(error)
+                                        if is_fatal_connection_error(&error)
+                                            || attempt >= policy.max_attempts =>
+                                    {
+                                        error!(%error, attempt, "failed to reconnect, closing the receiving stream");
+                                        state.manager.state.set_connection_state(
+                                            ConnectionState::Disconnected {
+                                                reason: error.to_string(),
+                                            },
+                                        );
+                                        return None;
+                                    }
+                                    Err(error) => {
+                                        warn!(%error, attempt, ?backoff, "failed to reconnect, retrying");
+                                        tokio::time::sleep(backoff).await;
+                                        backoff = (backoff * 2).min(policy.max_backoff);
+                                        attempt += 1;
+                                    }
+                                }
+                            }
+                        }
                     }
                 }
             };
@@ -913,35 +2336,67 @@ impl<S: Store> Manager<S, Registered> {
     ///
     /// This method will automatically update the [DataMessage::expire_timer] if it is set to
     /// [None] such that the chat will keep the current expire timer. If the expire timer is set,
-    /// it will be used as is, and the expire timer version will be incremented.
+    /// it will be used as is, and the expire timer version will be incremented (unless it is set
+    /// too). See [Self::send_message_with_timer_override] to send a single message with another
+    /// timer.
+    ///
+    /// If the recipient linked or unlinked devices since the last message, the sessions of the
+    /// devices that are gone are dropped and the message is sent again, a bounded number of times.
+    ///
+    /// Concurrent sends to the same recipient (e.g. from clones of the manager) are sent one after
+    /// the other, in call order, which keeps the session state consistent. Sends to other threads
+    /// are not held back.
+    ///
+    /// Fails with [Error::UnregisteredRecipient] if the recipient is not registered with Signal
+    /// (anymore), e.g. after deleting their account.
+    ///
+    /// If the server could not be reached, the message is kept to be sent later when enabled, see
+    /// [Self::set_persist_failed_sends].
     pub async fn send_message(
         &mut self,
         recipient: impl Into<ServiceId>,
         message: impl Into<ContentBody>,
         timestamp: u64,
     ) -> Result<(), Error<S::Error>> {
-        let mut sender = self.new_message_sender().await?;
         let recipient = recipient.into();
+        let content_body = message.into();
+        let pending_send = self.pending_send_copy(&content_body);
+        let result = self
+            .try_send_message(recipient, content_body, timestamp)
+            .await;
+        if let (Err(error), Some(content_body)) = (&result, pending_send) {
+            let thread = Thread::Contact(recipient.raw_uuid());
+            self.keep_pending_send(&thread, recipient, content_body, timestamp, error)
+                .await;
+        }
+        result
+    }
+
+    async fn try_send_message(
+        &mut self,
+        recipient: ServiceId,
+        mut content_body: ContentBody,
+        timestamp: u64,
+    ) -> Result<(), Error<S::Error>> {
+        let thread = Thread::Contact(recipient.raw_uuid());
+        let _send_guard = self.state.lock_thread_for_sending(&thread).await;
+
+        let mut sender = self.new_message_sender().await?;
 
         let online_only = false;
         // TODO: Populate this flag based on the recipient information
         //
         // Issue <https://github.com/whisperfish/presage/issues/252>
         let include_pni_signature = false;
-        let thread = Thread::Contact(recipient.raw_uuid());
-        let mut content_body: ContentBody = message.into();
 
         self.restore_thread_timer(&thread, &mut content_body).await;
 
-        let sender_certificate = self.sender_certificate().await?;
-        let unidentified_access = self
-            .store
-            .profile_key(&recipient)
-            .await?
-            .map(|profile_key| UnidentifiedAccess {
-                key: profile_key.derive_access_key().to_vec(),
-                certificate: sender_certificate.clone(),
-            });
+        let note_to_self = thread == self.note_to_self_thread();
+        let unidentified_access = if note_to_self {
+            None
+        } else {
+            self.unidentified_access(&recipient).await?
+        };
 
         // we need to put our profile key in DataMessage
         if let ContentBody::DataMessage(message) = &mut content_body {
@@ -953,16 +2408,80 @@ impl<S: Store> Manager<S, Registered> {
 
         ensure_data_message_timestamp(&mut content_body, timestamp);
 
-        sender
+        // like official clients, messages to Note to Self are only sent to our other devices, as
+        // transcripts of a message we sent
+        let sent_body = match &content_body {
+            ContentBody::DataMessage(message) if note_to_self => SyncMessage {
+                sent: Some(sync_message::Sent {
+                    destination_service_id: Some(recipient.service_id_string()),
+                    timestamp: Some(timestamp),
+                    message: Some(message.clone()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+            .into(),
+            content_body => content_body.clone(),
+        };
+
+        let mut sealed_sender = unidentified_access.is_some();
+        let result = sender
             .send_message(
                 &recipient,
                 unidentified_access,
-                content_body.clone(),
+                sent_body.clone(),
                 timestamp,
                 include_pni_signature,
                 online_only,
             )
-            .await?;
+            .await;
+        let mut result = match result {
+            Err(error) if sealed_sender && is_unidentified_access_refused(&error) => {
+                debug!(%error, "sealed sender refused, sending the message identified");
+                sealed_sender = false;
+                sender
+                    .send_message(
+                        &recipient,
+                        None,
+                        sent_body.clone(),
+                        timestamp,
+                        include_pni_signature,
+                        online_only,
+                    )
+                    .await
+            }
+            result => result,
+        };
+
+        // the recipient linked or unlinked a device since we last sent them a message
+        let mut retries = 0;
+        while let Err(error) = &result {
+            if retries == MAX_DEVICE_MISMATCH_RETRIES
+                || !self.drop_mismatched_sessions(&recipient, error).await
+            {
+                break;
+            }
+            retries += 1;
+            debug!(%error, retries, "device mismatch, sending the message again");
+            let unidentified_access = if sealed_sender {
+                self.unidentified_access(&recipient).await?
+            } else {
+                None
+            };
+            result = sender
+                .send_message(
+                    &recipient,
+                    unidentified_access,
+                    sent_body.clone(),
+                    timestamp,
+                    include_pni_signature,
+                    online_only,
+                )
+                .await;
+        }
+        if let Err(error) = result {
+            return Err(self.sending_error(error).await);
+        }
 
         // save the message
         let content = Content {
@@ -991,17 +2510,124 @@ impl<S: Store> Manager<S, Registered> {
         Ok(())
     }
 
-    /// Uploads one attachment prior to linking them in a message.
+    /// Sends a message like [Self::send_message], but trusts the identity key of the recipient and
+    /// retries once if it is not trusted, e.g. because it changed.
+    ///
+    /// This is meant for clients which don't let users verify safety numbers. Only
+    /// [Error::UntrustedIdentity] triggers a retry, other errors are returned as is.
+    pub async fn send_message_trusting_on_change(
+        &mut self,
+        recipient: impl Into<ServiceId>,
+        message: impl Into<ContentBody>,
+        timestamp: u64,
+    ) -> Result<(), Error<S::Error>> {
+        let recipient = recipient.into();
+        let message = message.into();
+        match self
+            .send_message(recipient, message.clone(), timestamp)
+            .await
+        {
+            Err(Error::UntrustedIdentity { service_id, key }) => {
+                warn!(
+                    service_id = %service_id.service_id_string(),
+                    "trusting the identity of the recipient and sending the message again"
+                );
+                self.trust_identity(&service_id, key).await?;
+                self.send_message(recipient, message, timestamp).await
+            }
+            result => result,
+        }
+    }
+
+    /// Sends a message to a [Thread], giving up after `timeout`.
+    ///
+    /// On expiry, [Error::Timeout] is returned and the message may or may not have been delivered
+    /// to some recipients. Retrying is safe for the sessions: the stores persist each session
+    /// record atomically, so an interrupted send at worst advances the ratchet for a message which
+    /// was never delivered, which recipients handle like any lost message. The retry uses a new
+    /// timestamp, so recipients who got the first attempt see the message twice.
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_message_with_timeout(
+        &mut self,
+        thread: &Thread,
+        message: impl Into<ContentBody>,
+        timeout: Duration,
+    ) -> Result<u64, Error<S::Error>> {
+        let timestamp = timestamp_now();
+        tokio::time::timeout(
+            timeout,
+            self.send_message_to_thread(thread, message, timestamp),
+        )
+        .await
+        .inspect_err(|_| warn!(?timeout, %thread, "sending message timed out"))??;
+        Ok(timestamp)
+    }
+
+    /// Sends a message to a [Thread] with a different expire timer than the one of the thread.
+    ///
+    /// The timer of the thread is left untouched, and following messages use it again.
+    ///
+    /// # Arguments
+    /// * `thread` - The thread to send the message to
+    /// * `message` - The message to send
+    /// * `timestamp` - The timestamp of the message, usually now
+    /// * `expire_timer` - The expire timer of this message, in seconds (0 to not expire)
+    pub async fn send_message_with_timer_override(
+        &mut self,
+        thread: &Thread,
+        message: impl Into<ContentBody>,
+        timestamp: u64,
+        expire_timer: u32,
+    ) -> Result<(), Error<S::Error>> {
+        let mut content_body = message.into();
+        if let ContentBody::DataMessage(data_message) = &mut content_body {
+            self.override_expire_timer(thread, data_message, expire_timer)
+                .await?;
+        }
+        self.send_message_to_thread(thread, content_body, timestamp)
+            .await
+    }
+
+    /// Sets the expire timer of a single message, without changing the one of the thread.
+    async fn override_expire_timer(
+        &self,
+        thread: &Thread,
+        data_message: &mut DataMessage,
+        expire_timer: u32,
+    ) -> Result<(), Error<S::Error>> {
+        // keeping the current version tells the recipients not to update the thread timer
+        let version = self
+            .store
+            .expire_timer(thread)
+            .await?
+            .map(|(_, version)| version)
+            .unwrap_or_default();
+        data_message.expire_timer = Some(expire_timer);
+        data_message.expire_timer_version = Some(version);
+        Ok(())
+    }
+
+    /// Uploads one attachment prior to linking it in a message.
+    ///
+    /// The returned pointer carries the CDN location and the key of the attachment, so it can be
+    /// reused in several messages (see [Self::send_message_with_attachments]) as long as the
+    /// attachment is kept on the CDN, instead of uploading the same data again.
+    ///
+    /// The dimensions, blurhash and caption set in the spec (see
+    /// [AttachmentSpecExt](crate::model::messages::AttachmentSpecExt)) are sent in the pointer, for
+    /// recipients to show a placeholder while downloading the attachment.
     pub async fn upload_attachment(
         &self,
         spec: AttachmentSpec,
         contents: Vec<u8>,
-    ) -> Result<Result<AttachmentPointer, AttachmentUploadError>, Error<S::Error>> {
+    ) -> Result<AttachmentPointer, Error<S::Error>> {
         Ok(self
             .new_message_sender()
             .await?
             .upload_attachment(spec, contents, &mut rng())
-            .await)
+            .await?)
     }
 
     /// Uploads attachments prior to linking them in a message.
@@ -1020,26 +2646,253 @@ impl<S: Store> Manager<S, Registered> {
         Ok(upload.await)
     }
 
+    /// Composes a data message with several features (attachments, quote, mentions, link
+    /// preview...), sent with [DataMessageBuilder::send].
+    ///
+    /// ```ignore
+    /// let timestamp = manager
+    ///     .compose_message()
+    ///     .body("look at this, \u{FFFC}")
+    ///     .attachments(attachments)
+    ///     .mentions(mentions)
+    ///     .quote(author, quoted_timestamp, "where was it?")
+    ///     .send(&thread)
+    ///     .await?;
+    /// ```
+    pub fn compose_message(&mut self) -> DataMessageBuilder<'_, S> {
+        DataMessageBuilder {
+            manager: self,
+            body: None,
+            attachments: Vec::new(),
+            view_once: false,
+            quote: None,
+            body_ranges: Vec::new(),
+            preview: None,
+            expire_timer: None,
+        }
+    }
+
+    /// Sends a message with already uploaded attachments to a [Thread].
+    ///
+    /// # Arguments
+    /// * `thread` - The thread to send the message to
+    /// * `body` - The text of the message
+    /// * `attachments` - Pointers to attachments returned by [Self::upload_attachment]
+    /// * `view_once` - Whether the attachment can only be viewed once by the recipients, in which
+    ///   case there must be exactly one image or video attachment.
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_message_with_attachments(
+        &mut self,
+        thread: &Thread,
+        body: impl Into<String>,
+        attachments: Vec<AttachmentPointer>,
+        view_once: bool,
+    ) -> Result<u64, Error<S::Error>> {
+        self.compose_message()
+            .body(body)
+            .attachments(attachments)
+            .view_once(view_once)
+            .send(thread)
+            .await
+    }
+
+    /// Sends a message mentioning group members to a [Thread].
+    ///
+    /// The ranges of the mentions are counted in UTF-16 code units, see [Mention::from_byte_range].
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_message_with_mentions(
+        &mut self,
+        thread: &Thread,
+        body: impl Into<String>,
+        mentions: Vec<Mention>,
+    ) -> Result<u64, Error<S::Error>> {
+        self.send_formatted_message(thread, body, mentions, Vec::new())
+            .await
+    }
+
+    /// Sends a message with styled text (bold, italic, spoilers...) to a [Thread].
+    ///
+    /// The ranges of the styles are counted in UTF-16 code units, see
+    /// [TextStyleRange::from_byte_range].
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_message_with_styles(
+        &mut self,
+        thread: &Thread,
+        body: impl Into<String>,
+        styles: Vec<TextStyleRange>,
+    ) -> Result<u64, Error<S::Error>> {
+        self.send_formatted_message(thread, body, Vec::new(), styles)
+            .await
+    }
+
+    /// Sends a message with both mentions and styled text to a [Thread].
+    ///
+    /// Mentions and styles are all sent as body ranges of the message.
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_formatted_message(
+        &mut self,
+        thread: &Thread,
+        body: impl Into<String>,
+        mentions: Vec<Mention>,
+        styles: Vec<TextStyleRange>,
+    ) -> Result<u64, Error<S::Error>> {
+        self.compose_message()
+            .body(body)
+            .mentions(mentions)
+            .styles(styles)
+            .send(thread)
+            .await
+    }
+
+    /// Sends a message with a link preview to a [Thread].
+    ///
+    /// The image of the preview, if any, is uploaded as an attachment. The URL of the preview
+    /// must be in the body, otherwise this fails with [Error::InvalidLinkPreview].
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_message_with_preview(
+        &mut self,
+        thread: &Thread,
+        body: impl Into<String>,
+        preview: LinkPreview,
+    ) -> Result<u64, Error<S::Error>> {
+        self.compose_message()
+            .body(body)
+            .preview(preview)
+            .send(thread)
+            .await
+    }
+
+    /// Uploads the image of a link preview, if any, and builds the preview to attach to a message
+    async fn upload_link_preview(&self, preview: LinkPreview) -> Result<Preview, Error<S::Error>> {
+        let image = match preview.image {
+            Some((spec, contents)) => Some(self.upload_attachment(spec, contents).await?),
+            None => None,
+        };
+        Ok(Preview {
+            url: Some(preview.url),
+            title: preview.title,
+            description: preview.description,
+            image,
+            date: None,
+        })
+    }
+
     /// Sends one message in a group (v2). The `master_key_bytes` is required to have 32 elements.
     ///
     /// This method will automatically update the [DataMessage::expire_timer] if it is set to
     /// [None] such that the chat will keep the current expire timer.
+    ///
+    /// Members who are not registered with Signal anymore are skipped, and the first other
+    /// failure is returned. See [Self::send_message_to_group_with_results] to get the result for
+    /// every member.
     pub async fn send_message_to_group(
         &mut self,
         master_key_bytes: &[u8],
         message: impl Into<ContentBody>,
         timestamp: u64,
     ) -> Result<(), Error<S::Error>> {
-        let mut content_body = message.into();
-        let master_key_bytes = master_key_bytes
-            .try_into()
-            .expect("Master key bytes to be of size 32.");
-        let thread = Thread::Group(master_key_bytes);
-
-        self.restore_thread_timer(&thread, &mut content_body).await;
-        ensure_data_message_timestamp(&mut content_body, timestamp);
+        let results = self
+            .send_message_to_group_with_results(master_key_bytes, message, timestamp)
+            .await?;
 
-        let mut sender = self.new_message_sender().await?;
+        // TODO: Handle the NotFound error in the future by removing all sessions to this UUID and marking it as unregistered, not sending any messages to this contact anymore.
+        results
+            .into_iter()
+            .map(|(_, res)| res)
+            .find(|res| match res {
+                Ok(_) => false,
+                // Ignore unregistered recipients, those mean that e.g. some contact in a group deleted his account.
+                Err(Error::UnregisteredRecipient(service_id)) => {
+                    debug!(service_id = %service_id.service_id_string(), "recipient not found, skipping sent message result");
+                    false
+                }
+                // return first error if any
+                Err(_) => true,
+            })
+            .transpose()?;
+
+        Ok(())
+    }
+
+    /// Sends one message in a group (v2) identified by its [GroupId], see
+    /// [Self::send_message_to_group].
+    ///
+    /// Fails with [Error::UnknownGroup] if the group is not stored locally.
+    pub async fn send_message_to_group_id(
+        &mut self,
+        group_id: &GroupId,
+        message: impl Into<ContentBody>,
+        timestamp: u64,
+    ) -> Result<(), Error<S::Error>> {
+        let master_key_bytes = self.master_key_for_group(group_id).await?;
+        self.send_message_to_group(&master_key_bytes, message, timestamp)
+            .await
+    }
+
+    /// Sends one message in a group (v2), and returns the result of the send for every member.
+    ///
+    /// Failing to send the message to some members doesn't prevent sending it to the others, so
+    /// that only the failed members can be retried. Members who are not registered with Signal
+    /// anymore get [Error::UnregisteredRecipient].
+    ///
+    /// The message is saved in the store unless it could not be sent to anyone.
+    ///
+    /// Like [Self::send_message], concurrent sends to the same group go out in call order, and the
+    /// message is kept to be sent later if the server could not be reached at all (when enabled).
+    ///
+    /// # Returns
+    /// * `Ok(results)` - The result for each member of the group (except this account)
+    /// * `Err(Error)` if the message could not be sent at all (e.g. unknown group)
+    pub async fn send_message_to_group_with_results(
+        &mut self,
+        master_key_bytes: &[u8],
+        message: impl Into<ContentBody>,
+        timestamp: u64,
+    ) -> Result<Vec<(ServiceId, Result<SentMessage, Error<S::Error>>)>, Error<S::Error>> {
+        let content_body = message.into();
+        let master_key_bytes: GroupMasterKeyBytes = master_key_bytes
+            .try_into()
+            .expect("Master key bytes to be of size 32.");
+        let pending_send = self.pending_send_copy(&content_body);
+        let result = self
+            .try_send_message_to_group(master_key_bytes, content_body, timestamp)
+            .await;
+        let error = match &result {
+            Err(error) => Some(error),
+            Ok(results) => unreached_group_error(results),
+        };
+        if let (Some(error), Some(content_body)) = (error, pending_send) {
+            let thread = Thread::Group(master_key_bytes);
+            let own_aci = self.state.data.service_ids.aci().into();
+            self.keep_pending_send(&thread, own_aci, content_body, timestamp, error)
+                .await;
+        }
+        result
+    }
+
+    async fn try_send_message_to_group(
+        &mut self,
+        master_key_bytes: GroupMasterKeyBytes,
+        mut content_body: ContentBody,
+        timestamp: u64,
+    ) -> Result<Vec<(ServiceId, Result<SentMessage, Error<S::Error>>)>, Error<S::Error>> {
+        let thread = Thread::Group(master_key_bytes);
+        let _send_guard = self.state.lock_thread_for_sending(&thread).await;
+
+        self.restore_thread_timer(&thread, &mut content_body).await;
+        ensure_data_message_timestamp(&mut content_body, timestamp);
+
+        let mut sender = self.new_message_sender().await?;
 
         let mut groups_manager = self.groups_manager().await?;
         let Some(group) =
@@ -1048,21 +2901,20 @@ impl<S: Store> Manager<S, Registered> {
             return Err(Error::UnknownGroup);
         };
 
-        let sender_certificate = self.sender_certificate().await?;
+        if group.announcements_only
+            && is_announcement(&content_body)
+            && !group.is_administrator(self.state.data.service_ids.aci())
+        {
+            return Err(Error::GroupAnnouncementsOnly);
+        }
+
         let mut recipients = Vec::new();
         for member in group
             .members
             .into_iter()
             .filter(|m| m.aci != self.state.data.service_ids.aci())
         {
-            let unidentified_access =
-                self.store
-                    .profile_key(&member.aci.into())
-                    .await?
-                    .map(|profile_key| UnidentifiedAccess {
-                        key: profile_key.derive_access_key().to_vec(),
-                        certificate: sender_certificate.clone(),
-                    });
+            let unidentified_access = self.unidentified_access(&member.aci.into()).await?;
             let include_pni_signature = false;
             recipients.push((
                 member.aci.into(),
@@ -1070,26 +2922,92 @@ impl<S: Store> Manager<S, Registered> {
                 include_pni_signature,
             ));
         }
+        let recipient_ids: Vec<ServiceId> = recipients.iter().map(|(id, _, _)| *id).collect();
+        let mut sealed_sender: Vec<bool> = recipients
+            .iter()
+            .map(|(_, unidentified_access, _)| unidentified_access.is_some())
+            .collect();
 
         let online_only = false;
-        let results = sender
+        let mut results = sender
             .send_message_to_group(recipients, content_body.clone(), timestamp, online_only)
             .await;
 
-        // TODO: Handle the NotFound error in the future by removing all sessions to this UUID and marking it as unregistered, not sending any messages to this contact anymore.
-        results
-            .into_iter()
-            .find(|res| match res {
-                Ok(_) => false,
-                // Ignore any NotFound errors, those mean that e.g. some contact in a group deleted his account.
-                Err(MessageSenderError::NotFound { service_id }) => {
-                    debug!(service_id = %service_id.service_id_string(), "recipient not found, skipping sent message result");
-                    false
+        // retry identified for the members who refused sealed sender
+        for ((service_id, result), sealed_sender) in recipient_ids
+            .iter()
+            .zip(&mut results)
+            .zip(&mut sealed_sender)
+        {
+            if *sealed_sender
+                && matches!(result, Err(error) if is_unidentified_access_refused(error))
+            {
+                debug!(
+                    service_id = %service_id.service_id_string(),
+                    "sealed sender refused, sending the message identified");
+                *sealed_sender = false;
+                *result = sender
+                    .send_message(
+                        service_id,
+                        None,
+                        content_body.clone(),
+                        timestamp,
+                        false,
+                        online_only,
+                    )
+                    .await;
+            }
+        }
+
+        // retry for the members who linked or unlinked a device since we last sent them a message
+        for ((service_id, result), sealed_sender) in
+            recipient_ids.iter().zip(&mut results).zip(sealed_sender)
+        {
+            let mut retries = 0;
+            while let Err(error) = result {
+                if retries == MAX_DEVICE_MISMATCH_RETRIES
+                    || !self.drop_mismatched_sessions(service_id, error).await
+                {
+                    break;
                 }
-                // return first error if any
-                Err(_) => true,
-            })
-            .transpose()?;
+                retries += 1;
+                debug!(
+                    service_id = %service_id.service_id_string(),
+                    retries,
+                    "device mismatch, sending the message again");
+                let unidentified_access = if sealed_sender {
+                    self.unidentified_access(service_id).await?
+                } else {
+                    None
+                };
+                *result = sender
+                    .send_message(
+                        service_id,
+                        unidentified_access,
+                        content_body.clone(),
+                        timestamp,
+                        false,
+                        online_only,
+                    )
+                    .await;
+            }
+        }
+
+        // results are in the same order as the recipients
+        let mut sending_results = Vec::with_capacity(results.len());
+        for (service_id, res) in recipient_ids.into_iter().zip(results) {
+            let res = match res {
+                Ok(sent) => Ok(sent),
+                Err(error) => Err(self.sending_error(error).await),
+            };
+            sending_results.push((service_id, res));
+        }
+        let results = sending_results;
+
+        if !results.is_empty() && results.iter().all(|(_, res)| res.is_err()) {
+            warn!("message could not be sent to any group member, not saving it");
+            return Ok(results);
+        }
 
         let content = Content {
             metadata: Metadata {
@@ -1114,201 +3032,845 @@ impl<S: Store> Manager<S, Registered> {
         )
         .await?;
 
-        Ok(())
+        Ok(results)
     }
 
-    async fn restore_thread_timer(&mut self, thread: &Thread, content_body: &mut ContentBody) {
-        let store_expire_timer = self.store.expire_timer(thread).await.unwrap_or_default();
-
-        if let ContentBody::DataMessage(DataMessage {
-            expire_timer: ref mut timer,
-            expire_timer_version: ref mut version,
-            ..
-        }) = content_body
+    /// Edits a message previously sent in a [Thread].
+    ///
+    /// Signal only allows editing messages within 24 hours after they were sent, older messages
+    /// are rejected with [Error::EditWindowExpired] before anything is sent.
+    ///
+    /// # Arguments
+    /// * `thread` - The thread the original message was sent in
+    /// * `target_sent_timestamp` - The timestamp of the original message
+    /// * `new_body` - The new text of the message
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - The timestamp of the edit
+    /// * `Err(Error)` if the edit is not allowed or sending fails
+    pub async fn send_edit_message(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        new_body: impl Into<String>,
+    ) -> Result<u64, Error<S::Error>> {
+        let timestamp = timestamp_now();
+        if timestamp.saturating_sub(target_sent_timestamp) > EDIT_MESSAGE_WINDOW.as_millis() as u64
         {
-            if timer.is_none() {
-                *timer = store_expire_timer.map(|(t, _)| t);
-                *version = Some(store_expire_timer.map(|(_, v)| v).unwrap_or_default());
-            } else {
-                *version = Some(store_expire_timer.map(|(_, v)| v).unwrap_or_default() + 1);
-            }
-        }
-    }
-
-    /// Clears all sessions established with [recipient](ServiceId).
-    pub async fn clear_sessions(&self, recipient: &ServiceId) -> Result<(), Error<S::Error>> {
-        use libsignal_service::session_store::SessionStoreExt;
-        self.store
-            .aci_protocol_store()
-            .delete_all_sessions(recipient)
-            .await?;
-        self.store
-            .pni_protocol_store()
-            .delete_all_sessions(recipient)
-            .await?;
-        Ok(())
-    }
-
-    /// Downloads and decrypts a single attachment.
-    pub async fn get_attachment(
-        &self,
-        attachment_pointer: &AttachmentPointer,
-    ) -> Result<Vec<u8>, Error<S::Error>> {
-        let expected_digest = attachment_pointer
-            .digest
-            .as_ref()
-            .ok_or_else(|| Error::UnexpectedAttachmentChecksum)?;
-
-        let mut service = self.identified_push_service();
-        let mut attachment_stream = service.get_attachment(attachment_pointer).await?;
-
-        let plaintext_len = attachment_pointer.size.and_then(|len| len.try_into().ok());
-
-        // We need the whole file for the crypto to check out
-        let mut ciphertext = Vec::with_capacity(plaintext_len.unwrap_or(0));
-        let size_bytes = attachment_stream.read_to_end(&mut ciphertext).await?;
-        trace!(size_bytes, "downloaded encrypted attachment");
-
-        let digest = sha2::Sha256::digest(&ciphertext);
-        if &digest[..] != expected_digest {
-            return Err(Error::UnexpectedAttachmentChecksum);
+            return Err(Error::EditWindowExpired);
         }
 
-        let key: [u8; 64] = attachment_pointer.key().try_into()?;
-
-        // Offload decryption of large attachments to another thread.
-        // Chose arbitrary threshold here.
-        const DECRYPT_IN_THREAD_THRESHOLD: usize = 100 * 1024;
-        if ciphertext.len() > DECRYPT_IN_THREAD_THRESHOLD {
-            ciphertext = tokio::task::spawn_blocking(move || {
-                decrypt_in_place(key, &mut ciphertext).map(|_| ciphertext)
-            })
-            .await
-            .expect("decryption in another thread")?;
-        } else {
-            decrypt_in_place(key, &mut ciphertext)?;
+        let edit_message = EditMessage {
+            target_sent_timestamp: Some(target_sent_timestamp),
+            data_message: Some(DataMessage {
+                body: Some(new_body.into()),
+                ..Default::default()
+            }),
         };
 
-        if let Some(len) = plaintext_len {
-            if len < ciphertext.len() {
-                // remove padding
-                ciphertext.truncate(len);
-            }
-        }
-
-        Ok(ciphertext)
-    }
+        self.send_message_to_thread(thread, edit_message, timestamp)
+            .await?;
 
-    /// Gets the metadata of a sticker
-    pub async fn sticker_metadata(
-        &mut self,
-        pack_id: &[u8],
-        sticker_id: u32,
-    ) -> Result<Option<Sticker>, Error<S::Error>> {
-        Ok(self.store.sticker_pack(pack_id).await?.and_then(|pack| {
-            pack.manifest
-                .stickers
-                .iter()
-                .find(|&x| x.id == sticker_id)
-                .cloned()
-        }))
+        Ok(timestamp)
     }
 
-    /// Installs a sticker pack and notifies other registered devices
-    pub async fn install_sticker_pack(
+    /// Deletes a message sent by us for everyone in a [Thread] (a.k.a. remote delete).
+    ///
+    /// Only messages sent by this account can be deleted, so the message must be in the store,
+    /// otherwise [Error::UnknownOutgoingMessage] is returned before anything is sent. The message
+    /// is also removed from the local store.
+    ///
+    /// # Arguments
+    /// * `thread` - The thread the message was sent in
+    /// * `target_sent_timestamp` - The timestamp of the message to delete
+    pub async fn delete_message(
         &mut self,
-        pack_id: &[u8],
-        pack_key: &[u8],
+        thread: &Thread,
+        target_sent_timestamp: u64,
     ) -> Result<(), Error<S::Error>> {
-        let sticker_pack_operation = StickerPackOperation {
-            pack_id: Some(pack_id.to_vec()),
-            pack_key: Some(pack_key.to_vec()),
-            r#type: Some(sticker_pack_operation::Type::Install as i32),
-        };
-
-        let unidentified_websocket = self.unidentified_websocket().await?;
-        download_sticker_pack(
-            self.store.clone(),
-            unidentified_websocket,
-            &sticker_pack_operation,
-        )
-        .await?;
+        let own_aci: ServiceId = self.state.data.service_ids.aci().into();
+        match self.store.message(thread, target_sent_timestamp).await? {
+            Some(message) if message.metadata.sender == own_aci => (),
+            _ => return Err(Error::UnknownOutgoingMessage),
+        }
 
-        // Sync the change with the other devices
-        let sync_message = SyncMessage {
-            sticker_pack_operation: vec![sticker_pack_operation],
+        let data_message = DataMessage {
+            delete: Some(Delete {
+                target_sent_timestamp: Some(target_sent_timestamp),
+            }),
             ..Default::default()
         };
+        self.send_message_to_thread(thread, data_message, timestamp_now())
+            .await?;
 
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis() as u64;
-
-        self.send_message(self.state.data.service_ids.aci(), sync_message, timestamp)
+        self.store
+            .delete_message(thread, target_sent_timestamp)
             .await?;
+        debug!(%thread, target_sent_timestamp, "message deleted for everyone");
 
         Ok(())
     }
 
-    /// Removes an installed sticker pack
-    pub async fn remove_sticker_pack(
+    /// Reacts to a message in a [Thread] with an emoji, or removes a previous reaction.
+    ///
+    /// # Arguments
+    /// * `thread` - The thread the message was sent in
+    /// * `target_author` - The author of the message to react to
+    /// * `target_sent_timestamp` - The sent timestamp of the message to react to
+    /// * `emoji` - The emoji to react with. When removing a reaction, this must be the emoji
+    ///   that was previously sent.
+    /// * `remove` - Whether to remove the reaction instead of adding it
+    pub async fn send_reaction(
         &mut self,
-        pack_id: &[u8],
-        pack_key: &[u8],
+        thread: &Thread,
+        target_author: ServiceId,
+        target_sent_timestamp: u64,
+        emoji: &str,
+        remove: bool,
     ) -> Result<(), Error<S::Error>> {
-        // Sync the change with the other clients
-        let sync_message = SyncMessage {
-            sticker_pack_operation: vec![StickerPackOperation {
-                pack_id: Some(pack_id.to_vec()),
-                pack_key: Some(pack_key.to_vec()), // The pack key might not be neccesary in the message
-                r#type: Some(sticker_pack_operation::Type::Remove as i32),
-            }],
+        let timestamp = timestamp_now();
+        let data_message = DataMessage {
+            reaction: Some(Reaction {
+                emoji: Some(emoji.to_owned()),
+                remove: Some(remove),
+                target_author_aci: Some(target_author.service_id_string()),
+                target_sent_timestamp: Some(target_sent_timestamp),
+                ..Default::default()
+            }),
+            timestamp: Some(timestamp),
             ..Default::default()
         };
 
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis() as u64;
-
-        self.send_message(self.state.data.service_ids.aci(), sync_message, timestamp)
-            .await?;
-
-        self.store.remove_sticker_pack(pack_id).await?;
-
-        Ok(())
+        self.send_message_to_thread(thread, data_message, timestamp)
+            .await
     }
 
-    /// Send a poll to a group
+    /// Replies to a message in a [Thread], quoting it.
     ///
-    /// Creates and sends a poll message to the specified group with a question and answer options.
+    /// If the quoted message is in the store, its attachments are referenced in the quote so that
+    /// clients can display thumbnails.
     ///
     /// # Arguments
-    /// * `master_key_bytes` - The group's master key (32 bytes)
-    /// * `question` - The poll question
-    /// * `options` - Vector of answer options (up to 10)
-    /// * `allow_multiple` - Whether voters can select multiple options
+    /// * `thread` - The thread the quoted message was sent in
+    /// * `quote_author` - The ACI of the author of the quoted message
+    /// * `quote_timestamp` - The sent timestamp of the quoted message
+    /// * `quote_text` - The (possibly shortened) body of the quoted message
+    /// * `body` - The body of the reply
     ///
     /// # Returns
-    /// * `Ok(u64)` - The poll's timestamp (needed for voting/terminating)
-    /// * `Err(Error)` if the send fails
-    pub async fn send_poll(
+    /// The timestamp of the reply
+    pub async fn send_quote_reply(
         &mut self,
-        master_key_bytes: &[u8],
-        question: impl Into<String>,
-        options: Vec<String>,
-        allow_multiple: bool,
+        thread: &Thread,
+        quote_author: ServiceId,
+        quote_timestamp: u64,
+        quote_text: &str,
+        body: &str,
     ) -> Result<u64, Error<S::Error>> {
-        let poll_create = PollCreate {
-            question: Some(question.into()),
-            allow_multiple: Some(allow_multiple),
-            options,
+        self.compose_message()
+            .body(body)
+            .quote(quote_author, quote_timestamp, quote_text)
+            .send(thread)
+            .await
+    }
+
+    /// Builds the quote of a message, referencing its attachments if it is in the store so that
+    /// clients can display thumbnails
+    async fn quote(
+        &self,
+        thread: &Thread,
+        author: ServiceId,
+        timestamp: u64,
+        text: String,
+    ) -> Result<Quote, Error<S::Error>> {
+        let attachments = match self.store.message(thread, timestamp).await? {
+            Some(quoted) => quoted
+                .data_message()
+                .map(|data_message| {
+                    data_message
+                        .attachments
+                        .iter()
+                        .map(|attachment| quote::QuotedAttachment {
+                            content_type: attachment.content_type.clone(),
+                            file_name: attachment.file_name.clone(),
+                            thumbnail: attachment
+                                .content_type()
+                                .starts_with("image/")
+                                .then(|| attachment.clone()),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
         };
+        Ok(Quote {
+            id: Some(timestamp),
+            author_aci: Some(author.service_id_string()),
+            text: Some(text),
+            attachments,
+            r#type: Some(quote::Type::Normal.into()),
+            ..Default::default()
+        })
+    }
 
-        let data_message = DataMessage {
-            poll_create: Some(poll_create),
-            group_v2: Some(GroupContextV2 {
+    /// Sends a typing indicator to a [Thread].
+    ///
+    /// Clients are expected to send a `started` indicator while the user is typing (and repeat
+    /// it every few seconds), and a stopped indicator once the user stops typing or sends the
+    /// message.
+    ///
+    /// Nothing is sent if typing indicators are disabled in the
+    /// [account settings](Self::account_settings).
+    ///
+    /// # Arguments
+    /// * `thread` - The thread the user is typing in
+    /// * `started` - Whether the user started or stopped typing
+    pub async fn send_typing(
+        &mut self,
+        thread: &Thread,
+        started: bool,
+    ) -> Result<(), Error<S::Error>> {
+        if !self.account_settings().await?.typing_indicators {
+            return Ok(());
+        }
+
+        let action = if started {
+            typing_message::Action::Started
+        } else {
+            typing_message::Action::Stopped
+        };
+        let group_id = match thread {
+            Thread::Contact(_) => None,
+            Thread::Group(master_key_bytes) => Some(
+                GroupSecretParams::derive_from_master_key(GroupMasterKey::new(*master_key_bytes))
+                    .get_group_identifier()
+                    .to_vec(),
+            ),
+        };
+
+        let timestamp = timestamp_now();
+        let typing_message = TypingMessage {
+            timestamp: Some(timestamp),
+            action: Some(action.into()),
+            group_id,
+        };
+
+        self.send_message_to_thread(thread, typing_message, timestamp)
+            .await
+    }
+
+    /// Tells `recipient` that we read the messages sent at the given `timestamps`.
+    ///
+    /// Several messages can (and should) be marked as read at once. Nothing is sent if read
+    /// receipts are disabled in the [account settings](Self::account_settings).
+    ///
+    /// # Arguments
+    /// * `recipient` - The sender of the messages that were read
+    /// * `timestamps` - The sent timestamps of the messages that were read
+    pub async fn send_read_receipt(
+        &mut self,
+        recipient: impl Into<ServiceId>,
+        timestamps: Vec<u64>,
+    ) -> Result<(), Error<S::Error>> {
+        self.send_receipt(recipient.into(), receipt_message::Type::Read, timestamps)
+            .await
+    }
+
+    /// Tells `recipient` that we viewed the messages (e.g. view-once media or voice notes) sent
+    /// at the given `timestamps`.
+    ///
+    /// Like read receipts, nothing is sent if they are disabled in the
+    /// [account settings](Self::account_settings).
+    ///
+    /// # Arguments
+    /// * `recipient` - The sender of the messages that were viewed
+    /// * `timestamps` - The sent timestamps of the messages that were viewed
+    pub async fn send_viewed_receipt(
+        &mut self,
+        recipient: impl Into<ServiceId>,
+        timestamps: Vec<u64>,
+    ) -> Result<(), Error<S::Error>> {
+        self.send_receipt(recipient.into(), receipt_message::Type::Viewed, timestamps)
+            .await
+    }
+
+    async fn send_receipt(
+        &mut self,
+        recipient: ServiceId,
+        r#type: receipt_message::Type,
+        timestamps: Vec<u64>,
+    ) -> Result<(), Error<S::Error>> {
+        if timestamps.is_empty() {
+            return Ok(());
+        }
+        if matches!(
+            r#type,
+            receipt_message::Type::Read | receipt_message::Type::Viewed
+        ) && !self.account_settings().await?.read_receipts
+        {
+            trace!(?r#type, "read receipts are disabled, not sending receipt");
+            return Ok(());
+        }
+
+        let receipt_message = ReceiptMessage {
+            r#type: Some(r#type.into()),
+            timestamp: timestamps,
+        };
+
+        self.send_message(recipient, receipt_message, timestamp_now())
+            .await
+    }
+
+    /// Sends a message to a [Thread], either a contact or a group.
+    ///
+    /// For groups, the group context is added to the data message if it is missing.
+    async fn send_message_to_thread(
+        &mut self,
+        thread: &Thread,
+        message: impl Into<ContentBody>,
+        timestamp: u64,
+    ) -> Result<(), Error<S::Error>> {
+        let mut content_body = message.into();
+        match thread {
+            Thread::Contact(uuid) => {
+                self.send_message(ServiceId::Aci((*uuid).into()), content_body, timestamp)
+                    .await
+            }
+            Thread::Group(master_key_bytes) => {
+                if let ContentBody::DataMessage(data_message)
+                | ContentBody::EditMessage(EditMessage {
+                    data_message: Some(data_message),
+                    ..
+                }) = &mut content_body
+                {
+                    data_message.group_v2.get_or_insert_with(|| GroupContextV2 {
+                        master_key: Some(master_key_bytes.to_vec()),
+                        revision: Some(0),
+                        group_change: None,
+                    });
+                }
+                self.send_message_to_group(master_key_bytes, content_body, timestamp)
+                    .await
+            }
+        }
+    }
+
+    async fn restore_thread_timer(&mut self, thread: &Thread, content_body: &mut ContentBody) {
+        let store_expire_timer = self.store.expire_timer(thread).await.unwrap_or_default();
+        apply_thread_timer(content_body, store_expire_timer);
+    }
+
+    /// Computes the safety number (60 digits) of the conversation with a contact, to be compared
+    /// with the one shown on their device.
+    ///
+    /// Only contacts known by their ACI and whose identity key was received have a safety number,
+    /// this fails with [Error::UnknownRecipient] otherwise.
+    pub async fn safety_number(&self, other: &ServiceId) -> Result<String, Error<S::Error>> {
+        Ok(self.fingerprint(other).await?.display_string()?)
+    }
+
+    /// Returns the payload of the QR code of the safety number, to be scanned by the contact.
+    pub async fn safety_number_qr(&self, other: &ServiceId) -> Result<Vec<u8>, Error<S::Error>> {
+        Ok(self.fingerprint(other).await?.scannable.serialize()?)
+    }
+
+    /// Compares the payload of the QR code scanned from the device of a contact with the safety
+    /// number of the conversation.
+    ///
+    /// Fails if the QR code was not generated for this conversation or with another version.
+    pub async fn verify_safety_number(
+        &self,
+        other: &ServiceId,
+        scanned: &[u8],
+    ) -> Result<bool, Error<S::Error>> {
+        Ok(self.fingerprint(other).await?.scannable.compare(scanned)?)
+    }
+
+    async fn fingerprint(&self, other: &ServiceId) -> Result<Fingerprint, Error<S::Error>> {
+        let ServiceId::Aci(other_aci) = other else {
+            return Err(Error::UnknownRecipient);
+        };
+        let aci_protocol_store = self.store.aci_protocol_store();
+        let address = ProtocolAddress::new(other.service_id_string(), *DEFAULT_DEVICE_ID);
+        let their_identity = aci_protocol_store
+            .get_identity(&address)
+            .await?
+            .ok_or(Error::UnknownRecipient)?;
+        let our_identity_key_pair = aci_protocol_store.get_identity_key_pair().await?;
+
+        Ok(Fingerprint::new(
+            SAFETY_NUMBER_VERSION,
+            SAFETY_NUMBER_ITERATIONS,
+            Uuid::from(self.aci()).as_bytes(),
+            our_identity_key_pair.identity_key(),
+            Uuid::from(*other_aci).as_bytes(),
+            &their_identity,
+        )?)
+    }
+
+    /// Returns whether the identity key of a contact is trusted.
+    pub async fn identity_state(
+        &self,
+        service_id: &ServiceId,
+    ) -> Result<IdentityState, Error<S::Error>> {
+        let address = ProtocolAddress::new(service_id.service_id_string(), *DEFAULT_DEVICE_ID);
+        Ok(
+            match self
+                .store
+                .aci_protocol_store()
+                .identity_trust(&address)
+                .await?
+            {
+                Some((_, true)) => IdentityState::Trusted,
+                Some((_, false)) => IdentityState::Untrusted,
+                None => IdentityState::Unknown,
+            },
+        )
+    }
+
+    /// Trusts the identity key of a contact, e.g. once the safety number was verified or after it
+    /// changed.
+    ///
+    /// The key is typically the one of an [IdentityChange] or of an [Error::UntrustedIdentity].
+    pub async fn trust_identity(
+        &mut self,
+        service_id: &ServiceId,
+        key: IdentityKey,
+    ) -> Result<(), Error<S::Error>> {
+        let address = ProtocolAddress::new(service_id.service_id_string(), *DEFAULT_DEVICE_ID);
+        self.store
+            .aci_protocol_store()
+            .set_identity_trust(&address, &key, true)
+            .await?;
+        Ok(())
+    }
+
+    /// Untrusts the identity key of a contact: sending them messages fails with
+    /// [Error::UntrustedIdentity] until it is trusted again.
+    ///
+    /// Fails with [Error::UnknownRecipient] if no identity key is known for this contact.
+    pub async fn untrust_identity(
+        &mut self,
+        service_id: &ServiceId,
+    ) -> Result<(), Error<S::Error>> {
+        let address = ProtocolAddress::new(service_id.service_id_string(), *DEFAULT_DEVICE_ID);
+        let mut protocol_store = self.store.aci_protocol_store();
+        let (key, _trusted) = protocol_store
+            .identity_trust(&address)
+            .await?
+            .ok_or(Error::UnknownRecipient)?;
+        protocol_store
+            .set_identity_trust(&address, &key, false)
+            .await?;
+        Ok(())
+    }
+
+    /// Turns a sending error caused by an untrusted identity into [Error::UntrustedIdentity]
+    async fn sending_error(&self, error: MessageSenderError) -> Error<S::Error> {
+        let MessageSenderError::UntrustedIdentity { address } = &error else {
+            return error.into();
+        };
+        let (Some(service_id), Ok(Some((key, _trusted)))) = (
+            ServiceId::parse_from_service_id_string(address.name()),
+            self.store
+                .aci_protocol_store()
+                .identity_trust(address)
+                .await,
+        ) else {
+            return error.into();
+        };
+        Error::UntrustedIdentity { service_id, key }
+    }
+
+    /// Drops the sessions invalidated by a 409 (mismatched devices) or 410 (stale devices)
+    /// response, so that sending again fetches the pre-key bundles of the recipient's current
+    /// devices.
+    ///
+    /// Returns whether the error was a device mismatch, i.e. whether the send can be retried.
+    async fn drop_mismatched_sessions(
+        &self,
+        recipient: &ServiceId,
+        error: &MessageSenderError,
+    ) -> bool {
+        use libsignal_service::session_store::SessionStoreExt;
+
+        let devices = match error {
+            // missing devices have no session yet: their pre-key bundles are fetched when sending
+            MessageSenderError::ServiceError(ServiceError::MismatchedDevicesException(
+                mismatched,
+            )) => &mismatched.extra_devices,
+            MessageSenderError::ServiceError(ServiceError::StaleDevices(stale)) => {
+                &stale.stale_devices
+            }
+            _ => return false,
+        };
+
+        let aci_protocol_store = self.store.aci_protocol_store();
+        for device_id in devices {
+            let Ok(device_id) = DeviceId::try_from(*device_id) else {
+                continue;
+            };
+            let address = ProtocolAddress::new(recipient.service_id_string(), device_id);
+            if let Err(error) = aci_protocol_store.delete_session(&address).await {
+                warn!(%error, %address, "failed to delete session of mismatched device");
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Clears all sessions established with [recipient](ServiceId).
+    pub async fn clear_sessions(&self, recipient: &ServiceId) -> Result<(), Error<S::Error>> {
+        use libsignal_service::session_store::SessionStoreExt;
+        self.store
+            .aci_protocol_store()
+            .delete_all_sessions(recipient)
+            .await?;
+        self.store
+            .pni_protocol_store()
+            .delete_all_sessions(recipient)
+            .await?;
+        Ok(())
+    }
+
+    /// Downloads and decrypts a single attachment.
+    pub async fn get_attachment(
+        &self,
+        attachment_pointer: &AttachmentPointer,
+    ) -> Result<Vec<u8>, Error<S::Error>> {
+        let expected_digest = attachment_pointer
+            .digest
+            .as_ref()
+            .ok_or_else(|| Error::UnexpectedAttachmentChecksum)?;
+
+        let mut service = self.identified_push_service();
+        let mut attachment_stream = service.get_attachment(attachment_pointer).await?;
+
+        let plaintext_len = attachment_pointer.size.and_then(|len| len.try_into().ok());
+
+        // We need the whole file for the crypto to check out
+        let mut ciphertext = Vec::with_capacity(plaintext_len.unwrap_or(0));
+        let size_bytes = attachment_stream.read_to_end(&mut ciphertext).await?;
+        trace!(size_bytes, "downloaded encrypted attachment");
+
+        let digest = sha2::Sha256::digest(&ciphertext);
+        if &digest[..] != expected_digest {
+            return Err(Error::UnexpectedAttachmentChecksum);
+        }
+
+        let key: [u8; 64] = attachment_pointer.key().try_into()?;
+
+        // Offload decryption of large attachments to another thread.
+        // Chose arbitrary threshold here.
+        const DECRYPT_IN_THREAD_THRESHOLD: usize = 100 * 1024;
+        if ciphertext.len() > DECRYPT_IN_THREAD_THRESHOLD {
+            ciphertext = tokio::task::spawn_blocking(move || {
+                decrypt_in_place(key, &mut ciphertext).map(|_| ciphertext)
+            })
+            .await
+            .expect("decryption in another thread")?;
+        } else {
+            decrypt_in_place(key, &mut ciphertext)?;
+        };
+
+        if let Some(len) = plaintext_len {
+            if len < ciphertext.len() {
+                // remove padding
+                ciphertext.truncate(len);
+            }
+        }
+
+        Ok(ciphertext)
+    }
+
+    /// Downloads and decrypts a single attachment like [Self::get_attachment], retrying on
+    /// transient errors.
+    ///
+    /// Network errors, timeouts, rate limits and server errors are retried with an exponential
+    /// backoff. When the attachment cannot be found on its CDN, the alternate CDN is tried.
+    /// Integrity errors (wrong MAC or checksum) are never retried, as downloading the same
+    /// attachment again would not fix them.
+    ///
+    /// # Arguments
+    /// * `attachment_pointer` - The attachment to download
+    /// * `policy` - How many times and how fast to retry
+    pub async fn download_attachment_with_retry(
+        &self,
+        attachment_pointer: &AttachmentPointer,
+        policy: RetryPolicy,
+    ) -> Result<Vec<u8>, Error<S::Error>> {
+        let mut attachment_pointer = Cow::Borrowed(attachment_pointer);
+        let mut backoff = policy.initial_backoff;
+        let mut tried_alternate_cdn = false;
+        let mut attempt = 1;
+
+        loop {
+            let error = match self.get_attachment(&attachment_pointer).await {
+                Ok(attachment) => return Ok(attachment),
+                Err(error) if attempt >= policy.max_attempts => return Err(error),
+                Err(error) => error,
+            };
+
+            if is_attachment_not_found(&error) && !tried_alternate_cdn {
+                let Some(cdn_number) = alternate_cdn(attachment_pointer.cdn_number()) else {
+                    return Err(error);
+                };
+                debug!(%error, cdn_number, "attachment not found, trying alternate CDN");
+                attachment_pointer.to_mut().cdn_number = Some(cdn_number);
+                tried_alternate_cdn = true;
+            } else if is_transient_download_error(&error) {
+                warn!(%error, attempt, ?backoff, "failed to download attachment, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(policy.max_backoff);
+            } else {
+                return Err(error);
+            }
+
+            attempt += 1;
+        }
+    }
+
+    /// Downloads and decrypts a single attachment into `writer`, without buffering it in memory.
+    ///
+    /// This is meant for large attachments, see [Self::get_attachment] for small ones.
+    ///
+    /// The integrity of the attachment can only be verified once it has been downloaded: when an
+    /// error is returned, the data written so far must be discarded. In this case, the end of the
+    /// attachment is never written.
+    ///
+    /// # Arguments
+    /// * `attachment_pointer` - The attachment to download
+    /// * `writer` - Where to write the decrypted attachment
+    pub async fn download_attachment_to<W: AsyncWrite + Unpin>(
+        &self,
+        attachment_pointer: &AttachmentPointer,
+        mut writer: W,
+    ) -> Result<(), Error<S::Error>> {
+        let expected_digest = attachment_pointer
+            .digest
+            .as_ref()
+            .ok_or_else(|| Error::UnexpectedAttachmentChecksum)?;
+        let key: [u8; 64] = attachment_pointer.key().try_into()?;
+        let plaintext_len = attachment_pointer.size.and_then(|len| len.try_into().ok());
+
+        let mut service = self.identified_push_service();
+        let mut attachment_stream = service.get_attachment(attachment_pointer).await?;
+
+        let mut decryptor = AttachmentDecryptor::new(key, plaintext_len);
+        let mut buf = vec![0; 64 * 1024];
+        let mut size_bytes = 0;
+        loop {
+            let n = attachment_stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            size_bytes += n;
+            writer.write_all(&decryptor.update(&buf[..n])).await?;
+        }
+        trace!(size_bytes, "downloaded encrypted attachment");
+
+        let (plaintext, digest) = decryptor.finalize()?;
+        if &digest[..] != expected_digest {
+            return Err(Error::UnexpectedAttachmentChecksum);
+        }
+        writer.write_all(&plaintext).await?;
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Gets the metadata of a sticker
+    pub async fn sticker_metadata(
+        &mut self,
+        pack_id: &[u8],
+        sticker_id: u32,
+    ) -> Result<Option<Sticker>, Error<S::Error>> {
+        Ok(self.store.sticker_pack(pack_id).await?.and_then(|pack| {
+            pack.manifest
+                .stickers
+                .iter()
+                .find(|&x| x.id == sticker_id)
+                .cloned()
+        }))
+    }
+
+    /// Installs a sticker pack and notifies other registered devices
+    ///
+    /// The manifest and the images of the stickers are downloaded from the CDN and stored.
+    pub async fn install_sticker_pack(
+        &mut self,
+        pack_id: &[u8],
+        pack_key: &[u8],
+    ) -> Result<StickerPack, Error<S::Error>> {
+        let sticker_pack_operation = StickerPackOperation {
+            pack_id: Some(pack_id.to_vec()),
+            pack_key: Some(pack_key.to_vec()),
+            r#type: Some(sticker_pack_operation::Type::Install as i32),
+        };
+
+        let unidentified_websocket = self.unidentified_websocket().await?;
+        let sticker_pack = download_sticker_pack(
+            self.store.clone(),
+            unidentified_websocket,
+            &sticker_pack_operation,
+        )
+        .await?;
+
+        // Sync the change with the other devices
+        let sync_message = SyncMessage {
+            sticker_pack_operation: vec![sticker_pack_operation],
+            ..Default::default()
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64;
+
+        self.send_message(self.state.data.service_ids.aci(), sync_message, timestamp)
+            .await?;
+
+        Ok(sticker_pack)
+    }
+
+    /// Returns the installed sticker packs
+    pub async fn installed_sticker_packs(&self) -> Result<Vec<StickerPack>, Error<S::Error>> {
+        Ok(self
+            .store
+            .sticker_packs()
+            .await?
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Sends a sticker to a [Thread].
+    ///
+    /// The image of the sticker is taken from the installed sticker pack, or downloaded from the
+    /// CDN if the pack is not installed, and uploaded as the attachment of the sticker.
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send_sticker(
+        &mut self,
+        thread: &Thread,
+        pack_id: &[u8],
+        pack_key: &[u8],
+        sticker_id: u32,
+    ) -> Result<u64, Error<S::Error>> {
+        let sticker = self.sticker_metadata(pack_id, sticker_id).await?;
+        let (emoji, content_type, bytes) = match sticker {
+            Some(Sticker {
+                emoji,
+                content_type,
+                bytes: Some(bytes),
+                ..
+            }) => (emoji, content_type, bytes),
+            sticker => {
+                let mut unidentified_websocket = self.unidentified_websocket().await?;
+                let bytes = download_sticker::<S>(
+                    &mut unidentified_websocket,
+                    pack_id,
+                    pack_key,
+                    sticker_id,
+                )
+                .await?;
+                let (emoji, content_type) = sticker
+                    .map(|sticker| (sticker.emoji, sticker.content_type))
+                    .unwrap_or_default();
+                (emoji, content_type, bytes)
+            }
+        };
+
+        let spec = AttachmentSpec {
+            content_type: content_type.unwrap_or_else(|| "image/webp".to_owned()),
+            length: bytes.len(),
+            file_name: None,
+            preview: None,
+            voice_note: None,
+            borderless: None,
+            width: None,
+            height: None,
+            caption: None,
+            blur_hash: None,
+        };
+        let attachment = self.upload_attachment(spec, bytes).await?;
+
+        let timestamp = timestamp_now();
+        let data_message = DataMessage {
+            sticker: Some(data_message::Sticker {
+                pack_id: Some(pack_id.to_vec()),
+                pack_key: Some(pack_key.to_vec()),
+                sticker_id: Some(sticker_id),
+                data: Some(attachment),
+                emoji,
+            }),
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
+
+        self.send_message_to_thread(thread, data_message, timestamp)
+            .await?;
+
+        Ok(timestamp)
+    }
+
+    /// Removes an installed sticker pack
+    pub async fn remove_sticker_pack(
+        &mut self,
+        pack_id: &[u8],
+        pack_key: &[u8],
+    ) -> Result<(), Error<S::Error>> {
+        // Sync the change with the other clients
+        let sync_message = SyncMessage {
+            sticker_pack_operation: vec![StickerPackOperation {
+                pack_id: Some(pack_id.to_vec()),
+                pack_key: Some(pack_key.to_vec()), // The pack key might not be neccesary in the message
+                r#type: Some(sticker_pack_operation::Type::Remove as i32),
+            }],
+            ..Default::default()
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64;
+
+        self.send_message(self.state.data.service_ids.aci(), sync_message, timestamp)
+            .await?;
+
+        self.store.remove_sticker_pack(pack_id).await?;
+
+        Ok(())
+    }
+
+    /// Send a poll to a group
+    ///
+    /// Creates and sends a poll message to the specified group with a question and answer options.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's master key (32 bytes)
+    /// * `question` - The poll question
+    /// * `options` - Vector of answer options (up to 10)
+    /// * `allow_multiple` - Whether voters can select multiple options
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - The poll's timestamp (needed for voting/terminating)
+    /// * `Err(Error)` if the send fails
+    pub async fn send_poll(
+        &mut self,
+        master_key_bytes: &[u8],
+        question: impl Into<String>,
+        options: Vec<String>,
+        allow_multiple: bool,
+    ) -> Result<u64, Error<S::Error>> {
+        let poll_create = PollCreate {
+            question: Some(question.into()),
+            allow_multiple: Some(allow_multiple),
+            options,
+        };
+
+        let data_message = DataMessage {
+            poll_create: Some(poll_create),
+            group_v2: Some(GroupContextV2 {
                 master_key: Some(master_key_bytes.to_vec()),
                 revision: Some(0),
                 ..Default::default()
@@ -1329,7 +3891,9 @@ impl<S: Store> Manager<S, Registered> {
 
     /// Vote on an existing poll in a group
     ///
-    /// Sends a vote message to the specified group poll.
+    /// Sends a vote message to the specified group poll. Voting again replaces the previous vote
+    /// (with all its options): each vote carries a counter incremented from our previous vote, so
+    /// that the latest one wins.
     ///
     /// # Arguments
     /// * `master_key_bytes` - The group's master key (32 bytes)
@@ -1347,13 +3911,21 @@ impl<S: Store> Manager<S, Registered> {
         poll_timestamp: u64,
         selected_options: Vec<u32>,
     ) -> Result<(), Error<S::Error>> {
-        let vote_count = selected_options.len() as u32;
+        let thread = Thread::Group(master_key_bytes.try_into()?);
+        let self_aci: ServiceId = self.state.data.service_ids.aci().into();
+        let previous_vote_count = self
+            .store
+            .poll_votes(&thread, poll_timestamp)
+            .await?
+            .into_iter()
+            .find(|vote| vote.voter == self_aci)
+            .map_or(0, |vote| vote.vote_count);
 
         let poll_vote = PollVote {
             target_author_aci_binary: Some(poll_author_aci.service_id_binary()),
             target_sent_timestamp: Some(poll_timestamp),
             option_indexes: selected_options,
-            vote_count: Some(vote_count),
+            vote_count: Some(previous_vote_count + 1),
         };
 
         let data_message = DataMessage {
@@ -1375,6 +3947,39 @@ impl<S: Store> Manager<S, Registered> {
             .await
     }
 
+    /// Retract our vote on a poll in a group, by voting for no option.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's master key (32 bytes)
+    /// * `poll_timestamp` - The timestamp of the original poll message
+    ///
+    /// # Returns
+    /// * `Ok(())` on success
+    /// * `Err(Error::UnknownPoll)` if the poll is not stored (its author is needed)
+    /// * `Err(Error)` if the send fails
+    pub async fn retract_vote(
+        &mut self,
+        master_key_bytes: &[u8],
+        poll_timestamp: u64,
+    ) -> Result<(), Error<S::Error>> {
+        let thread = Thread::Group(master_key_bytes.try_into()?);
+        let Some(ServiceId::Aci(poll_author_aci)) = self
+            .store
+            .message(&thread, poll_timestamp)
+            .await?
+            .map(|poll| poll.metadata.sender)
+        else {
+            return Err(Error::UnknownPoll);
+        };
+        self.vote_on_poll(
+            master_key_bytes,
+            poll_author_aci,
+            poll_timestamp,
+            Vec::new(),
+        )
+        .await
+    }
+
     /// Terminate an existing poll in a group
     ///
     /// Closes the poll, preventing further votes. Only the poll creator can terminate a poll.
@@ -1414,6 +4019,37 @@ impl<S: Store> Manager<S, Registered> {
             .await
     }
 
+    /// Returns the current results of a poll, from the votes received so far.
+    ///
+    /// Each voter counts once, with their latest vote, and only for their first option if the
+    /// poll doesn't allow selecting several. Once the poll is terminated (see
+    /// [Received::PollTerminated]), the results are final: later votes are ignored.
+    ///
+    /// # Arguments
+    /// * `thread` - The thread the poll was sent in
+    /// * `poll_timestamp` - The timestamp of the poll message
+    ///
+    /// # Returns
+    /// * `Ok(PollResults)` - The voters of each option
+    /// * `Err(Error::UnknownPoll)` if the poll is not stored
+    pub async fn poll_results(
+        &self,
+        thread: &Thread,
+        poll_timestamp: u64,
+    ) -> Result<PollResults, Error<S::Error>> {
+        let poll_message = self.store.message(thread, poll_timestamp).await?;
+        let Some(poll) = poll_message
+            .as_ref()
+            .and_then(|message| message.data_message())
+            .and_then(|data_message| data_message.poll_create.as_ref())
+        else {
+            return Err(Error::UnknownPoll);
+        };
+        let votes = self.store.poll_votes(thread, poll_timestamp).await?;
+        let terminated_at = self.store.poll_termination(thread, poll_timestamp).await?;
+        Ok(PollResults::tally(poll, &votes, terminated_at))
+    }
+
     pub async fn send_session_reset(
         &mut self,
         recipient: &ServiceId,
@@ -1430,6 +4066,38 @@ impl<S: Store> Manager<S, Registered> {
         Ok(())
     }
 
+    /// Fetches the current state of a GV2 group from the server and saves it in the store.
+    ///
+    /// Unlike the lookups done when sending or receiving messages, this always does a round-trip
+    /// to the server, so it works for groups that were never seen locally (e.g. created on another
+    /// device) and picks up changes made elsewhere.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    ///
+    /// # Returns
+    /// * `Ok(Group)` - The decrypted group, as persisted in the store
+    /// * `Err(Error)` if the group could not be fetched (e.g. we are not a member)
+    pub async fn fetch_group(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+    ) -> Result<Group, Error<S::Error>> {
+        debug!("fetching group from server");
+
+        let mut groups_manager = self.groups_manager().await?;
+        let encrypted_group = groups_manager
+            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
+            .await?;
+        let group = decrypt_group_state(master_key_bytes, encrypted_group)?;
+
+        self.store.save_group(*master_key_bytes, group).await?;
+
+        self.store
+            .group(*master_key_bytes)
+            .await?
+            .ok_or(Error::UnknownGroup)
+    }
+
     /// Creates a new GV2 group with the specified title and members.
     ///
     /// # Arguments
@@ -1438,6 +4106,7 @@ impl<S: Store> Manager<S, Registered> {
     ///
     /// # Returns
     /// * `Ok([u8; 32])` - The group's master key bytes (store this!)
+    /// * `Err(Error::GroupValidation)` if the title is too long for the server
     /// * `Err(Error)` if group creation fails
     ///
     /// # Example
@@ -1466,6 +4135,7 @@ impl<S: Store> Manager<S, Registered> {
         let master_key_bytes: [u8; 32] = rand::random();
         let group_master_key = GroupMasterKey::new(master_key_bytes);
         let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
+        GroupField::Title.validate(&GroupOperations::new(group_secret_params), &title)?;
 
         let server_public_params = self
             .state
@@ -1681,13 +4351,16 @@ impl<S: Store> Manager<S, Registered> {
 
     /// Adds a member to an existing GV2 group.
     ///
+    /// The member is added with the default role, see [Manager::add_group_member_with_role] to
+    /// add administrators.
+    ///
     /// # Arguments
     /// * `master_key_bytes` - The group's 32-byte master key
     /// * `member_aci` - The ACI of the member to add
     /// * `member_profile_key` - The profile key of the member to add
     ///
     /// # Returns
-    /// * `Ok(())` on success
+    /// * `Ok(u32)` - The new revision of the group
     /// * `Err(Error)` if the add operation fails
     ///
     /// # Example
@@ -1705,25 +4378,64 @@ impl<S: Store> Manager<S, Registered> {
         master_key_bytes: &[u8; 32],
         member_aci: Aci,
         member_profile_key: Option<ProfileKey>,
-    ) -> Result<(), Error<S::Error>> {
-        use libsignal_service::groups_v2::{GroupOperations, Role};
+    ) -> Result<u32, Error<S::Error>> {
+        self.add_group_member_with_role(
+            master_key_bytes,
+            member_aci,
+            member_profile_key,
+            GroupMemberRole::Default,
+        )
+        .await
+    }
 
-        info!(aci = %member_aci.service_id_string(), "adding member to group");
+    /// Adds a member with the given role to an existing GV2 group.
+    ///
+    /// Without a profile key (or if no profile key credential can be obtained for it), the member
+    /// is invited as a pending member with the same role, and gets it once the invite is accepted.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `member_aci` - The ACI of the member to add
+    /// * `member_profile_key` - The profile key of the member to add
+    /// * `role` - The role of the member in the group
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error)` if the add operation fails
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use presage::Manager;
+    /// # use presage::model::groups::GroupMemberRole;
+    /// # async fn example<S: presage::store::Store>(mut manager: Manager<S, presage::manager::Registered>) {
+    /// # let master_key_bytes = [0u8; 32];
+    /// # let member_aci = todo!();
+    /// # let member_profile_key = todo!();
+    /// manager
+    ///     .add_group_member_with_role(
+    ///         &master_key_bytes,
+    ///         member_aci,
+    ///         member_profile_key,
+    ///         GroupMemberRole::Administrator,
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn add_group_member_with_role(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        member_aci: Aci,
+        member_profile_key: Option<ProfileKey>,
+        role: GroupMemberRole,
+    ) -> Result<u32, Error<S::Error>> {
+        info!(aci = %member_aci.service_id_string(), ?role, "adding member to group");
 
-        let group_master_key = GroupMasterKey::new(*master_key_bytes);
-        let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
         let server_public_params = self
             .state
             .service_configuration()
             .zkgroup_server_public_params;
 
-        // Fetch current group to get revision
-        let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
-            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
-            .await?;
-        let current_revision = current_group.revision;
-
         // Try to fetch credential if we have a profile key
         let credential = if let Some(profile_key) = member_profile_key {
             match self
@@ -1741,387 +4453,920 @@ impl<S: Store> Manager<S, Registered> {
             None
         };
 
+        let self_aci = self.state.data.service_ids.aci();
+
+        self.apply_group_change(master_key_bytes, |group_ops, current_group| {
+            debug!(
+                current_revision = current_group.revision,
+                has_credential = credential.is_some(),
+                "building add member action"
+            );
+
+            if let Some(cred) = credential {
+                // Build add member action with credential presentation (full member)
+                let add_action = group_ops.build_add_member_action_with_credential(
+                    &cred,
+                    role.into(),
+                    &server_public_params,
+                );
+                Ok(group_change::Actions {
+                    add_members: vec![add_action],
+                    ..Default::default()
+                })
+            } else {
+                // No credential - add as pending invite
+                let add_pending_action = group_ops
+                    .build_add_pending_member_action(member_aci, self_aci, role.into())
+                    .map_err(|_| {
+                        Error::ServiceError(libsignal_service::prelude::ServiceError::GroupsV2Error)
+                    })?;
+                Ok(group_change::Actions {
+                    add_pending_members: vec![add_pending_action],
+                    ..Default::default()
+                })
+            }
+        })
+        .await
+    }
+
+    /// Removes a member from an existing GV2 group.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `member_aci` - The ACI of the member to remove
+    ///
+    /// # Returns
+    /// * `Ok(())` on success
+    /// * `Err(Error)` if the remove operation fails
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use presage::Manager;
+    /// # async fn example<S: presage::store::Store>(mut manager: Manager<S, presage::manager::Registered>) {
+    /// # let master_key_bytes = [0u8; 32];
+    /// # let member_aci = todo!();
+    /// manager.remove_group_member(&master_key_bytes, member_aci).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn remove_group_member(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        member_aci: Aci,
+    ) -> Result<(), Error<S::Error>> {
+        info!(aci = %member_aci.service_id_string(), "removing member from group");
+
+        let group_master_key = GroupMasterKey::new(*master_key_bytes);
+        let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
+
+        // Fetch current group to get revision
+        let mut groups_manager = self.groups_manager().await?;
+        let current_group = groups_manager
+            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
+            .await?;
+        let current_revision = current_group.revision;
+
+        // Build remove member action
         let group_ops = GroupOperations::new(group_secret_params);
+        let remove_action = group_ops
+            .build_remove_member_action(member_aci)
+            .map_err(|_| {
+                Error::ServiceError(libsignal_service::prelude::ServiceError::GroupsV2Error)
+            })?;
+
+        // Build actions
+        let actions = libsignal_service::proto::group_change::Actions {
+            revision: current_revision + 1,
+            delete_members: vec![remove_action],
+            ..Default::default()
+        };
+
+        // Modify the group
+        groups_manager
+            .modify_group(&mut rand::rng(), group_secret_params, actions)
+            .await?;
+
+        // Refresh local group state
+        if let Ok(Some(group)) =
+            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
+        {
+            debug!(group_title = %group.title, member_count = group.members.len(), "group updated after removing member");
+        }
+
+        Ok(())
+    }
+
+    /// Leaves a GV2 group.
+    ///
+    /// If we are the last administrator of the group, the member who joined the earliest is
+    /// promoted to administrator in the same change. The group is kept locally, without us as a
+    /// member (see [Group::is_member]).
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    ///
+    /// # Returns
+    /// * `Ok(())` on success
+    /// * `Err(Error::NoGroupAdministratorSuccessor)` if we are the last administrator and there is
+    ///   no other member to promote
+    /// * `Err(Error)` if the leave operation fails
+    pub async fn leave_group(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+    ) -> Result<(), Error<S::Error>> {
+        use libsignal_service::groups_v2::Role;
+
+        info!("leaving group");
+
         let self_aci = self.state.data.service_ids.aci();
+        let group_secret_params =
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(*master_key_bytes));
+
+        let revision = self
+            .apply_group_change(master_key_bytes, |group_ops, current_group| {
+                let other_admins = current_group
+                    .members
+                    .iter()
+                    .any(|member| member.aci != self_aci && member.role == Role::Administrator);
+
+                let modify_member_roles = if current_group.is_administrator(self_aci) && !other_admins {
+                    let successor = current_group
+                        .members
+                        .iter()
+                        .filter(|member| member.aci != self_aci)
+                        .min_by_key(|member| member.joined_at_revision)
+                        .ok_or(Error::NoGroupAdministratorSuccessor)?;
+                    info!(successor = %successor.aci.service_id_string(), "promoting successor to administrator");
+                    vec![group_change::actions::ModifyMemberRoleAction {
+                        user_id: zkgroup::serialize(
+                            &group_secret_params.encrypt_service_id(successor.aci.into()),
+                        ),
+                        role: Role::Administrator.into(),
+                    }]
+                } else {
+                    Vec::new()
+                };
+
+                let remove_action = group_ops
+                    .build_remove_member_action(self_aci)
+                    .map_err(|_| {
+                        Error::ServiceError(libsignal_service::prelude::ServiceError::GroupsV2Error)
+                    })?;
+
+                Ok(group_change::Actions {
+                    modify_member_roles,
+                    delete_members: vec![remove_action],
+                    ..Default::default()
+                })
+            })
+            .await?;
+
+        // We cannot fetch the group anymore, so update the stored one ourselves
+        if let Some(mut group) = self.store.group(*master_key_bytes).await? {
+            group.members.retain(|member| member.aci != self_aci);
+            group.revision = revision;
+            self.store.save_group(*master_key_bytes, group).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Updates the title of an existing GV2 group.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `new_title` - The new title for the group
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error::GroupValidation)` if it is too long for the server
+    /// * `Err(Error)` if the update operation fails
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use presage::Manager;
+    /// # async fn example<S: presage::store::Store>(mut manager: Manager<S, presage::manager::Registered>) {
+    /// # let master_key_bytes = [0u8; 32];
+    /// let revision = manager.update_group_title(&master_key_bytes, "New Group Name").await.unwrap();
+    /// # }
+    /// ```
+    pub async fn update_group_title(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        new_title: impl Into<String>,
+    ) -> Result<u32, Error<S::Error>> {
+        let new_title = new_title.into();
+        info!(%new_title, "updating group title");
+        GroupField::Title.validate(&group_operations(master_key_bytes), &new_title)?;
+
+        self.apply_group_change(master_key_bytes, |group_ops, _| {
+            Ok(group_change::Actions {
+                modify_title: Some(group_change::actions::ModifyTitleAction {
+                    title: group_ops.encrypt_title(&new_title, &mut rand::rng()),
+                }),
+                ..Default::default()
+            })
+        })
+        .await
+    }
+
+    /// Sets or removes the avatar of a GV2 group.
+    ///
+    /// Only removing the avatar, by passing an empty image, is supported for now: uploading the
+    /// encrypted avatar to the CDN requires the group avatar upload form, which libsignal-service
+    /// doesn't expose yet, so [Error::GroupAvatarUploadUnsupported] is returned for other images.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `image` - The new avatar, or empty to remove the current one
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn set_group_avatar(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        image: Vec<u8>,
+    ) -> Result<u32, Error<S::Error>> {
+        if !image.is_empty() {
+            return Err(Error::GroupAvatarUploadUnsupported);
+        }
+
+        info!("removing group avatar");
+
+        self.apply_group_change(master_key_bytes, |_, _| {
+            Ok(group_change::Actions {
+                modify_avatar: Some(group_change::actions::ModifyAvatarAction {
+                    avatar: String::new(),
+                }),
+                ..Default::default()
+            })
+        })
+        .await
+    }
+
+    /// Sets the disappearing messages timer for a GV2 group.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `duration_seconds` - Timer duration in seconds (0 to disable)
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn set_disappearing_messages_timer(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        duration_seconds: u32,
+    ) -> Result<u32, Error<S::Error>> {
+        info!(duration_seconds, "setting disappearing messages timer");
+
+        let revision = self
+            .apply_group_change(master_key_bytes, |group_ops, _| {
+                let timer = Timer {
+                    duration: duration_seconds,
+                };
+                Ok(group_change::Actions {
+                    modify_disappearing_messages_timer: Some(
+                        group_change::actions::ModifyDisappearingMessagesTimerAction {
+                            timer: group_ops
+                                .encrypt_disappearing_message_timer(&timer, &mut rand::rng()),
+                        },
+                    ),
+                    ..Default::default()
+                })
+            })
+            .await?;
+
+        self.store
+            .update_expire_timer(
+                &Thread::Group(*master_key_bytes),
+                duration_seconds,
+                revision,
+            )
+            .await?;
 
-        debug!(
-            current_revision,
-            has_credential = credential.is_some(),
-            "building add member action"
-        );
+        Ok(revision)
+    }
 
-        let actions = if let Some(cred) = credential {
-            // Build add member action with credential presentation (full member)
-            let add_action = group_ops.build_add_member_action_with_credential(
-                &cred,
-                Role::Default,
-                &server_public_params,
-            );
-            libsignal_service::proto::group_change::Actions {
-                revision: current_revision + 1,
-                add_members: vec![add_action],
-                ..Default::default()
+    /// Sets the disappearing messages timer of a [Thread].
+    ///
+    /// For contacts, this sends an expiration timer update to the contact. For groups, this
+    /// changes the group on the server (see [Self::set_disappearing_messages_timer]).
+    /// The timer is recorded in the store, so that messages sent afterwards in the thread use it.
+    ///
+    /// # Arguments
+    /// * `thread` - The thread to set the timer of
+    /// * `seconds` - Timer duration in seconds (0 to disable)
+    pub async fn set_expiration_timer(
+        &mut self,
+        thread: &Thread,
+        seconds: u32,
+    ) -> Result<(), Error<S::Error>> {
+        match thread {
+            Thread::Contact(uuid) => {
+                let timestamp = timestamp_now();
+                let data_message = DataMessage {
+                    flags: Some(DataMessageFlags::ExpirationTimerUpdate as u32),
+                    expire_timer: Some(seconds),
+                    timestamp: Some(timestamp),
+                    ..Default::default()
+                };
+                // the timer version is bumped when sending, and the timer saved with the message
+                self.send_message(ServiceId::Aci((*uuid).into()), data_message, timestamp)
+                    .await
             }
-        } else {
-            // No credential - add as pending invite
-            let add_pending_action = group_ops
-                .build_add_pending_member_action(member_aci, self_aci, Role::Default)
-                .map_err(|_| {
-                    Error::ServiceError(libsignal_service::prelude::ServiceError::GroupsV2Error)
-                })?;
-            libsignal_service::proto::group_change::Actions {
-                revision: current_revision + 1,
-                add_pending_members: vec![add_pending_action],
-                ..Default::default()
+            Thread::Group(master_key_bytes) => {
+                self.set_disappearing_messages_timer(master_key_bytes, seconds)
+                    .await?;
+                Ok(())
             }
-        };
-
-        // Modify the group
-        groups_manager
-            .modify_group(&mut rand::rng(), group_secret_params, actions)
-            .await?;
-
-        // Refresh local group state
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
-        {
-            debug!(group_title = %group.title, member_count = group.members.len(), "group updated after adding member");
         }
-
-        Ok(())
     }
 
-    /// Removes a member from an existing GV2 group.
+    /// Updates the description of an existing GV2 group.
     ///
     /// # Arguments
     /// * `master_key_bytes` - The group's 32-byte master key
-    /// * `member_aci` - The ACI of the member to remove
+    /// * `description` - The new description (empty string to clear)
     ///
     /// # Returns
-    /// * `Ok(())` on success
-    /// * `Err(Error)` if the remove operation fails
-    ///
-    /// # Example
-    /// ```no_run
-    /// # use presage::Manager;
-    /// # async fn example<S: presage::store::Store>(mut manager: Manager<S, presage::manager::Registered>) {
-    /// # let master_key_bytes = [0u8; 32];
-    /// # let member_aci = todo!();
-    /// manager.remove_group_member(&master_key_bytes, member_aci).await.unwrap();
-    /// # }
-    /// ```
-    pub async fn remove_group_member(
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error::GroupValidation)` if it is too long for the server
+    /// * `Err(Error)` if the update operation fails
+    pub async fn update_group_description(
         &mut self,
         master_key_bytes: &[u8; 32],
-        member_aci: Aci,
-    ) -> Result<(), Error<S::Error>> {
-        use libsignal_service::groups_v2::GroupOperations;
-
-        info!(aci = %member_aci.service_id_string(), "removing member from group");
+        description: impl Into<String>,
+    ) -> Result<u32, Error<S::Error>> {
+        let description = description.into();
+        info!(clear = description.is_empty(), "updating group description");
+        if !description.is_empty() {
+            GroupField::Description.validate(&group_operations(master_key_bytes), &description)?;
+        }
 
-        let group_master_key = GroupMasterKey::new(*master_key_bytes);
-        let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
+        self.apply_group_change(master_key_bytes, |group_ops, _| {
+            // an empty blob removes the description altogether
+            let description = if description.is_empty() {
+                Vec::new()
+            } else {
+                group_ops.encrypt_description(&description, &mut rand::rng())
+            };
+            Ok(group_change::Actions {
+                modify_description: Some(group_change::actions::ModifyDescriptionAction {
+                    description,
+                }),
+                ..Default::default()
+            })
+        })
+        .await
+    }
 
-        // Fetch current group to get revision
-        let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
-            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
+    /// Sets the description of a GV2 group.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `description` - The new description (empty string to clear)
+    #[deprecated(note = "use update_group_description")]
+    pub async fn set_group_description(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        description: impl Into<String>,
+    ) -> Result<(), Error<S::Error>> {
+        self.update_group_description(master_key_bytes, description)
             .await?;
-        let current_revision = current_group.revision;
+        Ok(())
+    }
 
-        // Build remove member action
-        let group_ops = GroupOperations::new(group_secret_params);
-        let remove_action = group_ops
-            .build_remove_member_action(member_aci)
-            .map_err(|_| {
-                Error::ServiceError(libsignal_service::prelude::ServiceError::GroupsV2Error)
-            })?;
+    /// Sets access control for a GV2 group.
+    ///
+    /// Controls who can add members, who can edit the group information (title, description,
+    /// avatar, disappearing messages timer) and who can join the group with its invite link. Only
+    /// administrators of the group can change it.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `add_members` - Who can add members
+    /// * `edit_info` - Who can edit the group information
+    /// * `add_from_invite_link` - Who can join with the invite link: [AccessRequired::Any] to
+    ///   join directly, [AccessRequired::Administrator] to require the approval of an
+    ///   administrator and [AccessRequired::Unsatisfiable] to disable the link
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error::NotGroupAdministrator)` if we are not an administrator of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn set_group_access_control(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        add_members: AccessRequired,
+        edit_info: AccessRequired,
+        add_from_invite_link: AccessRequired,
+    ) -> Result<u32, Error<S::Error>> {
+        info!(
+            ?add_members,
+            ?edit_info,
+            ?add_from_invite_link,
+            "setting group access control"
+        );
 
-        // Build actions
-        let actions = libsignal_service::proto::group_change::Actions {
-            revision: current_revision + 1,
-            delete_members: vec![remove_action],
-            ..Default::default()
+        let self_aci = self.state.data.service_ids.aci();
+        let access = |access: AccessRequired| {
+            i32::from(libsignal_service::groups_v2::AccessRequired::from(access))
         };
 
-        // Modify the group
-        groups_manager
-            .modify_group(&mut rand::rng(), group_secret_params, actions)
-            .await?;
+        self.apply_group_change(master_key_bytes, |_, current_group| {
+            if !current_group.is_administrator(self_aci) {
+                return Err(Error::NotGroupAdministrator);
+            }
 
-        // Refresh local group state
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
-        {
-            debug!(group_title = %group.title, member_count = group.members.len(), "group updated after removing member");
-        }
+            let link_enabled = matches!(
+                add_from_invite_link,
+                AccessRequired::Any | AccessRequired::Administrator
+            );
+            let modify_invite_link_password = (link_enabled
+                && current_group.invite_link_password.is_empty())
+            .then(|| group_change::actions::ModifyInviteLinkPasswordAction {
+                invite_link_password: new_invite_link_password(),
+            });
 
-        Ok(())
+            Ok(group_change::Actions {
+                modify_member_access: Some(
+                    group_change::actions::ModifyMembersAccessControlAction {
+                        members_access: access(add_members),
+                    },
+                ),
+                modify_attributes_access: Some(
+                    group_change::actions::ModifyAttributesAccessControlAction {
+                        attributes_access: access(edit_info),
+                    },
+                ),
+                modify_add_from_invite_link_access: Some(
+                    group_change::actions::ModifyAddFromInviteLinkAccessControlAction {
+                        add_from_invite_link_access: access(add_from_invite_link),
+                    },
+                ),
+                modify_invite_link_password,
+                ..Default::default()
+            })
+        })
+        .await
     }
 
-    /// Updates the title of an existing GV2 group.
+    /// Sets whether the group is announcements-only (only admins can send messages).
+    ///
+    /// Only administrators of the group can change it. Once enabled, sending a message in the
+    /// group as a regular member fails with [Error::GroupAnnouncementsOnly].
     ///
     /// # Arguments
     /// * `master_key_bytes` - The group's 32-byte master key
-    /// * `new_title` - The new title for the group
+    /// * `announcements_only` - true to restrict messages to admins only
     ///
     /// # Returns
-    /// * `Ok(())` on success
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error::NotGroupAdministrator)` if we are not an administrator of the group
     /// * `Err(Error)` if the update operation fails
+    pub async fn set_group_announcements_only(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+        announcements_only: bool,
+    ) -> Result<u32, Error<S::Error>> {
+        info!(announcements_only, "setting group announcements only");
+
+        let self_aci = self.state.data.service_ids.aci();
+
+        self.apply_group_change(master_key_bytes, |_, current_group| {
+            if !current_group.is_administrator(self_aci) {
+                return Err(Error::NotGroupAdministrator);
+            }
+            Ok(group_change::Actions {
+                modify_announcements_only: Some(
+                    group_change::actions::ModifyAnnouncementsOnlyAction { announcements_only },
+                ),
+                ..Default::default()
+            })
+        })
+        .await
+    }
+
+    /// Enables or disables joining a GV2 group via its invite link.
     ///
-    /// # Example
-    /// ```no_run
-    /// # use presage::Manager;
-    /// # async fn example<S: presage::store::Store>(mut manager: Manager<S, presage::manager::Registered>) {
-    /// # let master_key_bytes = [0u8; 32];
-    /// manager.update_group_title(&master_key_bytes, "New Group Name").await.unwrap();
-    /// # }
-    /// ```
-    pub async fn update_group_title(
+    /// When enabling the link of a group which never had one, a new invite link password is
+    /// generated. Anyone with the link can join without approval from an administrator.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    /// * `enabled` - Whether the invite link can be used to join the group
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn set_group_invite_link_enabled(
         &mut self,
         master_key_bytes: &[u8; 32],
-        new_title: impl Into<String>,
-    ) -> Result<(), Error<S::Error>> {
-        use libsignal_service::groups_v2::GroupOperations;
+        enabled: bool,
+    ) -> Result<u32, Error<S::Error>> {
+        use libsignal_service::groups_v2::AccessRequired;
 
-        let new_title = new_title.into();
-        info!(%new_title, "updating group title");
+        info!(enabled, "setting group invite link");
 
-        let group_master_key = GroupMasterKey::new(*master_key_bytes);
-        let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
+        self.apply_group_change(master_key_bytes, |_, current_group| {
+            let add_from_invite_link_access = if enabled {
+                AccessRequired::Any
+            } else {
+                AccessRequired::Unsatisfiable
+            };
+            let modify_invite_link_password = (enabled
+                && current_group.invite_link_password.is_empty())
+            .then(|| group_change::actions::ModifyInviteLinkPasswordAction {
+                invite_link_password: new_invite_link_password(),
+            });
+            Ok(group_change::Actions {
+                modify_add_from_invite_link_access: Some(
+                    group_change::actions::ModifyAddFromInviteLinkAccessControlAction {
+                        add_from_invite_link_access: add_from_invite_link_access.into(),
+                    },
+                ),
+                modify_invite_link_password,
+                ..Default::default()
+            })
+        })
+        .await
+    }
 
-        // Fetch current group to get revision
-        let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
-            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
-            .await?;
-        let current_revision = current_group.revision;
+    /// Rotates the invite link password of a GV2 group, invalidating any previous invite link.
+    ///
+    /// # Arguments
+    /// * `master_key_bytes` - The group's 32-byte master key
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn reset_group_invite_link(
+        &mut self,
+        master_key_bytes: &[u8; 32],
+    ) -> Result<u32, Error<S::Error>> {
+        info!("resetting group invite link");
+
+        self.apply_group_change(master_key_bytes, |_, _| {
+            Ok(group_change::Actions {
+                modify_invite_link_password: Some(
+                    group_change::actions::ModifyInviteLinkPasswordAction {
+                        invite_link_password: new_invite_link_password(),
+                    },
+                ),
+                ..Default::default()
+            })
+        })
+        .await
+    }
 
-        // Build modify title action
-        let group_ops = GroupOperations::new(group_secret_params);
-        let encrypted_title = group_ops.encrypt_title(&new_title, &mut rand::rng());
+    /// Returns the `https://signal.group/#...` invite link of a GV2 group, based on the group
+    /// stored locally.
+    ///
+    /// Returns [None] if the group is unknown or its invite link is disabled.
+    pub async fn group_invite_link(
+        &self,
+        master_key_bytes: &[u8; 32],
+    ) -> Result<Option<Url>, Error<S::Error>> {
+        use libsignal_service::groups_v2::AccessRequired;
 
-        // Build actions
-        let actions = libsignal_service::proto::group_change::Actions {
-            revision: current_revision + 1,
-            modify_title: Some(
-                libsignal_service::proto::group_change::actions::ModifyTitleAction {
-                    title: encrypted_title,
-                },
-            ),
-            ..Default::default()
+        let Some(group) = self.store.group(*master_key_bytes).await? else {
+            return Ok(None);
         };
 
-        // Modify the group
-        groups_manager
-            .modify_group(&mut rand::rng(), group_secret_params, actions)
-            .await?;
-
-        // Refresh local group state
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
-        {
-            debug!(group_title = %group.title, "group title updated");
+        let link_enabled = group.access_control.as_ref().is_some_and(|access_control| {
+            matches!(
+                access_control.add_from_invite_link,
+                AccessRequired::Any | AccessRequired::Administrator
+            )
+        });
+        if !link_enabled || group.invite_link_password.is_empty() {
+            return Ok(None);
         }
 
-        Ok(())
+        Ok(Some(
+            GroupInviteLink {
+                master_key: *master_key_bytes,
+                password: group.invite_link_password,
+            }
+            .to_url(),
+        ))
     }
 
-    /// Sets the disappearing messages timer for a GV2 group.
+    /// Joins a GV2 group with a `https://signal.group/#...` invite link.
+    ///
+    /// Depending on the group settings, this either joins the group directly or sends a request
+    /// to join, which has to be approved by an administrator. In the latter case,
+    /// [Error::GroupJoinPendingApproval] is returned.
     ///
     /// # Arguments
-    /// * `master_key_bytes` - The group's 32-byte master key
-    /// * `duration_seconds` - Timer duration in seconds (0 to disable)
-    pub async fn set_disappearing_messages_timer(
+    /// * `link` - The invite link of the group
+    ///
+    /// # Returns
+    /// * `Ok([u8; 32])` - The group's master key bytes, once joined
+    /// * `Err(Error::GroupJoinPendingApproval)` if an administrator needs to approve the request
+    /// * `Err(Error)` if the link is invalid or joining fails
+    pub async fn join_group_by_invite_link(
         &mut self,
-        master_key_bytes: &[u8; 32],
-        duration_seconds: u32,
-    ) -> Result<(), Error<S::Error>> {
-        use libsignal_service::groups_v2::{GroupOperations, Timer};
+        link: &Url,
+    ) -> Result<[u8; 32], Error<S::Error>> {
+        use libsignal_service::groups_v2::{AccessRequired, Role};
+        use libsignal_service::proto::MemberPendingAdminApproval;
 
-        info!(duration_seconds, "setting disappearing messages timer");
+        let GroupInviteLink {
+            master_key: master_key_bytes,
+            password,
+        } = GroupInviteLink::from_url(link).ok_or(Error::InvalidGroupInviteLink)?;
 
-        let group_master_key = GroupMasterKey::new(*master_key_bytes);
+        let group_master_key = GroupMasterKey::new(master_key_bytes);
         let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
+        let server_public_params = self
+            .state
+            .service_configuration()
+            .zkgroup_server_public_params;
 
+        // We are not a member (yet), so we can only see the public information of the group
         let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
-            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
+        let join_info = groups_manager
+            .fetch_group_join_info(&mut rand::rng(), group_secret_params, &password)
             .await?;
 
-        let group_ops = GroupOperations::new(group_secret_params);
-        let timer = Timer {
-            duration: duration_seconds,
+        let requires_approval = match join_info.add_from_invite_link() {
+            AccessRequired::Any => false,
+            AccessRequired::Administrator => true,
+            _ => return Err(Error::GroupInviteLinkDisabled),
         };
-        let encrypted_timer =
-            group_ops.encrypt_disappearing_message_timer(&timer, &mut rand::rng());
+        info!(requires_approval, "joining group with invite link");
 
-        let actions = libsignal_service::proto::group_change::Actions {
-            revision: current_group.revision + 1,
-            modify_disappearing_messages_timer: Some(
-                libsignal_service::proto::group_change::actions::ModifyDisappearingMessagesTimerAction {
-                    timer: encrypted_timer,
-                },
-            ),
-            ..Default::default()
+        let credential = self
+            .get_profile_credential(
+                self.state.data.service_ids.aci(),
+                self.state.data.profile_key(),
+                &server_public_params,
+            )
+            .await?;
+
+        let group_ops = GroupOperations::new(group_secret_params);
+        let actions = if requires_approval {
+            let presentation = server_public_params
+                .create_expiring_profile_key_credential_presentation(
+                    rand::random(),
+                    group_secret_params,
+                    credential,
+                );
+            group_change::Actions {
+                revision: join_info.revision + 1,
+                add_requesting_members: vec![
+                    group_change::actions::AddMemberPendingAdminApprovalAction {
+                        added: Some(MemberPendingAdminApproval {
+                            presentation: zkgroup::serialize(&presentation),
+                            ..Default::default()
+                        }),
+                    },
+                ],
+                ..Default::default()
+            }
+        } else {
+            let mut add_action = group_ops.build_add_member_action_with_credential(
+                &credential,
+                Role::Default,
+                &server_public_params,
+            );
+            add_action.join_from_invite_link = true;
+            group_change::Actions {
+                revision: join_info.revision + 1,
+                add_members: vec![add_action],
+                ..Default::default()
+            }
         };
 
         groups_manager
-            .modify_group(&mut rand::rng(), group_secret_params, actions)
+            .modify_group_with_invite_link_password(
+                &mut rand::rng(),
+                group_secret_params,
+                actions,
+                &password,
+            )
             .await?;
 
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
-        {
-            debug!(group_title = %group.title, "disappearing messages timer updated");
+        if requires_approval {
+            return Err(Error::GroupJoinPendingApproval(master_key_bytes));
         }
 
-        Ok(())
+        let group = self.fetch_group(&master_key_bytes).await?;
+        debug!(group_title = %group.title, "joined group with invite link");
+
+        Ok(master_key_bytes)
     }
 
-    /// Sets the description of a GV2 group.
+    /// Returns the ACIs of the users who requested to join a GV2 group with its invite link, and
+    /// are waiting for the approval of an administrator.
+    ///
+    /// This is based on the group stored locally, see [Self::fetch_group] to refresh it.
+    pub async fn pending_group_requests(
+        &self,
+        master_key_bytes: &[u8; 32],
+    ) -> Result<Vec<Aci>, Error<S::Error>> {
+        let group = self
+            .store
+            .group(*master_key_bytes)
+            .await?
+            .ok_or(Error::UnknownGroup)?;
+        Ok(group
+            .requesting_members
+            .into_iter()
+            .map(|member| member.aci)
+            .collect())
+    }
+
+    /// Approves the request of a user to join a GV2 group, who becomes a regular member.
     ///
     /// # Arguments
     /// * `master_key_bytes` - The group's 32-byte master key
-    /// * `description` - The new description (empty string to clear)
-    pub async fn set_group_description(
+    /// * `aci` - The ACI of the user who requested to join
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error::NotGroupAdministrator)` if we are not an administrator of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn approve_group_request(
         &mut self,
         master_key_bytes: &[u8; 32],
-        description: impl Into<String>,
-    ) -> Result<(), Error<S::Error>> {
-        use libsignal_service::groups_v2::GroupOperations;
-
-        let description = description.into();
-        info!("setting group description");
-
-        let group_master_key = GroupMasterKey::new(*master_key_bytes);
-        let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
-
-        let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
-            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
-            .await?;
-
-        let group_ops = GroupOperations::new(group_secret_params);
-        let encrypted_description = group_ops.encrypt_description(&description, &mut rand::rng());
-
-        let actions = libsignal_service::proto::group_change::Actions {
-            revision: current_group.revision + 1,
-            modify_description: Some(
-                libsignal_service::proto::group_change::actions::ModifyDescriptionAction {
-                    description: encrypted_description,
-                },
-            ),
-            ..Default::default()
-        };
+        aci: Aci,
+    ) -> Result<u32, Error<S::Error>> {
+        use libsignal_service::groups_v2::Role;
 
-        groups_manager
-            .modify_group(&mut rand::rng(), group_secret_params, actions)
-            .await?;
+        info!(aci = %aci.service_id_string(), "approving request to join group");
 
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
-        {
-            debug!(group_title = %group.title, "group description updated");
-        }
+        let self_aci = self.state.data.service_ids.aci();
+        let group_secret_params =
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(*master_key_bytes));
 
-        Ok(())
+        self.apply_group_change(master_key_bytes, |_, current_group| {
+            if !current_group.is_administrator(self_aci) {
+                return Err(Error::NotGroupAdministrator);
+            }
+            Ok(group_change::Actions {
+                promote_requesting_members: vec![
+                    group_change::actions::PromoteMemberPendingAdminApprovalAction {
+                        user_id: zkgroup::serialize(
+                            &group_secret_params.encrypt_service_id(aci.into()),
+                        ),
+                        role: Role::Default.into(),
+                    },
+                ],
+                ..Default::default()
+            })
+        })
+        .await
     }
 
-    /// Sets access control for a GV2 group.
-    ///
-    /// Controls who can modify group attributes (title, description, avatar)
-    /// and who can add members.
+    /// Denies the request of a user to join a GV2 group.
     ///
     /// # Arguments
     /// * `master_key_bytes` - The group's 32-byte master key
-    /// * `attributes_access` - Who can modify group attributes (None to leave unchanged)
-    /// * `members_access` - Who can add members (None to leave unchanged)
-    pub async fn set_group_access_control(
+    /// * `aci` - The ACI of the user who requested to join
+    ///
+    /// # Returns
+    /// * `Ok(u32)` - The new revision of the group
+    /// * `Err(Error::NotGroupAdministrator)` if we are not an administrator of the group
+    /// * `Err(Error)` if the update operation fails
+    pub async fn deny_group_request(
         &mut self,
         master_key_bytes: &[u8; 32],
-        attributes_access: Option<libsignal_service::groups_v2::AccessRequired>,
-        members_access: Option<libsignal_service::groups_v2::AccessRequired>,
-    ) -> Result<(), Error<S::Error>> {
-        info!("setting group access control");
-
-        let group_master_key = GroupMasterKey::new(*master_key_bytes);
-        let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
+        aci: Aci,
+    ) -> Result<u32, Error<S::Error>> {
+        info!(aci = %aci.service_id_string(), "denying request to join group");
 
-        let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
-            .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
-            .await?;
+        let self_aci = self.state.data.service_ids.aci();
+        let group_secret_params =
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(*master_key_bytes));
 
-        let actions = libsignal_service::proto::group_change::Actions {
-            revision: current_group.revision + 1,
-            modify_attributes_access: attributes_access.map(|a| {
-                libsignal_service::proto::group_change::actions::ModifyAttributesAccessControlAction {
-                    attributes_access: i32::from(a),
-                }
-            }),
-            modify_member_access: members_access.map(|a| {
-                libsignal_service::proto::group_change::actions::ModifyMembersAccessControlAction {
-                    members_access: i32::from(a),
-                }
-            }),
-            ..Default::default()
-        };
+        self.apply_group_change(master_key_bytes, |_, current_group| {
+            if !current_group.is_administrator(self_aci) {
+                return Err(Error::NotGroupAdministrator);
+            }
+            Ok(group_change::Actions {
+                delete_requesting_members: vec![
+                    group_change::actions::DeleteMemberPendingAdminApprovalAction {
+                        deleted_user_id: zkgroup::serialize(
+                            &group_secret_params.encrypt_service_id(aci.into()),
+                        ),
+                    },
+                ],
+                ..Default::default()
+            })
+        })
+        .await
+    }
 
-        groups_manager
-            .modify_group(&mut rand::rng(), group_secret_params, actions)
-            .await?;
+    /// Returns the master key of a stored GV2 group from its [GroupId], to call the group
+    /// methods with it.
+    ///
+    /// Fails with [Error::UnknownGroup] if the group is not stored locally, see
+    /// [Self::fetch_group] to store it.
+    pub async fn master_key_for_group(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<GroupMasterKeyBytes, Error<S::Error>> {
+        group_master_key_by_id(&self.store, &group_id.0)
+            .await?
+            .ok_or(Error::UnknownGroup)
+    }
 
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
-        {
-            debug!(group_title = %group.title, "group access control updated");
+    /// Checks whether a change to a GV2 group would be accepted, without committing it.
+    ///
+    /// The change is checked against the group stored locally (see [Self::fetch_group] to refresh
+    /// it) without any request to the server, e.g. to catch removing the last administrator or
+    /// adding a member who is already in the group, with a descriptive error.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the change looks valid
+    /// * `Err(Error::UnknownGroup)` if the group is not stored locally
+    /// * `Err(Error::InvalidGroupChange)` with the reason the change would be rejected
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use presage::Manager;
+    /// # use presage::model::groups::GroupChangeSpec;
+    /// # async fn example<S: presage::store::Store>(manager: Manager<S, presage::manager::Registered>) {
+    /// # let master_key_bytes = [0u8; 32];
+    /// # let member_aci = todo!();
+    /// manager
+    ///     .validate_group_change(&master_key_bytes, GroupChangeSpec::RemoveMember(member_aci))
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn validate_group_change(
+        &self,
+        master_key_bytes: &[u8; 32],
+        change: GroupChangeSpec,
+    ) -> Result<(), Error<S::Error>> {
+        let group = self
+            .store
+            .group(*master_key_bytes)
+            .await?
+            .ok_or(Error::UnknownGroup)?;
+        group.validate_change(self.state.data.service_ids.aci(), &change)?;
+        match &change {
+            GroupChangeSpec::SetTitle(title) => {
+                GroupField::Title.validate(&group_operations(master_key_bytes), title)?
+            }
+            GroupChangeSpec::SetDescription(Some(description)) => GroupField::Description
+                .validate(&group_operations(master_key_bytes), description)?,
+            _ => (),
         }
-
         Ok(())
     }
 
-    /// Sets whether the group is announcements-only (only admins can send messages).
+    /// Commits a change to a GV2 group on the server and refreshes the locally stored group.
     ///
-    /// # Arguments
-    /// * `master_key_bytes` - The group's 32-byte master key
-    /// * `announcements_only` - true to restrict messages to admins only
-    pub async fn set_group_announcements_only(
+    /// The `build_actions` closure gets the group operations (to encrypt attributes) and the
+    /// current state of the group, fetched from the server. The revision of the actions it
+    /// returns is always overwritten with the next revision of the group.
+    ///
+    /// Returns the new revision of the group.
+    async fn apply_group_change(
         &mut self,
         master_key_bytes: &[u8; 32],
-        announcements_only: bool,
-    ) -> Result<(), Error<S::Error>> {
-        info!(announcements_only, "setting group announcements only");
-
+        build_actions: impl FnOnce(
+            &GroupOperations,
+            &Group,
+        ) -> Result<group_change::Actions, Error<S::Error>>,
+    ) -> Result<u32, Error<S::Error>> {
         let group_master_key = GroupMasterKey::new(*master_key_bytes);
         let group_secret_params = GroupSecretParams::derive_from_master_key(group_master_key);
 
+        // Fetch current group to get revision
         let mut groups_manager = self.groups_manager().await?;
-        let current_group = groups_manager
+        let encrypted_group = groups_manager
             .fetch_encrypted_group(&mut rand::rng(), master_key_bytes)
             .await?;
+        let current_group = decrypt_group_state(master_key_bytes, encrypted_group)?;
 
-        let actions = libsignal_service::proto::group_change::Actions {
-            revision: current_group.revision + 1,
-            modify_announcements_only: Some(
-                libsignal_service::proto::group_change::actions::ModifyAnnouncementsOnlyAction {
-                    announcements_only,
-                },
-            ),
-            ..Default::default()
-        };
+        let group_ops = GroupOperations::new(group_secret_params);
+        let mut actions = build_actions(&group_ops, &current_group)?;
+        let revision = current_group.revision + 1;
+        actions.revision = revision;
 
         groups_manager
             .modify_group(&mut rand::rng(), group_secret_params, actions)
             .await?;
 
-        if let Ok(Some(group)) =
-            upsert_group(&self.store, &mut groups_manager, master_key_bytes, &0).await
+        // Refresh local group state
+        if let Ok(Some(group)) = upsert_group(
+            &self.store,
+            &mut groups_manager,
+            master_key_bytes,
+            &revision,
+        )
+        .await
         {
-            debug!(group_title = %group.title, "group announcements only updated");
+            debug!(group_title = %group.title, revision, "group updated");
         }
 
-        Ok(())
+        Ok(revision)
     }
 
     fn credentials(&self) -> ServiceCredentials {
@@ -2236,7 +5481,9 @@ impl<S: Store> Manager<S, Registered> {
         Ok(())
     }
 
-    /// As a primary device, unlink a secondary device.
+    /// As a primary device, unlink a secondary device, e.g. a lost one.
+    ///
+    /// The ids of the linked devices are given by [Manager::linked_devices].
     pub async fn unlink_secondary(
         &self,
         device_id: impl TryInto<DeviceId>,
@@ -2261,27 +5508,382 @@ impl<S: Store> Manager<S, Registered> {
             Some(self.state.data.profile_key),
         );
 
-        Ok(account_manager.linked_devices(&aci_protocol_store).await?)
+        Ok(account_manager.linked_devices(&aci_protocol_store).await?)
+    }
+
+    /// List the devices linked to the account, i.e. all the devices but the current one, with
+    /// their decrypted name, creation and last seen dates.
+    pub async fn linked_devices(&self) -> Result<Vec<DeviceInfo>, Error<S::Error>> {
+        let device_id = self.device_id();
+        let mut devices = self.devices().await?;
+        devices.retain(|device| device.id != device_id);
+        Ok(devices)
+    }
+
+    /// As a secondary device, unlink this device from the account and clear the registration
+    /// data from the local store.
+    ///
+    /// The primary device cannot be unlinked, see [Manager::delete_account] instead.
+    pub async fn unregister(mut self) -> Result<(), Error<S::Error>> {
+        if self.registration_type() != RegistrationType::Secondary {
+            return Err(Error::NotSecondaryDevice);
+        }
+        let device_id = self.device_id();
+        self.identified_websocket(false)
+            .await?
+            .unlink_device(device_id)
+            .await?;
+        self.store.clear_registration().await?;
+        Ok(())
+    }
+
+    /// Delete the account from Signal servers and clear local store.
+    ///
+    /// This permanently deletes the account from Signal servers, removing all groups,
+    /// contacts, and messages. The phone number can be re-registered afterwards with
+    /// a fresh account (existing contacts will see "safety number changed").
+    ///
+    /// After calling this method, the Manager is no longer usable.
+    ///
+    /// CAUTION: This is irreversible. All account data will be lost.
+    pub async fn delete_account(mut self) -> Result<(), Error<S::Error>> {
+        // Send delete request to Signal server
+        self.identified_websocket(false)
+            .await?
+            .unregister_account()
+            .await?;
+        // Clear local store
+        self.store.clear().await?;
+        Ok(())
+    }
+}
+
+/// Builder of an outgoing data message combining several features, see
+/// [Manager::compose_message].
+#[must_use = "the message is only sent by DataMessageBuilder::send"]
+pub struct DataMessageBuilder<'a, S> {
+    manager: &'a mut Manager<S, Registered>,
+    body: Option<String>,
+    attachments: Vec<AttachmentPointer>,
+    view_once: bool,
+    quote: Option<(ServiceId, u64, String)>,
+    body_ranges: Vec<BodyRange>,
+    preview: Option<LinkPreview>,
+    expire_timer: Option<u32>,
+}
+
+impl<S: Store> DataMessageBuilder<'_, S> {
+    /// The text of the message, an empty body being omitted
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        let body = body.into();
+        self.body = (!body.is_empty()).then_some(body);
+        self
+    }
+
+    /// Already uploaded attachments, see [Manager::upload_attachments]
+    pub fn attachments(mut self, attachments: Vec<AttachmentPointer>) -> Self {
+        self.attachments.extend(attachments);
+        self
+    }
+
+    /// Whether the attachment can only be viewed once by the recipients, in which case there must
+    /// be exactly one image or video attachment.
+    pub fn view_once(mut self, view_once: bool) -> Self {
+        self.view_once = view_once;
+        self
+    }
+
+    /// Replies to a message, see [Manager::send_quote_reply]
+    pub fn quote(mut self, author: ServiceId, timestamp: u64, text: impl Into<String>) -> Self {
+        self.quote = Some((author, timestamp, text.into()));
+        self
+    }
+
+    /// Mentions of group members, counted in UTF-16 code units (see [Mention::from_byte_range])
+    pub fn mentions(mut self, mentions: Vec<Mention>) -> Self {
+        self.body_ranges
+            .extend(mentions.into_iter().map(BodyRange::from));
+        self
+    }
+
+    /// Styles of the text, counted in UTF-16 code units (see [TextStyleRange::from_byte_range])
+    pub fn styles(mut self, styles: Vec<TextStyleRange>) -> Self {
+        self.body_ranges
+            .extend(styles.into_iter().map(BodyRange::from));
+        self
+    }
+
+    /// A link preview, whose URL must be in the body. Its image is uploaded when sending.
+    pub fn preview(mut self, preview: LinkPreview) -> Self {
+        self.preview = Some(preview);
+        self
+    }
+
+    /// The expire timer of this message only, in seconds, see
+    /// [Manager::send_message_with_timer_override]. Otherwise the timer of the thread is used.
+    pub fn expire_timer(mut self, expire_timer: u32) -> Self {
+        self.expire_timer = Some(expire_timer);
+        self
+    }
+
+    /// Sends the message to a [Thread].
+    ///
+    /// Fails with [Error::InvalidViewOnceAttachments] or [Error::InvalidLinkPreview] if the
+    /// attachments or the preview don't fit the message, before sending anything.
+    ///
+    /// # Returns
+    /// The timestamp of the message
+    pub async fn send(self, thread: &Thread) -> Result<u64, Error<S::Error>> {
+        let Self {
+            manager,
+            body,
+            attachments,
+            view_once,
+            quote,
+            body_ranges,
+            preview,
+            expire_timer,
+        } = self;
+
+        if view_once {
+            let [attachment] = attachments.as_slice() else {
+                return Err(Error::InvalidViewOnceAttachments);
+            };
+            let content_type = attachment.content_type();
+            if !content_type.starts_with("image/") && !content_type.starts_with("video/") {
+                return Err(Error::InvalidViewOnceAttachments);
+            }
+        }
+        if let Some(preview) = &preview {
+            if !body.as_deref().unwrap_or_default().contains(&preview.url) {
+                return Err(Error::InvalidLinkPreview);
+            }
+        }
+
+        let preview = match preview {
+            Some(preview) => vec![manager.upload_link_preview(preview).await?],
+            None => Vec::new(),
+        };
+        let quote = match quote {
+            Some((author, timestamp, text)) => {
+                Some(manager.quote(thread, author, timestamp, text).await?)
+            }
+            None => None,
+        };
+
+        let timestamp = timestamp_now();
+        let mut data_message = DataMessage {
+            body,
+            attachments,
+            is_view_once: view_once.then_some(true),
+            quote,
+            body_ranges,
+            preview,
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
+        if let Some(expire_timer) = expire_timer {
+            manager
+                .override_expire_timer(thread, &mut data_message, expire_timer)
+                .await?;
+        }
+
+        manager
+            .send_message_to_thread(thread, data_message, timestamp)
+            .await?;
+        Ok(timestamp)
+    }
+}
+
+/// How often the signed and last resort pre-keys are rotated while receiving messages
+const PRE_KEY_ROTATION_INTERVAL: Duration = Duration::from_secs(48 * 60 * 60);
+
+/// The current time as milliseconds since the UNIX epoch, as used for message timestamps
+fn timestamp_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
+
+/// Whether the CDN answered that it doesn't have the attachment (404 or 410)
+fn is_attachment_not_found<S: std::error::Error>(error: &Error<S>) -> bool {
+    matches!(
+        error,
+        Error::ServiceError(
+            ServiceError::NotFoundError | ServiceError::UnhandledResponseCode { http_code: 410 }
+        )
+    )
+}
+
+/// Whether downloading an attachment again might succeed after this error
+fn is_transient_download_error<S: std::error::Error>(error: &Error<S>) -> bool {
+    match error {
+        Error::IoError(_) | Error::Timeout(_) => true,
+        Error::ServiceError(error) => is_transient_service_error(error),
+        // including integrity errors, that won't fix themselves
+        _ => false,
+    }
+}
+
+/// The error to keep a group message to send later with, when it reached none of the members and
+/// sending it failed for at least one of them because the server could not be reached
+fn unreached_group_error<S: std::error::Error, T>(
+    results: &[(ServiceId, Result<T, Error<S>>)],
+) -> Option<&Error<S>> {
+    let errors: Vec<&Error<S>> = results
+        .iter()
+        .map(|(_, result)| result.as_ref().err())
+        .collect::<Option<_>>()?;
+    errors
+        .into_iter()
+        .find(|error| is_transient_send_error(error))
+}
+
+/// Whether only administrators can send this in an announcements-only group
+///
+/// Other members can still react, delete their messages, send receipts etc., anything else sent
+/// in a [DataMessage] (text, attachments, polls, contact shares, quotes…) is rejected by the server.
+fn is_announcement(content_body: &ContentBody) -> bool {
+    match content_body {
+        ContentBody::DataMessage(message) => message.reaction.is_none() && message.delete.is_none(),
+        ContentBody::EditMessage(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether sending a message failed because the server could not be reached, in which case
+/// sending it again later might work
+fn is_transient_send_error<S: std::error::Error>(error: &Error<S>) -> bool {
+    match error {
+        Error::MessageSenderError(error) => matches!(
+            error.as_ref(),
+            MessageSenderError::ServiceError(error) if is_transient_service_error(error)
+        ),
+        error => is_transient_download_error(error),
+    }
+}
+
+fn is_transient_service_error(error: &ServiceError) -> bool {
+    matches!(
+        error,
+        ServiceError::Timeout { .. }
+            | ServiceError::SendError { .. }
+            | ServiceError::IO(_)
+            | ServiceError::RateLimitExceeded
+            | ServiceError::UnhandledResponseCode {
+                http_code: 500..=599
+            }
+    )
+}
+
+/// The identity change carried by an envelope establishing a new session, if the identity key of
+/// its sender is not the one known so far
+async fn identity_change<P: IdentityKeyStore>(
+    envelope: &Envelope,
+    protocol_store: &P,
+) -> Option<IdentityChange> {
+    let (service_id, message) = match envelope.r#type() {
+        envelope::Type::PrekeyBundle => (
+            ServiceId::parse_from_service_id_string(envelope.source_service_id())?,
+            PreKeySignalMessage::try_from(envelope.content()).ok()?,
+        ),
+        envelope::Type::UnidentifiedSender => {
+            let usmc = sealed_sender_decrypt_to_usmc(envelope.content(), protocol_store)
+                .await
+                .ok()?;
+            if usmc.msg_type().ok()? != CiphertextMessageType::PreKey {
+                return None;
+            }
+            (
+                ServiceId::parse_from_service_id_string(usmc.sender().ok()?.sender_uuid().ok()?)?,
+                PreKeySignalMessage::try_from(usmc.contents().ok()?).ok()?,
+            )
+        }
+        _ => return None,
+    };
+
+    let address = ProtocolAddress::new(service_id.service_id_string(), *DEFAULT_DEVICE_ID);
+    let old_key = protocol_store.get_identity(&address).await.ok()??;
+    let new_key = *message.identity_key();
+    (old_key != new_key).then_some(IdentityChange {
+        service_id,
+        old_key,
+        new_key,
+    })
+}
+
+/// Reports the identity change once the envelope carrying it was authenticated, i.e. decrypted
+/// or only rejected because of the new identity
+fn notify_identity_change<T>(
+    state: &Registered,
+    identity_change: Option<IdentityChange>,
+    result: &Result<T, ServiceError>,
+) {
+    let Some(identity_change) = identity_change else {
+        return;
+    };
+    if matches!(
+        result,
+        Ok(_)
+            | Err(ServiceError::SignalProtocolError(
+                SignalProtocolError::UntrustedIdentity(_)
+            ))
+    ) {
+        info!(
+            service_id = %identity_change.service_id.service_id_string(),
+            "identity key changed"
+        );
+        // there might be no subscriber
+        let _ = state.identity_changes.send(identity_change);
+    }
+}
+
+/// Whether the recipient refused a sealed sender message, e.g. because its access key changed
+fn is_unidentified_access_refused(error: &MessageSenderError) -> bool {
+    matches!(
+        error,
+        MessageSenderError::ServiceError(ServiceError::Unauthorized)
+    )
+}
+
+/// Whether reconnecting after this error is pointless, e.g. when the account was unregistered
+fn is_fatal_connection_error<S: std::error::Error>(error: &Error<S>) -> bool {
+    match error {
+        Error::ServiceError(error) => matches!(error, ServiceError::Unauthorized),
+        Error::IoError(_) | Error::Timeout(_) => false,
+        // e.g. store errors
+        _ => true,
+    }
+}
+
+/// The other CDN where an attachment might be, Signal attachments being on CDN 2 or 3
+fn alternate_cdn(cdn_number: u32) -> Option<u32> {
+    match cdn_number {
+        2 => Some(3),
+        3 => Some(2),
+        _ => None,
     }
+}
 
-    /// Delete the account from Signal servers and clear local store.
-    ///
-    /// This permanently deletes the account from Signal servers, removing all groups,
-    /// contacts, and messages. The phone number can be re-registered afterwards with
-    /// a fresh account (existing contacts will see "safety number changed").
-    ///
-    /// After calling this method, the Manager is no longer usable.
-    ///
-    /// CAUTION: This is irreversible. All account data will be lost.
-    pub async fn delete_account(mut self) -> Result<(), Error<S::Error>> {
-        // Send delete request to Signal server
-        self.identified_websocket(false)
-            .await?
-            .unregister_account()
-            .await?;
-        // Clear local store
-        self.store.clear().await?;
-        Ok(())
+/// Applies the expire timer of a thread to an outgoing data message.
+///
+/// Messages without a timer get the one of the thread. Messages with a timer change the timer
+/// of the thread, so the version is incremented unless it is explicitly set.
+fn apply_thread_timer(content_body: &mut ContentBody, thread_timer: Option<(u32, u32)>) {
+    if let ContentBody::DataMessage(DataMessage {
+        expire_timer: ref mut timer,
+        expire_timer_version: ref mut version,
+        ..
+    }) = content_body
+    {
+        let thread_version = thread_timer.map(|(_, v)| v).unwrap_or_default();
+        if timer.is_none() {
+            *timer = thread_timer.map(|(t, _)| t);
+            *version = Some(thread_version);
+        } else if version.is_none() {
+            *version = Some(thread_version + 1);
+        }
     }
 }
 
@@ -2311,6 +5913,300 @@ fn ensure_data_message_timestamp(content_body: &mut ContentBody, timestamp: u64)
     }
 }
 
+/// Returns the operations to encrypt and decrypt the attributes of a group
+fn group_operations(master_key_bytes: &GroupMasterKeyBytes) -> GroupOperations {
+    GroupOperations::new(GroupSecretParams::derive_from_master_key(
+        GroupMasterKey::new(*master_key_bytes),
+    ))
+}
+
+/// Generates a random password for group invite links
+fn new_invite_link_password() -> Vec<u8> {
+    rand::random::<[u8; 16]>().to_vec()
+}
+
+/// Finds the master key of a stored group from its identifier (as used in e.g. typing messages)
+async fn group_master_key_by_id<C: ContentsStore>(
+    store: &C,
+    group_id: &[u8],
+) -> Result<Option<GroupMasterKeyBytes>, C::ContentsStoreError> {
+    for group in store.groups().await? {
+        let (master_key_bytes, _) = group?;
+        let group_identifier =
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(master_key_bytes))
+                .get_group_identifier();
+        if group_identifier.as_slice() == group_id {
+            return Ok(Some(master_key_bytes));
+        }
+    }
+    Ok(None)
+}
+
+/// Lists the blocked contacts and groups, to synchronize them with other devices
+async fn blocked_sync_message<C: ContentsStore>(
+    store: &C,
+) -> Result<sync_message::Blocked, C::ContentsStoreError> {
+    let mut blocked = sync_message::Blocked::default();
+    for thread in store.blocked().await? {
+        match thread {
+            Thread::Contact(uuid) => {
+                blocked.acis.push(uuid.to_string());
+                blocked.acis_binary.push(uuid.into_bytes().to_vec());
+            }
+            Thread::Group(master_key_bytes) => {
+                let group_identifier = GroupSecretParams::derive_from_master_key(
+                    GroupMasterKey::new(master_key_bytes),
+                )
+                .get_group_identifier();
+                blocked.group_ids.push(group_identifier.to_vec());
+            }
+        }
+    }
+    Ok(blocked)
+}
+
+/// Replaces the blocked contacts and groups with the ones synchronized from another device
+async fn save_configuration<S: StateStore>(
+    store: &S,
+    configuration: &sync_message::Configuration,
+) -> Result<(), S::StateStoreError> {
+    let mut settings = store.account_settings().await?.unwrap_or_default();
+    settings.apply(configuration);
+    debug!(?settings, "saving account settings");
+    store.save_account_settings(&settings).await
+}
+
+/// Marks the messages read on another device as read, and returns their threads
+async fn save_read<C: ContentsStore>(
+    store: &mut C,
+    read: &[sync_message::Read],
+) -> Result<Vec<Thread>, C::ContentsStoreError> {
+    let mut threads = Vec::new();
+    for read in read {
+        let sender = read
+            .sender_aci_binary
+            .as_deref()
+            .and_then(Aci::parse_from_service_id_binary)
+            .or_else(|| {
+                read.sender_aci
+                    .as_deref()
+                    .and_then(Aci::parse_from_service_id_string)
+            });
+        let (Some(sender), Some(timestamp)) = (sender, read.timestamp) else {
+            continue;
+        };
+        let Some(thread) = thread_of_message(store, sender.into(), timestamp).await? else {
+            debug!(timestamp, "ignoring read unknown message");
+            continue;
+        };
+        store.mark_read(&thread, timestamp).await?;
+        if !threads.contains(&thread) {
+            threads.push(thread);
+        }
+    }
+    Ok(threads)
+}
+
+/// Finds the thread of a received message, in the thread of its sender or else in the groups
+async fn thread_of_message<C: ContentsStore>(
+    store: &C,
+    sender: ServiceId,
+    timestamp: u64,
+) -> Result<Option<Thread>, C::ContentsStoreError> {
+    let thread = Thread::Contact(sender.raw_uuid());
+    find_thread_of_message(store, thread, sender, timestamp).await
+}
+
+/// Finds the thread of a message we sent, in the thread of the recipient or else in the groups
+async fn thread_of_sent_message<C: ContentsStore>(
+    store: &C,
+    own_aci: ServiceId,
+    recipient: ServiceId,
+    timestamp: u64,
+) -> Result<Option<Thread>, C::ContentsStoreError> {
+    let thread = Thread::Contact(recipient.raw_uuid());
+    find_thread_of_message(store, thread, own_aci, timestamp).await
+}
+
+/// Finds the thread of a message of `sender`, in `thread` or else in the groups
+async fn find_thread_of_message<C: ContentsStore>(
+    store: &C,
+    thread: Thread,
+    sender: ServiceId,
+    timestamp: u64,
+) -> Result<Option<Thread>, C::ContentsStoreError> {
+    let is_sent_by =
+        |message: Option<Content>| message.is_some_and(|message| message.metadata.sender == sender);
+
+    if is_sent_by(store.message(&thread, timestamp).await?) {
+        return Ok(Some(thread));
+    }
+    for group in store.groups().await? {
+        let (master_key_bytes, _group) = group?;
+        let thread = Thread::Group(master_key_bytes);
+        if is_sent_by(store.message(&thread, timestamp).await?) {
+            return Ok(Some(thread));
+        }
+    }
+    Ok(None)
+}
+
+/// Saves the statuses of the messages we sent which a receipt is about
+async fn save_receipt<C: ContentsStore>(
+    store: &mut C,
+    own_aci: ServiceId,
+    receipt: &Receipt,
+) -> Result<(), C::ContentsStoreError> {
+    for &timestamp in &receipt.timestamps {
+        let Some(thread) =
+            thread_of_sent_message(store, own_aci, receipt.sender, timestamp).await?
+        else {
+            debug!(timestamp, "receipt for an unknown message");
+            continue;
+        };
+        store
+            .save_message_status(&thread, timestamp, receipt.kind.into())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Records the phone number of a known contact carried by the transcript of a message we sent
+async fn save_phone_number<C: ContentsStore>(
+    store: &mut C,
+    content: &Content,
+) -> Result<(), C::ContentsStoreError> {
+    let ContentBody::SynchronizeMessage(SyncMessage {
+        sent:
+            Some(sync_message::Sent {
+                destination_e164: Some(e164),
+                ..
+            }),
+        ..
+    }) = &content.body
+    else {
+        return Ok(());
+    };
+    let Some(ServiceId::Aci(aci)) = content.sent_transcript().and_then(|sent| sent.destination)
+    else {
+        return Ok(());
+    };
+    let Ok(phone_number) = phonenumber::parse(None, e164) else {
+        debug!("invalid phone number in sent transcript");
+        return Ok(());
+    };
+    let Some(mut contact) = store.contact_by_id(&aci.into()).await? else {
+        return Ok(());
+    };
+    if contact.phone_number.as_ref() != Some(&phone_number) {
+        contact.phone_number = Some(phone_number);
+        store.save_contact(&contact).await?;
+    }
+    Ok(())
+}
+
+async fn save_blocked<C: ContentsStore>(
+    store: &mut C,
+    blocked: &sync_message::Blocked,
+) -> Result<(), C::ContentsStoreError> {
+    let mut threads = Vec::new();
+    for aci in blocked
+        .acis_binary
+        .iter()
+        .filter_map(|aci| Uuid::from_slice(aci).ok())
+        .chain(blocked.acis.iter().filter_map(|aci| aci.parse().ok()))
+    {
+        threads.push(Thread::Contact(aci));
+    }
+    for group_id in &blocked.group_ids {
+        match group_master_key_by_id(store, group_id).await? {
+            Some(master_key_bytes) => threads.push(Thread::Group(master_key_bytes)),
+            None => debug!("ignoring blocked unknown group"),
+        }
+    }
+
+    for thread in store.blocked().await? {
+        if !threads.contains(&thread) {
+            store.set_blocked(&thread, false).await?;
+        }
+    }
+    for thread in threads {
+        store.set_blocked(&thread, true).await?;
+    }
+    Ok(())
+}
+
+/// Whether a message was sent by a blocked contact, or in a blocked group
+async fn is_blocked<C: ContentsStore>(
+    store: &C,
+    service_ids: &ServiceIds,
+    content: &Content,
+) -> bool {
+    let sender = content.metadata.sender;
+    if sender == ServiceId::Aci(service_ids.aci()) {
+        // synchronization messages from our other devices
+        return false;
+    }
+
+    let mut threads = vec![Thread::Contact(sender.raw_uuid())];
+    if let Ok(thread @ Thread::Group(_)) = Thread::try_from(content) {
+        threads.push(thread);
+    }
+    for thread in threads {
+        match store.is_blocked(&thread).await {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(error) => warn!(%error, %thread, "failed to check whether thread is blocked"),
+        }
+    }
+    false
+}
+
+/// Builds a [TypingIndicator] from an incoming typing message, resolving the group it was sent in
+async fn typing_indicator<C: ContentsStore>(
+    store: &C,
+    metadata: &Metadata,
+    typing_message: &TypingMessage,
+) -> Option<TypingIndicator> {
+    let thread = match typing_message.group_id.as_deref() {
+        None => Thread::Contact(metadata.sender.raw_uuid()),
+        Some(group_id) => match group_master_key_by_id(store, group_id).await {
+            Ok(Some(master_key_bytes)) => Thread::Group(master_key_bytes),
+            Ok(None) => {
+                debug!("typing message in unknown group, skipping");
+                return None;
+            }
+            Err(error) => {
+                warn!(%error, "failed to look up group of typing message");
+                return None;
+            }
+        },
+    };
+
+    let action = match typing_message.action() {
+        typing_message::Action::Started => TypingAction::Started,
+        typing_message::Action::Stopped => TypingAction::Stopped,
+    };
+
+    Some(TypingIndicator {
+        sender: metadata.sender,
+        thread,
+        action,
+        timestamp: typing_message.timestamp.unwrap_or(metadata.timestamp),
+    })
+}
+
+/// Decrypts a group fetched from the server, including the attributes which are not encrypted
+fn decrypt_group_state(
+    master_key_bytes: &[u8],
+    encrypted_group: libsignal_service::proto::Group,
+) -> Result<Group, ServiceError> {
+    let announcements_only = encrypted_group.announcements_only;
+    let mut group: Group = decrypt_group(master_key_bytes, encrypted_group)?.into();
+    group.announcements_only = announcements_only;
+    Ok(group)
+}
+
 async fn upsert_group<S: Store>(
     store: &S,
     groups_manager: &mut GroupsManager<InMemoryCredentialsCache>,
@@ -2336,10 +6232,24 @@ async fn upsert_group<S: Store>(
             .await
         {
             Ok(encrypted_group) => {
-                let group = decrypt_group(master_key_bytes, encrypted_group)?;
+                let group = decrypt_group_state(master_key_bytes, encrypted_group)?;
+                let duration = group
+                    .disappearing_messages_timer
+                    .as_ref()
+                    .map(|timer| timer.duration)
+                    .unwrap_or_default();
                 if let Err(error) = store.save_group(master_key_bytes.try_into()?, group).await {
                     error!(%error, "failed to save group");
                 }
+                // keep the timer of the thread in sync with the group
+                let thread = Thread::Group(master_key_bytes.try_into()?);
+                if let Err(error) = store
+                    .clone()
+                    .update_expire_timer(&thread, duration, *revision)
+                    .await
+                {
+                    error!(%error, "failed to save group expire timer");
+                }
             }
             Err(error) => {
                 warn!(%error, "failed to fetch encrypted group")
@@ -2487,7 +6397,8 @@ async fn save_message<S: Store>(
                 });
             }
 
-            if let Some(expire_timer) = data_message.expire_timer {
+            // the timer of groups is part of the group state, and updated with it
+            if let (Some(expire_timer), Thread::Contact(_)) = (data_message.expire_timer, &thread) {
                 let version = data_message.expire_timer_version.unwrap_or(1);
                 store
                     .update_expire_timer(&thread, expire_timer, version)
@@ -2514,6 +6425,95 @@ async fn save_message<S: Store>(
                         None
                     }
                 }
+                DataMessage {
+                    reaction:
+                        Some(Reaction {
+                            emoji: Some(emoji),
+                            remove,
+                            target_sent_timestamp: Some(ts),
+                            ..
+                        }),
+                    ..
+                } => {
+                    // reactions are attached to the message they target
+                    let author = message.metadata.sender;
+                    if remove.unwrap_or_default() {
+                        store.remove_reaction(&thread, *ts, &author).await?;
+                        trace!(%thread, ts, "reaction removed");
+                    } else {
+                        let reaction = crate::model::messages::Reaction {
+                            emoji: emoji.clone(),
+                            author,
+                            timestamp: message.metadata.timestamp,
+                        };
+                        store.save_reaction(&thread, *ts, reaction).await?;
+                        trace!(%thread, ts, "reaction saved");
+                    }
+                    None
+                }
+                DataMessage {
+                    poll_vote:
+                        Some(PollVote {
+                            target_sent_timestamp: Some(ts),
+                            option_indexes,
+                            vote_count,
+                            ..
+                        }),
+                    ..
+                } => {
+                    // votes are attached to the poll they target, and ignored once it is closed
+                    let timestamp = message.metadata.timestamp;
+                    let voter = message.metadata.sender;
+                    let vote_count = vote_count.unwrap_or_default();
+                    let previous_vote_count = store
+                        .poll_votes(&thread, *ts)
+                        .await?
+                        .into_iter()
+                        .find(|vote| vote.voter == voter)
+                        .map(|vote| vote.vote_count);
+                    if store
+                        .poll_termination(&thread, *ts)
+                        .await?
+                        .is_some_and(|terminated_at| terminated_at < timestamp)
+                    {
+                        debug!(%thread, ts, "ignoring vote in terminated poll");
+                    } else if previous_vote_count.is_some_and(|previous| previous >= vote_count) {
+                        // votes can arrive out of order, the one with the highest count wins
+                        debug!(%thread, ts, vote_count, "ignoring stale poll vote");
+                    } else {
+                        let vote = crate::model::messages::PollVote {
+                            voter,
+                            option_indexes: option_indexes.clone(),
+                            vote_count,
+                            timestamp,
+                        };
+                        store.save_poll_vote(&thread, *ts, vote).await?;
+                        trace!(%thread, ts, "poll vote saved");
+                    }
+                    None
+                }
+                DataMessage {
+                    poll_terminate:
+                        Some(PollTerminate {
+                            target_sent_timestamp: Some(ts),
+                        }),
+                    ..
+                } => {
+                    // only the author of a poll can terminate it
+                    let poll_author = store
+                        .message(&thread, *ts)
+                        .await?
+                        .map(|poll| poll.metadata.sender);
+                    if poll_author.is_some_and(|author| author != message.metadata.sender) {
+                        warn!(%thread, ts, "ignoring termination of a poll by another member");
+                    } else {
+                        store
+                            .save_poll_termination(&thread, *ts, message.metadata.timestamp)
+                            .await?;
+                        trace!(%thread, ts, "poll terminated");
+                    }
+                    None
+                }
                 _ => Some(message),
             }
         }
@@ -2534,7 +6534,12 @@ async fn save_message<S: Store>(
             ..
         }) => {
             if let Some(mut existing_msg) = store.message(&thread, ts).await? {
-                existing_msg.metadata = message.metadata;
+                // keep the original timestamp, so the edit replaces the message instead of
+                // being stored next to it
+                existing_msg.metadata = Metadata {
+                    timestamp: existing_msg.metadata.timestamp,
+                    ..message.metadata
+                };
                 existing_msg.body = ContentBody::DataMessage(data_message);
                 // TODO: find a way to mark the message as edited (so that it's visible in a client)
                 trace!(%thread, ts, "message in thread edited");
@@ -2592,53 +6597,94 @@ async fn upsert_contact_from_profile<S: Store>(
     sender: ServiceId,
     profile_key: ProfileKey,
 ) -> Result<(), Error<<S as Store>::Error>> {
-    if store.contact_by_id(&sender.raw_uuid()).await?.is_none()
-        || store
-            .profile_key(&sender)
-            .await?
-            .is_none_or(|p| p.bytes != profile_key.bytes)
-    {
-        if let Some(aci) = sender.aci() {
-            let sender_uuid: Uuid = aci.into();
-            let encrypted_profile = identified_websocket
-                .retrieve_profile_by_id(aci, Some(profile_key))
-                .await?;
-            let profile_cipher = ProfileCipher::new(profile_key);
-            let decrypted_profile = profile_cipher.decrypt(encrypted_profile).unwrap();
+    let Some(aci) = sender.aci() else {
+        debug!("not storing profile for PNI contact");
+        return Ok(());
+    };
+    let sender_uuid: Uuid = aci.into();
 
-            let contact = Contact {
+    let existing_contact = store.contact_by_id(&sender_uuid).await?;
+    let profile_key_changed = update_profile_key(&mut store, aci, profile_key).await?;
+    if existing_contact.is_some() && !profile_key_changed {
+        return Ok(());
+    }
+
+    let encrypted_profile = identified_websocket
+        .retrieve_profile_by_id(aci, Some(profile_key))
+        .await?;
+    let profile_cipher = ProfileCipher::new(profile_key);
+    let decrypted_profile = profile_cipher.decrypt(encrypted_profile)?;
+    let name = decrypted_profile
+        .name
+        // FIXME: this assumes [firstname] [lastname]
+        .map(|pn| {
+            if let Some(family_name) = pn.family_name {
+                format!("{} {}", pn.given_name, family_name)
+            } else {
+                pn.given_name
+            }
+        })
+        .unwrap_or_default();
+
+    let contact = match existing_contact {
+        Some(mut contact) => {
+            info!(%sender_uuid, "updating contact after profile key change");
+            if !name.is_empty() {
+                contact.name = name;
+            }
+            contact.profile_key = profile_key.bytes.to_vec();
+            contact
+        }
+        None => {
+            info!(%sender_uuid, "saved contact on first sight");
+            Contact {
                 uuid: sender_uuid,
                 phone_number: None,
-                name: decrypted_profile
-                    .name
-                    // FIXME: this assumes [firstname] [lastname]
-                    .map(|pn| {
-                        if let Some(family_name) = pn.family_name {
-                            format!("{} {}", pn.given_name, family_name)
-                        } else {
-                            pn.given_name
-                        }
-                    })
-                    .unwrap_or_default(),
+                name,
                 profile_key: profile_key.bytes.to_vec(),
                 expire_timer: data_message.expire_timer.unwrap_or_default(),
                 expire_timer_version: data_message.expire_timer_version.unwrap_or(1),
                 inbox_position: 0,
                 avatar: None,
                 verified: Verified::default(),
-            };
-
-            info!(%sender_uuid, "saved contact on first sight");
-            store.save_contact(&contact).await?;
-            store.upsert_profile_key(&sender_uuid, profile_key).await?;
-        } else {
-            debug!("not storing profile for PNI contact");
+            }
         }
-    }
+    };
+    store.save_contact(&contact).await?;
+
     Ok(())
 }
 
+/// Saves the profile key of a contact, and drops the profile data cached with its previous key.
+///
+/// Returns whether the profile key changed.
+async fn update_profile_key<C: ContentsStore>(
+    store: &mut C,
+    aci: Aci,
+    profile_key: ProfileKey,
+) -> Result<bool, C::ContentsStoreError> {
+    let uuid: Uuid = aci.into();
+    let previous_profile_key = store.profile_key(&aci.into()).await?;
+    if previous_profile_key.is_some_and(|p| p.bytes == profile_key.bytes) {
+        return Ok(false);
+    }
+
+    if previous_profile_key.is_some() {
+        debug!(%uuid, "profile key changed, dropping cached profile");
+        store.clear_profile(&uuid).await?;
+    }
+    store.upsert_profile_key(&uuid, profile_key).await?;
+
+    if let Some(mut contact) = store.contact_by_id(&uuid).await? {
+        contact.profile_key = profile_key.bytes.to_vec();
+        store.save_contact(&contact).await?;
+    }
+
+    Ok(true)
+}
+
 async fn set_account_attributes<S: Store>(
+    store: &S,
     account_manager: &mut AccountManager,
     data: &RegistrationData,
 ) -> Result<(), Error<S::Error>> {
@@ -2646,9 +6692,21 @@ async fn set_account_attributes<S: Store>(
 
     let pni_registration_id = data.pni_registration_id.ok_or(Error::RelinkNecessary)?;
 
+    // the name of linked devices is encrypted with the identity key, so that only the other
+    // devices of the account can display it
+    let name = match data.device_name() {
+        Some(device_name) => {
+            let identity_key_pair = store.aci_protocol_store().get_identity_key_pair().await?;
+            let encrypted_name =
+                encrypt_device_name(&mut rng(), device_name, identity_key_pair.identity_key())?;
+            Some(STANDARD.encode(encrypted_name.encode_to_vec()))
+        }
+        None => None,
+    };
+
     account_manager
         .set_account_attributes(AccountAttributes {
-            name: data.device_name().map(|d| d.to_string()),
+            name,
             registration_id: data.registration_id,
             pni_registration_id,
             signaling_key: None,
@@ -2692,3 +6750,339 @@ async fn register_pre_keys<S: Store>(
     trace!("registered pre keys");
     Ok(())
 }
+
+/// Rotates the signed and last resort pre-keys if it was not done for [PRE_KEY_ROTATION_INTERVAL]
+async fn rotate_pre_keys_if_due<S: Store>(
+    store: &S,
+    websocket: &mut SignalWebSocket<websocket::Identified>,
+) -> Result<(), Error<S::Error>> {
+    if let Some(last_rotation) = store.last_pre_key_rotation().await? {
+        let elapsed = Duration::from_millis(timestamp_now().saturating_sub(last_rotation));
+        if elapsed < PRE_KEY_ROTATION_INTERVAL {
+            trace!(?elapsed, "pre-keys were rotated recently");
+            return Ok(());
+        }
+    }
+    rotate_pre_keys(store, websocket).await
+}
+
+async fn rotate_pre_keys<S: Store>(
+    store: &S,
+    websocket: &mut SignalWebSocket<websocket::Identified>,
+) -> Result<(), Error<S::Error>> {
+    debug!("rotating signed and last resort pre-keys");
+    rotate_signed_pre_key(
+        &mut store.aci_protocol_store(),
+        websocket,
+        ServiceIdKind::Aci,
+    )
+    .await?;
+    rotate_signed_pre_key(
+        &mut store.pni_protocol_store(),
+        websocket,
+        ServiceIdKind::Pni,
+    )
+    .await?;
+    store.save_last_pre_key_rotation(timestamp_now()).await?;
+    Ok(())
+}
+
+/// Generates a new signed pre-key and last resort Kyber pre-key, and uploads them.
+///
+/// The keys are saved before being uploaded, so that messages from contacts who fetched them
+/// right after the upload can be decrypted.
+async fn rotate_signed_pre_key<P: PreKeysStore>(
+    protocol_store: &mut P,
+    websocket: &mut SignalWebSocket<websocket::Identified>,
+    service_id_kind: ServiceIdKind,
+) -> Result<(), ServiceError> {
+    let identity_key_pair = protocol_store.get_identity_key_pair().await?;
+    let mut rng = rng();
+
+    let signed_pre_key_id = SignedPreKeyId::from(protocol_store.next_signed_pre_key_id().await?);
+    let key_pair = KeyPair::generate(&mut rng);
+    let signature = identity_key_pair
+        .private_key()
+        .calculate_signature(&key_pair.public_key.serialize(), &mut rng)?;
+    let signed_pre_key = SignedPreKeyRecord::new(
+        signed_pre_key_id,
+        Timestamp::from_epoch_millis(timestamp_now()),
+        &key_pair,
+        &signature,
+    );
+
+    let last_resort_key_id = KyberPreKeyId::from(protocol_store.next_pq_pre_key_id().await?);
+    let last_resort_key = KyberPreKeyRecord::generate(
+        kem::KeyType::Kyber1024,
+        last_resort_key_id,
+        identity_key_pair.private_key(),
+    )?;
+
+    protocol_store
+        .save_signed_pre_key(signed_pre_key_id, &signed_pre_key)
+        .await?;
+    protocol_store
+        .store_last_resort_kyber_pre_key(last_resort_key_id, &last_resort_key)
+        .await?;
+
+    websocket
+        .register_pre_keys(
+            service_id_kind,
+            PreKeyState {
+                pre_keys: Vec::new(),
+                signed_pre_key: Some(signed_pre_key.try_into()?),
+                pq_pre_keys: Vec::new(),
+                pq_last_resort_key: Some(last_resort_key.try_into()?),
+            },
+        )
+        .await?;
+
+    debug!(?service_id_kind, "rotated signed and last resort pre-keys");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use libsignal_service::content::ContentBody;
+    use libsignal_service::proto::{data_message, DataMessage, PollCreate, ReceiptMessage};
+
+    use libsignal_service::attachment_cipher::AttachmentCipherError;
+    use libsignal_service::push_service::ServiceError;
+
+    use super::{
+        apply_thread_timer, is_announcement, is_attachment_not_found, is_fatal_connection_error,
+        is_transient_download_error, is_unidentified_access_refused, unreached_group_error,
+        AccountBackup, MessageSenderError, Registered, RegistrationData, ACCOUNT_BACKUP_VERSION,
+    };
+    use crate::model::identity::OnNewIdentity;
+    use crate::store::{InMemoryStore, Thread};
+    use crate::Manager;
+    use libsignal_service::configuration::SignalServers;
+    use libsignal_service::prelude::{phonenumber, ProfileKey, Uuid};
+    use libsignal_service::protocol::{Aci, IdentityKeyPair, ServiceId};
+    use libsignal_service::push_service::ServiceIds;
+
+    type Error = crate::Error<std::io::Error>;
+
+    fn expire_timer(content_body: &ContentBody) -> (Option<u32>, Option<u32>) {
+        match content_body {
+            ContentBody::DataMessage(data_message) => {
+                (data_message.expire_timer, data_message.expire_timer_version)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn outgoing_message_inherits_thread_timer() {
+        let mut content_body = ContentBody::DataMessage(DataMessage {
+            body: Some("hello".to_owned()),
+            ..Default::default()
+        });
+        apply_thread_timer(&mut content_body, Some((3600, 2)));
+        assert_eq!(expire_timer(&content_body), (Some(3600), Some(2)));
+    }
+
+    #[test]
+    fn outgoing_message_without_thread_timer() {
+        let mut content_body = ContentBody::DataMessage(DataMessage::default());
+        apply_thread_timer(&mut content_body, None);
+        assert_eq!(expire_timer(&content_body), (None, Some(0)));
+    }
+
+    #[test]
+    fn outgoing_timer_change_bumps_version() {
+        let mut content_body = ContentBody::DataMessage(DataMessage {
+            expire_timer: Some(60),
+            ..Default::default()
+        });
+        apply_thread_timer(&mut content_body, Some((3600, 2)));
+        assert_eq!(expire_timer(&content_body), (Some(60), Some(3)));
+    }
+
+    #[test]
+    fn outgoing_timer_override_keeps_version() {
+        let mut content_body = ContentBody::DataMessage(DataMessage {
+            expire_timer: Some(60),
+            expire_timer_version: Some(2),
+            ..Default::default()
+        });
+        apply_thread_timer(&mut content_body, Some((3600, 2)));
+        assert_eq!(expire_timer(&content_body), (Some(60), Some(2)));
+    }
+
+    #[test]
+    fn attachment_integrity_errors_are_not_retried() {
+        assert!(!is_transient_download_error(
+            &Error::UnexpectedAttachmentChecksum
+        ));
+        assert!(!is_transient_download_error(&Error::AttachmentCipherError(
+            AttachmentCipherError::MacError
+        )));
+    }
+
+    #[test]
+    fn attachment_transient_errors_are_retried() {
+        assert!(is_transient_download_error(&Error::ServiceError(
+            ServiceError::UnhandledResponseCode { http_code: 503 }
+        )));
+        assert!(is_transient_download_error(&Error::ServiceError(
+            ServiceError::RateLimitExceeded
+        )));
+        assert!(!is_transient_download_error(&Error::ServiceError(
+            ServiceError::UnhandledResponseCode { http_code: 400 }
+        )));
+        assert!(is_attachment_not_found(&Error::ServiceError(
+            ServiceError::NotFoundError
+        )));
+    }
+
+    #[test]
+    fn fall_back_to_identified_sending() {
+        assert!(is_unidentified_access_refused(
+            &MessageSenderError::ServiceError(ServiceError::Unauthorized)
+        ));
+        assert!(!is_unidentified_access_refused(
+            &MessageSenderError::ServiceError(ServiceError::RateLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn announcements() {
+        let message = |message| ContentBody::DataMessage(message);
+        assert!(is_announcement(&message(DataMessage {
+            body: Some("hello".to_owned()),
+            ..Default::default()
+        })));
+        assert!(is_announcement(&message(DataMessage {
+            poll_create: Some(PollCreate::default()),
+            ..Default::default()
+        })));
+        assert!(is_announcement(&message(DataMessage {
+            contact: vec![Default::default()],
+            ..Default::default()
+        })));
+
+        assert!(!is_announcement(&message(DataMessage {
+            reaction: Some(data_message::Reaction::default()),
+            ..Default::default()
+        })));
+        assert!(!is_announcement(&message(DataMessage {
+            delete: Some(data_message::Delete::default()),
+            ..Default::default()
+        })));
+        assert!(!is_announcement(&ContentBody::ReceiptMessage(
+            ReceiptMessage::default()
+        )));
+    }
+
+    #[test]
+    fn group_send_kept_when_no_member_was_reached() {
+        let (alice, bob) = (
+            Aci::from(Uuid::new_v4()).into(),
+            Aci::from(Uuid::new_v4()).into(),
+        );
+        let unreachable = || {
+            Err(Error::ServiceError(ServiceError::UnhandledResponseCode {
+                http_code: 503,
+            }))
+        };
+
+        let results: Vec<(ServiceId, Result<(), Error>)> =
+            vec![(alice, unreachable()), (bob, unreachable())];
+        assert!(unreached_group_error(&results).is_some());
+        // members who are not registered anymore will never be reached
+        let results = vec![
+            (alice, unreachable()),
+            (bob, Err(Error::UnregisteredRecipient(bob))),
+        ];
+        assert!(unreached_group_error(&results).is_some());
+
+        let results = vec![(alice, unreachable()), (bob, Ok(()))];
+        assert!(unreached_group_error(&results).is_none());
+        let results = vec![(alice, Err(Error::UnregisteredRecipient(alice)))];
+        assert!(unreached_group_error(&results).is_none());
+        assert!(unreached_group_error::<std::io::Error, ()>(&[]).is_none());
+    }
+
+    #[test]
+    fn reconnect_unless_unregistered() {
+        assert!(is_fatal_connection_error(&Error::ServiceError(
+            ServiceError::Unauthorized
+        )));
+        assert!(is_fatal_connection_error(&Error::NotYetRegisteredError));
+        assert!(!is_fatal_connection_error(&Error::ServiceError(
+            ServiceError::UnhandledResponseCode { http_code: 502 }
+        )));
+        assert!(!is_fatal_connection_error(&Error::IoError(
+            std::io::ErrorKind::ConnectionReset.into()
+        )));
+    }
+
+    fn registration_data() -> RegistrationData {
+        RegistrationData {
+            signal_servers: SignalServers::Staging,
+            device_name: Some("presage".to_owned()),
+            phone_number: phonenumber::parse(None, "+41791234567").unwrap(),
+            service_ids: ServiceIds {
+                aci: Uuid::new_v4(),
+                pni: Uuid::new_v4(),
+            },
+            password: "password".to_owned(),
+            signaling_key: [0; 52],
+            device_id: Some(2),
+            registration_id: 1,
+            pni_registration_id: Some(2),
+            profile_key: ProfileKey::create([1; 32]),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_are_serialized_per_thread() {
+        let registered = Registered::with_data(registration_data());
+        let contact = Thread::Contact(Uuid::new_v4());
+        let group = Thread::Group([1; 32]);
+
+        let guard = registered.lock_thread_for_sending(&contact).await;
+        let mut next = Box::pin(registered.lock_thread_for_sending(&contact));
+        assert!(futures::poll!(next.as_mut()).is_pending());
+
+        // other threads are not held back
+        drop(registered.lock_thread_for_sending(&group).await);
+
+        drop(guard);
+        drop(next.await);
+
+        // unused locks are forgotten
+        drop(registered.lock_thread_for_sending(&group).await);
+        assert_eq!(registered.send_locks.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn export_and_import_account() -> Result<(), Box<dyn std::error::Error>> {
+        let mut rng = rand::rng();
+        let backup = AccountBackup {
+            version: ACCOUNT_BACKUP_VERSION,
+            registration_data: registration_data(),
+            aci_identity_key_pair: IdentityKeyPair::generate(&mut rng),
+            pni_identity_key_pair: IdentityKeyPair::generate(&mut rng),
+            master_key: None,
+        };
+        let json = serde_json::to_string(&backup)?;
+
+        let store = InMemoryStore::new(OnNewIdentity::Trust);
+        let manager = Manager::import_account(store.clone(), serde_json::from_str(&json)?).await?;
+        assert_eq!(
+            serde_json::to_string(&manager.export_account().await?)?,
+            json
+        );
+
+        // the store already contains an account
+        assert!(matches!(
+            Manager::import_account(store, serde_json::from_str(&json)?).await,
+            Err(crate::Error::AlreadyRegisteredError)
+        ));
+
+        Ok(())
+    }
+}
@@ -1,27 +1,52 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use libsignal_service::configuration::{ServiceConfiguration, SignalServers};
 use libsignal_service::prelude::phonenumber::PhoneNumber;
-use libsignal_service::push_service::PushService;
-use libsignal_service::websocket::registration::VerificationTransport;
+use libsignal_service::push_service::{PushService, ServiceError};
+use libsignal_service::websocket::registration::{
+    PushTokenType, RegistrationSessionMetadataResponse, VerificationTransport,
+};
 use rand::distr::{Alphanumeric, SampleString};
 use tracing::trace;
 
 use crate::store::Store;
-use crate::{Error, Manager};
+use crate::{Error, Manager, RegistrationError};
 
 use super::Confirmation;
 
+/// Delay before retrying when the server rate limits a request without saying for how long
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
 /// Options when registering a new main device
 #[derive(Debug)]
 pub struct RegistrationOptions<'a> {
     pub signal_servers: SignalServers,
     pub phone_number: PhoneNumber,
-    pub use_voice_call: bool,
+    /// How to receive the verification code, landlines and some VoIP numbers can only receive
+    /// voice calls
+    pub transport: VerificationTransport,
+    /// Solved captcha, after [RegistrationError::CaptchaRequired]
     pub captcha: Option<&'a str>,
+    /// Push token of the device, to which the server sends a push challenge
+    pub push_token: Option<PushToken<'a>>,
+    /// Push challenge received after [RegistrationError::PushChallengeRequired]
+    pub push_challenge: Option<&'a str>,
+    /// Token of a [RegistrationError] to resume its registration session instead of creating a
+    /// new one, e.g. to submit a push challenge
+    pub session_token: Option<&'a str>,
     pub force: bool,
 }
 
+/// Push token of the device being registered
+#[derive(Debug, Clone, Copy)]
+pub enum PushToken<'a> {
+    /// Firebase Cloud Messaging (Android)
+    Fcm(&'a str),
+    /// Apple Push Notification service (iOS)
+    Apn(&'a str),
+}
+
 /// Manager state where it is possible to register a new main device
 pub struct Registration;
 
@@ -31,13 +56,18 @@ impl<S: Store> Manager<S, Registration> {
     /// The returned value is a [confirmation manager](Manager::confirm_verification_code) which you then
     /// have to use to send the confirmation code.
     ///
+    /// If the server requires a captcha or a push challenge first, this fails with a
+    /// [RegistrationError] whose token can be passed back in
+    /// [RegistrationOptions::session_token], along with the solved captcha or the received push
+    /// challenge.
+    ///
     /// ```no_run
     /// use std::str::FromStr;
     ///
     /// use presage::libsignal_service::{
     ///     configuration::SignalServers, prelude::phonenumber::PhoneNumber,
     /// };
-    /// use presage::manager::RegistrationOptions;
+    /// use presage::manager::{RegistrationOptions, VerificationTransport};
     /// use presage::Manager;
     /// use presage::model::identity::OnNewIdentity;
     ///
@@ -52,8 +82,11 @@ impl<S: Store> Manager<S, Registration> {
     ///         RegistrationOptions {
     ///             signal_servers: SignalServers::Production,
     ///             phone_number: PhoneNumber::from_str("+16137827274")?,
-    ///             use_voice_call: false,
+    ///             transport: VerificationTransport::Sms,
     ///             captcha: None,
+    ///             push_token: None,
+    ///             push_challenge: None,
+    ///             session_token: None,
     ///             force: false,
     ///         },
     ///     )
@@ -69,8 +102,11 @@ impl<S: Store> Manager<S, Registration> {
         let RegistrationOptions {
             signal_servers,
             phone_number,
-            use_voice_call,
+            transport,
             captcha,
+            push_token,
+            push_challenge,
+            session_token,
             force,
         } = registration_options;
 
@@ -92,45 +128,91 @@ impl<S: Store> Manager<S, Registration> {
             .ws("/v1/websocket/", "/v1/keepalive", &[], None)
             .await?;
 
-        trace!("creating registration verification session");
+        let (push_token, push_token_type) = match push_token {
+            Some(PushToken::Fcm(token)) => (Some(token), Some(PushTokenType::Fcm)),
+            Some(PushToken::Apn(token)) => (Some(token), Some(PushTokenType::Apn)),
+            None => (None, None),
+        };
 
-        let phone_number_string = phone_number.to_string();
-        let mut session = unidentified_websocket
-            .create_verification_session(&phone_number_string, None, None, None)
-            .await?;
+        let mut session = match session_token {
+            Some(session_id) => {
+                trace!("resuming registration verification session");
+                unidentified_websocket
+                    .patch_verification_session(
+                        session_id,
+                        push_token,
+                        push_token_type,
+                        push_challenge,
+                        captcha,
+                        None,
+                    )
+                    .await
+                    .map_err(|error| rate_limited(error, None))?
+            }
+            None => {
+                trace!("creating registration verification session");
+                let phone_number_string = phone_number.to_string();
+                let session = unidentified_websocket
+                    .create_verification_session(&phone_number_string, None, None, None)
+                    .await
+                    .map_err(|error| rate_limited(error, None))?;
+                if push_token.is_some() {
+                    unidentified_websocket
+                        .patch_verification_session(
+                            &session.id,
+                            push_token,
+                            push_token_type,
+                            None,
+                            None,
+                            None,
+                        )
+                        .await
+                        .map_err(|error| rate_limited(error, None))?
+                } else {
+                    session
+                }
+            }
+        };
 
         if !session.allowed_to_request_code {
             if session.captcha_required() {
                 trace!("captcha required");
                 if captcha.is_none() {
-                    return Err(Error::CaptchaRequired);
+                    return Err(RegistrationError::CaptchaRequired { token: session.id }.into());
                 }
                 session = unidentified_websocket
                     .patch_verification_session(&session.id, None, None, None, captcha, None)
-                    .await?
+                    .await
+                    .map_err(|error| rate_limited(error, None))?
             }
             if session.push_challenge_required() {
-                return Err(Error::PushChallengeRequired);
+                trace!("push challenge required");
+                if push_challenge.is_none() {
+                    return Err(
+                        RegistrationError::PushChallengeRequired { token: session.id }.into(),
+                    );
+                }
+                session = unidentified_websocket
+                    .patch_verification_session(&session.id, None, None, push_challenge, None, None)
+                    .await
+                    .map_err(|error| rate_limited(error, None))?
             }
         }
 
         if !session.allowed_to_request_code {
+            if let Some(retry_after) = next_code_request(&session, &transport) {
+                return Err(RegistrationError::RateLimited { retry_after }.into());
+            }
             return Err(Error::RequestingCodeForbidden(session));
         }
 
         trace!("requesting verification code");
 
+        let retry_after = next_code_request(&session, &transport);
         session = unidentified_websocket
-            .request_verification_code(
-                &session.id,
-                crate::USER_AGENT,
-                if use_voice_call {
-                    VerificationTransport::Voice
-                } else {
-                    VerificationTransport::Sms
-                },
-            )
-            .await?;
+            .request_verification_code(&session.id, crate::USER_AGENT, transport)
+            .await
+            .map_err(|error| rate_limited(error, retry_after))?;
 
         let manager = Manager {
             store,
@@ -145,3 +227,30 @@ impl<S: Store> Manager<S, Registration> {
         Ok(manager)
     }
 }
+
+/// Delay before a verification code can be requested (again) with the chosen transport, as
+/// announced by the server
+fn next_code_request(
+    session: &RegistrationSessionMetadataResponse,
+    transport: &VerificationTransport,
+) -> Option<Duration> {
+    let seconds = match transport {
+        VerificationTransport::Sms => session.next_sms,
+        VerificationTransport::Voice => session.next_call,
+    }?;
+    Some(Duration::from_secs(seconds.max(0) as u64))
+}
+
+/// Turns the rate limiting of a registration request into [RegistrationError::RateLimited]
+pub(super) fn rate_limited<S: std::error::Error>(
+    error: ServiceError,
+    retry_after: Option<Duration>,
+) -> Error<S> {
+    match error {
+        ServiceError::RateLimitExceeded => RegistrationError::RateLimited {
+            retry_after: retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+        }
+        .into(),
+        error => error.into(),
+    }
+}
@@ -3,6 +3,8 @@ use libsignal_service::{
     models::Attachment,
     prelude::{phonenumber::PhoneNumber, Uuid},
     proto::Verified,
+    websocket::account::DeviceCapabilities,
+    Profile,
 };
 use serde::{Deserialize, Serialize};
 
@@ -44,3 +46,36 @@ impl From<libsignal_service::models::Contact> for Contact {
         }
     }
 }
+
+/// A decrypted profile together with the capabilities advertised by the account
+///
+/// The `about` text and emoji are decrypted as part of the [Profile].
+#[derive(Debug, Clone)]
+pub struct ProfileDetails {
+    pub profile: Profile,
+    pub capabilities: ProfileCapabilities,
+}
+
+/// Features supported by all the devices of an account
+///
+/// Sending to a group with sender keys requires every member to support them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileCapabilities {
+    pub sender_key: bool,
+    pub pni: bool,
+    pub gift_badges: bool,
+    pub stories: bool,
+    pub payment_activation: bool,
+}
+
+impl From<&DeviceCapabilities> for ProfileCapabilities {
+    fn from(c: &DeviceCapabilities) -> Self {
+        Self {
+            sender_key: c.sender_key,
+            pni: c.pni,
+            gift_badges: c.gift_badges,
+            stories: c.stories,
+            payment_activation: c.payment_activation,
+        }
+    }
+}
@@ -1,9 +1,16 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use libsignal_service::{
-    groups_v2::Role,
-    prelude::{AccessControl, ProfileKey, Timer, Uuid},
+    groups_v2::{self, GroupDecodingError, GroupOperations, Role},
+    prelude::{AccessControl, ProfileKey, ProtobufMessage, Timer, Uuid},
+    proto::{group_invite_link, GroupContextV2, GroupInviteLink as GroupInviteLinkProto},
     protocol::Aci,
+    zkgroup::{
+        groups::{GroupMasterKey, GroupSecretParams},
+        GroupMasterKeyBytes,
+    },
 };
 use serde::{Deserialize, Serialize};
+use url::Url;
 
 use super::ServiceIdType;
 use libsignal_service::utils::serde_aci;
@@ -20,6 +27,75 @@ pub struct Group {
     pub requesting_members: Vec<RequestingMember>,
     pub invite_link_password: Vec<u8>,
     pub description: Option<String>,
+    /// Whether only administrators can send messages in the group
+    #[serde(default)]
+    pub announcements_only: bool,
+}
+
+/// The role of a member in a group
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum GroupMemberRole {
+    /// Regular member
+    #[default]
+    Default,
+    /// Administrator, allowed to change the group settings and manage members
+    Administrator,
+}
+
+impl From<GroupMemberRole> for Role {
+    fn from(val: GroupMemberRole) -> Self {
+        match val {
+            GroupMemberRole::Default => Role::Default,
+            GroupMemberRole::Administrator => Role::Administrator,
+        }
+    }
+}
+
+impl From<Role> for GroupMemberRole {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Administrator => GroupMemberRole::Administrator,
+            _ => GroupMemberRole::Default,
+        }
+    }
+}
+
+/// Who is allowed to perform an action in a group, see
+/// [Manager::set_group_access_control](crate::Manager::set_group_access_control)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessRequired {
+    /// Any member of the group
+    Member,
+    /// Only administrators of the group
+    Administrator,
+    /// Nobody, e.g. to disable joining with the invite link
+    Unsatisfiable,
+    /// Anyone, even without being a member (only meaningful for the invite link)
+    Any,
+}
+
+impl From<groups_v2::AccessRequired> for AccessRequired {
+    fn from(access: groups_v2::AccessRequired) -> Self {
+        match access {
+            groups_v2::AccessRequired::Any => AccessRequired::Any,
+            groups_v2::AccessRequired::Member => AccessRequired::Member,
+            groups_v2::AccessRequired::Administrator => AccessRequired::Administrator,
+            groups_v2::AccessRequired::Unsatisfiable | groups_v2::AccessRequired::Unknown => {
+                AccessRequired::Unsatisfiable
+            }
+        }
+    }
+}
+
+impl From<AccessRequired> for groups_v2::AccessRequired {
+    fn from(access: AccessRequired) -> Self {
+        match access {
+            AccessRequired::Member => groups_v2::AccessRequired::Member,
+            AccessRequired::Administrator => groups_v2::AccessRequired::Administrator,
+            AccessRequired::Unsatisfiable => groups_v2::AccessRequired::Unsatisfiable,
+            AccessRequired::Any => groups_v2::AccessRequired::Any,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -52,6 +128,262 @@ pub struct RequestingMember {
     pub timestamp: u64,
 }
 
+/// Maximum length of a group title, in characters, as enforced by the Signal apps
+pub const GROUP_TITLE_MAX_LENGTH: usize = 32;
+
+/// Maximum length of a group description, in characters, as enforced by the Signal apps
+pub const GROUP_DESCRIPTION_MAX_LENGTH: usize = 480;
+
+/// A change to a group, to validate before committing it, see
+/// [Manager::validate_group_change](crate::Manager::validate_group_change)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupChangeSpec {
+    /// Add a member (or invite them) with the given role
+    AddMember { aci: Aci, role: GroupMemberRole },
+    /// Remove another member
+    RemoveMember(Aci),
+    /// Change the role of a member
+    SetMemberRole { aci: Aci, role: GroupMemberRole },
+    /// Leave the group
+    Leave,
+    /// Change the title
+    SetTitle(String),
+    /// Change (or remove) the description
+    SetDescription(Option<String>),
+    /// Allow only administrators to send messages, or everyone again
+    SetAnnouncementsOnly(bool),
+    /// Approve the request of a user to join with the invite link
+    ApproveRequest(Aci),
+    /// Deny the request of a user to join with the invite link
+    DenyRequest(Aci),
+}
+
+/// Why a [GroupChangeSpec] would be rejected
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GroupChangeError {
+    #[error("we are not a member of this group")]
+    NotMember,
+    #[error("only administrators of the group can do this")]
+    NotAdministrator,
+    #[error("{} is already a member of this group", .0.service_id_string())]
+    AlreadyMember(Aci),
+    #[error("{} is already invited to this group", .0.service_id_string())]
+    AlreadyInvited(Aci),
+    #[error("{} is not a member of this group", .0.service_id_string())]
+    UnknownMember(Aci),
+    #[error("{} did not request to join this group", .0.service_id_string())]
+    NoJoinRequest(Aci),
+    #[error("the group would be left without an administrator")]
+    LastAdministrator,
+    #[error("use leave instead of removing ourselves")]
+    RemoveSelf,
+    #[error("the group title cannot be empty")]
+    EmptyTitle,
+    #[error("the group title is longer than {GROUP_TITLE_MAX_LENGTH} characters")]
+    TitleTooLong,
+    #[error("the group description is longer than {GROUP_DESCRIPTION_MAX_LENGTH} characters")]
+    DescriptionTooLong,
+}
+
+/// Maximum length of an encrypted group title accepted by the server, in bytes
+pub const GROUP_TITLE_MAX_ENCRYPTED_LENGTH: usize = 1024;
+
+/// Maximum length of an encrypted group description accepted by the server, in bytes
+pub const GROUP_DESCRIPTION_MAX_ENCRYPTED_LENGTH: usize = 8192;
+
+/// An attribute of a group, see [GroupValidationError]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupField {
+    Title,
+    Description,
+}
+
+/// A group attribute is too long to be accepted by the server
+///
+/// Like the server, the length is that of the encrypted attribute: multi-byte characters count
+/// for all their bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("the group {field:?} is too long: at most {max_len} bytes once encrypted")]
+pub struct GroupValidationError {
+    pub field: GroupField,
+    /// Maximum length of the encrypted attribute, in bytes
+    pub max_len: usize,
+}
+
+impl GroupField {
+    /// Checks that `value` is short enough once encrypted for the group of `group_ops`
+    pub(crate) fn validate(
+        self,
+        group_ops: &GroupOperations,
+        value: &str,
+    ) -> Result<(), GroupValidationError> {
+        let mut rng = rand::rng();
+        let (encrypted_len, max_len) = match self {
+            GroupField::Title => (
+                group_ops.encrypt_title(value, &mut rng).len(),
+                GROUP_TITLE_MAX_ENCRYPTED_LENGTH,
+            ),
+            GroupField::Description => (
+                group_ops.encrypt_description(value, &mut rng).len(),
+                GROUP_DESCRIPTION_MAX_ENCRYPTED_LENGTH,
+            ),
+        };
+        if encrypted_len > max_len {
+            return Err(GroupValidationError {
+                field: self,
+                max_len,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Group {
+    /// Whether `aci` is a full member of the group
+    ///
+    /// Groups we left or were removed from are kept locally, and are inactive: we are no longer a
+    /// member of them.
+    pub fn is_member(&self, aci: Aci) -> bool {
+        self.members.iter().any(|member| member.aci == aci)
+    }
+
+    /// Whether `aci` is an administrator of the group
+    pub fn is_administrator(&self, aci: Aci) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.aci == aci && member.role == Role::Administrator)
+    }
+
+    /// Checks whether `self_aci` can apply `change` to the group, based on this (local) state of
+    /// the group.
+    ///
+    /// This doesn't guarantee that the server accepts the change, e.g. when the local state is
+    /// outdated, but catches the common mistakes without a round-trip.
+    pub fn validate_change(
+        &self,
+        self_aci: Aci,
+        change: &GroupChangeSpec,
+    ) -> Result<(), GroupChangeError> {
+        if !self.is_member(self_aci) {
+            return Err(GroupChangeError::NotMember);
+        }
+        let access_control = self.access_control.as_ref();
+        let is_administrator = self.is_administrator(self_aci);
+        let require_administrator = || {
+            is_administrator
+                .then_some(())
+                .ok_or(GroupChangeError::NotAdministrator)
+        };
+        let other_administrators = |aci: Aci| {
+            self.members
+                .iter()
+                .any(|member| member.aci != aci && member.role == Role::Administrator)
+        };
+
+        match change {
+            GroupChangeSpec::AddMember { aci, role } => {
+                let access = access_control
+                    .map_or(groups_v2::AccessRequired::Member, |access| access.members);
+                if !self.allows(self_aci, access) {
+                    return Err(GroupChangeError::NotAdministrator);
+                }
+                if *role == GroupMemberRole::Administrator {
+                    require_administrator()?;
+                }
+                if self.is_member(*aci) {
+                    return Err(GroupChangeError::AlreadyMember(*aci));
+                }
+                if self
+                    .pending_members
+                    .iter()
+                    .any(|member| member.uuid == aci.raw_uuid())
+                {
+                    return Err(GroupChangeError::AlreadyInvited(*aci));
+                }
+            }
+            GroupChangeSpec::RemoveMember(aci) => {
+                if *aci == self_aci {
+                    return Err(GroupChangeError::RemoveSelf);
+                }
+                require_administrator()?;
+                if !self.is_member(*aci) {
+                    return Err(GroupChangeError::UnknownMember(*aci));
+                }
+            }
+            GroupChangeSpec::SetMemberRole { aci, role } => {
+                require_administrator()?;
+                if !self.is_member(*aci) {
+                    return Err(GroupChangeError::UnknownMember(*aci));
+                }
+                if *role == GroupMemberRole::Default
+                    && self.is_administrator(*aci)
+                    && !other_administrators(*aci)
+                {
+                    return Err(GroupChangeError::LastAdministrator);
+                }
+            }
+            GroupChangeSpec::Leave => {
+                // the earliest member is promoted when the last administrator leaves
+                if is_administrator && !other_administrators(self_aci) && self.members.len() == 1 {
+                    return Err(GroupChangeError::LastAdministrator);
+                }
+            }
+            GroupChangeSpec::SetTitle(title) => {
+                self.require_attributes_access(self_aci)?;
+                if title.trim().is_empty() {
+                    return Err(GroupChangeError::EmptyTitle);
+                }
+                if title.chars().count() > GROUP_TITLE_MAX_LENGTH {
+                    return Err(GroupChangeError::TitleTooLong);
+                }
+            }
+            GroupChangeSpec::SetDescription(description) => {
+                self.require_attributes_access(self_aci)?;
+                if description.as_ref().is_some_and(|description| {
+                    description.chars().count() > GROUP_DESCRIPTION_MAX_LENGTH
+                }) {
+                    return Err(GroupChangeError::DescriptionTooLong);
+                }
+            }
+            GroupChangeSpec::SetAnnouncementsOnly(_) => require_administrator()?,
+            GroupChangeSpec::ApproveRequest(aci) | GroupChangeSpec::DenyRequest(aci) => {
+                require_administrator()?;
+                if !self
+                    .requesting_members
+                    .iter()
+                    .any(|member| member.aci == *aci)
+                {
+                    return Err(GroupChangeError::NoJoinRequest(*aci));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn require_attributes_access(&self, aci: Aci) -> Result<(), GroupChangeError> {
+        let access = self
+            .access_control
+            .as_ref()
+            .map_or(groups_v2::AccessRequired::Member, |access| {
+                access.attributes
+            });
+        if self.allows(aci, access) {
+            Ok(())
+        } else {
+            Err(GroupChangeError::NotAdministrator)
+        }
+    }
+
+    fn allows(&self, aci: Aci, access: groups_v2::AccessRequired) -> bool {
+        match access {
+            groups_v2::AccessRequired::Any => true,
+            groups_v2::AccessRequired::Member => self.is_member(aci),
+            groups_v2::AccessRequired::Administrator => self.is_administrator(aci),
+            groups_v2::AccessRequired::Unsatisfiable | groups_v2::AccessRequired::Unknown => false,
+        }
+    }
+}
+
 impl From<libsignal_service::groups_v2::Group> for Group {
     fn from(val: libsignal_service::groups_v2::Group) -> Self {
         Group {
@@ -65,6 +397,8 @@ impl From<libsignal_service::groups_v2::Group> for Group {
             requesting_members: val.requesting_members.into_iter().map(Into::into).collect(),
             invite_link_password: val.invite_link_password,
             description: val.description,
+            // not encrypted, so it is copied from the fetched group by the manager
+            announcements_only: false,
         }
     }
 }
@@ -101,3 +435,312 @@ impl From<libsignal_service::groups_v2::RequestingMember> for RequestingMember {
         }
     }
 }
+
+/// A change of a group, carried (encrypted) by the group context of a message
+///
+/// See [ContentExt::group_change](crate::store::ContentExt::group_change). Only the most common
+/// changes are decoded, the new state of the group can be fetched for the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupChange {
+    /// Who changed the group
+    pub editor: Aci,
+    /// Revision of the group after the change
+    pub revision: u32,
+    /// Members who were added, or whose request to join was approved
+    pub added: Vec<Aci>,
+    /// Members who were removed, or who left
+    pub removed: Vec<Aci>,
+    /// Members whose role changed, with their new role
+    pub role_changes: Vec<(Aci, GroupMemberRole)>,
+    /// The new title, if it changed
+    pub title_changed: Option<String>,
+    /// The new description (or [None] if it was removed), if it changed
+    pub description_changed: Option<Option<String>>,
+    /// The new disappearing messages timer (or [None] if disabled), if it changed
+    pub timer_changed: Option<Option<Timer>>,
+    /// Whether only administrators can send messages now, if it changed
+    pub announcements_only_changed: Option<bool>,
+}
+
+impl GroupChange {
+    /// Decrypts the group change of a group context, if it has one
+    pub fn decrypt(group_context: &GroupContextV2) -> Result<Option<Self>, GroupDecodingError> {
+        let Some(master_key_bytes) = group_context
+            .master_key
+            .as_deref()
+            .and_then(|bytes| GroupMasterKeyBytes::try_from(bytes).ok())
+        else {
+            return Ok(None);
+        };
+        let group_secret_params =
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(master_key_bytes));
+        let Some(changes) = GroupOperations::new(group_secret_params)
+            .decrypt_group_context(group_context.clone())?
+        else {
+            return Ok(None);
+        };
+
+        let mut group_change = Self {
+            editor: changes.editor,
+            revision: changes.revision,
+            added: Vec::new(),
+            removed: Vec::new(),
+            role_changes: Vec::new(),
+            title_changed: None,
+            description_changed: None,
+            timer_changed: None,
+            announcements_only_changed: None,
+        };
+        for change in changes.changes {
+            match change {
+                groups_v2::GroupChange::NewMember(member) => group_change.added.push(member.aci),
+                groups_v2::GroupChange::PromoteRequestingMember { aci, .. } => {
+                    group_change.added.push(aci)
+                }
+                groups_v2::GroupChange::DeleteMember(aci) => group_change.removed.push(aci),
+                groups_v2::GroupChange::ModifyMemberRole { aci, role } => {
+                    group_change.role_changes.push((aci, role.into()))
+                }
+                groups_v2::GroupChange::Title(title) => group_change.title_changed = Some(title),
+                groups_v2::GroupChange::Description(description) => {
+                    group_change.description_changed = Some(description)
+                }
+                groups_v2::GroupChange::Timer(timer) => group_change.timer_changed = Some(timer),
+                groups_v2::GroupChange::AnnouncementOnly(announcements_only) => {
+                    group_change.announcements_only_changed = Some(announcements_only)
+                }
+                _ => (),
+            }
+        }
+        Ok(Some(group_change))
+    }
+}
+
+/// The identifier of a GV2 group, derived from its master key
+///
+/// This is the id carried by e.g. typing messages and blocked lists, and used by other clients to
+/// refer to a group. See [Manager::master_key_for_group](crate::Manager::master_key_for_group) to
+/// find the master key of a stored group from its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GroupId(pub [u8; 32]);
+
+impl GroupId {
+    /// Derives the identifier of the group with this master key
+    pub fn from_master_key(master_key_bytes: &GroupMasterKeyBytes) -> Self {
+        Self(
+            GroupSecretParams::derive_from_master_key(GroupMasterKey::new(*master_key_bytes))
+                .get_group_identifier(),
+        )
+    }
+}
+
+impl TryFrom<&[u8]> for GroupId {
+    type Error = std::array::TryFromSliceError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        bytes.try_into().map(Self)
+    }
+}
+
+/// Base URL of group invite links, the contents of the link are in the fragment
+const GROUP_INVITE_LINK_BASE_URL: &str = "https://signal.group/";
+
+/// The contents of a `https://signal.group/#...` group invite link
+#[derive(derive_more::Debug, Clone, PartialEq, Eq)]
+pub struct GroupInviteLink {
+    #[debug(ignore)]
+    pub master_key: GroupMasterKeyBytes,
+    #[debug(ignore)]
+    pub password: Vec<u8>,
+}
+
+impl GroupInviteLink {
+    /// Encodes the invite link as an URL that can be opened by Signal apps.
+    pub fn to_url(&self) -> Url {
+        let contents = GroupInviteLinkProto {
+            contents: Some(group_invite_link::Contents::ContentsV1(
+                group_invite_link::GroupInviteLinkContentsV1 {
+                    group_master_key: self.master_key.to_vec(),
+                    invite_link_password: self.password.clone(),
+                },
+            )),
+        };
+        let mut url = Url::parse(GROUP_INVITE_LINK_BASE_URL).expect("valid base URL");
+        url.set_fragment(Some(&URL_SAFE_NO_PAD.encode(contents.encode_to_vec())));
+        url
+    }
+
+    /// Parses a group invite link, returns [None] if the URL is not a valid invite link.
+    pub fn from_url(url: &Url) -> Option<Self> {
+        if url.scheme() != "https" || url.host_str() != Some("signal.group") {
+            return None;
+        }
+        let bytes = URL_SAFE_NO_PAD.decode(url.fragment()?).ok()?;
+        match GroupInviteLinkProto::decode(bytes.as_slice())
+            .ok()?
+            .contents?
+        {
+            group_invite_link::Contents::ContentsV1(contents) => Some(Self {
+                master_key: contents.group_master_key.try_into().ok()?,
+                password: contents.invite_link_password,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_invite_link_roundtrip() {
+        let link = GroupInviteLink {
+            master_key: [42; 32],
+            password: vec![7; 16],
+        };
+        let url = link.to_url();
+        assert_eq!(url.host_str(), Some("signal.group"));
+        assert!(!url.fragment().unwrap().contains('='));
+        assert_eq!(GroupInviteLink::from_url(&url), Some(link));
+    }
+
+    fn aci(n: u128) -> Aci {
+        Aci::from(Uuid::from_u128(n))
+    }
+
+    fn member(aci: Aci, role: Role, joined_at_revision: u32) -> Member {
+        Member {
+            aci,
+            role,
+            profile_key: ProfileKey::create([0; 32]),
+            joined_at_revision,
+        }
+    }
+
+    fn group(members: Vec<Member>) -> Group {
+        Group {
+            title: "Test".to_owned(),
+            avatar: String::new(),
+            disappearing_messages_timer: None,
+            access_control: None,
+            revision: 1,
+            members,
+            pending_members: Vec::new(),
+            requesting_members: Vec::new(),
+            invite_link_password: Vec::new(),
+            description: None,
+            announcements_only: false,
+        }
+    }
+
+    #[test]
+    fn test_validate_group_change_administrators() {
+        let (admin, other) = (aci(1), aci(2));
+        let group = group(vec![
+            member(admin, Role::Administrator, 0),
+            member(other, Role::Default, 1),
+        ]);
+
+        assert_eq!(
+            group.validate_change(
+                admin,
+                &GroupChangeSpec::SetMemberRole {
+                    aci: admin,
+                    role: GroupMemberRole::Default
+                }
+            ),
+            Err(GroupChangeError::LastAdministrator)
+        );
+        assert_eq!(
+            group.validate_change(other, &GroupChangeSpec::RemoveMember(admin)),
+            Err(GroupChangeError::NotAdministrator)
+        );
+        assert_eq!(
+            group.validate_change(admin, &GroupChangeSpec::RemoveMember(other)),
+            Ok(())
+        );
+        // the other member is promoted when the last administrator leaves
+        assert_eq!(
+            group.validate_change(admin, &GroupChangeSpec::Leave),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validate_group_change_members() {
+        let (admin, other, stranger) = (aci(1), aci(2), aci(3));
+        let group = group(vec![
+            member(admin, Role::Administrator, 0),
+            member(other, Role::Default, 1),
+        ]);
+
+        assert_eq!(
+            group.validate_change(
+                admin,
+                &GroupChangeSpec::AddMember {
+                    aci: other,
+                    role: GroupMemberRole::Default
+                }
+            ),
+            Err(GroupChangeError::AlreadyMember(other))
+        );
+        assert_eq!(
+            group.validate_change(admin, &GroupChangeSpec::RemoveMember(stranger)),
+            Err(GroupChangeError::UnknownMember(stranger))
+        );
+        assert_eq!(
+            group.validate_change(stranger, &GroupChangeSpec::Leave),
+            Err(GroupChangeError::NotMember)
+        );
+        assert_eq!(
+            group.validate_change(admin, &GroupChangeSpec::ApproveRequest(stranger)),
+            Err(GroupChangeError::NoJoinRequest(stranger))
+        );
+        assert_eq!(
+            group.validate_change(other, &GroupChangeSpec::SetTitle("a".repeat(33))),
+            Err(GroupChangeError::TitleTooLong)
+        );
+    }
+
+    #[test]
+    fn test_group_id_from_master_key() {
+        let group_id = GroupId::from_master_key(&[42; 32]);
+        assert_eq!(GroupId::from_master_key(&[42; 32]), group_id);
+        assert_ne!(GroupId::from_master_key(&[7; 32]), group_id);
+        assert_eq!(
+            GroupId::try_from(group_id.0.as_slice()).ok(),
+            Some(group_id)
+        );
+    }
+
+    #[test]
+    fn test_group_field_validation_counts_bytes() {
+        let group_ops = GroupOperations::new(GroupSecretParams::derive_from_master_key(
+            GroupMasterKey::new([42; 32]),
+        ));
+        assert_eq!(
+            GroupField::Title.validate(&group_ops, &"a".repeat(600)),
+            Ok(())
+        );
+        // same number of characters, but 2 bytes each
+        assert_eq!(
+            GroupField::Title.validate(&group_ops, &"é".repeat(600)),
+            Err(GroupValidationError {
+                field: GroupField::Title,
+                max_len: GROUP_TITLE_MAX_ENCRYPTED_LENGTH
+            })
+        );
+        assert_eq!(
+            GroupField::Description.validate(&group_ops, &"é".repeat(600)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_group_invite_link_invalid() {
+        let url = Url::parse("https://signal.group/#not-a-link").unwrap();
+        assert_eq!(GroupInviteLink::from_url(&url), None);
+        let url = Url::parse("https://example.com/").unwrap();
+        assert_eq!(GroupInviteLink::from_url(&url), None);
+    }
+}
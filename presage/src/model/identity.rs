@@ -1,6 +1,33 @@
+use libsignal_service::protocol::{IdentityKey, ServiceId};
+
 /// Whether to trust or reject new identities
 #[derive(Debug, Clone)]
 pub enum OnNewIdentity {
     Reject,
     Trust,
 }
+
+/// Whether the identity key of a contact is trusted, see
+/// [Manager::identity_state](crate::Manager::identity_state)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityState {
+    /// Messages can be exchanged with the contact
+    Trusted,
+    /// The identity key was untrusted, or it changed and new identities are rejected: it must be
+    /// trusted before sending messages again
+    Untrusted,
+    /// No identity key is known for this contact yet
+    Unknown,
+}
+
+/// The identity key of a contact changed, e.g. after they reinstalled Signal, so the safety number
+/// of the conversation changed too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityChange {
+    /// Whose identity changed (usually an ACI)
+    pub service_id: ServiceId,
+    /// The identity key known until now
+    pub old_key: IdentityKey,
+    /// The identity key used by the contact from now on
+    pub new_key: IdentityKey,
+}
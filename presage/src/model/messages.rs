@@ -1,4 +1,19 @@
-use libsignal_service::prelude::Content;
+use std::ops::Range;
+
+use libsignal_service::content::ContentBody;
+use libsignal_service::prelude::{Content, DeviceId, Uuid};
+use libsignal_service::proto::{
+    attachment_pointer, body_range,
+    data_message::{GroupCallUpdate, PollCreate},
+    receipt_message,
+    sync_message::{self, call_event},
+    AttachmentPointer, BodyRange, DataMessage, GroupContextV2, SyncMessage,
+};
+use libsignal_service::protocol::{Aci, ServiceId};
+use libsignal_service::sender::AttachmentSpec;
+
+use crate::model::groups::GroupId;
+use crate::store::Thread;
 
 #[derive(Debug)]
 pub enum Received {
@@ -10,6 +25,861 @@ pub enum Received {
     /// Contacts can be later queried in the store.
     Contacts,
 
-    /// Incoming decrypted message with metadata and content
-    Content(Box<Content>),
+    /// Incoming decrypted message with metadata and content, and the metadata of the envelope
+    /// it was received in
+    Content(Box<Content>, EnvelopeMetadata),
+
+    /// Somebody started or stopped typing in a thread
+    Typing(TypingIndicator),
+
+    /// Some of our messages were delivered to, read or viewed by a contact
+    Receipt(Receipt),
+
+    /// Messages of these threads were read on another device of this account, and marked as
+    /// read in the store
+    Read(Vec<Thread>),
+
+    /// The author of a poll terminated it, see
+    /// [Manager::poll_results](crate::Manager::poll_results) for its final results
+    PollTerminated { thread: Thread, poll_timestamp: u64 },
+
+    /// A call to show in the call history: a group call was started or updated, or a call was
+    /// answered, missed or deleted on another device of this account
+    CallEvent(CallEvent),
+
+    /// The connection was lost and re-established, see [crate::manager::ReceiveOptions::reconnect]
+    ///
+    /// Messages received in the meantime will follow, but clients might want to refresh their state.
+    Reconnected,
+}
+
+/// Metadata of the envelope of a received message, see [Received::Content]
+///
+/// Unlike the timestamp set by the sender, the timestamp of the server doesn't depend on the
+/// sender's clock, which makes it more reliable to sort messages chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvelopeMetadata {
+    /// Who sent the message (usually an ACI)
+    pub sender: ServiceId,
+    /// When the message was sent, according to the sender (also the id of the message)
+    pub sent_timestamp: u64,
+    /// When the message was received by the server
+    pub server_received_timestamp: u64,
+    /// Unique id given to the envelope by the server
+    pub server_guid: Option<Uuid>,
+}
+
+/// Metadata of a received envelope which is known before decrypting it, see
+/// [ReceiveOptions::envelope_filter](crate::manager::ReceiveOptions::envelope_filter).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncomingEnvelope {
+    /// Who sent the envelope, or `None` with sealed sender, where the sender is encrypted
+    pub sender: Option<ServiceId>,
+    /// Device of the sender, unless sealed sender is used
+    pub sender_device: Option<u32>,
+    /// Which of our identities (ACI or PNI) the envelope is for
+    pub destination: Option<ServiceId>,
+    /// When the message was sent, according to the sender
+    pub sent_timestamp: u64,
+    /// When the envelope was received by the server
+    pub server_received_timestamp: u64,
+    /// Unique id given to the envelope by the server
+    pub server_guid: Option<Uuid>,
+}
+
+/// A typing indicator sent by a contact, in a 1-1 or group thread.
+///
+/// Clients usually show it until a stopped indicator is received, or after a timeout of a few
+/// seconds (typing indicators are repeated while the contact keeps typing).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingIndicator {
+    /// Who is typing (usually an ACI)
+    pub sender: ServiceId,
+    /// Where the sender is typing
+    pub thread: Thread,
+    /// Whether the sender started or stopped typing
+    pub action: TypingAction,
+    /// When the typing indicator was sent
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypingAction {
+    Started,
+    Stopped,
+}
+
+/// An entry of the call history, see [Received::CallEvent]
+///
+/// Calls themselves are not supported by presage: these events only tell that calls happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallEvent {
+    /// Who or where the call was with
+    pub peer: CallPeer,
+    pub kind: CallKind,
+    /// Whether the call was made or received by this account, if known
+    pub direction: Option<CallDirection>,
+    pub status: CallStatus,
+    /// Identifier of the call, shared by the events of the same call
+    pub call_id: Option<u64>,
+    /// When the call happened
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallPeer {
+    Contact(Uuid),
+    Group(GroupId),
+    /// The room id of a call link
+    CallLink(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Audio,
+    Video,
+    Group,
+    /// A call started from a call link
+    AdHoc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallDirection {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallStatus {
+    /// The call was answered, or joined
+    Accepted,
+    /// The call was missed or declined
+    NotAccepted,
+    /// The call was deleted from the call history
+    Deleted,
+    /// The call was seen ongoing, without joining it
+    Observed,
+}
+
+impl CallEvent {
+    /// The call event carried by a message: either the update of a group call sent in a group,
+    /// or a call event synchronized from another device of this account.
+    pub fn from_content(content: &Content) -> Option<Self> {
+        match &content.body {
+            ContentBody::DataMessage(DataMessage {
+                group_call_update: Some(GroupCallUpdate { .. }),
+                group_v2:
+                    Some(GroupContextV2 {
+                        master_key: Some(master_key),
+                        ..
+                    }),
+                ..
+            }) => Some(Self {
+                peer: CallPeer::Group(GroupId::from_master_key(
+                    master_key.as_slice().try_into().ok()?,
+                )),
+                kind: CallKind::Group,
+                direction: Some(CallDirection::Incoming),
+                status: CallStatus::Observed,
+                call_id: None,
+                timestamp: content.metadata.timestamp,
+            }),
+            ContentBody::SynchronizeMessage(SyncMessage {
+                call_event: Some(call_event),
+                ..
+            }) => Self::from_sync_message(call_event),
+            _ => None,
+        }
+    }
+
+    fn from_sync_message(call_event: &sync_message::CallEvent) -> Option<Self> {
+        let peer_id = call_event.peer_id.as_deref()?;
+        let (kind, peer) = match call_event.r#type() {
+            call_event::Type::AudioCall => (
+                CallKind::Audio,
+                CallPeer::Contact(Uuid::from_slice(peer_id).ok()?),
+            ),
+            call_event::Type::VideoCall => (
+                CallKind::Video,
+                CallPeer::Contact(Uuid::from_slice(peer_id).ok()?),
+            ),
+            call_event::Type::GroupCall => (
+                CallKind::Group,
+                CallPeer::Group(GroupId::try_from(peer_id).ok()?),
+            ),
+            call_event::Type::AdHocCall => (CallKind::AdHoc, CallPeer::CallLink(peer_id.to_vec())),
+            call_event::Type::UnknownType => return None,
+        };
+        let direction = match call_event.direction() {
+            call_event::Direction::Incoming => Some(CallDirection::Incoming),
+            call_event::Direction::Outgoing => Some(CallDirection::Outgoing),
+            call_event::Direction::UnknownDirection => None,
+        };
+        let status = match call_event.event() {
+            call_event::Event::Accepted => CallStatus::Accepted,
+            call_event::Event::NotAccepted => CallStatus::NotAccepted,
+            call_event::Event::Delete => CallStatus::Deleted,
+            call_event::Event::Observed => CallStatus::Observed,
+            call_event::Event::UnknownAction => return None,
+        };
+        Some(Self {
+            peer,
+            kind,
+            direction,
+            status,
+            call_id: call_event.call_id,
+            timestamp: call_event.timestamp?,
+        })
+    }
+}
+
+/// A reaction to a message, as stored in [ContentsStore](crate::store::ContentsStore).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reaction {
+    /// The emoji used to react
+    pub emoji: String,
+    /// Who reacted (usually an ACI)
+    pub author: ServiceId,
+    /// When the reaction was sent
+    pub timestamp: u64,
+}
+
+/// A vote in a poll, as stored in [ContentsStore](crate::store::ContentsStore).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollVote {
+    /// Who voted (usually an ACI)
+    pub voter: ServiceId,
+    /// Indexes of the selected options, empty if the voter retracted their vote
+    pub option_indexes: Vec<u32>,
+    /// How many times the voter voted in this poll, including this vote: a vote only replaces
+    /// a previous one with a lower count
+    pub vote_count: u32,
+    /// When the vote was sent
+    pub timestamp: u64,
+}
+
+/// The current results of a poll, see [Manager::poll_results](crate::Manager::poll_results).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollResults {
+    /// The question asked
+    pub question: String,
+    /// Whether voters can select several options
+    pub allow_multiple: bool,
+    /// The results of each option, in the order of the poll
+    pub options: Vec<PollOptionResults>,
+    /// Whether the author of the poll terminated it, the results are final then
+    pub terminated: bool,
+}
+
+/// The results of an option of a poll
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollOptionResults {
+    /// The text of the option
+    pub option: String,
+    /// Who selected this option, in the order they voted
+    pub voters: Vec<ServiceId>,
+}
+
+impl PollOptionResults {
+    /// How many voters selected this option
+    pub fn votes(&self) -> usize {
+        self.voters.len()
+    }
+}
+
+impl PollResults {
+    /// Counts the `votes` (at most one per voter) of a poll, terminated at `terminated_at` if it
+    /// was.
+    ///
+    /// Only the first selected option counts when the poll doesn't allow several, and options
+    /// that don't exist are ignored, as well as votes sent after the termination.
+    pub fn tally(poll: &PollCreate, votes: &[PollVote], terminated_at: Option<u64>) -> Self {
+        let allow_multiple = poll.allow_multiple.unwrap_or_default();
+        let mut options: Vec<PollOptionResults> = poll
+            .options
+            .iter()
+            .map(|option| PollOptionResults {
+                option: option.clone(),
+                voters: Vec::new(),
+            })
+            .collect();
+
+        let mut votes: Vec<&PollVote> = votes
+            .iter()
+            .filter(|vote| {
+                terminated_at.is_none_or(|terminated_at| vote.timestamp <= terminated_at)
+            })
+            .collect();
+        votes.sort_by_key(|vote| vote.timestamp);
+        for vote in votes {
+            let selected = if allow_multiple {
+                &vote.option_indexes[..]
+            } else {
+                &vote.option_indexes[..vote.option_indexes.len().min(1)]
+            };
+            for index in selected {
+                if let Some(option) = options.get_mut(*index as usize) {
+                    if !option.voters.contains(&vote.voter) {
+                        option.voters.push(vote.voter);
+                    }
+                }
+            }
+        }
+
+        Self {
+            question: poll.question.clone().unwrap_or_default(),
+            allow_multiple,
+            options,
+            terminated: terminated_at.is_some(),
+        }
+    }
+}
+
+/// A delivery, read or viewed receipt for messages we sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    /// Who sent the receipt, taken from the envelope
+    pub sender: ServiceId,
+    /// What happened to the messages
+    pub kind: ReceiptKind,
+    /// The sent timestamps of the messages the receipt is about
+    pub timestamps: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiptKind {
+    Delivery,
+    Read,
+    Viewed,
+}
+
+impl From<receipt_message::Type> for ReceiptKind {
+    fn from(r#type: receipt_message::Type) -> Self {
+        match r#type {
+            receipt_message::Type::Delivery => Self::Delivery,
+            receipt_message::Type::Read => Self::Read,
+            receipt_message::Type::Viewed => Self::Viewed,
+        }
+    }
+}
+
+/// How far a message we sent went, see [Manager::message_status](crate::Manager::message_status)
+///
+/// Statuses are ordered: a message which was read was also delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MessageStatus {
+    /// The message could not be sent yet, see
+    /// [Manager::pending_sends](crate::Manager::pending_sends)
+    Pending,
+    /// The message was accepted by the server
+    Sent,
+    /// The message was delivered to a device of a recipient
+    Delivered,
+    /// The message was read (or viewed) by a recipient
+    Read,
+}
+
+impl From<ReceiptKind> for MessageStatus {
+    fn from(kind: ReceiptKind) -> Self {
+        match kind {
+            ReceiptKind::Delivery => Self::Delivered,
+            ReceiptKind::Read | ReceiptKind::Viewed => Self::Read,
+        }
+    }
+}
+
+/// An edit of a previously sent message, see [ContentExt::edit](crate::store::ContentExt::edit).
+#[derive(Debug, Clone, Copy)]
+pub struct MessageEdit<'a> {
+    /// Timestamp of the original message being edited
+    pub target_sent_timestamp: u64,
+    /// The new version of the message, replacing the original one
+    pub data_message: &'a DataMessage,
+}
+
+impl MessageEdit<'_> {
+    /// The new text of the message
+    pub fn body(&self) -> Option<&str> {
+        self.data_message.body.as_deref()
+    }
+
+    /// The new attachments of the message
+    pub fn attachments(&self) -> &[AttachmentPointer] {
+        &self.data_message.attachments
+    }
+}
+
+/// A message we sent from another of our devices, see
+/// [ContentExt::sent_transcript](crate::store::ContentExt::sent_transcript).
+#[derive(Debug, Clone, Copy)]
+pub struct SentTranscript<'a> {
+    /// The contact the message was sent to, [None] for group messages
+    pub destination: Option<ServiceId>,
+    /// Which of our devices sent the message
+    pub sender_device: DeviceId,
+    /// Timestamp of the sent message
+    pub timestamp: u64,
+    /// The sent message (or the new version of an edited message), if any
+    pub data_message: Option<&'a DataMessage>,
+}
+
+/// A link preview to send along with a message, see
+/// [Manager::send_message_with_preview](crate::Manager::send_message_with_preview).
+///
+/// The page is not fetched by presage: its metadata is provided by the client.
+#[derive(Debug, Clone)]
+pub struct LinkPreview {
+    /// The previewed URL, which must be in the body of the message
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The image of the page, uploaded as an attachment
+    pub image: Option<(AttachmentSpec, Vec<u8>)>,
+}
+
+/// How an attachment is displayed, available before downloading it
+///
+/// This lets clients reserve the space of the attachment and show a placeholder while it is being
+/// downloaded. Missing or zero dimensions are [None].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttachmentMetadata {
+    /// Whether the attachment is a voice note recorded in the app, rather than an audio file
+    pub voice_message: bool,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// A [BlurHash](https://blurha.sh) of the image, to render as a placeholder
+    pub blur_hash: Option<String>,
+    pub caption: Option<String>,
+}
+
+impl AttachmentMetadata {
+    /// The width and height of the attachment, if both are known
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.width.zip(self.height)
+    }
+}
+
+impl From<&AttachmentPointer> for AttachmentMetadata {
+    fn from(attachment_pointer: &AttachmentPointer) -> Self {
+        let flags = attachment_pointer.flags.unwrap_or_default();
+        Self {
+            voice_message: flags & attachment_pointer::Flags::VoiceMessage as u32 != 0,
+            width: attachment_pointer.width.filter(|&width| width > 0),
+            height: attachment_pointer.height.filter(|&height| height > 0),
+            blur_hash: attachment_pointer
+                .blur_hash
+                .clone()
+                .filter(|blur_hash| !blur_hash.is_empty()),
+            caption: attachment_pointer
+                .caption
+                .clone()
+                .filter(|caption| !caption.is_empty()),
+        }
+    }
+}
+
+/// Setters for the display metadata of an outgoing attachment, which is sent along with the
+/// attachment pointer (see [AttachmentMetadata])
+pub trait AttachmentSpecExt {
+    /// Marks an audio attachment as a voice note, which is rendered with a waveform.
+    ///
+    /// The attachment pointer has no room for the waveform nor the duration: recipients compute
+    /// them from the downloaded audio.
+    fn with_voice_message(self, voice_message: bool) -> Self;
+
+    fn with_dimensions(self, width: u32, height: u32) -> Self;
+
+    fn with_blur_hash(self, blur_hash: impl Into<String>) -> Self;
+
+    fn with_caption(self, caption: impl Into<String>) -> Self;
+}
+
+impl AttachmentSpecExt for AttachmentSpec {
+    fn with_voice_message(self, voice_message: bool) -> Self {
+        Self {
+            voice_note: Some(voice_message),
+            ..self
+        }
+    }
+
+    fn with_dimensions(self, width: u32, height: u32) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+            ..self
+        }
+    }
+
+    fn with_blur_hash(self, blur_hash: impl Into<String>) -> Self {
+        Self {
+            blur_hash: Some(blur_hash.into()),
+            ..self
+        }
+    }
+
+    fn with_caption(self, caption: impl Into<String>) -> Self {
+        Self {
+            caption: Some(caption.into()),
+            ..self
+        }
+    }
+}
+
+/// A mention of a group member in the body of a message
+///
+/// As in official clients, `start` and `length` are counted in UTF-16 code units (not bytes nor
+/// chars), see [Mention::from_byte_range] and [Mention::byte_range] to convert them. The mentioned
+/// name is usually replaced in the body by the object replacement character (`U+FFFC`), for
+/// clients to render it with the current name of the member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mention {
+    /// Start of the mention in the body, in UTF-16 code units
+    pub start: u32,
+    /// Length of the mention in the body, in UTF-16 code units
+    pub length: u32,
+    pub mentioned_aci: Aci,
+}
+
+impl Mention {
+    /// The mention of the text of the body at the given range of bytes
+    ///
+    /// Returns [None] if the range is out of the body or not on char boundaries.
+    pub fn from_byte_range(body: &str, range: Range<usize>, mentioned_aci: Aci) -> Option<Self> {
+        let prefix = body.get(..range.start)?;
+        let mentioned = body.get(range)?;
+        Some(Self {
+            start: utf16_len(prefix),
+            length: utf16_len(mentioned),
+            mentioned_aci,
+        })
+    }
+
+    /// The range of bytes of the mention in the body
+    ///
+    /// Returns [None] if the mention is out of the body or splits a char.
+    pub fn byte_range(&self, body: &str) -> Option<Range<usize>> {
+        let start = byte_offset(body, self.start)?;
+        let end = byte_offset(body, self.start.checked_add(self.length)?)?;
+        Some(start..end)
+    }
+
+    /// The mention carried by a body range, if it is one
+    pub fn from_body_range(body_range: &BodyRange) -> Option<Self> {
+        let Some(body_range::AssociatedValue::MentionAci(aci)) = &body_range.associated_value
+        else {
+            return None;
+        };
+        Some(Self {
+            start: body_range.start(),
+            length: body_range.length(),
+            mentioned_aci: Aci::parse_from_service_id_string(aci)?,
+        })
+    }
+}
+
+impl From<Mention> for BodyRange {
+    fn from(mention: Mention) -> Self {
+        Self {
+            start: Some(mention.start),
+            length: Some(mention.length),
+            associated_value: Some(body_range::AssociatedValue::MentionAci(
+                mention.mentioned_aci.service_id_string(),
+            )),
+        }
+    }
+}
+
+/// A style of text, see [TextStyleRange]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextStyle {
+    Bold,
+    Italic,
+    Strikethrough,
+    Monospace,
+    /// Hidden until the user taps on it
+    Spoiler,
+}
+
+impl TextStyle {
+    fn from_proto(style: body_range::Style) -> Option<Self> {
+        match style {
+            body_range::Style::None => None,
+            body_range::Style::Bold => Some(Self::Bold),
+            body_range::Style::Italic => Some(Self::Italic),
+            body_range::Style::Spoiler => Some(Self::Spoiler),
+            body_range::Style::Strikethrough => Some(Self::Strikethrough),
+            body_range::Style::Monospace => Some(Self::Monospace),
+        }
+    }
+}
+
+impl From<TextStyle> for body_range::Style {
+    fn from(style: TextStyle) -> Self {
+        match style {
+            TextStyle::Bold => Self::Bold,
+            TextStyle::Italic => Self::Italic,
+            TextStyle::Strikethrough => Self::Strikethrough,
+            TextStyle::Monospace => Self::Monospace,
+            TextStyle::Spoiler => Self::Spoiler,
+        }
+    }
+}
+
+/// A styled range of the body of a message
+///
+/// Like for [Mention], `start` and `length` are counted in UTF-16 code units. Ranges can overlap
+/// to combine styles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyleRange {
+    /// Start of the range in the body, in UTF-16 code units
+    pub start: u32,
+    /// Length of the range in the body, in UTF-16 code units
+    pub length: u32,
+    pub style: TextStyle,
+}
+
+impl TextStyleRange {
+    /// The style of the text of the body at the given range of bytes
+    ///
+    /// Returns [None] if the range is out of the body or not on char boundaries.
+    pub fn from_byte_range(body: &str, range: Range<usize>, style: TextStyle) -> Option<Self> {
+        let prefix = body.get(..range.start)?;
+        let styled = body.get(range)?;
+        Some(Self {
+            start: utf16_len(prefix),
+            length: utf16_len(styled),
+            style,
+        })
+    }
+
+    /// The range of bytes of the styled text in the body
+    ///
+    /// Returns [None] if the range is out of the body or splits a char.
+    pub fn byte_range(&self, body: &str) -> Option<Range<usize>> {
+        let start = byte_offset(body, self.start)?;
+        let end = byte_offset(body, self.start.checked_add(self.length)?)?;
+        Some(start..end)
+    }
+
+    /// The style carried by a body range, if it is one
+    pub fn from_body_range(body_range: &BodyRange) -> Option<Self> {
+        let Some(body_range::AssociatedValue::Style(style)) = body_range.associated_value else {
+            return None;
+        };
+        Some(Self {
+            start: body_range.start(),
+            length: body_range.length(),
+            style: TextStyle::from_proto(body_range::Style::try_from(style).ok()?)?,
+        })
+    }
+}
+
+impl From<TextStyleRange> for BodyRange {
+    fn from(range: TextStyleRange) -> Self {
+        Self {
+            start: Some(range.start),
+            length: Some(range.length),
+            associated_value: Some(body_range::AssociatedValue::Style(
+                body_range::Style::from(range.style).into(),
+            )),
+        }
+    }
+}
+
+fn utf16_len(s: &str) -> u32 {
+    s.chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+/// The offset in bytes of an offset in UTF-16 code units, if it is on a char boundary
+fn byte_offset(s: &str, utf16_offset: u32) -> Option<usize> {
+    let mut utf16_len = 0;
+    for (offset, c) in s.char_indices() {
+        if utf16_len == utf16_offset {
+            return Some(offset);
+        }
+        utf16_len += c.len_utf16() as u32;
+    }
+    (utf16_len == utf16_offset).then_some(s.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_event_from_sync_message() {
+        let uuid = Uuid::new_v4();
+        let sync_call_event =
+            |r#type: call_event::Type, peer_id: Vec<u8>| sync_message::CallEvent {
+                peer_id: Some(peer_id),
+                call_id: Some(42),
+                timestamp: Some(1000),
+                r#type: Some(r#type.into()),
+                direction: Some(call_event::Direction::Incoming.into()),
+                event: Some(call_event::Event::NotAccepted.into()),
+            };
+
+        let missed_call = CallEvent::from_sync_message(&sync_call_event(
+            call_event::Type::VideoCall,
+            uuid.into_bytes().to_vec(),
+        ));
+        assert_eq!(
+            missed_call,
+            Some(CallEvent {
+                peer: CallPeer::Contact(uuid),
+                kind: CallKind::Video,
+                direction: Some(CallDirection::Incoming),
+                status: CallStatus::NotAccepted,
+                call_id: Some(42),
+                timestamp: 1000,
+            })
+        );
+
+        let group_call = CallEvent::from_sync_message(&sync_call_event(
+            call_event::Type::GroupCall,
+            vec![7; 32],
+        ))
+        .unwrap();
+        assert_eq!(group_call.peer, CallPeer::Group(GroupId([7; 32])));
+
+        // the peer of a 1-1 call must be an ACI
+        assert_eq!(
+            CallEvent::from_sync_message(&sync_call_event(
+                call_event::Type::AudioCall,
+                vec![7; 32]
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn attachment_metadata_from_pointer() {
+        let attachment_pointer = AttachmentPointer {
+            flags: Some(attachment_pointer::Flags::VoiceMessage as u32),
+            width: Some(640),
+            height: Some(480),
+            blur_hash: Some("LEHV6nWB2yk8pyo0adR*.7kCMdnj".to_owned()),
+            caption: Some(String::new()),
+            ..Default::default()
+        };
+        let metadata = AttachmentMetadata::from(&attachment_pointer);
+        assert!(metadata.voice_message);
+        assert_eq!(metadata.dimensions(), Some((640, 480)));
+        assert_eq!(
+            metadata.blur_hash.as_deref(),
+            Some("LEHV6nWB2yk8pyo0adR*.7kCMdnj")
+        );
+        assert_eq!(metadata.caption, None);
+
+        let attachment_pointer = AttachmentPointer {
+            width: Some(0),
+            height: Some(480),
+            ..Default::default()
+        };
+        let metadata = AttachmentMetadata::from(&attachment_pointer);
+        assert!(!metadata.voice_message);
+        assert_eq!(metadata.dimensions(), None);
+    }
+
+    #[test]
+    fn mention_utf16_offsets() {
+        let aci = Aci::from(Uuid::new_v4());
+        // the emoji is 4 bytes, 2 UTF-16 code units and 1 char
+        let body = "🎉 hi \u{FFFC}!";
+        let start = body.find('\u{FFFC}').unwrap();
+        let mention = Mention::from_byte_range(body, start..start + 3, aci).unwrap();
+        assert_eq!((mention.start, mention.length), (6, 1));
+        assert_eq!(mention.byte_range(body), Some(start..start + 3));
+
+        let body_range = BodyRange::from(mention);
+        assert_eq!(Mention::from_body_range(&body_range), Some(mention));
+    }
+
+    #[test]
+    fn mentions_and_styles_share_body_ranges() {
+        let aci = Aci::from(Uuid::new_v4());
+        let body = "hey \u{FFFC}, this is *important*";
+        let mention = Mention {
+            start: 4,
+            length: 1,
+            mentioned_aci: aci,
+        };
+        let style = TextStyleRange {
+            start: 15,
+            length: 11,
+            style: TextStyle::Bold,
+        };
+        assert_eq!(
+            style.byte_range(body).map(|range| &body[range]),
+            Some("*important*")
+        );
+
+        let body_ranges = [BodyRange::from(mention), BodyRange::from(style)];
+        let mentions: Vec<_> = body_ranges
+            .iter()
+            .filter_map(Mention::from_body_range)
+            .collect();
+        let styles: Vec<_> = body_ranges
+            .iter()
+            .filter_map(TextStyleRange::from_body_range)
+            .collect();
+        assert_eq!(mentions, [mention]);
+        assert_eq!(styles, [style]);
+    }
+
+    #[test]
+    fn mention_out_of_body() {
+        let aci = Aci::from(Uuid::new_v4());
+        let body = "🎉";
+        assert_eq!(Mention::from_byte_range(body, 1..2, aci), None);
+        let splitting_emoji = Mention {
+            start: 1,
+            length: 1,
+            mentioned_aci: aci,
+        };
+        assert_eq!(splitting_emoji.byte_range(body), None);
+        let out_of_body = Mention {
+            start: 2,
+            length: 1,
+            mentioned_aci: aci,
+        };
+        assert_eq!(out_of_body.byte_range(body), None);
+    }
+
+    #[test]
+    fn poll_results_tally() {
+        let (alice, bob) = (
+            ServiceId::from(Aci::from(Uuid::new_v4())),
+            ServiceId::from(Aci::from(Uuid::new_v4())),
+        );
+        let vote = |voter, option_indexes: &[u32], timestamp| PollVote {
+            voter,
+            option_indexes: option_indexes.to_vec(),
+            vote_count: 1,
+            timestamp,
+        };
+        let mut poll = PollCreate {
+            question: Some("Lunch?".to_owned()),
+            allow_multiple: Some(false),
+            options: vec!["Pizza".to_owned(), "Sushi".to_owned()],
+        };
+        let votes = [vote(bob, &[1, 0], 2), vote(alice, &[1, 7], 1)];
+
+        let results = PollResults::tally(&poll, &votes, None);
+        assert_eq!(results.question, "Lunch?");
+        assert!(!results.terminated);
+        assert_eq!(results.options[0].votes(), 0);
+        assert_eq!(results.options[1].voters, [alice, bob]);
+
+        poll.allow_multiple = Some(true);
+        let results = PollResults::tally(&poll, &votes, None);
+        assert_eq!(results.options[0].voters, [bob]);
+        assert_eq!(results.options[1].voters, [alice, bob]);
+
+        // bob voted after the poll was terminated
+        let results = PollResults::tally(&poll, &votes, Some(1));
+        assert!(results.terminated);
+        assert_eq!(results.options[0].votes(), 0);
+        assert_eq!(results.options[1].voters, [alice]);
+    }
 }
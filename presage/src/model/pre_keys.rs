@@ -0,0 +1,12 @@
+/// Number of pre-keys available for new contacts to start a session with this account
+///
+/// See [Manager::prekey_count](crate::Manager::prekey_count).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreKeyCounts {
+    /// One-time (elliptic curve) pre-keys left on the server
+    pub one_time: u32,
+    /// One-time Kyber pre-keys left on the server
+    pub kyber: u32,
+    /// Signed pre-keys in the local store
+    pub signed: u32,
+}
@@ -0,0 +1,47 @@
+use libsignal_service::proto::sync_message;
+use serde::{Deserialize, Serialize};
+
+/// Privacy settings of the account, synchronized between its devices
+///
+/// See [Manager::account_settings](crate::Manager::account_settings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSettings {
+    /// Whether read (and viewed) receipts are sent
+    pub read_receipts: bool,
+    /// Whether typing indicators are sent
+    pub typing_indicators: bool,
+    /// Whether link previews are generated for sent messages
+    pub link_previews: bool,
+    /// Whether to show which messages were received with sealed sender
+    pub unidentified_delivery_indicators: bool,
+}
+
+impl Default for AccountSettings {
+    /// The defaults of official clients
+    fn default() -> Self {
+        Self {
+            read_receipts: true,
+            typing_indicators: true,
+            link_previews: true,
+            unidentified_delivery_indicators: false,
+        }
+    }
+}
+
+impl AccountSettings {
+    /// Applies the settings synchronized from another device, keeping the ones it didn't set
+    pub fn apply(&mut self, configuration: &sync_message::Configuration) {
+        let sync_message::Configuration {
+            read_receipts,
+            unidentified_delivery_indicators,
+            typing_indicators,
+            link_previews,
+            ..
+        } = *configuration;
+        self.read_receipts = read_receipts.unwrap_or(self.read_receipts);
+        self.typing_indicators = typing_indicators.unwrap_or(self.typing_indicators);
+        self.link_previews = link_previews.unwrap_or(self.link_previews);
+        self.unidentified_delivery_indicators =
+            unidentified_delivery_indicators.unwrap_or(self.unidentified_delivery_indicators);
+    }
+}
@@ -43,3 +43,62 @@ pub(crate) mod serde_profile_key {
         }
     }
 }
+
+pub(crate) mod serde_identity_key_pair {
+    use base64::{engine::general_purpose, Engine};
+    use libsignal_service::protocol::IdentityKeyPair;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S>(key_pair: &IdentityKeyPair, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(key_pair.serialize()))
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<IdentityKeyPair, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = general_purpose::STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)?;
+        IdentityKeyPair::try_from(&*bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+pub(crate) mod serde_optional_master_key {
+    use base64::{engine::general_purpose, Engine};
+    use libsignal_service::prelude::MasterKey;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S>(
+        master_key: &Option<MasterKey>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match master_key {
+            Some(master_key) => {
+                serializer.serialize_some(&general_purpose::STANDARD.encode(master_key.inner))
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<MasterKey>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|master_key| {
+                let bytes = general_purpose::STANDARD
+                    .decode(master_key)
+                    .map_err(serde::de::Error::custom)?;
+                MasterKey::from_slice(&bytes)
+                    .map_err(|_| serde::de::Error::invalid_length(bytes.len(), &"32 bytes"))
+            })
+            .transpose()
+    }
+}
@@ -1,19 +1,38 @@
 //! Traits that are used by the manager for storing the data.
-
-use std::{fmt, future::Future, ops::RangeBounds, time::SystemTime};
+//!
+//! The [Manager](crate::Manager) is generic over a [Store], so any storage backend can be used by
+//! implementing it along with its sub-traits:
+//!
+//! - [StateStore]: the registration data, identity key pairs and sender certificate,
+//! - [ContentsStore]: messages, contacts, groups, profiles and sticker packs,
+//! - the protocol stores ([Store::AciStore] and [Store::PniStore]) used to encrypt and decrypt
+//!   messages: identities, sessions, pre-keys and sender keys, one for each identity, along with
+//!   [IdentityTrustStore].
+//!
+//! All of them share the same error type [Store::Error]. `presage-store-sqlite` is the reference
+//! implementation, and `InMemoryStore` (behind the `in-memory-store` feature) a simpler one.
+
+use std::{
+    fmt,
+    future::Future,
+    ops::{Bound, RangeBounds},
+    time::SystemTime,
+};
 
 use libsignal_service::{
     content::{ContentBody, Metadata},
     groups_v2::Timer,
     pre_keys::PreKeysStore,
-    prelude::{Content, MasterKey, ProfileKey, Uuid, UuidError},
+    prelude::{phonenumber::PhoneNumber, Content, MasterKey, ProfileKey, Uuid, UuidError},
     proto::{
+        self,
+        data_message::{self, Quote},
         sync_message::{self, Sent},
-        verified, DataMessage, EditMessage, GroupContextV2, SyncMessage, Verified,
+        verified, DataMessage, EditMessage, GroupContextV2, Preview, SyncMessage, Verified,
     },
     protocol::{
         IdentityKey, IdentityKeyPair, ProtocolAddress, ProtocolStore, SenderCertificate,
-        SenderKeyStore, ServiceId,
+        SenderKeyStore, ServiceId, SignalProtocolError,
     },
     push_service::DEFAULT_DEVICE_ID,
     session_store::SessionStoreExt,
@@ -21,14 +40,27 @@ use libsignal_service::{
     Profile,
 };
 use serde::{Deserialize, Serialize};
-use tracing::trace;
+use tracing::{trace, warn};
 
 use crate::{
     manager::RegistrationData,
-    model::{contacts::Contact, groups::Group},
+    model::{
+        contacts::Contact,
+        groups::{Group, GroupChange},
+        messages::{
+            Mention, MessageEdit, MessageStatus, PollVote, Reaction, SentTranscript, TextStyleRange,
+        },
+        settings::AccountSettings,
+    },
     AvatarBytes,
 };
 
+#[cfg(any(test, feature = "in-memory-store"))]
+mod memory;
+
+#[cfg(any(test, feature = "in-memory-store"))]
+pub use memory::{InMemoryProtocolStore, InMemoryStore, InMemoryStoreError};
+
 /// An error trait implemented by store error types
 pub trait StoreError: std::error::Error + Sync + Send {}
 
@@ -41,11 +73,15 @@ pub trait StateStore {
         &self,
     ) -> impl Future<Output = Result<Option<RegistrationData>, Self::StateStoreError>>;
 
+    /// Save the identity key pair of the ACI identity, returned by the
+    /// [IdentityKeyStore](libsignal_service::protocol::IdentityKeyStore) of [Store::AciStore]
     fn set_aci_identity_key_pair(
         &self,
         key_pair: IdentityKeyPair,
     ) -> impl Future<Output = Result<(), Self::StateStoreError>>;
 
+    /// Save the identity key pair of the PNI identity, returned by the
+    /// [IdentityKeyStore](libsignal_service::protocol::IdentityKeyStore) of [Store::PniStore]
     fn set_pni_identity_key_pair(
         &self,
         key_pair: IdentityKeyPair,
@@ -57,10 +93,12 @@ pub trait StateStore {
         state: &RegistrationData,
     ) -> impl Future<Output = Result<(), Self::StateStoreError>> + Send;
 
+    /// Load the cached sender certificate, used to send sealed sender messages
     fn sender_certificate(
         &self,
     ) -> impl Future<Output = Result<Option<SenderCertificate>, Self::StateStoreError>>;
 
+    /// Cache the sender certificate
     fn save_sender_certificate(
         &self,
         certificate: &SenderCertificate,
@@ -72,10 +110,35 @@ pub trait StateStore {
     /// Clear registration data (including keys), but keep received messages, groups and contacts.
     fn clear_registration(&mut self) -> impl Future<Output = Result<(), Self::StateStoreError>>;
 
+    /// Load the privacy settings of the account, [None] if they were never saved
+    fn account_settings(
+        &self,
+    ) -> impl Future<Output = Result<Option<AccountSettings>, Self::StateStoreError>>;
+
+    /// Save the privacy settings of the account
+    fn save_account_settings(
+        &self,
+        settings: &AccountSettings,
+    ) -> impl Future<Output = Result<(), Self::StateStoreError>>;
+
+    /// Load when the signed pre-keys were last rotated (in milliseconds since the epoch), [None]
+    /// if they never were
+    fn last_pre_key_rotation(
+        &self,
+    ) -> impl Future<Output = Result<Option<u64>, Self::StateStoreError>>;
+
+    /// Save when the signed pre-keys were last rotated (in milliseconds since the epoch)
+    fn save_last_pre_key_rotation(
+        &self,
+        timestamp: u64,
+    ) -> impl Future<Output = Result<(), Self::StateStoreError>>;
+
+    /// Load the master key of the account (synchronized from the primary device)
     fn fetch_master_key(
         &self,
     ) -> impl Future<Output = Result<Option<MasterKey>, Self::StateStoreError>>;
 
+    /// Save or remove the master key of the account
     fn store_master_key(
         &self,
         master_key: Option<&MasterKey>,
@@ -146,6 +209,227 @@ pub trait ContentsStore: Send + Sync {
         range: impl RangeBounds<u64>,
     ) -> impl Future<Output = Result<Self::MessagesIter, Self::ContentsStoreError>>;
 
+    /// Retrieve at most `limit` messages from a [Thread] relative to a timestamp, e.g. to load
+    /// them page by page.
+    ///
+    /// Messages are sorted from the newest to the oldest, except with [MessageRange::After] where
+    /// they are sorted from the oldest to the newest.
+    fn messages_page(
+        &self,
+        thread: &Thread,
+        range: MessageRange,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<Content>, Self::ContentsStoreError>> {
+        async move {
+            match range {
+                MessageRange::Latest => self.messages(thread, ..).await?.take(limit).collect(),
+                MessageRange::Before(timestamp) => self
+                    .messages(thread, ..timestamp)
+                    .await?
+                    .take(limit)
+                    .collect(),
+                MessageRange::After(timestamp) => {
+                    let mut messages: Vec<Content> = self
+                        .messages(thread, (Bound::Excluded(timestamp), Bound::Unbounded))
+                        .await?
+                        .collect::<Result<_, _>>()?;
+                    messages.reverse();
+                    messages.truncate(limit);
+                    Ok(messages)
+                }
+            }
+        }
+    }
+
+    // Reactions
+
+    /// Save a reaction to the message sent at `target_sent_timestamp` in a [Thread].
+    ///
+    /// The reaction replaces any previous reaction from the same author to the same message.
+    /// The target message does not need to be stored yet: reactions arriving before their
+    /// message are kept and show up once it is stored.
+    fn save_reaction(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        reaction: Reaction,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Remove the reaction of `author` to the message sent at `target_sent_timestamp` in a
+    /// [Thread].
+    fn remove_reaction(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        author: &ServiceId,
+    ) -> impl Future<Output = Result<bool, Self::ContentsStoreError>>;
+
+    /// Retrieve the reactions to the message sent at `target_sent_timestamp` in a [Thread],
+    /// oldest first.
+    fn reactions(
+        &self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+    ) -> impl Future<Output = Result<Vec<Reaction>, Self::ContentsStoreError>>;
+
+    // Polls
+
+    /// Save a vote in the poll sent at `poll_sent_timestamp` in a [Thread].
+    ///
+    /// The vote replaces any previous vote from the same voter in the same poll. Like reactions,
+    /// votes arriving before their poll are kept.
+    fn save_poll_vote(
+        &mut self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+        vote: PollVote,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Retrieve the votes in the poll sent at `poll_sent_timestamp` in a [Thread], at most one
+    /// per voter, oldest first.
+    fn poll_votes(
+        &self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+    ) -> impl Future<Output = Result<Vec<PollVote>, Self::ContentsStoreError>>;
+
+    /// Save that the poll sent at `poll_sent_timestamp` in a [Thread] was terminated at
+    /// `timestamp`, after which no vote counts anymore.
+    fn save_poll_termination(
+        &mut self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+        timestamp: u64,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Retrieve when the poll sent at `poll_sent_timestamp` in a [Thread] was terminated, [None]
+    /// if it is still open.
+    fn poll_termination(
+        &self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+    ) -> impl Future<Output = Result<Option<u64>, Self::ContentsStoreError>>;
+
+    // Message statuses
+
+    /// Save that the message we sent at `sent_timestamp` in a [Thread] reached `status` (as told
+    /// by a receipt), unless it already reached a further status.
+    fn save_message_status(
+        &mut self,
+        thread: &Thread,
+        sent_timestamp: u64,
+        status: MessageStatus,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Retrieve the furthest status reached by the message we sent at `sent_timestamp` in a
+    /// [Thread], [None] if no receipt was received for it.
+    fn message_status(
+        &self,
+        thread: &Thread,
+        sent_timestamp: u64,
+    ) -> impl Future<Output = Result<Option<MessageStatus>, Self::ContentsStoreError>>;
+
+    // Pending sends
+
+    /// Save a message which could not be sent to a [Thread], to be sent again later (see
+    /// [Manager::retry_pending_sends](crate::Manager::retry_pending_sends)).
+    ///
+    /// The message replaces any pending message with the same timestamp in the same thread.
+    fn save_pending_send(
+        &mut self,
+        thread: &Thread,
+        content: Content,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Forget the pending message sent at `timestamp` in a [Thread], once it was sent.
+    fn remove_pending_send(
+        &mut self,
+        thread: &Thread,
+        timestamp: u64,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Retrieve all the pending messages with their thread, oldest first.
+    fn pending_sends(
+        &self,
+    ) -> impl Future<Output = Result<Vec<(Thread, Content)>, Self::ContentsStoreError>>;
+
+    /// Search for messages whose body contains all the words of `query`, most recent first.
+    ///
+    /// The search is restricted to a [Thread] if provided.
+    fn search_messages(
+        &self,
+        query: &str,
+        thread: Option<&Thread>,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<Content>, Self::ContentsStoreError>>;
+
+    /// Retrieve the metadata of all threads with messages, most recently active first.
+    ///
+    /// Implementations are expected to maintain it when messages are saved, instead of scanning
+    /// all messages.
+    fn threads(
+        &self,
+    ) -> impl Future<Output = Result<Vec<ThreadMetadata>, Self::ContentsStoreError>>;
+
+    /// Number of received messages in a [Thread] which have not been read yet
+    fn unread_count(
+        &self,
+        thread: &Thread,
+    ) -> impl Future<Output = Result<u64, Self::ContentsStoreError>>;
+
+    /// Mark the received messages of a [Thread] sent up to `up_to_timestamp` (included) as read.
+    ///
+    /// Returns the sender and timestamp of the messages which were not read yet.
+    fn mark_read(
+        &mut self,
+        thread: &Thread,
+        up_to_timestamp: u64,
+    ) -> impl Future<Output = Result<Vec<(ServiceId, u64)>, Self::ContentsStoreError>>;
+
+    /// Block or unblock a [Thread], i.e. a contact or a group.
+    fn set_blocked(
+        &mut self,
+        thread: &Thread,
+        blocked: bool,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Whether a [Thread] is blocked
+    fn is_blocked(
+        &self,
+        thread: &Thread,
+    ) -> impl Future<Output = Result<bool, Self::ContentsStoreError>>;
+
+    /// Retrieve all the blocked contacts and groups
+    fn blocked(&self) -> impl Future<Output = Result<Vec<Thread>, Self::ContentsStoreError>>;
+
+    /// Archive or unarchive a [Thread]
+    fn set_archived(
+        &mut self,
+        thread: &Thread,
+        archived: bool,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Whether a [Thread] is archived
+    fn is_archived(
+        &self,
+        thread: &Thread,
+    ) -> impl Future<Output = Result<bool, Self::ContentsStoreError>>;
+
+    /// Mute a [Thread] until a timestamp (in milliseconds), or unmute it.
+    fn set_muted_until(
+        &mut self,
+        thread: &Thread,
+        muted_until: Option<u64>,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
+    /// Timestamp (in milliseconds) until which a [Thread] was muted, if it was
+    ///
+    /// The timestamp can be in the past, once the thread is no longer muted.
+    fn muted_until(
+        &self,
+        thread: &Thread,
+    ) -> impl Future<Output = Result<Option<u64>, Self::ContentsStoreError>>;
+
     /// Get the expire timer from a [Thread], which corresponds to either [Contact::expire_timer]
     /// or [Group::disappearing_messages_timer].
     fn expire_timer(
@@ -204,6 +488,17 @@ pub trait ContentsStore: Send + Sync {
         }
     }
 
+    /// Record that the envelope with this server GUID was processed at `timestamp` (in
+    /// milliseconds since the epoch), and forget the envelopes processed before `expire_before`.
+    ///
+    /// Returns false if the envelope was already recorded, i.e. if it was delivered again.
+    fn mark_envelope_processed(
+        &self,
+        server_guid: Uuid,
+        timestamp: u64,
+        expire_before: u64,
+    ) -> impl Future<Output = Result<bool, Self::ContentsStoreError>>;
+
     // Contacts
 
     /// Clear all saved synchronized contact data
@@ -226,6 +521,12 @@ pub trait ContentsStore: Send + Sync {
         id: &Uuid,
     ) -> impl Future<Output = Result<Option<Contact>, Self::ContentsStoreError>> + Send;
 
+    /// Get contact data for a single user by their phone number.
+    fn contact_by_phone_number(
+        &self,
+        phone_number: &PhoneNumber,
+    ) -> impl Future<Output = Result<Option<Contact>, Self::ContentsStoreError>> + Send;
+
     /// Delete all cached group data
     fn clear_groups(&mut self) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
 
@@ -303,6 +604,15 @@ pub trait ContentsStore: Send + Sync {
         key: ProfileKey,
     ) -> impl Future<Output = Result<Option<AvatarBytes>, Self::ContentsStoreError>>;
 
+    /// Remove the cached profile, profile avatar and profile credential of a contact, but keep
+    /// its profile key.
+    ///
+    /// This is used when a contact rotates its profile key, which makes the cached data stale.
+    fn clear_profile(
+        &mut self,
+        uuid: &Uuid,
+    ) -> impl Future<Output = Result<(), Self::ContentsStoreError>>;
+
     // Profile Credentials (for GV2 group operations)
 
     /// Save an expiring profile key credential for a user.
@@ -358,7 +668,36 @@ pub trait ContentsStore: Send + Sync {
     ) -> impl Future<Output = Result<Self::StickerPacksIter, Self::ContentsStoreError>>;
 }
 
+/// Trust decisions about the identity keys of contacts, complementing the [IdentityKeyStore] of
+/// a protocol store
+///
+/// An identity key which is not trusted must be rejected by
+/// [IdentityKeyStore::is_trusted_identity]. When a changed identity key is rejected (see
+/// [OnNewIdentity](crate::model::identity::OnNewIdentity)), it should be saved as untrusted so it
+/// can be trusted later.
+///
+/// [IdentityKeyStore]: libsignal_service::protocol::IdentityKeyStore
+/// [IdentityKeyStore::is_trusted_identity]: libsignal_service::protocol::IdentityKeyStore::is_trusted_identity
+pub trait IdentityTrustStore {
+    /// Saves the identity key of a contact, and whether it is trusted
+    fn set_identity_trust(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        trusted: bool,
+    ) -> impl Future<Output = Result<(), SignalProtocolError>>;
+
+    /// The identity key of a contact, if known, and whether it is trusted
+    fn identity_trust(
+        &self,
+        address: &ProtocolAddress,
+    ) -> impl Future<Output = Result<Option<(IdentityKey, bool)>, SignalProtocolError>>;
+}
+
 /// The manager store trait combining all other stores into a single one
+///
+/// Stores are cloned by the manager (e.g. to receive messages in the background) and all clones
+/// are expected to share the same data, like connections to the same database would.
 pub trait Store:
     StateStore<StateStoreError = Self::Error>
     + ContentsStore<ContentsStoreError = Self::Error>
@@ -367,15 +706,22 @@ pub trait Store:
     + Clone
     + 'static
 {
+    /// Error type of all the operations of the store
     type Error: StoreError;
+    /// Protocol store of the ACI (account) identity: its identity key pair, the identities of
+    /// contacts, sessions, pre-keys and sender keys.
     type AciStore: ProtocolStore
+        + IdentityTrustStore
         + PreKeysStore
         + SenderKeyStore
         + SessionStoreExt
         + Send
         + Sync
         + Clone;
+    /// Protocol store of the PNI (phone number) identity, which must be kept apart from the ACI
+    /// one.
     type PniStore: ProtocolStore
+        + IdentityTrustStore
         + PreKeysStore
         + SenderKeyStore
         + SessionStoreExt
@@ -390,8 +736,10 @@ pub trait Store:
         &mut self,
     ) -> impl Future<Output = Result<(), <Self as StateStore>::StateStoreError>> + Send;
 
+    /// Protocol store of the ACI identity, sharing its data with this store
     fn aci_protocol_store(&self) -> Self::AciStore;
 
+    /// Protocol store of the PNI identity, sharing its data with this store
     fn pni_protocol_store(&self) -> Self::PniStore;
 }
 
@@ -406,6 +754,39 @@ pub enum Thread {
     Group(GroupMasterKeyBytes),
 }
 
+/// Which messages of a thread to retrieve, see [ContentsStore::messages_page]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageRange {
+    /// The most recent messages
+    Latest,
+    /// The messages sent before a timestamp (excluded)
+    Before(u64),
+    /// The messages sent after a timestamp (excluded)
+    After(u64),
+}
+
+/// Metadata of a [Thread], e.g. to show a list of conversations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadMetadata {
+    pub thread: Thread,
+    /// Timestamp of the last message, if any
+    pub last_message_timestamp: Option<u64>,
+    /// Number of received messages which have not been read yet
+    pub unread_count: u64,
+    /// Beginning of the body of the last message, if it has one
+    pub snippet: Option<String>,
+    pub archived: bool,
+    /// Timestamp (in milliseconds) until which the thread was muted, if it was
+    pub muted_until: Option<u64>,
+}
+
+impl ThreadMetadata {
+    /// Whether the thread is muted at the given timestamp (in milliseconds)
+    pub fn is_muted_at(&self, timestamp: u64) -> bool {
+        self.muted_until.is_some_and(|until| until > timestamp)
+    }
+}
+
 impl fmt::Display for Thread {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -431,6 +812,19 @@ impl TryFrom<&Content> for Thread {
                     }),
                 ..
             }) => Ok(Self::Contact(Uuid::parse_str(uuid)?)),
+            // [1-1] Message sent by us with another device, from recent clients
+            ContentBody::SynchronizeMessage(SyncMessage {
+                sent:
+                    Some(Sent {
+                        destination_service_id: None,
+                        destination_service_id_binary: Some(bytes),
+                        ..
+                    }),
+                ..
+            }) => match ServiceId::parse_from_service_id_binary(bytes) {
+                Some(destination) => Ok(Self::Contact(destination.raw_uuid())),
+                None => Uuid::from_slice(bytes).map(Self::Contact),
+            },
             // [Group] message from somebody else
             ContentBody::DataMessage(DataMessage {
                 group_v2:
@@ -504,6 +898,123 @@ impl TryFrom<&Content> for Thread {
 /// Extension trait of [`Content`]
 pub trait ContentExt {
     fn timestamp(&self) -> u64;
+
+    /// The edit of a previous message, if this content is one.
+    ///
+    /// This covers edits sent by others as well as edits sent by us from another device.
+    /// Note that an edit can be received before the message it edits, in which case clients
+    /// should keep it around until the original message shows up.
+    fn edit(&self) -> Option<MessageEdit<'_>>;
+
+    /// The data message carried by this content, if any.
+    ///
+    /// This covers messages sent by others, messages sent by us from another device, and the new
+    /// version of edited messages.
+    fn data_message(&self) -> Option<&DataMessage>;
+
+    /// The message this content replies to, if it is a reply.
+    fn quote(&self) -> Option<&Quote> {
+        self.data_message()?.quote.as_ref()
+    }
+
+    /// The link previews of this message, to show along with its body.
+    fn previews(&self) -> &[Preview] {
+        self.data_message()
+            .map(|data_message| data_message.preview.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The mentions of group members in the body of this message.
+    fn mentions(&self) -> Vec<Mention> {
+        self.data_message()
+            .map(|data_message| {
+                data_message
+                    .body_ranges
+                    .iter()
+                    .filter_map(Mention::from_body_range)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The styles of the text of the body of this message.
+    fn text_styles(&self) -> Vec<TextStyleRange> {
+        self.data_message()
+            .map(|data_message| {
+                data_message
+                    .body_ranges
+                    .iter()
+                    .filter_map(TextStyleRange::from_body_range)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The change of a group carried by this message (members added or removed, new title...),
+    /// if it has one.
+    ///
+    /// The change is decrypted with the master key of the group, and [None] is returned if it
+    /// cannot be.
+    fn group_change(&self) -> Option<GroupChange> {
+        let group_context = self.data_message()?.group_v2.as_ref()?;
+        GroupChange::decrypt(group_context)
+            .inspect_err(|error| warn!(%error, "failed to decrypt group change"))
+            .ok()
+            .flatten()
+    }
+
+    /// The sticker sent by this message, if it is one.
+    ///
+    /// The ids of the pack and of the sticker can be used to render it from an installed sticker
+    /// pack, otherwise its image is the attachment of the sticker.
+    fn sticker(&self) -> Option<&data_message::Sticker> {
+        self.data_message()?.sticker.as_ref()
+    }
+
+    /// The gift badge sent by this message, if it is one.
+    ///
+    /// Redeeming badges is not supported by presage, but the receipt credential presentation of
+    /// the badge is kept in the message for clients which do.
+    fn gift_badge(&self) -> Option<&data_message::GiftBadge> {
+        self.data_message()?.gift_badge.as_ref()
+    }
+
+    /// The payment notification (or activation request) carried by this message, if any.
+    ///
+    /// Payments are not supported by presage, but such messages are stored like the others, with
+    /// the MobileCoin receipt of the payment, so that they are not missing from the thread.
+    fn payment(&self) -> Option<&data_message::Payment> {
+        self.data_message()?.payment.as_ref()
+    }
+
+    /// The transcript of a message we sent from another of our devices, if this content is one.
+    ///
+    /// Such messages are received as sync messages, and stored in the thread of their destination.
+    fn sent_transcript(&self) -> Option<SentTranscript<'_>>;
+
+    /// Whether this content was received with sealed sender (unidentified delivery), as told by
+    /// the type of its envelope when it was decrypted.
+    fn was_sealed_sender(&self) -> bool;
+
+    /// The sender authenticated by the sender certificate of a sealed sender message, or [None]
+    /// if the message was received identified.
+    fn sealed_sender(&self) -> Option<ServiceId>;
+
+    /// Whether this content is a view-once message.
+    ///
+    /// Clients should only show the attachment of such a message once, and then delete it.
+    fn is_view_once(&self) -> bool {
+        self.data_message()
+            .and_then(|data_message| data_message.is_view_once)
+            .unwrap_or_default()
+    }
+
+    /// The protobuf [Content](proto::Content) of this content, to read the fields presage doesn't
+    /// expose (yet).
+    ///
+    /// It is rebuilt from the parsed body, so it has all the fields known to the protobuf
+    /// definitions of `libsignal-service`, but not the ones added to Signal after them.
+    fn raw_proto(&self) -> proto::Content;
 }
 
 impl ContentExt for Content {
@@ -537,6 +1048,85 @@ impl ContentExt for Content {
             _ => self.metadata.timestamp,
         }
     }
+
+    fn edit(&self) -> Option<MessageEdit<'_>> {
+        match &self.body {
+            ContentBody::EditMessage(EditMessage {
+                target_sent_timestamp: Some(ts),
+                data_message: Some(data_message),
+            })
+            | ContentBody::SynchronizeMessage(SyncMessage {
+                sent:
+                    Some(sync_message::Sent {
+                        edit_message:
+                            Some(EditMessage {
+                                target_sent_timestamp: Some(ts),
+                                data_message: Some(data_message),
+                            }),
+                        ..
+                    }),
+                ..
+            }) => Some(MessageEdit {
+                target_sent_timestamp: *ts,
+                data_message,
+            }),
+            _ => None,
+        }
+    }
+
+    fn sent_transcript(&self) -> Option<SentTranscript<'_>> {
+        let ContentBody::SynchronizeMessage(SyncMessage {
+            sent: Some(sent), ..
+        }) = &self.body
+        else {
+            return None;
+        };
+        let destination = sent
+            .destination_service_id
+            .as_deref()
+            .and_then(ServiceId::parse_from_service_id_string)
+            .or_else(|| {
+                sent.destination_service_id_binary
+                    .as_deref()
+                    .and_then(ServiceId::parse_from_service_id_binary)
+            });
+        Some(SentTranscript {
+            destination,
+            sender_device: self.metadata.sender_device,
+            timestamp: sent.timestamp.unwrap_or(self.metadata.timestamp),
+            data_message: sent
+                .message
+                .as_ref()
+                .or_else(|| sent.edit_message.as_ref()?.data_message.as_ref()),
+        })
+    }
+
+    fn was_sealed_sender(&self) -> bool {
+        self.metadata.unidentified_sender
+    }
+
+    fn sealed_sender(&self) -> Option<ServiceId> {
+        self.was_sealed_sender().then_some(self.metadata.sender)
+    }
+
+    fn raw_proto(&self) -> proto::Content {
+        self.body.clone().into_proto()
+    }
+
+    fn data_message(&self) -> Option<&DataMessage> {
+        match &self.body {
+            ContentBody::DataMessage(data_message)
+            | ContentBody::SynchronizeMessage(SyncMessage {
+                sent:
+                    Some(sync_message::Sent {
+                        message: Some(data_message),
+                        ..
+                    }),
+                ..
+            }) => Some(data_message),
+            _ => self.edit().map(|edit| edit.data_message),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
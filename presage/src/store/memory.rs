@@ -0,0 +1,1785 @@
+//! A store keeping everything in memory, e.g. to test the [Manager](crate::Manager) without a
+//! database.
+//!
+//! Nothing is persisted: all the data is lost when the last clone of the store is dropped.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeBounds,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use libsignal_service::{
+    content::ContentBody,
+    models::Attachment,
+    pre_keys::{KyberPreKeyStoreExt, PreKeysStore},
+    prelude::{phonenumber::PhoneNumber, Content, DeviceId, MasterKey, ProfileKey, Uuid},
+    protocol::{
+        CiphertextMessageType, Direction, IdentityChange, IdentityKey, IdentityKeyPair,
+        IdentityKeyStore, KyberPreKeyId, KyberPreKeyRecord, KyberPreKeyStore, PreKeyId,
+        PreKeyRecord, PreKeyStore, ProtocolAddress, ProtocolStore, PublicKey, SenderCertificate,
+        SenderKeyRecord, SenderKeyStore, ServiceId, SessionRecord, SessionStore,
+        SignalProtocolError, SignedPreKeyId, SignedPreKeyRecord, SignedPreKeyStore,
+    },
+    push_service::DEFAULT_DEVICE_ID,
+    session_store::SessionStoreExt,
+    zkgroup::GroupMasterKeyBytes,
+    Profile,
+};
+use tracing::warn;
+
+use crate::{
+    manager::RegistrationData,
+    model::{
+        contacts::Contact,
+        groups::Group,
+        identity::OnNewIdentity,
+        messages::{MessageStatus, PollVote, Reaction},
+        settings::AccountSettings,
+    },
+    store::{
+        ContentExt, ContentsStore, IdentityTrustStore, StateStore, StickerPack, Store, StoreError,
+        Thread, ThreadMetadata,
+    },
+    AvatarBytes,
+};
+
+/// Maximum number of characters of [ThreadMetadata::snippet]
+const SNIPPET_LEN: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InMemoryStoreError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Protocol(#[from] SignalProtocolError),
+    #[error("invalid format")]
+    InvalidFormat,
+}
+
+impl StoreError for InMemoryStoreError {}
+
+/// A [Store] keeping all the data in memory
+///
+/// Clones share the same data, like clones of a store backed by a database would.
+#[derive(Clone)]
+pub struct InMemoryStore {
+    trust_new_identities: OnNewIdentity,
+    state: Arc<RwLock<State>>,
+    contents: Arc<RwLock<Contents>>,
+    aci: Arc<RwLock<ProtocolData>>,
+    pni: Arc<RwLock<ProtocolData>>,
+}
+
+impl InMemoryStore {
+    pub fn new(trust_new_identities: OnNewIdentity) -> Self {
+        Self {
+            trust_new_identities,
+            state: Default::default(),
+            contents: Default::default(),
+            aci: Default::default(),
+            pni: Default::default(),
+        }
+    }
+
+    fn own_aci(&self) -> Option<ServiceId> {
+        read(&self.state)
+            .registration
+            .as_ref()
+            .map(|data| data.service_ids.aci().into())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    registration: Option<RegistrationData>,
+    aci_identity_key_pair: Option<Box<[u8]>>,
+    pni_identity_key_pair: Option<Box<[u8]>>,
+    sender_certificate: Option<Vec<u8>>,
+    master_key: Option<Vec<u8>>,
+    account_settings: Option<AccountSettings>,
+    last_pre_key_rotation: Option<u64>,
+}
+
+#[derive(Default)]
+struct Contents {
+    threads: HashMap<Thread, ThreadData>,
+    contacts: HashMap<Uuid, Contact>,
+    /// Groups serialized as JSON, as they cannot be cloned
+    groups: HashMap<GroupMasterKeyBytes, Vec<u8>>,
+    group_avatars: HashMap<GroupMasterKeyBytes, AvatarBytes>,
+    profile_keys: HashMap<Uuid, [u8; 32]>,
+    profiles: HashMap<Uuid, Profile>,
+    profile_avatars: HashMap<Uuid, AvatarBytes>,
+    /// Profile credentials with their expiration time
+    profile_credentials: HashMap<Uuid, (Vec<u8>, u64)>,
+    sticker_packs: HashMap<Vec<u8>, StickerPack>,
+    /// Server GUIDs of the processed envelopes, with the time they were processed
+    processed_envelopes: HashMap<Uuid, u64>,
+    /// Messages which could not be sent yet, in the order they were saved
+    pending_sends: Vec<(Thread, Content)>,
+}
+
+#[derive(Default)]
+struct ThreadData {
+    messages: BTreeMap<u64, StoredMessage>,
+    /// Reactions by timestamp of their target message
+    reactions: BTreeMap<u64, Vec<Reaction>>,
+    /// Poll votes by timestamp of their poll
+    poll_votes: BTreeMap<u64, Vec<PollVote>>,
+    /// When polls were terminated, by timestamp of the poll
+    poll_terminations: BTreeMap<u64, u64>,
+    /// Statuses of the messages we sent, by their timestamp
+    message_statuses: BTreeMap<u64, MessageStatus>,
+    unread_count: u64,
+    last_read_timestamp: u64,
+    blocked: bool,
+    archived: bool,
+    muted_until: Option<u64>,
+}
+
+impl ThreadData {
+    fn clear_messages(&mut self) {
+        self.messages.clear();
+        self.reactions.clear();
+        self.poll_votes.clear();
+        self.poll_terminations.clear();
+        self.message_statuses.clear();
+        self.unread_count = 0;
+    }
+}
+
+struct StoredMessage {
+    content: Content,
+    unread: bool,
+}
+
+impl Store for InMemoryStore {
+    type Error = InMemoryStoreError;
+
+    type AciStore = InMemoryProtocolStore;
+
+    type PniStore = InMemoryProtocolStore;
+
+    async fn clear(&mut self) -> Result<(), InMemoryStoreError> {
+        *write(&self.state) = State::default();
+        Ok(())
+    }
+
+    fn aci_protocol_store(&self) -> Self::AciStore {
+        InMemoryProtocolStore {
+            store: self.clone(),
+            identity: IdentityType::Aci,
+        }
+    }
+
+    fn pni_protocol_store(&self) -> Self::PniStore {
+        InMemoryProtocolStore {
+            store: self.clone(),
+            identity: IdentityType::Pni,
+        }
+    }
+}
+
+impl StateStore for InMemoryStore {
+    type StateStoreError = InMemoryStoreError;
+
+    async fn load_registration_data(
+        &self,
+    ) -> Result<Option<RegistrationData>, Self::StateStoreError> {
+        Ok(read(&self.state).registration.clone())
+    }
+
+    async fn set_aci_identity_key_pair(
+        &self,
+        key_pair: IdentityKeyPair,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).aci_identity_key_pair = Some(key_pair.serialize());
+        Ok(())
+    }
+
+    async fn set_pni_identity_key_pair(
+        &self,
+        key_pair: IdentityKeyPair,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).pni_identity_key_pair = Some(key_pair.serialize());
+        Ok(())
+    }
+
+    async fn save_registration_data(
+        &mut self,
+        state: &RegistrationData,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).registration = Some(state.clone());
+        Ok(())
+    }
+
+    async fn sender_certificate(&self) -> Result<Option<SenderCertificate>, Self::StateStoreError> {
+        read(&self.state)
+            .sender_certificate
+            .as_ref()
+            .map(|bytes| SenderCertificate::deserialize(bytes))
+            .transpose()
+            .map_err(From::from)
+    }
+
+    async fn save_sender_certificate(
+        &self,
+        certificate: &SenderCertificate,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).sender_certificate = Some(certificate.serialized()?.to_vec());
+        Ok(())
+    }
+
+    async fn is_registered(&self) -> bool {
+        read(&self.state).registration.is_some()
+    }
+
+    async fn clear_registration(&mut self) -> Result<(), Self::StateStoreError> {
+        let mut state = write(&self.state);
+        state.registration = None;
+        state.last_pre_key_rotation = None;
+        drop(state);
+        *write(&self.aci) = ProtocolData::default();
+        *write(&self.pni) = ProtocolData::default();
+        Ok(())
+    }
+
+    async fn account_settings(&self) -> Result<Option<AccountSettings>, Self::StateStoreError> {
+        Ok(read(&self.state).account_settings)
+    }
+
+    async fn save_account_settings(
+        &self,
+        settings: &AccountSettings,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).account_settings = Some(*settings);
+        Ok(())
+    }
+
+    async fn last_pre_key_rotation(&self) -> Result<Option<u64>, Self::StateStoreError> {
+        Ok(read(&self.state).last_pre_key_rotation)
+    }
+
+    async fn save_last_pre_key_rotation(
+        &self,
+        timestamp: u64,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).last_pre_key_rotation = Some(timestamp);
+        Ok(())
+    }
+
+    async fn fetch_master_key(&self) -> Result<Option<MasterKey>, Self::StateStoreError> {
+        read(&self.state)
+            .master_key
+            .as_ref()
+            .map(|bytes| MasterKey::from_slice(bytes))
+            .transpose()
+            .map_err(|_| InMemoryStoreError::InvalidFormat)
+    }
+
+    async fn store_master_key(
+        &self,
+        master_key: Option<&MasterKey>,
+    ) -> Result<(), Self::StateStoreError> {
+        write(&self.state).master_key = master_key.map(|key| key.inner.to_vec());
+        Ok(())
+    }
+}
+
+impl ContentsStore for InMemoryStore {
+    type ContentsStoreError = InMemoryStoreError;
+
+    type ContactsIter = std::vec::IntoIter<Result<Contact, Self::ContentsStoreError>>;
+
+    type GroupsIter =
+        std::vec::IntoIter<Result<(GroupMasterKeyBytes, Group), Self::ContentsStoreError>>;
+
+    type MessagesIter = std::vec::IntoIter<Result<Content, Self::ContentsStoreError>>;
+
+    type StickerPacksIter = std::vec::IntoIter<Result<StickerPack, Self::ContentsStoreError>>;
+
+    async fn clear_profiles(&mut self) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        contents.profiles.clear();
+        contents.profile_keys.clear();
+        contents.profile_avatars.clear();
+        Ok(())
+    }
+
+    async fn clear_contents(&mut self) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        contents.threads.clear();
+        contents.contacts.clear();
+        contents.groups.clear();
+        contents.group_avatars.clear();
+        contents.sticker_packs.clear();
+        contents.processed_envelopes.clear();
+        contents.pending_sends.clear();
+        Ok(())
+    }
+
+    async fn clear_messages(&mut self) -> Result<(), Self::ContentsStoreError> {
+        // threads also hold settings like blocking, which are kept
+        for thread in write(&self.contents).threads.values_mut() {
+            thread.clear_messages();
+        }
+        Ok(())
+    }
+
+    async fn clear_thread(&mut self, thread: &Thread) -> Result<(), Self::ContentsStoreError> {
+        if let Some(thread) = write(&self.contents).threads.get_mut(thread) {
+            thread.clear_messages();
+        }
+        Ok(())
+    }
+
+    async fn save_message(
+        &self,
+        thread: &Thread,
+        content: Content,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let incoming = matches!(content.body, ContentBody::DataMessage(_))
+            && self.own_aci() != Some(content.metadata.sender);
+        let timestamp = content.metadata.timestamp;
+
+        let mut contents = write(&self.contents);
+        let thread = contents.threads.entry(thread.clone()).or_default();
+        // a message saved again (e.g. when edited) keeps its read state
+        let unread = match thread.messages.get(&timestamp) {
+            Some(message) => message.unread,
+            None => {
+                let unread = incoming && thread.last_read_timestamp < timestamp;
+                if unread {
+                    thread.unread_count += 1;
+                }
+                unread
+            }
+        };
+        thread
+            .messages
+            .insert(timestamp, StoredMessage { content, unread });
+        Ok(())
+    }
+
+    async fn delete_message(
+        &mut self,
+        thread: &Thread,
+        timestamp: u64,
+    ) -> Result<bool, Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        let Some(thread) = contents.threads.get_mut(thread) else {
+            return Ok(false);
+        };
+        thread.reactions.remove(&timestamp);
+        thread.poll_votes.remove(&timestamp);
+        thread.poll_terminations.remove(&timestamp);
+        thread.message_statuses.remove(&timestamp);
+        let Some(message) = thread.messages.remove(&timestamp) else {
+            return Ok(false);
+        };
+        if message.unread {
+            thread.unread_count -= 1;
+        }
+        Ok(true)
+    }
+
+    async fn message(
+        &self,
+        thread: &Thread,
+        timestamp: u64,
+    ) -> Result<Option<Content>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .and_then(|thread| thread.messages.get(&timestamp))
+            .map(|message| message.content.clone()))
+    }
+
+    async fn messages(
+        &self,
+        thread: &Thread,
+        range: impl RangeBounds<u64>,
+    ) -> Result<Self::MessagesIter, Self::ContentsStoreError> {
+        let contents = read(&self.contents);
+        let messages: Vec<_> = contents
+            .threads
+            .get(thread)
+            .into_iter()
+            .flat_map(|thread| thread.messages.iter().rev())
+            .filter(|(timestamp, _)| range.contains(timestamp))
+            .map(|(_, message)| Ok(message.content.clone()))
+            .collect();
+        Ok(messages.into_iter())
+    }
+
+    async fn save_reaction(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        reaction: Reaction,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        // the reaction might arrive before any message in the thread
+        let reactions = contents
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .reactions
+            .entry(target_sent_timestamp)
+            .or_default();
+        reactions.retain(|r| r.author != reaction.author);
+        reactions.push(reaction);
+        reactions.sort_by_key(|r| r.timestamp);
+        Ok(())
+    }
+
+    async fn remove_reaction(
+        &mut self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+        author: &ServiceId,
+    ) -> Result<bool, Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        let Some(reactions) = contents
+            .threads
+            .get_mut(thread)
+            .and_then(|thread| thread.reactions.get_mut(&target_sent_timestamp))
+        else {
+            return Ok(false);
+        };
+        let len = reactions.len();
+        reactions.retain(|r| &r.author != author);
+        Ok(reactions.len() < len)
+    }
+
+    async fn save_poll_vote(
+        &mut self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+        vote: PollVote,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        // the vote might arrive before any message in the thread
+        let votes = contents
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .poll_votes
+            .entry(poll_sent_timestamp)
+            .or_default();
+        votes.retain(|v| v.voter != vote.voter);
+        votes.push(vote);
+        votes.sort_by_key(|v| v.timestamp);
+        Ok(())
+    }
+
+    async fn poll_votes(
+        &self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+    ) -> Result<Vec<PollVote>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .and_then(|thread| thread.poll_votes.get(&poll_sent_timestamp))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn save_poll_termination(
+        &mut self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+        timestamp: u64,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .poll_terminations
+            .insert(poll_sent_timestamp, timestamp);
+        Ok(())
+    }
+
+    async fn poll_termination(
+        &self,
+        thread: &Thread,
+        poll_sent_timestamp: u64,
+    ) -> Result<Option<u64>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .and_then(|thread| thread.poll_terminations.get(&poll_sent_timestamp))
+            .copied())
+    }
+
+    async fn save_message_status(
+        &mut self,
+        thread: &Thread,
+        sent_timestamp: u64,
+        status: MessageStatus,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        let message_status = contents
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .message_statuses
+            .entry(sent_timestamp)
+            .or_insert(status);
+        *message_status = (*message_status).max(status);
+        Ok(())
+    }
+
+    async fn message_status(
+        &self,
+        thread: &Thread,
+        sent_timestamp: u64,
+    ) -> Result<Option<MessageStatus>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .and_then(|thread| thread.message_statuses.get(&sent_timestamp))
+            .copied())
+    }
+
+    async fn save_pending_send(
+        &mut self,
+        thread: &Thread,
+        content: Content,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        let timestamp = content.timestamp();
+        match contents
+            .pending_sends
+            .iter_mut()
+            .find(|(pending_thread, pending)| {
+                pending_thread == thread && pending.timestamp() == timestamp
+            }) {
+            Some((_, pending)) => *pending = content,
+            None => contents.pending_sends.push((thread.clone(), content)),
+        }
+        Ok(())
+    }
+
+    async fn remove_pending_send(
+        &mut self,
+        thread: &Thread,
+        timestamp: u64,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .pending_sends
+            .retain(|(pending_thread, pending)| {
+                pending_thread != thread || pending.timestamp() != timestamp
+            });
+        Ok(())
+    }
+
+    async fn pending_sends(&self) -> Result<Vec<(Thread, Content)>, Self::ContentsStoreError> {
+        let mut pending_sends = read(&self.contents).pending_sends.clone();
+        pending_sends.sort_by_key(|(_, content)| content.timestamp());
+        Ok(pending_sends)
+    }
+
+    async fn reactions(
+        &self,
+        thread: &Thread,
+        target_sent_timestamp: u64,
+    ) -> Result<Vec<Reaction>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .and_then(|thread| thread.reactions.get(&target_sent_timestamp))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn search_messages(
+        &self,
+        query: &str,
+        thread: Option<&Thread>,
+        limit: usize,
+    ) -> Result<Vec<Content>, Self::ContentsStoreError> {
+        let query_words: HashSet<String> = words(query).collect();
+        if query_words.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let contents = read(&self.contents);
+        let mut messages: Vec<&Content> = contents
+            .threads
+            .iter()
+            .filter(|(t, _)| thread.is_none_or(|thread| thread == *t))
+            .flat_map(|(_, thread)| thread.messages.values())
+            .map(|message| &message.content)
+            .filter(|content| {
+                let body = content
+                    .data_message()
+                    .and_then(|data_message| data_message.body.as_deref())
+                    .unwrap_or_default();
+                query_words.is_subset(&words(body).collect())
+            })
+            .collect();
+        messages.sort_by_key(|content| std::cmp::Reverse(content.metadata.timestamp));
+        Ok(messages.into_iter().take(limit).cloned().collect())
+    }
+
+    async fn threads(&self) -> Result<Vec<ThreadMetadata>, Self::ContentsStoreError> {
+        let contents = read(&self.contents);
+        let mut threads: Vec<ThreadMetadata> = contents
+            .threads
+            .iter()
+            .filter_map(|(thread, data)| {
+                let (timestamp, last_message) = data.messages.last_key_value()?;
+                Some(ThreadMetadata {
+                    thread: thread.clone(),
+                    last_message_timestamp: Some(*timestamp),
+                    unread_count: data.unread_count,
+                    snippet: snippet(&last_message.content),
+                    archived: data.archived,
+                    muted_until: data.muted_until,
+                })
+            })
+            .collect();
+        threads.sort_by_key(|metadata| std::cmp::Reverse(metadata.last_message_timestamp));
+        Ok(threads)
+    }
+
+    async fn unread_count(&self, thread: &Thread) -> Result<u64, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .map(|thread| thread.unread_count)
+            .unwrap_or_default())
+    }
+
+    async fn mark_read(
+        &mut self,
+        thread: &Thread,
+        up_to_timestamp: u64,
+    ) -> Result<Vec<(ServiceId, u64)>, Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        let thread = contents.threads.entry(thread.clone()).or_default();
+        let mut newly_read = Vec::new();
+        for (timestamp, message) in thread.messages.range_mut(..=up_to_timestamp) {
+            if message.unread {
+                message.unread = false;
+                newly_read.push((message.content.metadata.sender, *timestamp));
+            }
+        }
+        thread.unread_count -= newly_read.len() as u64;
+        thread.last_read_timestamp = thread.last_read_timestamp.max(up_to_timestamp);
+        Ok(newly_read)
+    }
+
+    async fn set_blocked(
+        &mut self,
+        thread: &Thread,
+        blocked: bool,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .blocked = blocked;
+        Ok(())
+    }
+
+    async fn is_blocked(&self, thread: &Thread) -> Result<bool, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .is_some_and(|thread| thread.blocked))
+    }
+
+    async fn blocked(&self) -> Result<Vec<Thread>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .iter()
+            .filter(|(_, data)| data.blocked)
+            .map(|(thread, _)| thread.clone())
+            .collect())
+    }
+
+    async fn set_archived(
+        &mut self,
+        thread: &Thread,
+        archived: bool,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .archived = archived;
+        Ok(())
+    }
+
+    async fn is_archived(&self, thread: &Thread) -> Result<bool, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .is_some_and(|thread| thread.archived))
+    }
+
+    async fn set_muted_until(
+        &mut self,
+        thread: &Thread,
+        muted_until: Option<u64>,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .threads
+            .entry(thread.clone())
+            .or_default()
+            .muted_until = muted_until;
+        Ok(())
+    }
+
+    async fn muted_until(&self, thread: &Thread) -> Result<Option<u64>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .threads
+            .get(thread)
+            .and_then(|thread| thread.muted_until))
+    }
+
+    async fn mark_envelope_processed(
+        &self,
+        server_guid: Uuid,
+        timestamp: u64,
+        expire_before: u64,
+    ) -> Result<bool, Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        contents
+            .processed_envelopes
+            .retain(|_, processed_at| *processed_at >= expire_before);
+        if contents.processed_envelopes.contains_key(&server_guid) {
+            return Ok(false);
+        }
+        contents.processed_envelopes.insert(server_guid, timestamp);
+        Ok(true)
+    }
+
+    async fn clear_contacts(&mut self) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents).contacts.clear();
+        Ok(())
+    }
+
+    async fn save_contact(&mut self, contact: &Contact) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .contacts
+            .insert(contact.uuid, clone_contact(contact));
+        Ok(())
+    }
+
+    async fn contacts(&self) -> Result<Self::ContactsIter, Self::ContentsStoreError> {
+        let contents = read(&self.contents);
+        let mut contacts: Vec<Contact> = contents.contacts.values().map(clone_contact).collect();
+        contacts.sort_by_key(|contact| contact.inbox_position);
+        Ok(contacts.into_iter().map(Ok).collect::<Vec<_>>().into_iter())
+    }
+
+    async fn contact_by_id(&self, id: &Uuid) -> Result<Option<Contact>, Self::ContentsStoreError> {
+        Ok(read(&self.contents).contacts.get(id).map(clone_contact))
+    }
+
+    async fn contact_by_phone_number(
+        &self,
+        phone_number: &PhoneNumber,
+    ) -> Result<Option<Contact>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .contacts
+            .values()
+            .find(|contact| contact.phone_number.as_ref() == Some(phone_number))
+            .map(clone_contact))
+    }
+
+    async fn clear_groups(&mut self) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        contents.groups.clear();
+        contents.group_avatars.clear();
+        Ok(())
+    }
+
+    async fn save_group(
+        &self,
+        master_key: GroupMasterKeyBytes,
+        group: impl Into<Group>,
+    ) -> Result<(), Self::ContentsStoreError> {
+        let group = serde_json::to_vec(&group.into())?;
+        write(&self.contents).groups.insert(master_key, group);
+        Ok(())
+    }
+
+    async fn groups(&self) -> Result<Self::GroupsIter, Self::ContentsStoreError> {
+        let groups: Vec<_> = read(&self.contents)
+            .groups
+            .iter()
+            .map(|(master_key, group)| Ok((*master_key, serde_json::from_slice(group)?)))
+            .collect();
+        Ok(groups.into_iter())
+    }
+
+    async fn group(
+        &self,
+        master_key: GroupMasterKeyBytes,
+    ) -> Result<Option<Group>, Self::ContentsStoreError> {
+        read(&self.contents)
+            .groups
+            .get(&master_key)
+            .map(|group| serde_json::from_slice(group))
+            .transpose()
+            .map_err(From::from)
+    }
+
+    async fn save_group_avatar(
+        &self,
+        master_key: GroupMasterKeyBytes,
+        avatar: &AvatarBytes,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .group_avatars
+            .insert(master_key, avatar.clone());
+        Ok(())
+    }
+
+    async fn group_avatar(
+        &self,
+        master_key: GroupMasterKeyBytes,
+    ) -> Result<Option<AvatarBytes>, Self::ContentsStoreError> {
+        Ok(read(&self.contents).group_avatars.get(&master_key).cloned())
+    }
+
+    async fn upsert_profile_key(
+        &mut self,
+        uuid: &Uuid,
+        key: ProfileKey,
+    ) -> Result<bool, Self::ContentsStoreError> {
+        let previous = write(&self.contents).profile_keys.insert(*uuid, key.bytes);
+        Ok(previous.is_none())
+    }
+
+    async fn profile_key(
+        &self,
+        service_id: &ServiceId,
+    ) -> Result<Option<ProfileKey>, Self::ContentsStoreError> {
+        Ok(read(&self.contents)
+            .profile_keys
+            .get(&service_id.raw_uuid())
+            .map(|bytes| ProfileKey::create(*bytes)))
+    }
+
+    async fn save_profile(
+        &mut self,
+        uuid: Uuid,
+        key: ProfileKey,
+        profile: Profile,
+    ) -> Result<(), Self::ContentsStoreError> {
+        self.upsert_profile_key(&uuid, key).await?;
+        write(&self.contents).profiles.insert(uuid, profile);
+        Ok(())
+    }
+
+    async fn profile(
+        &self,
+        uuid: Uuid,
+        key: ProfileKey,
+    ) -> Result<Option<Profile>, Self::ContentsStoreError> {
+        let contents = read(&self.contents);
+        if contents.profile_keys.get(&uuid) != Some(&key.bytes) {
+            return Ok(None);
+        }
+        Ok(contents.profiles.get(&uuid).cloned())
+    }
+
+    async fn save_profile_avatar(
+        &mut self,
+        uuid: Uuid,
+        _key: ProfileKey,
+        profile: &AvatarBytes,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .profile_avatars
+            .insert(uuid, profile.clone());
+        Ok(())
+    }
+
+    async fn profile_avatar(
+        &self,
+        uuid: Uuid,
+        _key: ProfileKey,
+    ) -> Result<Option<AvatarBytes>, Self::ContentsStoreError> {
+        Ok(read(&self.contents).profile_avatars.get(&uuid).cloned())
+    }
+
+    async fn clear_profile(&mut self, uuid: &Uuid) -> Result<(), Self::ContentsStoreError> {
+        let mut contents = write(&self.contents);
+        contents.profiles.remove(uuid);
+        contents.profile_avatars.remove(uuid);
+        contents.profile_credentials.remove(uuid);
+        Ok(())
+    }
+
+    async fn save_profile_credential(
+        &mut self,
+        uuid: Uuid,
+        credential_bytes: Vec<u8>,
+        expiration_time: u64,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .profile_credentials
+            .insert(uuid, (credential_bytes, expiration_time));
+        Ok(())
+    }
+
+    async fn profile_credential(
+        &self,
+        uuid: &Uuid,
+    ) -> Result<Option<Vec<u8>>, Self::ContentsStoreError> {
+        let now = now_secs();
+        Ok(read(&self.contents)
+            .profile_credentials
+            .get(uuid)
+            .filter(|(_, expiration_time)| *expiration_time > now)
+            .map(|(credential, _)| credential.clone()))
+    }
+
+    async fn clear_expired_credentials(&mut self) -> Result<u64, Self::ContentsStoreError> {
+        let now = now_secs();
+        let mut contents = write(&self.contents);
+        let len = contents.profile_credentials.len();
+        contents
+            .profile_credentials
+            .retain(|_, (_, expiration_time)| *expiration_time > now);
+        Ok((len - contents.profile_credentials.len()) as u64)
+    }
+
+    async fn add_sticker_pack(
+        &mut self,
+        pack: &StickerPack,
+    ) -> Result<(), Self::ContentsStoreError> {
+        write(&self.contents)
+            .sticker_packs
+            .insert(pack.id.clone(), pack.clone());
+        Ok(())
+    }
+
+    async fn sticker_pack(
+        &self,
+        id: &[u8],
+    ) -> Result<Option<StickerPack>, Self::ContentsStoreError> {
+        Ok(read(&self.contents).sticker_packs.get(id).cloned())
+    }
+
+    async fn remove_sticker_pack(&mut self, id: &[u8]) -> Result<bool, Self::ContentsStoreError> {
+        Ok(write(&self.contents).sticker_packs.remove(id).is_some())
+    }
+
+    async fn sticker_packs(&self) -> Result<Self::StickerPacksIter, Self::ContentsStoreError> {
+        let packs: Vec<_> = read(&self.contents)
+            .sticker_packs
+            .values()
+            .cloned()
+            .map(Ok)
+            .collect();
+        Ok(packs.into_iter())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum IdentityType {
+    Aci,
+    Pni,
+}
+
+/// The protocol store of an [InMemoryStore], for either the ACI or the PNI identity
+#[derive(Clone)]
+pub struct InMemoryProtocolStore {
+    store: InMemoryStore,
+    identity: IdentityType,
+}
+
+#[derive(Default)]
+struct ProtocolData {
+    sessions: HashMap<(String, u8), SessionRecord>,
+    /// Identity keys of contacts and whether they are trusted
+    identities: HashMap<String, (IdentityKey, bool)>,
+    pre_keys: BTreeMap<u32, PreKeyRecord>,
+    signed_pre_keys: BTreeMap<u32, SignedPreKeyRecord>,
+    /// Kyber pre-keys and whether they are last resort keys
+    kyber_pre_keys: BTreeMap<u32, (KyberPreKeyRecord, bool)>,
+    /// Base keys seen with last resort kyber pre-keys
+    base_keys_seen: HashSet<(u32, u32, Vec<u8>)>,
+    sender_keys: HashMap<(String, u8, Uuid), SenderKeyRecord>,
+}
+
+impl InMemoryProtocolStore {
+    fn data(&self) -> RwLockReadGuard<'_, ProtocolData> {
+        match self.identity {
+            IdentityType::Aci => read(&self.store.aci),
+            IdentityType::Pni => read(&self.store.pni),
+        }
+    }
+
+    fn data_mut(&self) -> RwLockWriteGuard<'_, ProtocolData> {
+        match self.identity {
+            IdentityType::Aci => write(&self.store.aci),
+            IdentityType::Pni => write(&self.store.pni),
+        }
+    }
+}
+
+impl ProtocolStore for InMemoryProtocolStore {}
+
+#[async_trait(?Send)]
+impl SessionStore for InMemoryProtocolStore {
+    async fn load_session(
+        &self,
+        address: &ProtocolAddress,
+    ) -> Result<Option<SessionRecord>, SignalProtocolError> {
+        let key = (address.name().to_owned(), address.device_id().into());
+        Ok(self.data().sessions.get(&key).cloned())
+    }
+
+    async fn store_session(
+        &mut self,
+        address: &ProtocolAddress,
+        record: &SessionRecord,
+    ) -> Result<(), SignalProtocolError> {
+        let key = (address.name().to_owned(), address.device_id().into());
+        self.data_mut().sessions.insert(key, record.clone());
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SessionStoreExt for InMemoryProtocolStore {
+    async fn get_sub_device_sessions(
+        &self,
+        name: &ServiceId,
+    ) -> Result<Vec<DeviceId>, SignalProtocolError> {
+        let address = name.raw_uuid().to_string();
+        let default_device_id: u8 = (*DEFAULT_DEVICE_ID).into();
+        Ok(self
+            .data()
+            .sessions
+            .keys()
+            .filter(|(name, device_id)| *name == address && *device_id != default_device_id)
+            .filter_map(|(_, device_id)| u32::from(*device_id).try_into().ok())
+            .collect())
+    }
+
+    async fn delete_session(&self, address: &ProtocolAddress) -> Result<(), SignalProtocolError> {
+        let key = (address.name().to_owned(), address.device_id().into());
+        self.data_mut().sessions.remove(&key);
+        Ok(())
+    }
+
+    async fn delete_all_sessions(&self, name: &ServiceId) -> Result<usize, SignalProtocolError> {
+        let address = name.raw_uuid().to_string();
+        let mut data = self.data_mut();
+        let len = data.sessions.len();
+        data.sessions.retain(|(name, _), _| *name != address);
+        Ok(len - data.sessions.len())
+    }
+}
+
+#[async_trait(?Send)]
+impl PreKeyStore for InMemoryProtocolStore {
+    async fn get_pre_key(&self, prekey_id: PreKeyId) -> Result<PreKeyRecord, SignalProtocolError> {
+        self.data()
+            .pre_keys
+            .get(&prekey_id.into())
+            .cloned()
+            .ok_or(SignalProtocolError::InvalidPreKeyId)
+    }
+
+    async fn save_pre_key(
+        &mut self,
+        prekey_id: PreKeyId,
+        record: &PreKeyRecord,
+    ) -> Result<(), SignalProtocolError> {
+        self.data_mut()
+            .pre_keys
+            .insert(prekey_id.into(), record.clone());
+        Ok(())
+    }
+
+    async fn remove_pre_key(&mut self, prekey_id: PreKeyId) -> Result<(), SignalProtocolError> {
+        self.data_mut().pre_keys.remove(&prekey_id.into());
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl PreKeysStore for InMemoryProtocolStore {
+    async fn next_pre_key_id(&self) -> Result<u32, SignalProtocolError> {
+        Ok(next_id(&self.data().pre_keys))
+    }
+
+    async fn next_signed_pre_key_id(&self) -> Result<u32, SignalProtocolError> {
+        Ok(next_id(&self.data().signed_pre_keys))
+    }
+
+    async fn next_pq_pre_key_id(&self) -> Result<u32, SignalProtocolError> {
+        Ok(next_id(&self.data().kyber_pre_keys))
+    }
+
+    async fn signed_pre_keys_count(&self) -> Result<usize, SignalProtocolError> {
+        Ok(self.data().signed_pre_keys.len())
+    }
+
+    async fn kyber_pre_keys_count(&self, _last_resort: bool) -> Result<usize, SignalProtocolError> {
+        Ok(self.data().kyber_pre_keys.len())
+    }
+
+    async fn signed_prekey_id(&self) -> Result<Option<SignedPreKeyId>, SignalProtocolError> {
+        Ok(self
+            .data()
+            .signed_pre_keys
+            .last_key_value()
+            .map(|(id, _)| (*id).into()))
+    }
+
+    async fn last_resort_kyber_prekey_id(
+        &self,
+    ) -> Result<Option<KyberPreKeyId>, SignalProtocolError> {
+        Ok(self
+            .data()
+            .kyber_pre_keys
+            .iter()
+            .rev()
+            .find(|(_, (_, is_last_resort))| *is_last_resort)
+            .map(|(id, _)| (*id).into()))
+    }
+}
+
+#[async_trait(?Send)]
+impl SignedPreKeyStore for InMemoryProtocolStore {
+    async fn get_signed_pre_key(
+        &self,
+        signed_prekey_id: SignedPreKeyId,
+    ) -> Result<SignedPreKeyRecord, SignalProtocolError> {
+        self.data()
+            .signed_pre_keys
+            .get(&signed_prekey_id.into())
+            .cloned()
+            .ok_or(SignalProtocolError::InvalidSignedPreKeyId)
+    }
+
+    async fn save_signed_pre_key(
+        &mut self,
+        signed_prekey_id: SignedPreKeyId,
+        record: &SignedPreKeyRecord,
+    ) -> Result<(), SignalProtocolError> {
+        self.data_mut()
+            .signed_pre_keys
+            .insert(signed_prekey_id.into(), record.clone());
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl KyberPreKeyStore for InMemoryProtocolStore {
+    async fn get_kyber_pre_key(
+        &self,
+        kyber_prekey_id: KyberPreKeyId,
+    ) -> Result<KyberPreKeyRecord, SignalProtocolError> {
+        self.data()
+            .kyber_pre_keys
+            .get(&kyber_prekey_id.into())
+            .map(|(record, _)| record.clone())
+            .ok_or(SignalProtocolError::InvalidKyberPreKeyId)
+    }
+
+    async fn save_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<(), SignalProtocolError> {
+        self.data_mut()
+            .kyber_pre_keys
+            .entry(kyber_prekey_id.into())
+            .and_modify(|(existing, _)| *existing = record.clone())
+            .or_insert_with(|| (record.clone(), false));
+        Ok(())
+    }
+
+    /// Mark the entry for `kyber_prekey_id` as "used": one-time keys are removed, while base keys
+    /// used with last-resort keys are remembered to reject their reuse.
+    async fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<(), SignalProtocolError> {
+        let kyber_prekey_id: u32 = kyber_prekey_id.into();
+        let mut data = self.data_mut();
+        let is_last_resort = data
+            .kyber_pre_keys
+            .get(&kyber_prekey_id)
+            .map(|(_, is_last_resort)| *is_last_resort)
+            .ok_or(SignalProtocolError::InvalidKyberPreKeyId)?;
+
+        if is_last_resort {
+            let base_key_seen = (
+                kyber_prekey_id,
+                ec_prekey_id.into(),
+                base_key.serialize().to_vec(),
+            );
+            if !data.base_keys_seen.insert(base_key_seen) {
+                return Err(SignalProtocolError::InvalidMessage(
+                    CiphertextMessageType::PreKey,
+                    "reused base key",
+                ));
+            }
+        } else {
+            data.kyber_pre_keys.remove(&kyber_prekey_id);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl KyberPreKeyStoreExt for InMemoryProtocolStore {
+    async fn store_last_resort_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<(), SignalProtocolError> {
+        self.data_mut()
+            .kyber_pre_keys
+            .insert(kyber_prekey_id.into(), (record.clone(), true));
+        Ok(())
+    }
+
+    async fn load_last_resort_kyber_pre_keys(
+        &self,
+    ) -> Result<Vec<KyberPreKeyRecord>, SignalProtocolError> {
+        Ok(self
+            .data()
+            .kyber_pre_keys
+            .values()
+            .filter(|(_, is_last_resort)| *is_last_resort)
+            .map(|(record, _)| record.clone())
+            .collect())
+    }
+
+    async fn remove_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+    ) -> Result<(), SignalProtocolError> {
+        self.data_mut()
+            .kyber_pre_keys
+            .remove(&kyber_prekey_id.into());
+        Ok(())
+    }
+
+    async fn mark_all_one_time_kyber_pre_keys_stale_if_necessary(
+        &mut self,
+        _stale_time: DateTime<Utc>,
+    ) -> Result<(), SignalProtocolError> {
+        // one-time Kyber pre-keys are removed once used, there are no stale ones to track
+        Ok(())
+    }
+
+    async fn delete_all_stale_one_time_kyber_pre_keys(
+        &mut self,
+        _threshold: DateTime<Utc>,
+        _min_count: usize,
+    ) -> Result<(), SignalProtocolError> {
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl IdentityKeyStore for InMemoryProtocolStore {
+    async fn get_identity_key_pair(&self) -> Result<IdentityKeyPair, SignalProtocolError> {
+        let state = read(&self.store.state);
+        let key_pair = match self.identity {
+            IdentityType::Aci => state.aci_identity_key_pair.as_deref(),
+            IdentityType::Pni => state.pni_identity_key_pair.as_deref(),
+        };
+        let key_pair = key_pair.ok_or_else(|| {
+            SignalProtocolError::InvalidState(
+                "failed to load identity key pair",
+                "no identity key pair".into(),
+            )
+        })?;
+        IdentityKeyPair::try_from(key_pair)
+    }
+
+    async fn get_local_registration_id(&self) -> Result<u32, SignalProtocolError> {
+        read(&self.store.state)
+            .registration
+            .as_ref()
+            .map(|data| data.registration_id)
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState(
+                    "failed to load registration ID",
+                    "no registration data".into(),
+                )
+            })
+    }
+
+    async fn save_identity(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+    ) -> Result<IdentityChange, SignalProtocolError> {
+        let mut data = self.data_mut();
+        let previous = data.identities.get_mut(address.name());
+        let replaced = previous.is_some();
+        match previous {
+            // an identity stays untrusted until it is explicitly trusted
+            Some((key, _trusted)) => *key = *identity,
+            None => {
+                data.identities
+                    .insert(address.name().to_owned(), (*identity, true));
+            }
+        }
+        Ok(if replaced {
+            IdentityChange::ReplacedExisting
+        } else {
+            IdentityChange::NewOrUnchanged
+        })
+    }
+
+    async fn is_trusted_identity(
+        &self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        _direction: Direction,
+    ) -> Result<bool, SignalProtocolError> {
+        match self.identity_trust(address).await? {
+            Some((known_key, trusted)) if identity == &known_key => Ok(trusted),
+            // when we encounter some identity we know, we need to decide whether we trust it or not
+            Some(_) => match self.store.trust_new_identities {
+                OnNewIdentity::Trust => Ok(true),
+                OnNewIdentity::Reject => {
+                    // keep the new identity, so it can be trusted explicitly
+                    warn!(%address, "rejecting changed identity");
+                    self.data_mut()
+                        .identities
+                        .insert(address.name().to_owned(), (*identity, false));
+                    Ok(false)
+                }
+            },
+            None => {
+                // when we encounter a new identity, we trust it by default
+                warn!(%address, "trusting new identity");
+                Ok(true)
+            }
+        }
+    }
+
+    async fn get_identity(
+        &self,
+        address: &ProtocolAddress,
+    ) -> Result<Option<IdentityKey>, SignalProtocolError> {
+        Ok(self
+            .data()
+            .identities
+            .get(address.name())
+            .map(|(key, _trusted)| *key))
+    }
+}
+
+impl IdentityTrustStore for InMemoryProtocolStore {
+    async fn set_identity_trust(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        trusted: bool,
+    ) -> Result<(), SignalProtocolError> {
+        self.data_mut()
+            .identities
+            .insert(address.name().to_owned(), (*identity, trusted));
+        Ok(())
+    }
+
+    async fn identity_trust(
+        &self,
+        address: &ProtocolAddress,
+    ) -> Result<Option<(IdentityKey, bool)>, SignalProtocolError> {
+        Ok(self.data().identities.get(address.name()).copied())
+    }
+}
+
+#[async_trait(?Send)]
+impl SenderKeyStore for InMemoryProtocolStore {
+    async fn store_sender_key(
+        &mut self,
+        sender: &ProtocolAddress,
+        distribution_id: Uuid,
+        record: &SenderKeyRecord,
+    ) -> Result<(), SignalProtocolError> {
+        let key = (
+            sender.name().to_owned(),
+            sender.device_id().into(),
+            distribution_id,
+        );
+        self.data_mut().sender_keys.insert(key, record.clone());
+        Ok(())
+    }
+
+    async fn load_sender_key(
+        &mut self,
+        sender: &ProtocolAddress,
+        distribution_id: Uuid,
+    ) -> Result<Option<SenderKeyRecord>, SignalProtocolError> {
+        let key = (
+            sender.name().to_owned(),
+            sender.device_id().into(),
+            distribution_id,
+        );
+        Ok(self.data().sender_keys.get(&key).cloned())
+    }
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn next_id<V>(records: &BTreeMap<u32, V>) -> u32 {
+    records.last_key_value().map(|(id, _)| id + 1).unwrap_or(1)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before UNIX epoch")
+        .as_secs()
+}
+
+/// Lowercase words of a text, to search messages like the full-text search of a database would
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn snippet(content: &Content) -> Option<String> {
+    let body = content.data_message()?.body.as_deref()?;
+    Some(body.chars().take(SNIPPET_LEN).collect())
+}
+
+fn clone_contact(contact: &Contact) -> Contact {
+    Contact {
+        uuid: contact.uuid,
+        phone_number: contact.phone_number.clone(),
+        name: contact.name.clone(),
+        verified: contact.verified.clone(),
+        profile_key: contact.profile_key.clone(),
+        expire_timer: contact.expire_timer,
+        expire_timer_version: contact.expire_timer_version,
+        inbox_position: contact.inbox_position,
+        avatar: contact.avatar.as_ref().map(|avatar| Attachment {
+            content_type: avatar.content_type.clone(),
+            reader: avatar.reader.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libsignal_service::{
+        content::Metadata,
+        prelude::phonenumber,
+        proto::DataMessage,
+        protocol::{Aci, IdentityKeyPair},
+    };
+
+    use super::*;
+
+    fn text_message(sender: Aci, timestamp: u64, body: &str) -> Content {
+        Content {
+            metadata: Metadata {
+                sender: sender.into(),
+                destination: sender.into(),
+                sender_device: *DEFAULT_DEVICE_ID,
+                server_guid: None,
+                timestamp,
+                needs_receipt: false,
+                unidentified_sender: false,
+                was_plaintext: false,
+            },
+            body: DataMessage {
+                body: Some(body.to_owned()),
+                timestamp: Some(timestamp),
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_changed_identity() -> Result<(), Box<dyn std::error::Error>> {
+        let address = ProtocolAddress::new(Uuid::new_v4().to_string(), *DEFAULT_DEVICE_ID);
+        let mut rng = rand::rng();
+        let identity = *IdentityKeyPair::generate(&mut rng).identity_key();
+        let new_identity = *IdentityKeyPair::generate(&mut rng).identity_key();
+
+        for (on_new_identity, trusted) in
+            [(OnNewIdentity::Trust, true), (OnNewIdentity::Reject, false)]
+        {
+            let mut store = InMemoryStore::new(on_new_identity).aci_protocol_store();
+            assert!(
+                store
+                    .is_trusted_identity(&address, &identity, Direction::Receiving)
+                    .await?
+            );
+            store.save_identity(&address, &identity).await?;
+            assert!(
+                store
+                    .is_trusted_identity(&address, &identity, Direction::Receiving)
+                    .await?
+            );
+            assert_eq!(
+                store
+                    .is_trusted_identity(&address, &new_identity, Direction::Receiving)
+                    .await?,
+                trusted
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn untrusted_identity() -> Result<(), Box<dyn std::error::Error>> {
+        let address = ProtocolAddress::new(Uuid::new_v4().to_string(), *DEFAULT_DEVICE_ID);
+        let mut rng = rand::rng();
+        let identity = *IdentityKeyPair::generate(&mut rng).identity_key();
+        let new_identity = *IdentityKeyPair::generate(&mut rng).identity_key();
+
+        let mut store = InMemoryStore::new(OnNewIdentity::Reject).aci_protocol_store();
+        store.save_identity(&address, &identity).await?;
+        assert_eq!(
+            store.identity_trust(&address).await?,
+            Some((identity, true))
+        );
+
+        store.set_identity_trust(&address, &identity, false).await?;
+        assert!(
+            !store
+                .is_trusted_identity(&address, &identity, Direction::Sending)
+                .await?
+        );
+
+        // the rejected identity is kept, untrusted
+        store.set_identity_trust(&address, &identity, true).await?;
+        assert!(
+            !store
+                .is_trusted_identity(&address, &new_identity, Direction::Receiving)
+                .await?
+        );
+        assert_eq!(
+            store.identity_trust(&address).await?,
+            Some((new_identity, false))
+        );
+
+        store
+            .set_identity_trust(&address, &new_identity, true)
+            .await?;
+        assert!(
+            store
+                .is_trusted_identity(&address, &new_identity, Direction::Sending)
+                .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn messages_and_threads() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = InMemoryStore::new(OnNewIdentity::Trust);
+        let uuid = Uuid::new_v4();
+        let alice = Aci::from(uuid);
+        let thread = Thread::Contact(uuid);
+
+        for (timestamp, body) in [(1, "hello"), (2, "how are you?"), (3, "Hello again")] {
+            store
+                .save_message(&thread, text_message(alice, timestamp, body))
+                .await?;
+        }
+
+        let timestamps: Vec<u64> = store
+            .messages(&thread, 2..)
+            .await?
+            .map(|message| message.map(|m| m.metadata.timestamp))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(timestamps, [3, 2]);
+
+        let found = store.search_messages("HELLO", None, 10).await?;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].metadata.timestamp, 3);
+
+        let threads = store.threads().await?;
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].last_message_timestamp, Some(3));
+        assert_eq!(threads[0].unread_count, 3);
+        assert_eq!(threads[0].snippet.as_deref(), Some("Hello again"));
+
+        assert_eq!(store.mark_read(&thread, 2).await?.len(), 2);
+        assert_eq!(store.unread_count(&thread).await?, 1);
+
+        assert!(store.delete_message(&thread, 3).await?);
+        assert_eq!(store.unread_count(&thread).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn processed_envelopes() -> Result<(), Box<dyn std::error::Error>> {
+        let store = InMemoryStore::new(OnNewIdentity::Trust);
+        let (guid, other_guid) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // the same envelope delivered twice is only processed once
+        assert!(store.mark_envelope_processed(guid, 10, 0).await?);
+        assert!(!store.mark_envelope_processed(guid, 20, 0).await?);
+        assert!(store.mark_envelope_processed(other_guid, 30, 0).await?);
+
+        // until it is forgotten after the retention window
+        assert!(store.mark_envelope_processed(guid, 40, 15).await?);
+        assert!(!store.mark_envelope_processed(other_guid, 50, 15).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn message_statuses_only_move_forward() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = InMemoryStore::new(OnNewIdentity::Trust);
+        let thread = Thread::Contact(Uuid::new_v4());
+
+        assert_eq!(store.message_status(&thread, 10).await?, None);
+        store
+            .save_message_status(&thread, 10, MessageStatus::Read)
+            .await?;
+        // a late delivery receipt
+        store
+            .save_message_status(&thread, 10, MessageStatus::Delivered)
+            .await?;
+        assert_eq!(
+            store.message_status(&thread, 10).await?,
+            Some(MessageStatus::Read)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pending_sends() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = InMemoryStore::new(OnNewIdentity::Trust);
+        let uuid = Uuid::new_v4();
+        let aci = Aci::from(uuid);
+        let contact = Thread::Contact(uuid);
+        let group = Thread::Group([1; 32]);
+
+        store
+            .save_pending_send(&contact, text_message(aci, 20, "second"))
+            .await?;
+        store
+            .save_pending_send(&group, text_message(aci, 10, "first"))
+            .await?;
+        // saving the same message again replaces it
+        store
+            .save_pending_send(&contact, text_message(aci, 20, "second, again"))
+            .await?;
+
+        let pending_sends = store.pending_sends().await?;
+        let pending: Vec<_> = pending_sends
+            .iter()
+            .map(|(thread, content)| (thread, content.data_message().unwrap().body()))
+            .collect();
+        assert_eq!(pending, [(&group, "first"), (&contact, "second, again")]);
+
+        store.remove_pending_send(&group, 10).await?;
+        store.remove_pending_send(&contact, 10).await?;
+        assert_eq!(store.pending_sends().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn contacts_by_phone_number() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = InMemoryStore::new(OnNewIdentity::Trust);
+        let phone_number = phonenumber::parse(None, "+41791234567")?;
+        let contact = Contact {
+            uuid: Uuid::new_v4(),
+            phone_number: Some(phone_number.clone()),
+            name: "Alice".to_owned(),
+            verified: Default::default(),
+            profile_key: vec![0; 32],
+            expire_timer: 0,
+            expire_timer_version: 2,
+            inbox_position: 0,
+            avatar: None,
+        };
+        store.save_contact(&contact).await?;
+
+        let found = store.contact_by_phone_number(&phone_number).await?;
+        assert_eq!(found.map(|contact| contact.uuid), Some(contact.uuid));
+        let other_phone_number = phonenumber::parse(None, "+41797654321")?;
+        assert!(store
+            .contact_by_phone_number(&other_phone_number)
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn archived_and_muted_threads() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = InMemoryStore::new(OnNewIdentity::Trust);
+        let uuid = Uuid::new_v4();
+        let thread = Thread::Contact(uuid);
+
+        assert!(!store.is_archived(&thread).await?);
+        assert_eq!(store.muted_until(&thread).await?, None);
+
+        store.set_archived(&thread, true).await?;
+        store.set_muted_until(&thread, Some(5000)).await?;
+        store
+            .save_message(&thread, text_message(Aci::from(uuid), 1000, "hello"))
+            .await?;
+
+        let threads = store.threads().await?;
+        assert!(threads[0].archived);
+        assert!(threads[0].is_muted_at(4000));
+        assert!(!threads[0].is_muted_at(5000));
+
+        store.set_archived(&thread, false).await?;
+        store.set_muted_until(&thread, None).await?;
+        assert!(!store.is_archived(&thread).await?);
+        assert_eq!(store.muted_until(&thread).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stale_kyber_pre_keys_are_no_ops() -> Result<(), Box<dyn std::error::Error>> {
+        let store = InMemoryStore::new(OnNewIdentity::Trust);
+        let mut protocol_store = store.aci_protocol_store();
+
+        protocol_store
+            .mark_all_one_time_kyber_pre_keys_stale_if_necessary(Utc::now())
+            .await?;
+        protocol_store
+            .delete_all_stale_one_time_kyber_pre_keys(Utc::now(), 0)
+            .await?;
+        assert!(protocol_store
+            .load_last_resort_kyber_pre_keys()
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
@@ -18,9 +18,13 @@
 #[cfg(test)]
 mod group_crud_integration_tests {
     use presage::{
+        libsignal_service::content::DataMessage,
+        libsignal_service::groups_v2::Role,
         libsignal_service::prelude::{ProfileKey, Uuid},
         libsignal_service::protocol::Aci,
+        model::groups::GroupMemberRole,
         model::identity::OnNewIdentity,
+        store::ContentsStore,
         Manager,
     };
     use presage_store_sqlite::SqliteStore;
@@ -131,6 +135,58 @@ mod group_crud_integration_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_add_member_as_admin() -> anyhow::Result<()> {
+        let Some((db_path, members)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH and member environment variables");
+            return Ok(());
+        };
+
+        if members.len() < 2 {
+            println!("Skipping test: Need TEST_MEMBER_2_UUID and TEST_MEMBER_2_PROFILE_KEY for add member test");
+            return Ok(());
+        }
+
+        // You must set this to an existing group's master key
+        let master_key_hex = std::env::var("TEST_GROUP_MASTER_KEY")
+            .expect("Set TEST_GROUP_MASTER_KEY to test adding members");
+        let master_key_bytes: [u8; 32] = hex::decode(&master_key_hex)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Master key must be 32 bytes"))?;
+
+        // Load registered manager
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+
+        // Add the second member as administrator
+        let (member_uuid, member_profile_key) = &members[1];
+        let member_aci: Aci = (*member_uuid).into();
+
+        manager
+            .add_group_member_with_role(
+                &master_key_bytes,
+                member_aci,
+                Some(*member_profile_key),
+                GroupMemberRole::Administrator,
+            )
+            .await?;
+
+        let group = manager.fetch_group(&master_key_bytes).await?;
+        let member = group
+            .members
+            .iter()
+            .find(|m| m.aci == member_aci)
+            .expect("member to be part of the group");
+        assert_eq!(member.role, Role::Administrator);
+
+        println!("✅ Member added as administrator!");
+        println!("   Member UUID: {}", member_uuid);
+        println!("\n📱 Check your mobile Signal app to verify the new member is an admin");
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[ignore] // Requires real Signal account and network access
     async fn test_remove_member() -> anyhow::Result<()> {
@@ -165,6 +221,184 @@ mod group_crud_integration_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_leave_group() -> anyhow::Result<()> {
+        let Some((db_path, members)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH and member environment variables");
+            return Ok(());
+        };
+
+        // Load registered manager
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+
+        // Create a group to leave, we are its only administrator
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let group_title = format!("Leave Test Group {}", timestamp);
+        let initial_members: Vec<(Aci, Option<ProfileKey>)> =
+            vec![(members[0].0.into(), Some(members[0].1))];
+        let (master_key, _pending) = manager.create_group(&group_title, initial_members).await?;
+        println!("✅ Group created: {}", group_title);
+
+        // Wait for propagation
+        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+
+        // The first member is promoted to administrator, since we are the last one
+        manager.leave_group(&master_key).await?;
+
+        let group = manager
+            .store()
+            .group(master_key)
+            .await?
+            .expect("left groups are kept locally");
+        assert!(!group.is_member(manager.aci()));
+        let successor: Aci = members[0].0.into();
+        assert!(group
+            .members
+            .iter()
+            .any(|member| member.aci == successor && member.role == Role::Administrator));
+        println!("✅ Left the group");
+        println!("\n📱 Check your mobile Signal app: we left the group and the member is its administrator");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_update_group_title_and_description() -> anyhow::Result<()> {
+        let Some((db_path, _members)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH and member environment variables");
+            return Ok(());
+        };
+
+        // You must set this to an existing group's master key
+        let master_key_hex = std::env::var("TEST_GROUP_MASTER_KEY")
+            .expect("Set TEST_GROUP_MASTER_KEY to test updating the group");
+        let master_key_bytes: [u8; 32] = hex::decode(&master_key_hex)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Master key must be 32 bytes"))?;
+
+        // Load registered manager
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let new_title = format!("Renamed Group {}", timestamp);
+
+        let title_revision = manager
+            .update_group_title(&master_key_bytes, &new_title)
+            .await?;
+        println!("✅ Title updated (revision {})", title_revision);
+        println!("   Title: {}", new_title);
+
+        let description_revision = manager
+            .update_group_description(&master_key_bytes, "Set by the presage integration tests")
+            .await?;
+        assert_eq!(description_revision, title_revision + 1);
+        println!("✅ Description set (revision {})", description_revision);
+
+        // An empty description clears it
+        let cleared_revision = manager
+            .update_group_description(&master_key_bytes, "")
+            .await?;
+        assert_eq!(cleared_revision, description_revision + 1);
+        println!("✅ Description cleared (revision {})", cleared_revision);
+
+        println!("\n📱 Check your mobile Signal app to verify the new title appears");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_set_announcements_only() -> anyhow::Result<()> {
+        let Some((db_path, _members)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH and member environment variables");
+            return Ok(());
+        };
+
+        // You must set this to the master key of an existing group we administrate
+        let master_key_hex = std::env::var("TEST_GROUP_MASTER_KEY")
+            .expect("Set TEST_GROUP_MASTER_KEY to test announcements-only groups");
+        let master_key_bytes: [u8; 32] = hex::decode(&master_key_hex)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Master key must be 32 bytes"))?;
+
+        // Load registered manager
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+
+        let revision = manager
+            .set_group_announcements_only(&master_key_bytes, true)
+            .await?;
+        let group = manager.fetch_group(&master_key_bytes).await?;
+        assert!(group.announcements_only);
+        println!("✅ Announcements-only enabled (revision {})", revision);
+
+        // Administrators can still send messages
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let message = DataMessage {
+            body: Some("Only administrators can send messages now".to_string()),
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
+        manager
+            .send_message_to_group(&master_key_bytes, message, timestamp)
+            .await?;
+        println!("✅ Message sent as administrator");
+
+        let revision = manager
+            .set_group_announcements_only(&master_key_bytes, false)
+            .await?;
+        println!("✅ Announcements-only disabled (revision {})", revision);
+        println!("\n📱 Check your mobile Signal app to verify the group setting changed twice");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_approve_group_request() -> anyhow::Result<()> {
+        let Some((db_path, _members)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH and member environment variables");
+            return Ok(());
+        };
+
+        // You must set this to the master key of a group we administrate, which requires approval
+        // to join with its invite link, and someone must have requested to join it
+        let master_key_hex = std::env::var("TEST_GROUP_MASTER_KEY")
+            .expect("Set TEST_GROUP_MASTER_KEY to test approving requests to join");
+        let master_key_bytes: [u8; 32] = hex::decode(&master_key_hex)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Master key must be 32 bytes"))?;
+
+        // Load registered manager
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+
+        manager.fetch_group(&master_key_bytes).await?;
+        let requests = manager.pending_group_requests(&master_key_bytes).await?;
+        let Some(aci) = requests.first().copied() else {
+            println!("Skipping test: nobody requested to join the group");
+            return Ok(());
+        };
+
+        let revision = manager
+            .approve_group_request(&master_key_bytes, aci)
+            .await?;
+        let group = manager.fetch_group(&master_key_bytes).await?;
+        assert!(group.is_member(aci));
+        assert!(!manager
+            .pending_group_requests(&master_key_bytes)
+            .await?
+            .contains(&aci));
+        println!("✅ Request approved (revision {})", revision);
+        println!("\n📱 Check your mobile Signal app to verify the user joined the group");
+
+        Ok(())
+    }
+
     #[tokio::test]
     #[ignore] // Requires real Signal account and network access
     async fn test_full_group_crud_lifecycle() -> anyhow::Result<()> {
@@ -0,0 +1,107 @@
+// Integration test for 1-1 messaging features (typing indicators, reactions, ...)
+//
+// NOTE: This is a template for manual integration testing.
+// Automated testing requires:
+// 1. A registered Signal account on staging servers
+// 2. A contact with a known UUID
+// 3. Network access to Signal staging servers
+// 4. Mobile Signal app connected to staging to verify the results
+//
+// To run manual tests:
+// 1. Set up environment variables (see get_test_config)
+// 2. Run: cargo test --test messaging_integration_test -- --ignored
+
+#[cfg(test)]
+mod messaging_integration_tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use presage::{
+        libsignal_service::{
+            content::DataMessage,
+            prelude::Uuid,
+            protocol::{Aci, ServiceId},
+        },
+        model::identity::OnNewIdentity,
+        store::Thread,
+        Manager,
+    };
+    use presage_store_sqlite::SqliteStore;
+
+    /// Helper to get test configuration from environment
+    /// Set these environment variables to run the test:
+    /// - TEST_SIGNAL_DB_PATH: Path to SQLite database (should be registered on staging)
+    /// - TEST_RECIPIENT_UUID: UUID of the contact to send messages to
+    fn get_test_config() -> Option<(String, Uuid)> {
+        let db_path = std::env::var("TEST_SIGNAL_DB_PATH").ok()?;
+        let recipient_uuid = std::env::var("TEST_RECIPIENT_UUID")
+            .ok()
+            .and_then(|s| Uuid::parse_str(&s).ok())?;
+        Some((db_path, recipient_uuid))
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_send_typing() -> anyhow::Result<()> {
+        let Some((db_path, recipient_uuid)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH, TEST_RECIPIENT_UUID");
+            return Ok(());
+        };
+
+        // Load registered manager
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+        let thread = Thread::Contact(recipient_uuid);
+
+        manager.send_typing(&thread, true).await?;
+        println!("✅ Typing STARTED sent");
+
+        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+
+        manager.send_typing(&thread, false).await?;
+        println!("✅ Typing STOPPED sent");
+        println!(
+            "\n📱 Check your mobile Signal app: the typing indicator should show for ~3 seconds"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires real Signal account and network access
+    async fn test_send_reaction() -> anyhow::Result<()> {
+        let Some((db_path, recipient_uuid)) = get_test_config() else {
+            println!("Skipping test: Set TEST_SIGNAL_DB_PATH, TEST_RECIPIENT_UUID");
+            return Ok(());
+        };
+
+        let store = SqliteStore::open_with_passphrase(&db_path, None, OnNewIdentity::Trust).await?;
+        let mut manager = Manager::load_registered(store).await?;
+        let thread = Thread::Contact(recipient_uuid);
+        let recipient: ServiceId = Aci::from(recipient_uuid).into();
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let message = DataMessage {
+            body: Some("React to me!".to_string()),
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
+        manager.send_message(recipient, message, timestamp).await?;
+        println!("✅ Message sent at {timestamp}");
+
+        let own_aci: ServiceId = manager.registration_data().service_ids.aci().into();
+        manager
+            .send_reaction(&thread, own_aci, timestamp, "👍", false)
+            .await?;
+        println!("✅ Reacted with 👍");
+
+        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+
+        manager
+            .send_reaction(&thread, own_aci, timestamp, "👍", true)
+            .await?;
+        println!("✅ Reaction removed");
+        println!("\n📱 Check your mobile Signal app: the 👍 should appear, then disappear");
+
+        Ok(())
+    }
+}
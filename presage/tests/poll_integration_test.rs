@@ -15,7 +15,9 @@
 
 #[cfg(test)]
 mod poll_integration_tests {
-    use presage::{libsignal_service::prelude::Uuid, model::identity::OnNewIdentity, Manager};
+    use presage::{
+        libsignal_service::prelude::Uuid, model::identity::OnNewIdentity, store::Thread, Manager,
+    };
     use presage_store_sqlite::SqliteStore;
     use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -169,9 +171,7 @@ mod poll_integration_tests {
         );
         let options = vec!["One".to_string(), "Two".to_string(), "Three".to_string()];
 
-        let poll_timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
-
-        manager
+        let poll_timestamp = manager
             .send_poll(
                 &master_key,
                 question,
@@ -202,6 +202,18 @@ mod poll_integration_tests {
         manager.terminate_poll(&master_key, poll_timestamp).await?;
         println!("✅ Step 3: Poll terminated");
 
+        // 4. Read the final results, our own vote and termination are stored when sent
+        let master_key: [u8; 32] = master_key.try_into().expect("32 bytes master key");
+        let results = manager
+            .poll_results(&Thread::Group(master_key), poll_timestamp)
+            .await?;
+        assert!(results.terminated);
+        for (index, option) in results.options.iter().enumerate() {
+            println!("   {}: {} vote(s)", option.option, option.votes());
+            assert_eq!(option.votes(), usize::from(index != 1));
+        }
+        println!("✅ Step 4: Final results read");
+
         println!("✅ Full poll lifecycle test completed successfully!");
         Ok(())
     }